@@ -0,0 +1,146 @@
+use client::{
+    content::ContentStore,
+    error::{ClientError, ClientResult},
+    harmony_rust_sdk::{
+        api::{
+            auth::{auth_step::Step, next_step_request::form_fields::Field},
+            profile::GetProfileRequest,
+        },
+        client::{api::auth::AuthStepResponse, AuthStatus},
+    },
+    smol_str::SmolStr,
+    Client, Session, Uri,
+};
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines, Stdin};
+
+/// Reads a session file written by either loqui or `loqui-cli login`.
+pub async fn read_session(path: &std::path::Path) -> ClientResult<Session> {
+    let raw = tokio::fs::read(path).await.map_err(|err| {
+        ClientError::Custom(format!(
+            "couldn't read session file {} (use `loqui-cli login <homeserver>` first): {}",
+            path.display(),
+            err
+        ))
+    })?;
+    toml::from_slice(&raw)
+        .map_err(|err| ClientError::Custom(format!("couldn't parse session file {}: {}", path.display(), err)))
+}
+
+/// Creates a client from a saved session.
+pub async fn client_from_session(session: Session, content_store: Arc<ContentStore>) -> ClientResult<Client> {
+    let homeserver = session
+        .homeserver
+        .parse::<Uri>()
+        .map_err(|err| ClientError::UrlParse(session.homeserver.to_string(), err))?;
+    let mut client = Client::new(homeserver, Some(session.into()), content_store).await?;
+    client.user_id = client.inner().auth_status().session().map(|s| s.user_id);
+    Ok(client)
+}
+
+/// Goes through the homeserver's auth steps on the terminal, then saves the session
+/// the same way the GUI does, so both can use it.
+pub async fn login(homeserver: Uri, content_store: Arc<ContentStore>) -> ClientResult<Session> {
+    let client = Client::new(homeserver, None, content_store.clone()).await?;
+    let inner = client.inner_arc();
+    let mut input = BufReader::new(tokio::io::stdin()).lines();
+
+    inner.begin_auth().await?;
+    let mut step = inner
+        .next_auth_step(AuthStepResponse::Initial)
+        .await?
+        .and_then(|s| s.step);
+
+    while let Some(auth_step) = step {
+        let response = match auth_step.step {
+            Some(Step::Choice(choice)) => {
+                println!("{}", choice.title);
+                for (index, option) in choice.options.iter().enumerate() {
+                    println!("  {}) {}", index + 1, option);
+                }
+                let answer = prompt(&mut input, "choice").await?;
+                let option = answer
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|index| choice.options.get(index.wrapping_sub(1)))
+                    .or_else(|| choice.options.iter().find(|option| **option == answer))
+                    .ok_or_else(|| ClientError::Custom(format!("no such choice: {}", answer)))?;
+                AuthStepResponse::Choice(option.clone())
+            }
+            Some(Step::Form(form)) => {
+                println!("{}", form.title);
+                let mut fields = Vec::with_capacity(form.fields.len());
+                for field in form.fields {
+                    let value = prompt(&mut input, &field.name).await?;
+                    fields.push(match field.r#type.as_str() {
+                        "number" => Field::Number(
+                            value
+                                .parse()
+                                .map_err(|_| ClientError::Custom(format!("{} must be a number", field.name)))?,
+                        ),
+                        "password" | "new-password" => Field::Bytes(value.into_bytes()),
+                        _ => Field::String(value),
+                    });
+                }
+                AuthStepResponse::form(fields)
+            }
+            _ => {
+                return Err(ClientError::Custom(
+                    "this homeserver uses an auth step loqui-cli doesn't support".to_string(),
+                ))
+            }
+        };
+        step = inner.next_auth_step(response).await?.and_then(|s| s.step);
+    }
+
+    let session = match client.auth_status() {
+        AuthStatus::Complete(session) => session,
+        _ => return Err(ClientError::Custom("authentication did not complete".to_string())),
+    };
+    let homeserver: SmolStr = inner.homeserver_url().to_string().into();
+    let user_id = session.user_id;
+    let user_profile = inner.call(GetProfileRequest::new(user_id)).await?;
+    let session = Session {
+        session_token: session.session_token.into(),
+        user_id: user_id.to_string().into(),
+        user_name: user_profile
+            .profile
+            .as_ref()
+            .map_or_else(SmolStr::default, |profile| profile.user_name.as_str().into()),
+        homeserver,
+    };
+
+    let session_file = content_store.session_path(&session.homeserver, user_id);
+    let ser = toml::ser::to_vec(&session)
+        .map_err(|err| ClientError::Custom(format!("couldn't serialize session: {}", err)))?;
+    tokio::fs::write(&session_file, ser).await.map_err(|err| {
+        ClientError::Custom(format!(
+            "couldn't write session file to {}: {}",
+            session_file.display(),
+            err
+        ))
+    })?;
+    let latest = content_store.latest_session_file();
+    let _ = tokio::fs::remove_file(latest).await;
+    tokio::fs::hard_link(&session_file, latest).await.map_err(|err| {
+        ClientError::Custom(format!(
+            "couldn't link session file ({}) to latest session ({}): {}",
+            session_file.display(),
+            latest.display(),
+            err
+        ))
+    })?;
+
+    Ok(session)
+}
+
+async fn prompt(input: &mut Lines<BufReader<Stdin>>, name: &str) -> ClientResult<String> {
+    let mut stdout = tokio::io::stdout();
+    stdout.write_all(format!("{}: ", name).as_bytes()).await?;
+    stdout.flush().await?;
+    input
+        .next_line()
+        .await?
+        .map(|line| line.trim().to_string())
+        .ok_or_else(|| ClientError::Custom("unexpected end of input".to_string()))
+}
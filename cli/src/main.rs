@@ -0,0 +1,589 @@
+use std::{path::PathBuf, sync::Arc};
+
+use chrono::TimeZone;
+use client::{
+    content::{self, ContentStore},
+    error::{ClientError, ClientResult},
+    harmony_rust_sdk::{
+        api::{
+            chat::{
+                get_channel_messages_request::Direction,
+                stream_event::{ChannelCreated, Event as ChatEvent, GuildAddedToList, GuildUpdated, MessageSent},
+                Event, GetGuildChannelsRequest, GetGuildListRequest, GetGuildRequest, Message as HarmonyMessage,
+            },
+            profile::GetProfileRequest,
+        },
+        client::{
+            api::{
+                chat::{channel::GetChannelMessages, message::SendMessage, EventSource},
+                rest::{upload_extract_id, FileId},
+            },
+            EventsReadSocket, EventsWriteSocket,
+        },
+    },
+    journal,
+    member::Members,
+    message::{Attachment, Content, Message},
+    AHashMap, Client, ConnectionStatus, Uri,
+};
+use tracing_subscriber::{fmt, prelude::*, EnvFilter};
+
+mod auth;
+
+const USAGE: &str = "\
+Usage: loqui-cli [--session <file>] <command> [arguments]
+
+Commands:
+    login <homeserver>                     log in and save the session
+    guilds                                 list guilds you are in
+    channels <guild>                       list channels of a guild
+    read <guild> <channel> [--limit N]     print the last N (default 50) messages of a channel
+    send <guild> <channel> <text>...       send a text message, `-` or piped input reads the text from stdin
+    upload <guild> <channel> <file>...     upload files and send them as a message
+    tail [guild]...                        print new messages as they arrive
+    replay <journal>                       replay a recorded journal into a fresh client and print its state
+
+Options:
+    --session <file>    session file to use, defaults to the latest session loqui used
+    --                  stop reading options, everything after is an argument
+
+Options go before the arguments of `send`, anything after its channel is sent as text.
+
+Set LOQUI_RECORD_EVENTS to record everything received into a journal in the data directory.
+";
+
+/// How many messages `read` prints if `--limit` isn't given.
+const DEFAULT_READ_LIMIT: usize = 50;
+
+#[derive(Debug)]
+enum Command {
+    Login(Uri),
+    Guilds,
+    Channels(u64),
+    Read {
+        guild_id: u64,
+        channel_id: u64,
+        limit: usize,
+    },
+    Send {
+        guild_id: u64,
+        channel_id: u64,
+        text: String,
+    },
+    Upload {
+        guild_id: u64,
+        channel_id: u64,
+        files: Vec<PathBuf>,
+    },
+    Tail(Vec<u64>),
+    Replay(PathBuf),
+}
+
+struct Args {
+    session: Option<PathBuf>,
+    command: Command,
+}
+
+fn parse_id(value: Option<String>, name: &str) -> ClientResult<u64> {
+    let value = value.ok_or_else(|| ClientError::Custom(format!("missing {} id", name)))?;
+    value
+        .parse()
+        .map_err(|_| ClientError::Custom(format!("invalid {} id: {}", name, value)))
+}
+
+/// Positional arguments and options of a command.
+#[derive(Default)]
+struct CommandArgs {
+    positional: Vec<String>,
+    limit: Option<usize>,
+    help: bool,
+}
+
+/// Splits the arguments of a command into options and positional arguments.
+///
+/// Options are only recognized until `--`, and for commands that end with free text (`text_after`),
+/// until that many positional arguments were given, so the text is kept as it was written.
+fn parse_command_args(
+    command: &str,
+    mut args: impl Iterator<Item = String>,
+    text_after: Option<usize>,
+) -> ClientResult<CommandArgs> {
+    let mut parsed = CommandArgs::default();
+    let mut options = true;
+
+    while let Some(arg) = args.next() {
+        if options && arg == "--" {
+            options = false;
+            continue;
+        }
+        if options && text_after.map_or(false, |count| parsed.positional.len() >= count) {
+            options = false;
+        }
+        if !options || arg == "-" || !arg.starts_with('-') {
+            parsed.positional.push(arg);
+            continue;
+        }
+        match arg.as_str() {
+            "--limit" if command == "read" => {
+                let value = args
+                    .next()
+                    .ok_or_else(|| ClientError::Custom("--limit needs a number".to_string()))?;
+                parsed.limit = Some(
+                    value
+                        .parse()
+                        .map_err(|_| ClientError::Custom(format!("invalid limit: {}", value)))?,
+                );
+            }
+            "-h" | "--help" => parsed.help = true,
+            _ => return Err(ClientError::Custom(format!("unknown option for {}: {}", command, arg))),
+        }
+    }
+
+    Ok(parsed)
+}
+
+fn parse_args(args: impl Iterator<Item = String>) -> ClientResult<Args> {
+    let mut args = args.peekable();
+    let mut session = None;
+    let mut help = false;
+
+    // Options before the command
+    while let Some(arg) = args.next_if(|arg| arg.starts_with('-')) {
+        match arg.as_str() {
+            "--session" => {
+                session = Some(
+                    args.next()
+                        .ok_or_else(|| ClientError::Custom("--session needs a file".to_string()))?
+                        .into(),
+                )
+            }
+            "-h" | "--help" => help = true,
+            "--" => break,
+            _ => return Err(ClientError::Custom(format!("unknown option: {}", arg))),
+        }
+    }
+
+    let name = match args.next() {
+        Some(name) if !help => name,
+        _ => {
+            print!("{}", USAGE);
+            std::process::exit(0);
+        }
+    };
+    let text_after = if name == "send" { Some(2) } else { None };
+    let command_args = parse_command_args(&name, args, text_after)?;
+    if command_args.help {
+        print!("{}", USAGE);
+        std::process::exit(0);
+    }
+
+    let mut positional = command_args.positional.into_iter();
+    let command = match name.as_str() {
+        "login" => {
+            let homeserver = positional
+                .next()
+                .ok_or_else(|| ClientError::Custom("missing homeserver".to_string()))?;
+            Command::Login(
+                homeserver
+                    .parse()
+                    .map_err(|err| ClientError::UrlParse(homeserver.clone(), err))?,
+            )
+        }
+        "guilds" => Command::Guilds,
+        "channels" => Command::Channels(parse_id(positional.next(), "guild")?),
+        "read" => Command::Read {
+            guild_id: parse_id(positional.next(), "guild")?,
+            channel_id: parse_id(positional.next(), "channel")?,
+            limit: command_args.limit.unwrap_or(DEFAULT_READ_LIMIT),
+        },
+        "send" => Command::Send {
+            guild_id: parse_id(positional.next(), "guild")?,
+            channel_id: parse_id(positional.next(), "channel")?,
+            text: positional.collect::<Vec<_>>().join(" "),
+        },
+        "upload" => Command::Upload {
+            guild_id: parse_id(positional.next(), "guild")?,
+            channel_id: parse_id(positional.next(), "channel")?,
+            files: positional.map(PathBuf::from).collect(),
+        },
+        "tail" => Command::Tail(
+            positional
+                .map(|id| parse_id(Some(id), "guild"))
+                .collect::<ClientResult<_>>()?,
+        ),
+        "replay" => Command::Replay(
+            positional
+                .next()
+                .ok_or_else(|| ClientError::Custom("missing journal file".to_string()))?
+                .into(),
+        ),
+        "help" => {
+            print!("{}", USAGE);
+            std::process::exit(0);
+        }
+        other => return Err(ClientError::Custom(format!("unknown command: {}", other))),
+    };
+
+    Ok(Args { session, command })
+}
+
+#[tokio::main]
+async fn main() {
+    tracing_subscriber::registry()
+        .with(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::from("warn")))
+        .with(fmt::layer().with_writer(std::io::stderr))
+        .init();
+
+    if let Err(err) = run().await {
+        eprintln!("error: {}", err);
+        std::process::exit(1);
+    }
+}
+
+async fn run() -> ClientResult<()> {
+    let args = parse_args(std::env::args().skip(1))?;
+
+    let content_store = Arc::new(ContentStore::default());
+    content_store.create_req_dirs()?;
+
+    let command = match args.command {
+        Command::Login(homeserver) => {
+            let session = auth::login(homeserver, content_store).await?;
+            println!("logged in as {}", session);
+            return Ok(());
+        }
+        Command::Replay(journal) => return replay(&journal, content_store).await,
+        command => command,
+    };
+
+    let session_file = args
+        .session
+        .unwrap_or_else(|| content_store.latest_session_file().to_path_buf());
+    let session = auth::read_session(&session_file).await?;
+    let mut client = auth::client_from_session(session, content_store).await?;
+    if journal::recording_enabled() {
+        let path = client.start_recording()?;
+        eprintln!("recording events to {}", path.display());
+    }
+
+    match command {
+        Command::Login(_) | Command::Replay(_) => unreachable!("handled before loading a session"),
+        Command::Guilds => {
+            load_guilds(&mut client).await?;
+            for (guild_id, guild) in client.guilds.iter() {
+                println!("{}\t{}", guild_id, guild.name);
+            }
+        }
+        Command::Channels(guild_id) => {
+            load_channels(&mut client, guild_id).await?;
+            if let Some(guild) = client.guilds.get(&guild_id) {
+                for (channel_id, channel) in guild.channels.iter() {
+                    let kind = if channel.is_category { "category" } else { "text" };
+                    println!("{}\t{}\t{}", channel_id, kind, channel.name);
+                }
+            }
+        }
+        Command::Read {
+            guild_id,
+            channel_id,
+            limit,
+        } => {
+            let messages = read_messages(&client, guild_id, channel_id, limit).await?;
+            let mut names = AHashMap::new();
+            for (message_id, message) in messages {
+                let message = Message::from(message);
+                let name = username(&client, &mut names, message.sender).await;
+                println!("{}", format_message(message_id, &name, &message, &client.members));
+            }
+        }
+        Command::Send {
+            guild_id,
+            channel_id,
+            text,
+        } => {
+            // Only wait for stdin if something is piped in, a terminal would just block
+            let text = if text == "-" || (text.is_empty() && !atty::is(atty::Stream::Stdin)) {
+                let mut text = String::new();
+                tokio::io::AsyncReadExt::read_to_string(&mut tokio::io::stdin(), &mut text).await?;
+                text
+            } else if text.is_empty() {
+                return Err(ClientError::Custom("missing text to send".to_string()));
+            } else {
+                text
+            };
+            let message_id = send_content(&client, guild_id, channel_id, Content::text(text)).await?;
+            println!("{}", message_id);
+        }
+        Command::Upload {
+            guild_id,
+            channel_id,
+            files,
+        } => {
+            if files.is_empty() {
+                return Err(ClientError::Custom("no files to upload".to_string()));
+            }
+            let mut attachments = Vec::with_capacity(files.len());
+            for file in files {
+                let data = tokio::fs::read(&file).await?;
+                let kind = content::infer_type_from_bytes(&data);
+                let name = content::get_filename(&file);
+                let size = data.len() as u32;
+                let id = upload_extract_id(client.inner(), name.clone(), kind.clone(), data).await?;
+                attachments.push(Attachment {
+                    id: FileId::Id(id),
+                    kind,
+                    name,
+                    size,
+                    resolution: None,
+                    minithumbnail: None,
+                });
+            }
+            let message_id = send_content(&client, guild_id, channel_id, Content::Files(attachments)).await?;
+            println!("{}", message_id);
+        }
+        Command::Tail(guild_ids) => tail(&mut client, guild_ids).await?,
+    }
+
+    Ok(())
+}
+
+/// Loads the guild list and guild names into the client.
+async fn load_guilds(client: &mut Client) -> ClientResult<()> {
+    let inner = client.inner_arc();
+    let guilds = inner.call(GetGuildListRequest::new()).await?.guilds;
+    for guild in guilds {
+        let guild_id = guild.guild_id;
+        client.process_event(Event::Chat(ChatEvent::GuildAddedToList(GuildAddedToList {
+            guild_id,
+            homeserver: guild.server_id,
+        })));
+        let guild = inner
+            .call(GetGuildRequest::new(guild_id))
+            .await?
+            .guild
+            .unwrap_or_default();
+        client.process_event(Event::Chat(ChatEvent::EditedGuild(GuildUpdated {
+            guild_id,
+            new_metadata: guild.metadata,
+            new_name: Some(guild.name),
+            new_picture: guild.picture,
+        })));
+    }
+    Ok(())
+}
+
+/// Loads the channels of a guild into the client.
+async fn load_channels(client: &mut Client, guild_id: u64) -> ClientResult<()> {
+    if client.guilds.is_empty() {
+        load_guilds(client).await?;
+    }
+    let channels = client
+        .inner()
+        .call(GetGuildChannelsRequest::new(guild_id))
+        .await?
+        .channels;
+    for c in channels {
+        if let Some(channel) = c.channel {
+            client.process_event(Event::Chat(ChatEvent::CreatedChannel(ChannelCreated {
+                guild_id,
+                channel_id: c.channel_id,
+                kind: channel.kind,
+                name: channel.channel_name,
+                metadata: channel.metadata,
+                ..Default::default()
+            })));
+        }
+    }
+    Ok(())
+}
+
+/// Fetches the newest `limit` messages of a channel, oldest first.
+async fn read_messages(
+    client: &Client,
+    guild_id: u64,
+    channel_id: u64,
+    limit: usize,
+) -> ClientResult<Vec<(u64, HarmonyMessage)>> {
+    let mut messages = Vec::with_capacity(limit);
+    let mut before = None;
+
+    while messages.len() < limit {
+        let mut request = GetChannelMessages::new(guild_id, channel_id).with_direction(Direction::BeforeUnspecified);
+        if let Some(message_id) = before {
+            request = request.with_message_id(message_id);
+        }
+        let response = client.inner().call(request).await?;
+        messages.extend(
+            response
+                .messages
+                .into_iter()
+                .flat_map(|m| Some((m.message_id, m.message?))),
+        );
+        match messages.last() {
+            Some((oldest_id, _)) if !response.reached_top && before != Some(*oldest_id) => before = Some(*oldest_id),
+            _ => break,
+        }
+    }
+
+    messages.truncate(limit);
+    messages.reverse();
+    Ok(messages)
+}
+
+async fn send_content(client: &Client, guild_id: u64, channel_id: u64, content: Content) -> ClientResult<u64> {
+    let message = SendMessage::new(guild_id, channel_id).with_content(client::harmony_rust_sdk::api::chat::Content {
+        content: Some(content.to_harmony(&client.members, &client.emote_packs)),
+    });
+    Ok(client.inner().call(message).await?.message_id)
+}
+
+/// Subscribes to events of the homeserver and the given guilds.
+async fn subscribe(client: &Client, guild_ids: &[u64]) -> ClientResult<(EventsWriteSocket, EventsReadSocket)> {
+    let (mut tx, rx) = client.inner().subscribe_events(false).await?.split();
+    tx.add_source(EventSource::Homeserver).await?;
+    for guild_id in guild_ids {
+        tx.add_source(EventSource::Guild(*guild_id)).await?;
+    }
+    Ok((tx, rx))
+}
+
+/// Streams events and prints every message sent in the given guilds (or every guild if none given).
+async fn tail(client: &mut Client, guild_ids: Vec<u64>) -> ClientResult<()> {
+    load_guilds(client).await?;
+    let guild_ids = if guild_ids.is_empty() {
+        client.guilds.keys().copied().collect()
+    } else {
+        guild_ids
+    };
+    for guild_id in guild_ids.iter() {
+        load_channels(client, *guild_id).await?;
+    }
+
+    // Kept alive so the stream isn't closed from our side
+    let (mut _tx, mut rx) = subscribe(client, &guild_ids).await?;
+    let mut names = AHashMap::new();
+    let mut attempt = 0;
+    loop {
+        let event = match rx.get_event().await {
+            Ok(Some(event)) => {
+                attempt = 0;
+                event
+            }
+            lost => {
+                match lost {
+                    Err(err) => eprintln!("events stream failed: {}, reconnecting", err),
+                    _ => eprintln!("events stream closed, reconnecting"),
+                }
+                // Waits longer after every failed attempt, so a homeserver that keeps failing isn't hammered
+                tokio::time::sleep(ConnectionStatus::backoff(attempt)).await;
+                attempt += 1;
+                match subscribe(client, &guild_ids).await {
+                    Ok((tx, new_rx)) => {
+                        _tx = tx;
+                        rx = new_rx;
+                    }
+                    Err(err) => eprintln!("couldn't subscribe to events: {}", err),
+                }
+                continue;
+            }
+        };
+
+        if let Event::Chat(ChatEvent::SentMessage(MessageSent {
+            guild_id,
+            channel_id,
+            message_id,
+            message: Some(message),
+            ..
+        })) = &event
+        {
+            let message = Message::from(message.clone());
+            let name = username(client, &mut names, message.sender).await;
+            let channel_name = client
+                .guilds
+                .get(guild_id)
+                .and_then(|g| g.channels.get(channel_id))
+                .map_or_else(|| channel_id.to_string(), |c| c.name.to_string());
+            println!(
+                "{}\t{}\t#{}\t{}",
+                guild_id,
+                channel_id,
+                channel_name,
+                format_message(*message_id, &name, &message, &client.members)
+            );
+        }
+
+        client.process_event(event);
+    }
+}
+
+/// Replays a journal into a client that isn't connected to anything, then prints what it ended up with.
+async fn replay(journal_path: &std::path::Path, content_store: Arc<ContentStore>) -> ClientResult<()> {
+    let entries = journal::read_journal(journal_path)?;
+    let entry_count = entries.len();
+    // The homeserver is never contacted, replaying only touches local state
+    let homeserver = "https://localhost".parse::<Uri>().unwrap();
+    let mut client = Client::new(homeserver, None, content_store).await?;
+    let post = journal::replay(&mut client, entries)?;
+
+    println!("replayed {} entries ({} post process events)", entry_count, post.len());
+    println!(
+        "{} members, {} emote packs",
+        client.members.len(),
+        client.emote_packs.len()
+    );
+    for (guild_id, guild) in client.guilds.iter() {
+        println!("{}\t{}", guild_id, guild.name);
+        for (channel_id, channel) in guild.channels.iter() {
+            println!(
+                "\t{}\t#{}\t{} messages{}",
+                channel_id,
+                channel.name,
+                channel.messages.len(),
+                if channel.reached_top { ", reached top" } else { "" }
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Gets the username of a user, fetching their profile if we didn't see it before.
+async fn username(client: &Client, names: &mut AHashMap<u64, String>, user_id: u64) -> String {
+    if let Some(name) = names.get(&user_id) {
+        return name.clone();
+    }
+    let name = client
+        .inner()
+        .call(GetProfileRequest::new(user_id))
+        .await
+        .ok()
+        .and_then(|resp| resp.profile)
+        .map_or_else(|| user_id.to_string(), |profile| profile.user_name);
+    names.insert(user_id, name.clone());
+    name
+}
+
+fn format_message(message_id: u64, sender: &str, message: &Message, members: &Members) -> String {
+    let timestamp = chrono::Local.from_utc_datetime(&message.timestamp);
+    let content = match &message.content {
+        Content::Text(text) => text.text.clone(),
+        Content::Files(attachments) => attachments
+            .iter()
+            .map(|a| format!("[file {} ({})]", a.name, a.id))
+            .collect::<Vec<_>>()
+            .join(" "),
+        Content::Embeds(embeds) => embeds
+            .iter()
+            .map(|e| format!("[embed {}]", e.title))
+            .collect::<Vec<_>>()
+            .join(" "),
+        Content::System(system) => format!("[{}]", system.describe(members)),
+    };
+    format!(
+        "{}\t{}\t{}: {}",
+        message_id,
+        timestamp.format("%Y-%m-%d %H:%M"),
+        sender,
+        content
+    )
+}
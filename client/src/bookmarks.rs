@@ -0,0 +1,103 @@
+//! Messages the user saved to come back to later.
+//!
+//! Bookmarks only exist locally; they are saved to disk whenever they change.
+
+use serde::{Deserialize, Serialize};
+
+use super::error::{ClientError, ClientResult};
+
+/// A saved message, with a snapshot of it so it can be shown without fetching it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Bookmark {
+    pub guild_id: u64,
+    pub channel_id: u64,
+    pub message_id: u64,
+    pub sender: u64,
+    /// The sender's name when the message was saved.
+    pub sender_name: String,
+    /// When the message was sent, in seconds since the UNIX epoch.
+    pub timestamp: i64,
+    /// The message's content when it was saved.
+    pub snapshot: String,
+    /// A note the user wrote about the message.
+    #[serde(default)]
+    pub note: String,
+}
+
+impl Bookmark {
+    pub fn is_for(&self, guild_id: u64, channel_id: u64, message_id: u64) -> bool {
+        self.guild_id == guild_id && self.channel_id == channel_id && self.message_id == message_id
+    }
+}
+
+/// Saved messages, newest bookmark first.
+#[derive(Debug, Clone, Default)]
+pub struct Bookmarks {
+    entries: Vec<Bookmark>,
+}
+
+impl Bookmarks {
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Bookmark> + '_ {
+        self.entries.iter()
+    }
+
+    pub fn get(&self, guild_id: u64, channel_id: u64, message_id: u64) -> Option<&Bookmark> {
+        self.entries.iter().find(|b| b.is_for(guild_id, channel_id, message_id))
+    }
+
+    pub fn contains(&self, guild_id: u64, channel_id: u64, message_id: u64) -> bool {
+        self.get(guild_id, channel_id, message_id).is_some()
+    }
+
+    /// Adds a bookmark, unless the message is already saved. Returns whether it was added.
+    pub fn add(&mut self, bookmark: Bookmark) -> bool {
+        if self.contains(bookmark.guild_id, bookmark.channel_id, bookmark.message_id) {
+            return false;
+        }
+        self.entries.insert(0, bookmark);
+        true
+    }
+
+    pub fn remove(&mut self, guild_id: u64, channel_id: u64, message_id: u64) -> Option<Bookmark> {
+        let pos = self
+            .entries
+            .iter()
+            .position(|b| b.is_for(guild_id, channel_id, message_id))?;
+        Some(self.entries.remove(pos))
+    }
+
+    /// Changes the note of a bookmark. Returns `false` if the message isn't saved.
+    pub fn set_note(&mut self, guild_id: u64, channel_id: u64, message_id: u64, note: String) -> bool {
+        match self
+            .entries
+            .iter_mut()
+            .find(|b| b.is_for(guild_id, channel_id, message_id))
+        {
+            Some(bookmark) => {
+                bookmark.note = note;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        // Serializing plain structs to a Vec can't fail
+        serde_json::to_vec(&self.entries).unwrap()
+    }
+
+    /// Decodes bookmarks created by [`Bookmarks::encode`].
+    pub fn decode(raw: &[u8]) -> ClientResult<Self> {
+        let entries = serde_json::from_slice(raw)
+            .map_err(|err| ClientError::Custom(format!("couldn't decode bookmarks: {}", err)))?;
+        Ok(Self { entries })
+    }
+}
@@ -0,0 +1,82 @@
+use chrono::NaiveDateTime;
+use harmony_rust_sdk::api::{
+    chat::{FormattedText, Message as HarmonyMessage},
+    exports::prost::Message as _,
+};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    error::{ClientError, ClientResult},
+    history::Messages,
+    message::{Message, Revision},
+};
+
+/// How many of the newest acknowledged messages are kept on disk per channel.
+pub const MAX_CACHED_MESSAGES: usize = 200;
+
+#[derive(Serialize, Deserialize)]
+struct CachedMessage {
+    message_id: u64,
+    /// Base64 encoded protobuf message.
+    message: String,
+    /// Earlier versions of the text, oldest first.
+    revisions: Vec<CachedRevision>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CachedRevision {
+    /// Base64 encoded protobuf formatted text.
+    text: String,
+    /// Seconds since the UNIX epoch.
+    written_at: i64,
+}
+
+/// Encodes the newest acknowledged messages of a channel, up to the first gap in its history.
+///
+/// Messages are stored newest first, the same way the homeserver returns a history page.
+pub fn encode_messages(messages: &Messages) -> Vec<u8> {
+    let cached = messages
+        .iter()
+        .rev()
+        .filter_map(|(id, message)| Some((id.id()?, message)))
+        // Stop at the first gap, a cache can't have messages missing between the ones it has
+        .take_while(|(message_id, _)| !messages.has_gap_after(*message_id))
+        .map(|(message_id, message)| CachedMessage {
+            message_id,
+            message: base64::encode(HarmonyMessage::from(message.clone()).encode_to_vec()),
+            revisions: message
+                .revisions
+                .iter()
+                .map(|revision| CachedRevision {
+                    text: base64::encode(revision.text.encode_to_vec()),
+                    written_at: revision.written_at.timestamp(),
+                })
+                .collect(),
+        })
+        .take(MAX_CACHED_MESSAGES)
+        .collect::<Vec<_>>();
+    // Serializing plain structs to a Vec can't fail
+    serde_json::to_vec(&cached).unwrap()
+}
+
+/// Decodes a message cache created by [`encode_messages`], newest first.
+pub fn decode_messages(raw: &[u8]) -> ClientResult<Vec<(u64, Message)>> {
+    let decode_err =
+        |err: &dyn std::fmt::Display| ClientError::Custom(format!("couldn't decode message cache: {}", err));
+
+    let cached: Vec<CachedMessage> = serde_json::from_slice(raw).map_err(|err| decode_err(&err))?;
+    let mut messages = Vec::with_capacity(cached.len());
+    for entry in cached {
+        let data = base64::decode(&entry.message).map_err(|err| decode_err(&err))?;
+        let mut message = Message::from(HarmonyMessage::decode(data.as_slice()).map_err(|err| decode_err(&err))?);
+        for revision in entry.revisions {
+            let data = base64::decode(&revision.text).map_err(|err| decode_err(&err))?;
+            message.revisions.push(Revision {
+                text: FormattedText::decode(data.as_slice()).map_err(|err| decode_err(&err))?,
+                written_at: NaiveDateTime::from_timestamp(revision.written_at, 0),
+            });
+        }
+        messages.push((entry.message_id, message));
+    }
+    Ok(messages)
+}
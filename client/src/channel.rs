@@ -1,25 +1,63 @@
 use crate::role::RolePerms;
 
-use super::message::Messages;
-use ahash::RandomState;
-use harmony_rust_sdk::api::chat::{permission::has_permission, Permission};
+use super::{
+    error::ClientResult, history::Messages, message::MessageId, read_markers::ReadMarker, viewport::Viewport,
+    InnerClient,
+};
+use ahash::{AHashMap, AHashSet, RandomState};
+use chrono::NaiveDateTime;
+use harmony_rust_sdk::{
+    api::chat::{permission::has_permission, Permission},
+    client::api::chat::channel::GetChannelMessages,
+};
 use indexmap::IndexMap;
 use smol_str::SmolStr;
 
 pub type Channels = IndexMap<u64, Channel, RandomState>;
 
+/// Messages connected to a message by replies, see [`Channel::thread`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Thread {
+    /// The messages the message replies to, oldest first.
+    pub parents: Vec<u64>,
+    /// The first parent that isn't loaded; anything above it is unknown until it's fetched.
+    pub missing_parent: Option<u64>,
+    /// Loaded replies to the message (and replies to those), in display order with their depth.
+    pub replies: Vec<(MessageId, usize)>,
+}
+
+/// Where the first message written on or after a date is, as far as the loaded messages tell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateLocation {
+    Loaded(MessageId),
+    /// Every known message is older, so the latest messages are the closest.
+    Latest,
+    /// The message isn't loaded, see [`fetch_first_message_since`].
+    NotLoaded {
+        /// The oldest loaded message, which history before can be fetched from. `None` if history
+        /// has to be fetched from the newest message.
+        oldest_loaded: Option<u64>,
+    },
+}
+
 #[derive(Debug, Clone)]
 pub struct Channel {
     pub name: SmolStr,
     pub is_category: bool,
     pub messages: Messages,
-    pub last_known_message_id: u64,
-    pub looking_at_message: usize,
+    /// Where the event history of this channel is scrolled to.
+    pub viewport: Viewport,
     pub loading_messages_history: bool,
     pub reached_top: bool,
+    /// Whether the loaded messages go up to the newest message of the channel.
+    ///
+    /// After jumping to a message in a channel whose newest messages weren't loaded, only messages
+    /// around it are, and new messages aren't added until newer pages are fetched up to the present.
+    /// Messages missing between loaded ones are tracked by [`Messages`] instead.
+    pub reached_bottom: bool,
     pub perms: Vec<Permission>,
     pub role_perms: RolePerms,
-    pub has_unread: bool,
+    pub read_marker: ReadMarker,
     pub looking_at_channel: bool,
     pub init_fetching: bool,
     pub uploading_files: Vec<String>,
@@ -29,4 +67,210 @@ impl Channel {
     pub fn has_perm(&self, query: &str) -> bool {
         has_permission(self.perms.iter().map(|p| (p.matches.as_str(), p.ok)), query).unwrap_or(false)
     }
+
+    pub fn has_unread(&self) -> bool {
+        self.read_marker.has_unread()
+    }
+
+    /// The newest acknowledged message in this channel.
+    pub fn newest_ack_message_id(&self) -> Option<u64> {
+        self.messages.keys().rev().find_map(|id| id.id())
+    }
+
+    /// Marks every message in this channel as read.
+    pub fn mark_read(&mut self) {
+        let newest = self.newest_ack_message_id();
+        self.read_marker.read_up_to(newest);
+    }
+
+    /// The message to fetch older history before, if messages are missing right before the message
+    /// at `index`.
+    pub fn missing_before(&self, index: usize) -> Option<u64> {
+        let is_missing = match index.checked_sub(1) {
+            Some(prev) => self
+                .messages
+                .get_index(prev)
+                .and_then(|(id, _)| id.id())
+                .map_or(false, |id| self.messages.has_gap_after(id)),
+            None => !self.reached_top,
+        };
+        if !is_missing {
+            return None;
+        }
+        self.messages.keys().skip(index).find_map(MessageId::id)
+    }
+
+    /// The message to fetch newer history after, if messages are missing right after the message at
+    /// `index`.
+    pub fn missing_after(&self, index: usize) -> Option<u64> {
+        let message_id = self.messages.keys().take(index + 1).rev().find_map(MessageId::id)?;
+        let is_newest = self.newest_ack_message_id() == Some(message_id);
+        (self.messages.has_gap_after(message_id) || (is_newest && !self.reached_bottom)).then(|| message_id)
+    }
+
+    /// Finds the first loaded message written on or after `since`.
+    ///
+    /// Messages are ordered by time, so this is a binary search over them.
+    pub fn locate_date(&self, since: NaiveDateTime) -> DateLocation {
+        let (mut low, mut high) = (0, self.messages.len());
+        while low < high {
+            let mid = (low + high) / 2;
+            if self.messages.get_index(mid).map_or(false, |(_, m)| m.timestamp < since) {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+
+        // Unless history is loaded up to the first message, older ones may be written after `since` too,
+        // and the same goes for messages missing right before the one found
+        if (low == 0 && !self.reached_top) || self.missing_before(low).is_some() {
+            return DateLocation::NotLoaded {
+                oldest_loaded: self.messages.keys().skip(low).find_map(MessageId::id),
+            };
+        }
+        // Same for newer ones if older messages were jumped to
+        if low == self.messages.len() && !self.reached_bottom {
+            return DateLocation::NotLoaded { oldest_loaded: None };
+        }
+        self.messages
+            .get_index(low)
+            .map_or(DateLocation::Latest, |(id, _)| DateLocation::Loaded(*id))
+    }
+
+    /// Collects the reply chain above `message_id` and the reply tree below it.
+    pub fn thread(&self, message_id: u64) -> Thread {
+        let mut thread = Thread::default();
+
+        let mut current = self.messages.get(&MessageId::Ack(message_id));
+        while let Some(parent_id) = current.and_then(|m| m.reply_to).filter(|id| *id != 0) {
+            // Guard against reply cycles, which a misbehaving homeserver could send
+            if parent_id == message_id || thread.parents.contains(&parent_id) {
+                break;
+            }
+            current = self.messages.get(&MessageId::Ack(parent_id));
+            if current.is_none() {
+                thread.missing_parent = Some(parent_id);
+                break;
+            }
+            thread.parents.push(parent_id);
+        }
+        thread.parents.reverse();
+
+        let mut children: AHashMap<u64, Vec<MessageId>> = AHashMap::new();
+        for (id, message) in &self.messages {
+            if let Some(parent_id) = message.reply_to {
+                children.entry(parent_id).or_default().push(*id);
+            }
+        }
+        let mut visited = AHashSet::new();
+        let mut stack = children
+            .get(&message_id)
+            .map_or_else(Vec::new, |c| c.iter().rev().map(|id| (*id, 1)).collect());
+        while let Some((id, depth)) = stack.pop() {
+            if !visited.insert(id) {
+                continue;
+            }
+            thread.replies.push((id, depth));
+            if let Some(c) = id.id().and_then(|id| children.get(&id)) {
+                stack.extend(c.iter().rev().map(|id| (*id, depth + 1)));
+            }
+        }
+
+        thread
+    }
+}
+
+/// A binary search over the history of a channel for the first message written on or after a date,
+/// which [`fetch_first_message_since`] narrows one history page at a time.
+///
+/// The homeserver orders history by message id, so the ids of messages grow with when they were
+/// written, and history before any id can be fetched to see which side of the date it's on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DateSearch {
+    pub since: NaiveDateTime,
+    /// Every message with this id or a lower one was written before `since`.
+    after: u64,
+    /// The oldest message known to be written on or after `since`. `None` until the newest page of
+    /// history is fetched.
+    before: Option<u64>,
+    /// When the message closest to `since` in the last fetched page was written, to show how far
+    /// the search got.
+    pub reached: Option<NaiveDateTime>,
+}
+
+impl DateSearch {
+    /// A search for the first message written on or after `since`. `before` is a message known to be
+    /// written on or after it, if there is one, so only messages up to it are searched.
+    pub fn new(since: NaiveDateTime, before: Option<u64>) -> Self {
+        Self {
+            since,
+            after: 0,
+            before,
+            reached: None,
+        }
+    }
+}
+
+/// What [`fetch_first_message_since`] found out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateSearchStep {
+    /// The first message written on or after the date, or `None` if every message is older.
+    Found(Option<u64>),
+    /// The search goes on from here.
+    Narrowed(DateSearch),
+}
+
+/// Fetches one history page for a [`DateSearch`], halving the ids it can be at.
+///
+/// The first page is the newest one, and the next ones end halfway between the newest id known to
+/// be older than the date and the oldest one known to be newer. A date jump takes as many pages as
+/// it takes to halve the ids of a channel down to one, and the caller can show how far it got and
+/// stop in between.
+pub async fn fetch_first_message_since(
+    inner: &InnerClient,
+    guild_id: u64,
+    channel_id: u64,
+    mut search: DateSearch,
+) -> ClientResult<DateSearchStep> {
+    // Halfway, rounded up so the page before the oldest newer message is fetched last
+    let probe = match search.before {
+        Some(before) if before <= search.after + 1 => return Ok(DateSearchStep::Found(Some(before))),
+        Some(before) => Some(search.after + 1 + (before - search.after) / 2),
+        None => None,
+    };
+    let mut request = GetChannelMessages::new(guild_id, channel_id);
+    if let Some(message_id) = probe {
+        request = request.with_message_id(message_id);
+    }
+    let response = inner.call(request).await?;
+
+    // Pages are newest first
+    let since = search.since.timestamp().max(0) as u64;
+    let mut page = response
+        .messages
+        .into_iter()
+        .flat_map(|m| Some((m.message?.created_at, m.message_id)))
+        .collect::<Vec<_>>();
+    page.sort_unstable();
+    let pos = page.partition_point(|(created_at, _)| *created_at < since);
+    let written_at = |created_at: u64| NaiveDateTime::from_timestamp(created_at as i64, 0);
+
+    Ok(match (probe, page.get(pos)) {
+        // Every message is older
+        (None, None) => DateSearchStep::Found(None),
+        // So is every message before the probe
+        (Some(probe), None) => {
+            search.after = probe - 1;
+            search.reached = page.last().map(|(created_at, _)| written_at(*created_at));
+            DateSearchStep::Narrowed(search)
+        }
+        (_, Some((created_at, message_id))) if pos == 0 && !response.reached_top => {
+            search.before = Some(*message_id);
+            search.reached = Some(written_at(*created_at));
+            DateSearchStep::Narrowed(search)
+        }
+        // The page goes from before the date to after it, or starts at the first message
+        (_, Some((_, message_id))) => DateSearchStep::Found(Some(*message_id)),
+    })
 }
@@ -0,0 +1,225 @@
+//! Slash commands typed in the composer.
+//!
+//! A command is a message starting with `/`. Commands are looked up in [`COMMANDS`], so adding
+//! one only needs a new entry there and a way for the UI to run it. Messages that should start with
+//! a `/` can be written with `//`.
+
+use harmony_rust_sdk::api::profile::UserStatus;
+
+use super::error::{ClientError, ClientResult};
+
+/// What a command asks for, once its arguments are parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SlashCommand {
+    /// Send this text as a message.
+    Say(String),
+    SetStatus(UserStatus),
+    SetUsername(String),
+    JoinGuild(String),
+    LeaveGuild,
+    Upload,
+    ComposeEmbed,
+    ClearComposer,
+    Search(String),
+    Help,
+}
+
+/// A command the composer understands.
+pub struct CommandInfo {
+    pub name: &'static str,
+    /// How the arguments are written, shown in completions and help.
+    pub usage: &'static str,
+    pub description: &'static str,
+    /// Values the argument can take, offered as completions.
+    pub arg_values: &'static [&'static str],
+    parse: fn(&str) -> Result<SlashCommand, &'static str>,
+}
+
+impl CommandInfo {
+    /// The command with its arguments, like `/status <online|idle|dnd>`.
+    pub fn usage_line(&self) -> String {
+        if self.usage.is_empty() {
+            format!("/{}", self.name)
+        } else {
+            format!("/{} {}", self.name, self.usage)
+        }
+    }
+
+    /// Line describing the command, for help.
+    pub fn help_line(&self) -> String {
+        format!("{}: {}", self.usage_line(), self.description)
+    }
+}
+
+fn required(args: &str) -> Result<String, &'static str> {
+    (!args.is_empty()).then(|| args.to_string()).ok_or("missing argument")
+}
+
+fn no_args(args: &str, command: SlashCommand) -> Result<SlashCommand, &'static str> {
+    args.is_empty()
+        .then(|| command)
+        .ok_or("this command doesn't take arguments")
+}
+
+pub const COMMANDS: &[CommandInfo] = &[
+    CommandInfo {
+        name: "me",
+        usage: "<action>",
+        description: "Send an action, shown in italics",
+        arg_values: &[],
+        parse: |args| required(args).map(|action| SlashCommand::Say(format!("*{}*", action))),
+    },
+    CommandInfo {
+        name: "shrug",
+        usage: "[message]",
+        description: "Send a message followed by a shrug",
+        arg_values: &[],
+        parse: |args| {
+            let shrug = "¯\\_(ツ)_/¯";
+            let text = if args.is_empty() {
+                shrug.to_string()
+            } else {
+                format!("{} {}", args, shrug)
+            };
+            Ok(SlashCommand::Say(text))
+        },
+    },
+    CommandInfo {
+        name: "status",
+        usage: "<online|idle|dnd>",
+        description: "Change your status",
+        arg_values: &["online", "idle", "dnd"],
+        parse: |args| {
+            let status = match args {
+                "online" => UserStatus::Online,
+                "idle" => UserStatus::Idle,
+                "dnd" => UserStatus::DoNotDisturb,
+                _ => return Err("status must be one of online, idle or dnd"),
+            };
+            Ok(SlashCommand::SetStatus(status))
+        },
+    },
+    CommandInfo {
+        name: "nick",
+        usage: "<username>",
+        description: "Change your username",
+        arg_values: &[],
+        parse: |args| required(args).map(SlashCommand::SetUsername),
+    },
+    CommandInfo {
+        name: "join",
+        usage: "<invite>",
+        description: "Join a guild with an invite",
+        arg_values: &[],
+        parse: |args| match args.split_whitespace().count() {
+            1 => Ok(SlashCommand::JoinGuild(args.to_string())),
+            0 => Err("missing argument"),
+            _ => Err("invites can't contain spaces"),
+        },
+    },
+    CommandInfo {
+        name: "leave",
+        usage: "",
+        description: "Leave the current guild",
+        arg_values: &[],
+        parse: |args| no_args(args, SlashCommand::LeaveGuild),
+    },
+    CommandInfo {
+        name: "topic",
+        usage: "<topic>",
+        description: "Set the topic of the current channel (not supported, channels don't have topics in Harmony)",
+        arg_values: &[],
+        parse: |_| Err("not supported, channels don't have topics in Harmony"),
+    },
+    CommandInfo {
+        name: "upload",
+        usage: "",
+        description: "Pick files to attach to your message",
+        arg_values: &[],
+        parse: |args| no_args(args, SlashCommand::Upload),
+    },
+    CommandInfo {
+        name: "embed",
+        usage: "",
+        description: "Write an embed and send it",
+        arg_values: &[],
+        parse: |args| no_args(args, SlashCommand::ComposeEmbed),
+    },
+    CommandInfo {
+        name: "clear",
+        usage: "",
+        description: "Discard the message you're writing, with its reply and attached files",
+        arg_values: &[],
+        parse: |args| no_args(args, SlashCommand::ClearComposer),
+    },
+    CommandInfo {
+        name: "search",
+        usage: "[query]",
+        description: "Search the messages of the current channel",
+        arg_values: &[],
+        parse: |args| Ok(SlashCommand::Search(args.to_string())),
+    },
+    CommandInfo {
+        name: "help",
+        usage: "",
+        description: "Show help, with the list of commands",
+        arg_values: &[],
+        parse: |args| no_args(args, SlashCommand::Help),
+    },
+];
+
+pub fn find(name: &str) -> Option<&'static CommandInfo> {
+    COMMANDS.iter().find(|command| command.name == name)
+}
+
+/// Parses the composer's text as a command.
+///
+/// Returns `None` if the text isn't a command. Unknown commands and wrong arguments are errors, so
+/// they aren't sent as messages by mistake.
+pub fn parse(text: &str) -> Option<ClientResult<SlashCommand>> {
+    let text = text.trim();
+    let rest = text.strip_prefix('/')?;
+    if rest.starts_with('/') {
+        return Some(Ok(SlashCommand::Say(rest.to_string())));
+    }
+
+    let (name, args) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    let result = match find(name) {
+        Some(command) => (command.parse)(args.trim())
+            .map_err(|err| ClientError::Custom(format!("/{}: {} (usage: {})", name, err, command.usage_line()))),
+        None => Err(ClientError::Custom(format!(
+            "unknown command /{}, use /help to see the available commands",
+            name
+        ))),
+    };
+    Some(result)
+}
+
+/// Completions for the word being typed, which starts at `word_start` in the composer's `text`.
+///
+/// At the start of the text, these are names of commands. After a command, they are values for its
+/// argument.
+pub fn completions(text: &str, word_start: usize, word: &str) -> Vec<&'static str> {
+    if word_start == 0 {
+        return match word.strip_prefix('/') {
+            Some(name) if !name.starts_with('/') => COMMANDS
+                .iter()
+                .map(|command| command.name)
+                .filter(|command| command.starts_with(name))
+                .collect(),
+            _ => Vec::new(),
+        };
+    }
+
+    text.get(..word_start)
+        .and_then(|before| before.trim_end().strip_prefix('/'))
+        .and_then(find)
+        .map_or_else(Vec::new, |command| {
+            command
+                .arg_values
+                .iter()
+                .copied()
+                .filter(|value| value.starts_with(word))
+                .collect()
+        })
+}
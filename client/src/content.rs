@@ -8,6 +8,8 @@ pub const MAX_THUMB_SIZE: u64 = 1000 * 500; // 500kb
 pub const SESSIONS_DIR_NAME: &str = "sessions";
 pub const LOG_FILENAME: &str = "log";
 pub const CONTENT_DIR_NAME: &str = "content";
+pub const MESSAGES_DIR_NAME: &str = "messages";
+pub const JOURNALS_DIR_NAME: &str = "journals";
 pub const THEME_NAME: &str = "theme.toml";
 
 pub fn infer_type_from_bytes(data: &[u8]) -> String {
@@ -29,17 +31,21 @@ pub struct ContentStore {
     sessions_dir: PathBuf,
     log_file: PathBuf,
     content_dir: PathBuf,
+    messages_dir: PathBuf,
+    journals_dir: PathBuf,
     theme_file: PathBuf,
 }
 
 impl Default for ContentStore {
     fn default() -> Self {
-        let (sessions_dir, log_file, content_dir, theme_file) =
+        let (sessions_dir, log_file, content_dir, messages_dir, journals_dir, theme_file) =
             match directories_next::ProjectDirs::from("nodomain", "yusdacra", "loqui") {
                 Some(app_dirs) => (
                     app_dirs.data_dir().join(SESSIONS_DIR_NAME),
                     app_dirs.data_dir().join(LOG_FILENAME),
                     app_dirs.cache_dir().join(CONTENT_DIR_NAME),
+                    app_dirs.data_dir().join(MESSAGES_DIR_NAME),
+                    app_dirs.data_dir().join(JOURNALS_DIR_NAME),
                     app_dirs.config_dir().join(THEME_NAME),
                 ),
                 // Fallback to current working directory if no HOME is present
@@ -47,6 +53,8 @@ impl Default for ContentStore {
                     SESSIONS_DIR_NAME.into(),
                     LOG_FILENAME.into(),
                     CONTENT_DIR_NAME.into(),
+                    MESSAGES_DIR_NAME.into(),
+                    JOURNALS_DIR_NAME.into(),
                     THEME_NAME.into(),
                 ),
             };
@@ -56,12 +64,30 @@ impl Default for ContentStore {
             sessions_dir,
             log_file,
             content_dir,
+            messages_dir,
+            journals_dir,
             theme_file,
         }
     }
 }
 
 impl ContentStore {
+    /// Creates a content store that keeps everything under one directory.
+    pub fn from_root(root: impl AsRef<Path>) -> Self {
+        let root = root.as_ref();
+        let sessions_dir = root.join(SESSIONS_DIR_NAME);
+
+        Self {
+            latest_session_file: sessions_dir.join("latest"),
+            sessions_dir,
+            log_file: root.join(LOG_FILENAME),
+            content_dir: root.join(CONTENT_DIR_NAME),
+            messages_dir: root.join(MESSAGES_DIR_NAME),
+            journals_dir: root.join(JOURNALS_DIR_NAME),
+            theme_file: root.join(THEME_NAME),
+        }
+    }
+
     pub fn session_path(&self, homeserver: &str, user_id: u64) -> PathBuf {
         self.sessions_dir()
             .join(format!("{}_{}", urlencoding::encode(homeserver), user_id))
@@ -73,6 +99,45 @@ impl ContentStore {
         self.content_dir().join(normalized_id.as_ref())
     }
 
+    pub fn message_cache_path(&self, homeserver: &str, guild_id: u64, channel_id: u64) -> PathBuf {
+        self.messages_dir().join(format!(
+            "{}_{}_{}",
+            urlencoding::encode(homeserver),
+            guild_id,
+            channel_id
+        ))
+    }
+
+    /// Path of the unsent messages of a user.
+    pub fn outbox_path(&self, homeserver: &str, user_id: u64) -> PathBuf {
+        self.messages_dir()
+            .join(format!("{}_{}.outbox", urlencoding::encode(homeserver), user_id))
+    }
+
+    /// Path of the read markers of a user.
+    pub fn read_markers_path(&self, homeserver: &str, user_id: u64) -> PathBuf {
+        self.messages_dir()
+            .join(format!("{}_{}.read", urlencoding::encode(homeserver), user_id))
+    }
+
+    /// Path of the bookmarks of a user.
+    pub fn bookmarks_path(&self, homeserver: &str, user_id: u64) -> PathBuf {
+        self.messages_dir()
+            .join(format!("{}_{}.bookmarks", urlencoding::encode(homeserver), user_id))
+    }
+
+    /// Path of the drafts of a user.
+    pub fn drafts_path(&self, homeserver: &str, user_id: u64) -> PathBuf {
+        self.messages_dir()
+            .join(format!("{}_{}.drafts", urlencoding::encode(homeserver), user_id))
+    }
+
+    /// Path of a new event journal, named after the time it was created.
+    pub fn new_journal_path(&self) -> PathBuf {
+        let now = std::time::UNIX_EPOCH.elapsed().unwrap_or_default();
+        self.journals_dir().join(format!("{}.jsonl", now.as_millis()))
+    }
+
     pub fn content_mimetype(&self, id: &FileId) -> String {
         infer::get_from_path(self.content_path(id))
             .ok()
@@ -90,6 +155,8 @@ impl ContentStore {
 
         create_dir_all(self.content_dir())?;
         create_dir_all(self.sessions_dir())?;
+        create_dir_all(self.messages_dir())?;
+        create_dir_all(self.journals_dir())?;
         create_dir_all(self.log_file().parent().unwrap_or_else(|| Path::new(".")))?;
         create_dir_all(self.theme_file().parent().unwrap_or_else(|| Path::new(".")))?;
 
@@ -108,6 +175,14 @@ impl ContentStore {
         self.sessions_dir.as_path()
     }
 
+    pub fn messages_dir(&self) -> &Path {
+        self.messages_dir.as_path()
+    }
+
+    pub fn journals_dir(&self) -> &Path {
+        self.journals_dir.as_path()
+    }
+
     pub fn log_file(&self) -> &Path {
         self.log_file.as_path()
     }
@@ -142,4 +217,12 @@ pub struct ThemeRaw {
     pub accent_color: String,
     #[serde(default)]
     pub mention_color: String,
+    #[serde(default)]
+    pub code_keyword_color: String,
+    #[serde(default)]
+    pub code_string_color: String,
+    #[serde(default)]
+    pub code_number_color: String,
+    #[serde(default)]
+    pub code_comment_color: String,
 }
@@ -0,0 +1,91 @@
+//! Word-level differences between two versions of a text.
+
+/// A part of the new text and how it relates to the old one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// Splits text into words and the whitespace between them, so that joining the parts gives back the text.
+fn split_words(text: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut in_space = None;
+    for (pos, c) in text.char_indices() {
+        let is_space = c.is_whitespace();
+        if in_space.map_or(false, |was_space| was_space != is_space) {
+            parts.push(&text[start..pos]);
+            start = pos;
+        }
+        in_space = Some(is_space);
+    }
+    if start < text.len() {
+        parts.push(&text[start..]);
+    }
+    parts
+}
+
+fn push_change<'a>(changes: &mut Vec<Change<'a>>, source: &'a str, change: Change<'a>) {
+    // Consecutive parts of the same kind follow each other in `source`, so they can be joined
+    if let Some(last) = changes.last_mut() {
+        let joined = match (*last, change) {
+            (Change::Same(prev), Change::Same(next)) => Some(Change::Same(join(source, prev, next))),
+            (Change::Removed(prev), Change::Removed(next)) => Some(Change::Removed(join(source, prev, next))),
+            (Change::Added(prev), Change::Added(next)) => Some(Change::Added(join(source, prev, next))),
+            _ => None,
+        };
+        if let Some(joined) = joined {
+            *last = joined;
+            return;
+        }
+    }
+    changes.push(change);
+}
+
+/// Joins two slices of `source` that follow each other.
+fn join<'a>(source: &'a str, prev: &str, next: &str) -> &'a str {
+    let start = prev.as_ptr() as usize - source.as_ptr() as usize;
+    let end = next.as_ptr() as usize - source.as_ptr() as usize + next.len();
+    &source[start..end]
+}
+
+/// Compares two texts word by word.
+///
+/// Unchanged and added parts are slices of `new`, removed parts are slices of `old`. Removed parts
+/// come before the parts that replaced them.
+pub fn diff_words<'a>(old: &'a str, new: &'a str) -> Vec<Change<'a>> {
+    let old_parts = split_words(old);
+    let new_parts = split_words(new);
+
+    // Length of the longest common subsequence of the parts after each position
+    let width = new_parts.len() + 1;
+    let mut lcs = vec![0_usize; (old_parts.len() + 1) * width];
+    for i in (0..old_parts.len()).rev() {
+        for j in (0..new_parts.len()).rev() {
+            lcs[i * width + j] = if old_parts[i] == new_parts[j] {
+                lcs[(i + 1) * width + j + 1] + 1
+            } else {
+                lcs[(i + 1) * width + j].max(lcs[i * width + j + 1])
+            };
+        }
+    }
+
+    let mut changes = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old_parts.len() || j < new_parts.len() {
+        if i < old_parts.len() && j < new_parts.len() && old_parts[i] == new_parts[j] {
+            push_change(&mut changes, new, Change::Same(new_parts[j]));
+            i += 1;
+            j += 1;
+        } else if j == new_parts.len() || (i < old_parts.len() && lcs[(i + 1) * width + j] >= lcs[i * width + j + 1]) {
+            push_change(&mut changes, old, Change::Removed(old_parts[i]));
+            i += 1;
+        } else {
+            push_change(&mut changes, new, Change::Added(new_parts[j]));
+            j += 1;
+        }
+    }
+    changes
+}
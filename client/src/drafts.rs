@@ -0,0 +1,139 @@
+//! Messages the user started writing in each channel but didn't send yet.
+//!
+//! Drafts are saved to disk so they survive a restart.
+
+use std::str::FromStr;
+
+use ahash::AHashMap;
+use harmony_rust_sdk::client::api::rest::FileId;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    error::{ClientError, ClientResult},
+    message::Attachment,
+};
+
+/// What the composer had in a channel.
+#[derive(Debug, Clone, Default)]
+pub struct Draft {
+    pub text: String,
+    pub reply_to: Option<u64>,
+    /// Uploaded files that will be sent with the message.
+    pub attachments: Vec<Attachment>,
+}
+
+impl Draft {
+    pub fn is_empty(&self) -> bool {
+        self.text.trim().is_empty() && self.reply_to.is_none() && self.attachments.is_empty()
+    }
+}
+
+/// Drafts of every channel, keyed by guild ID and channel ID.
+#[derive(Debug, Clone, Default)]
+pub struct Drafts {
+    drafts: AHashMap<(u64, u64), Draft>,
+}
+
+impl Drafts {
+    pub fn get(&self, guild_id: u64, channel_id: u64) -> Option<&Draft> {
+        self.drafts.get(&(guild_id, channel_id))
+    }
+
+    pub fn has_draft(&self, guild_id: u64, channel_id: u64) -> bool {
+        self.drafts.contains_key(&(guild_id, channel_id))
+    }
+
+    /// Replaces the draft of a channel. Empty drafts are removed.
+    pub fn set(&mut self, guild_id: u64, channel_id: u64, draft: Draft) {
+        if draft.is_empty() {
+            self.drafts.remove(&(guild_id, channel_id));
+        } else {
+            self.drafts.insert((guild_id, channel_id), draft);
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let saved = self
+            .drafts
+            .iter()
+            .map(|(&(guild_id, channel_id), draft)| SavedDraft {
+                guild_id,
+                channel_id,
+                text: draft.text.clone(),
+                reply_to: draft.reply_to,
+                attachments: draft.attachments.iter().map(SavedAttachment::from).collect(),
+            })
+            .collect::<Vec<_>>();
+        // Serializing plain structs to a Vec can't fail
+        serde_json::to_vec(&saved).unwrap()
+    }
+
+    /// Decodes drafts created by [`Drafts::encode`].
+    pub fn decode(raw: &[u8]) -> ClientResult<Self> {
+        let saved: Vec<SavedDraft> = serde_json::from_slice(raw)
+            .map_err(|err| ClientError::Custom(format!("couldn't decode drafts: {}", err)))?;
+        let drafts = saved
+            .into_iter()
+            .map(|draft| {
+                let attachments = draft
+                    .attachments
+                    .into_iter()
+                    .filter_map(SavedAttachment::into_attachment)
+                    .collect();
+                (
+                    (draft.guild_id, draft.channel_id),
+                    Draft {
+                        text: draft.text,
+                        reply_to: draft.reply_to,
+                        attachments,
+                    },
+                )
+            })
+            .collect();
+        Ok(Self { drafts })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedDraft {
+    guild_id: u64,
+    channel_id: u64,
+    text: String,
+    reply_to: Option<u64>,
+    #[serde(default)]
+    attachments: Vec<SavedAttachment>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedAttachment {
+    id: String,
+    kind: String,
+    name: String,
+    size: u32,
+    resolution: Option<(u32, u32)>,
+}
+
+impl From<&Attachment> for SavedAttachment {
+    fn from(attachment: &Attachment) -> Self {
+        SavedAttachment {
+            id: attachment.id.to_string(),
+            kind: attachment.kind.clone(),
+            name: attachment.name.clone(),
+            size: attachment.size,
+            resolution: attachment.resolution,
+        }
+    }
+}
+
+impl SavedAttachment {
+    fn into_attachment(self) -> Option<Attachment> {
+        Some(Attachment {
+            id: FileId::from_str(&self.id).ok()?,
+            kind: self.kind,
+            name: self.name,
+            size: self.size,
+            resolution: self.resolution,
+            minithumbnail: None,
+        })
+    }
+}
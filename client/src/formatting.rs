@@ -0,0 +1,461 @@
+//! Converting between the markdown messages are kept as and Harmony's formatted text.
+//!
+//! Messages are rendered and edited as markdown, so formats that have a markdown syntax (bold,
+//! italic, code, user mentions and emotes) are turned into it when a message is received, and back
+//! into formats when one is sent. Other formats, like colors, are kept as formats over the markdown.
+//!
+//! Format offsets are in bytes.
+
+use harmony_rust_sdk::api::chat::{
+    format::{self, color::Kind},
+    Format, FormattedText,
+};
+
+use super::{emotes::EmotePacks, member::Members};
+
+/// Colors a format can give to text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextColor {
+    Dim,
+    Bright,
+    Negative,
+    Positive,
+    Info,
+    Warning,
+}
+
+/// How text covered by a format that has no markdown syntax is shown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FormatStyle {
+    Color(TextColor),
+    RoleMention(u64),
+}
+
+/// The style of the text at `offset` in a message's markdown, if a format changes it.
+pub fn style_at(formats: &[Format], offset: usize) -> Option<FormatStyle> {
+    formats
+        .iter()
+        .filter(|f| (f.start as usize..(f.start + f.length) as usize).contains(&offset))
+        .find_map(|f| match f.format.as_ref()? {
+            format::Format::Color(color) => {
+                let color = match Kind::from_i32(color.kind).unwrap_or(Kind::DimUnspecified) {
+                    Kind::DimUnspecified => TextColor::Dim,
+                    Kind::Bright => TextColor::Bright,
+                    Kind::Negative => TextColor::Negative,
+                    Kind::Positive => TextColor::Positive,
+                    Kind::Info => TextColor::Info,
+                    Kind::Warning => TextColor::Warning,
+                };
+                Some(FormatStyle::Color(color))
+            }
+            format::Format::RoleMention(mention) => Some(FormatStyle::RoleMention(mention.role_id)),
+            _ => None,
+        })
+}
+
+enum Markdown {
+    /// Markers put around the formatted text.
+    Wrap(String, &'static str),
+    /// Syntax that replaces the formatted text.
+    Replace(String),
+}
+
+fn to_markdown(format: &format::Format) -> Option<Markdown> {
+    let markdown = match format {
+        format::Format::Bold(_) => Markdown::Wrap("**".to_string(), "**"),
+        format::Format::Italic(_) => Markdown::Wrap("*".to_string(), "*"),
+        format::Format::Monospace(_) => Markdown::Wrap("`".to_string(), "`"),
+        format::Format::CodeBlock(block) => Markdown::Wrap(format!("```{}\n", block.language), "```"),
+        format::Format::UserMention(mention) => Markdown::Replace(format!("<@{}>", mention.user_id)),
+        format::Format::Emoji(emoji) => Markdown::Replace(format!("<:{}:>", emoji.image_id)),
+        _ => return None,
+    };
+    Some(markdown)
+}
+
+/// Byte range of a format in `text`, moved to character boundaries.
+fn format_range(text: &str, format: &Format) -> (usize, usize) {
+    let floor = |mut pos: usize| {
+        pos = pos.min(text.len());
+        while !text.is_char_boundary(pos) {
+            pos -= 1;
+        }
+        pos
+    };
+    let start = floor(format.start as usize);
+    (start, floor(start + format.length as usize))
+}
+
+/// Whether the text at `offset` in a message's markdown is bold and italic because of formats kept
+/// over it, see [`from_harmony`].
+pub fn emphasis_at(formats: &[Format], offset: usize) -> (bool, bool) {
+    formats
+        .iter()
+        .filter(|f| (f.start as usize..(f.start + f.length) as usize).contains(&offset))
+        .fold((false, false), |(bold, italic), f| match f.format {
+            Some(format::Format::Bold(_)) => (true, italic),
+            Some(format::Format::Italic(_)) => (bold, true),
+            _ => (bold, italic),
+        })
+}
+
+/// Turns formatted text received from the homeserver into markdown.
+///
+/// Formats without a markdown syntax are kept, with their offsets moved to the markdown. So are
+/// formats that cross others, like bold over "abc de" and italic over "de fgh", since their markers
+/// can't be nested.
+pub fn from_harmony(formatted: FormattedText) -> FormattedText {
+    let FormattedText { text, format: formats } = formatted;
+
+    let mut wraps = Vec::new();
+    let mut replaces = Vec::new();
+    let mut kept = Vec::new();
+    for format in formats {
+        let (start, end) = format_range(&text, &format);
+        match format.format.as_ref().and_then(to_markdown) {
+            Some(Markdown::Wrap(open, close)) if start < end => wraps.push((start, end, open, close, format)),
+            Some(Markdown::Replace(syntax)) if start < end => replaces.push((start, end, syntax)),
+            Some(_) => {}
+            None => kept.push((start, end, format)),
+        }
+    }
+    // Outer formats are opened first
+    wraps.sort_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)));
+    let mut nested: Vec<(usize, usize, String, &str)> = Vec::with_capacity(wraps.len());
+    for (start, end, open, close, format) in wraps {
+        let crosses = nested
+            .iter()
+            .any(|(other_start, other_end, ..)| *other_start < start && start < *other_end && *other_end < end);
+        if crosses {
+            kept.push((start, end, format));
+        } else {
+            nested.push((start, end, open, close));
+        }
+    }
+    let wraps = nested;
+    replaces.sort_by_key(|r| r.0);
+
+    let mut markdown = String::with_capacity(text.len());
+    // Markdown offset of every byte offset in the text, for the formats that are kept
+    let mut offsets = vec![0; text.len() + 1];
+    let mut open = Vec::<usize>::new();
+    let mut next_wrap = 0;
+    let mut next_replace = 0;
+    let mut pos = 0;
+    loop {
+        while let Some(at) = open.iter().rposition(|index| wraps[*index].1 <= pos) {
+            markdown.push_str(wraps[open.remove(at)].3);
+        }
+        offsets[pos] = markdown.len();
+        while let Some((start, _, opening, _)) = wraps.get(next_wrap) {
+            if *start > pos {
+                break;
+            }
+            // Formats starting inside a replaced mention or emote can't be shown
+            if *start == pos {
+                markdown.push_str(opening);
+                open.push(next_wrap);
+            }
+            next_wrap += 1;
+        }
+        if pos == text.len() {
+            break;
+        }
+
+        while replaces.get(next_replace).map_or(false, |r| r.0 < pos) {
+            next_replace += 1;
+        }
+        match replaces.get(next_replace) {
+            Some((start, end, syntax)) if *start == pos => {
+                markdown.push_str(syntax);
+                for offset in &mut offsets[pos + 1..*end] {
+                    *offset = markdown.len();
+                }
+                pos = *end;
+                next_replace += 1;
+            }
+            _ => {
+                let c = text[pos..]
+                    .chars()
+                    .next()
+                    .expect("pos is a character boundary before the end");
+                markdown.push(c);
+                for offset in &mut offsets[pos + 1..pos + c.len_utf8()] {
+                    *offset = markdown.len();
+                }
+                pos += c.len_utf8();
+            }
+        }
+    }
+
+    let format = kept
+        .into_iter()
+        .map(|(start, end, format)| Format {
+            start: offsets[start] as u32,
+            length: (offsets[end] - offsets[start]) as u32,
+            ..format
+        })
+        .collect();
+    FormattedText { text: markdown, format }
+}
+
+struct Converter<'a> {
+    markdown: &'a str,
+    members: &'a Members,
+    emote_packs: &'a EmotePacks,
+    text: String,
+    formats: Vec<Format>,
+    /// Text offset of every byte offset in the markdown, for the formats that are kept.
+    offsets: Vec<usize>,
+}
+
+impl<'a> Converter<'a> {
+    /// Skips markdown syntax, so it doesn't end up in the text.
+    fn skip(&mut self, from: usize, to: usize) {
+        for offset in &mut self.offsets[from..to] {
+            *offset = self.text.len();
+        }
+    }
+
+    /// Copies markdown to the text without converting it.
+    fn copy(&mut self, from: usize, to: usize) {
+        for (pos, c) in self.markdown[from..to].char_indices() {
+            for offset in &mut self.offsets[from + pos..from + pos + c.len_utf8()] {
+                *offset = self.text.len();
+            }
+            self.text.push(c);
+        }
+    }
+
+    fn push_format(&mut self, start: usize, format: format::Format) {
+        self.formats.push(Format {
+            start: start as u32,
+            length: (self.text.len() - start) as u32,
+            format: Some(format),
+        });
+    }
+
+    /// Converts the markdown between `from` and `to`.
+    fn convert(&mut self, from: usize, to: usize) {
+        let markdown = self.markdown;
+        let mut pos = from;
+        while pos < to {
+            let rest = &markdown[pos..to];
+            let start = self.text.len();
+
+            if let Some(inner) = rest
+                .strip_prefix("```")
+                .and_then(|r| r.find("```").map(|end| &r[..end]))
+            {
+                let (language, code_at) = inner.find('\n').map_or(("", 0), |nl| (&inner[..nl], nl + 1));
+                let code_start = pos + 3 + code_at;
+                let code_end = pos + 3 + inner.len();
+                self.skip(pos, code_start);
+                self.copy(code_start, code_end);
+                self.skip(code_end, code_end + 3);
+                self.push_format(
+                    start,
+                    format::Format::CodeBlock(format::CodeBlock {
+                        language: language.to_string(),
+                    }),
+                );
+                pos = code_end + 3;
+            } else if let Some(len) = delimited(markdown, pos, to, "**") {
+                self.skip(pos, pos + 2);
+                self.convert(pos + 2, pos + 2 + len);
+                self.skip(pos + 2 + len, pos + 4 + len);
+                self.push_format(start, format::Format::Bold(format::Bold {}));
+                pos += len + 4;
+            } else if let Some(len) = delimited(markdown, pos, to, "*") {
+                self.skip(pos, pos + 1);
+                self.convert(pos + 1, pos + 1 + len);
+                self.skip(pos + 1 + len, pos + 2 + len);
+                self.push_format(start, format::Format::Italic(format::Italic {}));
+                pos += len + 2;
+            } else if let Some(len) = delimited(markdown, pos, to, "`") {
+                self.skip(pos, pos + 1);
+                self.copy(pos + 1, pos + 1 + len);
+                self.skip(pos + 1 + len, pos + 2 + len);
+                self.push_format(start, format::Format::Monospace(format::Monospace {}));
+                pos += len + 2;
+            } else if let Some((user_id, len)) = mention(rest) {
+                self.skip(pos, pos + len);
+                match self.members.get(&user_id) {
+                    Some(member) => {
+                        self.text.push('@');
+                        self.text.push_str(&member.username);
+                    }
+                    None => self.text.push_str(&rest[..len]),
+                }
+                self.push_format(start, format::Format::UserMention(format::UserMention { user_id }));
+                pos += len;
+            } else if let Some((image_id, len)) = emote(rest) {
+                self.skip(pos, pos + len);
+                let name = self.emote_packs.values().find_map(|pack| pack.emotes.get(image_id));
+                match name {
+                    Some(name) => {
+                        self.text.push(':');
+                        self.text.push_str(name);
+                        self.text.push(':');
+                    }
+                    None => self.text.push_str(&rest[..len]),
+                }
+                self.push_format(
+                    start,
+                    format::Format::Emoji(format::Emoji {
+                        image_id: image_id.to_string(),
+                        ..Default::default()
+                    }),
+                );
+                pos += len;
+            } else {
+                let len = rest.chars().next().expect("rest isn't empty").len_utf8();
+                self.copy(pos, pos + len);
+                pos += len;
+            }
+        }
+    }
+}
+
+/// Whether `c` is part of a word, for deciding if a delimiter is inside one.
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Whether a delimiter between `before` and `after` can open and close a format.
+///
+/// These are CommonMark's flanking rules, with its intraword rule for `_` applied to every delimiter:
+/// a delimiter has to be next to text on the side of the formatted text, and one inside a word
+/// doesn't format, so "2 * 3 * 4" and "file_*_name*" are kept as they are.
+fn flanking(before: Option<char>, after: Option<char>) -> (bool, bool) {
+    let space = |c: Option<char>| c.map_or(true, char::is_whitespace);
+    let punctuation = |c: Option<char>| c.map_or(false, |c| !c.is_whitespace() && !is_word(c));
+    let left = !space(after) && (!punctuation(after) || space(before) || punctuation(before));
+    let right = !space(before) && (!punctuation(before) || space(after) || punctuation(after));
+    (
+        left && (!right || punctuation(before)),
+        right && (!left || punctuation(after)),
+    )
+}
+
+/// Length of the text between `delimiter` at `pos` in `markdown` and the one closing it before `to`,
+/// if it isn't empty.
+///
+/// Delimiters are matched as whole runs of their character, so a `*` doesn't close on half of a `**`.
+fn delimited(markdown: &str, pos: usize, to: usize, delimiter: &str) -> Option<usize> {
+    let marker = delimiter.chars().next().expect("delimiters aren't empty");
+    let before = |at: usize| markdown[..at].chars().next_back();
+    let after = |at: usize| markdown[at + delimiter.len()..].chars().next();
+
+    if !markdown[pos..to].starts_with(delimiter) || after(pos) == Some(marker) || !flanking(before(pos), after(pos)).0 {
+        return None;
+    }
+    let open_end = pos + delimiter.len();
+    markdown[open_end..to]
+        .match_indices(delimiter)
+        .map(|(at, _)| open_end + at)
+        .find(|at| {
+            *at > open_end
+                && before(*at) != Some(marker)
+                && after(*at) != Some(marker)
+                && flanking(before(*at), after(*at)).1
+        })
+        .map(|at| at - open_end)
+}
+
+fn mention(text: &str) -> Option<(u64, usize)> {
+    let rest = text.strip_prefix("<@")?;
+    let end = rest.find('>')?;
+    let user_id = rest[..end].parse().ok()?;
+    Some((user_id, end + 3))
+}
+
+fn emote(text: &str) -> Option<(&str, usize)> {
+    let rest = text.strip_prefix("<:")?;
+    let end = rest.find(":>")?;
+    (end > 0).then(|| (&rest[..end], end + 4))
+}
+
+/// Replaces the text of `markdown` with `new_text`, keeping its formats over the text that didn't change.
+///
+/// Formats after the changed text are moved with it, and ones around it grow or shrink to cover
+/// what replaced it. Formats left empty are dropped.
+pub fn with_edited_text(markdown: &FormattedText, new_text: String) -> FormattedText {
+    let old_text = &markdown.text;
+    let prefix = old_text
+        .char_indices()
+        .zip(new_text.chars())
+        .find(|((_, a), b)| a != b)
+        .map_or(old_text.len().min(new_text.len()), |((at, _), _)| at);
+    let suffix = old_text[prefix..]
+        .chars()
+        .rev()
+        .zip(new_text[prefix..].chars().rev())
+        .take_while(|(a, b)| a == b)
+        .map(|(a, _)| a.len_utf8())
+        .sum::<usize>();
+    let (old_end, new_end) = (old_text.len() - suffix, new_text.len() - suffix);
+
+    let shifted = |pos: usize| pos - old_end + new_end;
+    // Text inserted right at the start or end of a format is left out of it, and formats are cut
+    // where they overlap text that was replaced
+    let moved_start = |pos: usize| match pos {
+        pos if pos >= old_end => shifted(pos),
+        pos if pos <= prefix => pos,
+        _ => new_end,
+    };
+    let moved_end = |pos: usize| match pos {
+        pos if pos <= prefix => pos,
+        pos if pos >= old_end => shifted(pos),
+        _ => prefix,
+    };
+    let format = markdown
+        .format
+        .iter()
+        .filter_map(|format| {
+            let (start, end) = format_range(old_text, format);
+            let (start, end) = (moved_start(start), moved_end(end));
+            (start < end).then(|| Format {
+                start: start as u32,
+                length: (end - start) as u32,
+                ..format.clone()
+            })
+        })
+        .collect();
+    FormattedText { text: new_text, format }
+}
+
+/// Turns markdown into formatted text to send to the homeserver.
+///
+/// Mentions and emotes are written with the names in `members` and `emote_packs`, so clients that
+/// ignore formats can still show them. The formats in `markdown` are kept, with their offsets moved
+/// to the text.
+pub fn to_harmony(markdown: &FormattedText, members: &Members, emote_packs: &EmotePacks) -> FormattedText {
+    let mut converter = Converter {
+        markdown: &markdown.text,
+        members,
+        emote_packs,
+        text: String::with_capacity(markdown.text.len()),
+        formats: Vec::new(),
+        offsets: vec![0; markdown.text.len() + 1],
+    };
+    converter.convert(0, markdown.text.len());
+    converter.skip(markdown.text.len(), markdown.text.len() + 1);
+
+    let Converter {
+        text,
+        mut formats,
+        offsets,
+        ..
+    } = converter;
+    for format in &markdown.format {
+        let (start, end) = format_range(&markdown.text, format);
+        formats.push(Format {
+            start: offsets[start] as u32,
+            length: (offsets[end] - offsets[start]) as u32,
+            ..format.clone()
+        });
+    }
+    formats.sort_by_key(|f| f.start);
+    FormattedText { text, format: formats }
+}
@@ -0,0 +1,365 @@
+//! Lightweight syntax highlighting for code blocks in messages.
+//!
+//! This doesn't parse anything; it only recognizes keywords, strings, numbers and comments,
+//! which is enough to make code shared in chat easier to read.
+
+/// What a piece of code is, which decides how it is colored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Highlight {
+    Plain,
+    Keyword,
+    String,
+    Number,
+    Comment,
+}
+
+struct Language {
+    names: &'static [&'static str],
+    keywords: &'static [&'static str],
+    line_comment: &'static [&'static str],
+    block_comment: Option<(&'static str, &'static str)>,
+    quotes: &'static [char],
+}
+
+const LANGUAGES: &[Language] = &[
+    Language {
+        names: &["rust", "rs"],
+        keywords: &[
+            "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern", "false",
+            "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return",
+            "self", "Self", "static", "struct", "super", "trait", "true", "type", "unsafe", "use", "where", "while",
+        ],
+        line_comment: &["//"],
+        block_comment: Some(("/*", "*/")),
+        quotes: &['"'],
+    },
+    Language {
+        names: &["python", "py"],
+        keywords: &[
+            "and", "as", "assert", "async", "await", "break", "class", "continue", "def", "del", "elif", "else",
+            "except", "False", "finally", "for", "from", "global", "if", "import", "in", "is", "lambda", "None",
+            "nonlocal", "not", "or", "pass", "raise", "return", "True", "try", "while", "with", "yield",
+        ],
+        line_comment: &["#"],
+        block_comment: None,
+        quotes: &['"', '\''],
+    },
+    Language {
+        names: &["javascript", "js", "jsx", "typescript", "ts", "tsx"],
+        keywords: &[
+            "async",
+            "await",
+            "break",
+            "case",
+            "catch",
+            "class",
+            "const",
+            "continue",
+            "default",
+            "delete",
+            "do",
+            "else",
+            "export",
+            "extends",
+            "false",
+            "finally",
+            "for",
+            "from",
+            "function",
+            "if",
+            "import",
+            "in",
+            "instanceof",
+            "interface",
+            "let",
+            "new",
+            "null",
+            "return",
+            "switch",
+            "this",
+            "throw",
+            "true",
+            "try",
+            "type",
+            "typeof",
+            "undefined",
+            "var",
+            "void",
+            "while",
+            "yield",
+        ],
+        line_comment: &["//"],
+        block_comment: Some(("/*", "*/")),
+        quotes: &['"', '\'', '`'],
+    },
+    Language {
+        names: &["c", "h", "cpp", "c++", "cc", "hpp", "cxx"],
+        keywords: &[
+            "auto",
+            "bool",
+            "break",
+            "case",
+            "char",
+            "class",
+            "const",
+            "continue",
+            "default",
+            "delete",
+            "do",
+            "double",
+            "else",
+            "enum",
+            "extern",
+            "false",
+            "float",
+            "for",
+            "goto",
+            "if",
+            "int",
+            "long",
+            "namespace",
+            "new",
+            "nullptr",
+            "private",
+            "protected",
+            "public",
+            "return",
+            "short",
+            "signed",
+            "sizeof",
+            "static",
+            "struct",
+            "switch",
+            "template",
+            "this",
+            "true",
+            "typedef",
+            "union",
+            "unsigned",
+            "using",
+            "virtual",
+            "void",
+            "volatile",
+            "while",
+        ],
+        line_comment: &["//"],
+        block_comment: Some(("/*", "*/")),
+        quotes: &['"', '\''],
+    },
+    Language {
+        names: &["go", "golang"],
+        keywords: &[
+            "break",
+            "case",
+            "chan",
+            "const",
+            "continue",
+            "default",
+            "defer",
+            "else",
+            "false",
+            "fallthrough",
+            "for",
+            "func",
+            "go",
+            "goto",
+            "if",
+            "import",
+            "interface",
+            "map",
+            "nil",
+            "package",
+            "range",
+            "return",
+            "select",
+            "struct",
+            "switch",
+            "true",
+            "type",
+            "var",
+        ],
+        line_comment: &["//"],
+        block_comment: Some(("/*", "*/")),
+        quotes: &['"', '`'],
+    },
+    Language {
+        names: &["java", "kotlin", "kt"],
+        keywords: &[
+            "abstract",
+            "break",
+            "case",
+            "catch",
+            "class",
+            "continue",
+            "default",
+            "do",
+            "else",
+            "enum",
+            "extends",
+            "false",
+            "final",
+            "finally",
+            "for",
+            "fun",
+            "if",
+            "implements",
+            "import",
+            "interface",
+            "new",
+            "null",
+            "object",
+            "package",
+            "private",
+            "protected",
+            "public",
+            "return",
+            "static",
+            "super",
+            "switch",
+            "this",
+            "throw",
+            "true",
+            "try",
+            "val",
+            "var",
+            "void",
+            "when",
+            "while",
+        ],
+        line_comment: &["//"],
+        block_comment: Some(("/*", "*/")),
+        quotes: &['"', '\''],
+    },
+    Language {
+        names: &["sh", "bash", "shell", "zsh", "console"],
+        keywords: &[
+            "case", "do", "done", "echo", "elif", "else", "esac", "exit", "export", "fi", "for", "function", "if",
+            "in", "local", "return", "then", "until", "while",
+        ],
+        line_comment: &["#"],
+        block_comment: None,
+        quotes: &['"', '\''],
+    },
+    Language {
+        names: &["json"],
+        keywords: &["true", "false", "null"],
+        line_comment: &[],
+        block_comment: None,
+        quotes: &['"'],
+    },
+    Language {
+        names: &["toml", "ini"],
+        keywords: &["true", "false"],
+        line_comment: &["#", ";"],
+        block_comment: None,
+        quotes: &['"', '\''],
+    },
+];
+
+fn find_language(name: &str) -> Option<&'static Language> {
+    let name = name.to_lowercase();
+    LANGUAGES
+        .iter()
+        .find(|language| language.names.contains(&name.as_str()))
+}
+
+/// Splits `code` into lines of highlighted pieces.
+///
+/// `language` is the info string of a code fence, like `rust` or `js`. Code in unknown languages
+/// is returned as plain text.
+pub fn highlight<'a>(code: &'a str, language: Option<&str>) -> Vec<Vec<(Highlight, &'a str)>> {
+    let language = language
+        .and_then(|info| info.split(|c: char| c == ',' || c.is_whitespace()).next())
+        .and_then(find_language);
+    let language = match language {
+        Some(language) => language,
+        None => {
+            return code.lines().map(|line| vec![(Highlight::Plain, line)]).collect();
+        }
+    };
+
+    let mut block_comment_end = None;
+    code.lines()
+        .map(|line| highlight_line(line, language, &mut block_comment_end))
+        .collect()
+}
+
+fn highlight_line<'a>(
+    line: &'a str,
+    language: &Language,
+    block_comment_end: &mut Option<&'static str>,
+) -> Vec<(Highlight, &'a str)> {
+    // Highlights with the byte range they cover in the line
+    let mut ranges: Vec<(Highlight, usize, usize)> = Vec::new();
+    let mut push = |highlight, start: usize, end: usize| match ranges.last_mut() {
+        // Keep neighbouring pieces of the same kind together, so there are fewer of them to show
+        Some((last, _, last_end)) if *last == highlight => *last_end = end,
+        _ => ranges.push((highlight, start, end)),
+    };
+
+    let mut pos = 0;
+    while pos < line.len() {
+        let rest = &line[pos..];
+        let (highlight, len) = if let Some(end) = *block_comment_end {
+            let len = match rest.find(end) {
+                Some(end_pos) => {
+                    *block_comment_end = None;
+                    end_pos + end.len()
+                }
+                None => rest.len(),
+            };
+            (Highlight::Comment, len)
+        } else if language.line_comment.iter().any(|start| rest.starts_with(start)) {
+            (Highlight::Comment, rest.len())
+        } else if let Some((start, end)) = language.block_comment.filter(|(start, _)| rest.starts_with(start)) {
+            *block_comment_end = Some(end);
+            (Highlight::Comment, start.len())
+        } else {
+            let first = rest.chars().next().expect("rest isn't empty");
+            if language.quotes.contains(&first) {
+                (Highlight::String, string_len(rest, first))
+            } else if first.is_ascii_digit() {
+                let len = rest
+                    .find(|c: char| !(c.is_alphanumeric() || c == '.' || c == '_'))
+                    .unwrap_or(rest.len());
+                (Highlight::Number, len)
+            } else if first.is_alphanumeric() || first == '_' {
+                let len = rest
+                    .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                    .unwrap_or(rest.len());
+                let highlight = if language.keywords.contains(&&rest[..len]) {
+                    Highlight::Keyword
+                } else {
+                    Highlight::Plain
+                };
+                (highlight, len)
+            } else {
+                (Highlight::Plain, first.len_utf8())
+            }
+        };
+        push(highlight, pos, pos + len);
+        pos += len;
+    }
+
+    ranges
+        .into_iter()
+        .map(|(highlight, start, end)| (highlight, &line[start..end]))
+        .collect()
+}
+
+/// Length of the string starting at the beginning of `text`, including its quotes.
+///
+/// Strings that aren't closed end at the end of the line.
+fn string_len(text: &str, quote: char) -> usize {
+    let mut escaped = false;
+    for (pos, c) in text.char_indices().skip(1) {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == quote {
+            return pos + c.len_utf8();
+        }
+    }
+    text.len()
+}
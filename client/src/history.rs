@@ -0,0 +1,258 @@
+//! Loaded messages of a channel, and the parts of its history that weren't loaded between them.
+
+use ahash::{AHashMap, AHashSet};
+use indexmap::map::{Iter, Keys};
+
+use super::{
+    message::{Message, MessageId},
+    IndexMap,
+};
+
+/// Messages of a channel in the order they were sent, followed by the ones that aren't
+/// acknowledged yet.
+///
+/// Loaded history can have gaps, for example after jumping to an older message. They are recorded
+/// by the acknowledged message they follow. History missing before the first or after the last
+/// loaded message is tracked by the channel instead, with `reached_top` and `reached_bottom`.
+#[derive(Debug, Default, Clone)]
+pub struct Messages {
+    messages: IndexMap<MessageId, Message>,
+    gaps: AHashSet<u64>,
+    /// Bumped whenever messages are added or removed, or change how they are shown.
+    revision: u64,
+    /// The revision each message was added or last changed at.
+    revisions: AHashMap<MessageId, u64>,
+}
+
+impl Messages {
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    pub fn get(&self, id: &MessageId) -> Option<&Message> {
+        self.messages.get(id)
+    }
+
+    /// Borrows a message mutably. Call [`Messages::changed`] if it changes how the message is shown.
+    pub fn get_mut(&mut self, id: &MessageId) -> Option<&mut Message> {
+        self.messages.get_mut(id)
+    }
+
+    pub fn contains_key(&self, id: &MessageId) -> bool {
+        self.messages.contains_key(id)
+    }
+
+    pub fn get_index(&self, index: usize) -> Option<(&MessageId, &Message)> {
+        self.messages.get_index(index)
+    }
+
+    pub fn get_index_of(&self, id: &MessageId) -> Option<usize> {
+        self.messages.get_index_of(id)
+    }
+
+    pub fn keys(&self) -> Keys<'_, MessageId, Message> {
+        self.messages.keys()
+    }
+
+    pub fn iter(&self) -> Iter<'_, MessageId, Message> {
+        self.messages.iter()
+    }
+
+    /// Changes whenever messages are added, removed or changed, so what is computed from them can be
+    /// kept until it does.
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// Changes whenever the message is added or changed.
+    pub fn revision_of(&self, id: &MessageId) -> u64 {
+        self.revisions.get(id).copied().unwrap_or_default()
+    }
+
+    /// Records that a message changed how it's shown, like its text or whether it has reactions, so
+    /// what was computed from it is computed again. Changes nothing if the message isn't loaded.
+    pub fn changed(&mut self, id: MessageId) {
+        if !self.messages.contains_key(&id) {
+            return;
+        }
+        self.revision += 1;
+        self.revisions.insert(id, self.revision);
+    }
+
+    /// Whether messages that aren't loaded may come right after this message.
+    pub fn has_gap_after(&self, message_id: u64) -> bool {
+        self.gaps.contains(&message_id)
+    }
+
+    /// Records that no messages are missing right after this message.
+    pub fn close_gap_after(&mut self, message_id: u64) {
+        self.gaps.remove(&message_id);
+    }
+
+    /// Records that messages that aren't loaded may come right after this message.
+    pub fn open_gap_after(&mut self, message_id: u64) {
+        self.gaps.insert(message_id);
+    }
+
+    /// Whether there is a gap anywhere after the message at `index`.
+    pub fn has_gap_from(&self, index: usize) -> bool {
+        self.gaps
+            .iter()
+            .filter_map(|id| self.messages.get_index_of(&MessageId::Ack(*id)))
+            .any(|gap_index| gap_index >= index)
+    }
+
+    /// Replaces a message, or adds it after every other message if it isn't loaded.
+    ///
+    /// This is for messages that were just sent; use [`Messages::merge`] for history.
+    pub fn insert(&mut self, id: MessageId, message: Message) -> Option<Message> {
+        let replaced = self.messages.insert(id, message);
+        self.changed(id);
+        replaced
+    }
+
+    /// Removes a message, keeping its place in history.
+    pub fn remove(&mut self, id: &MessageId) -> Option<Message> {
+        let index = self.messages.get_index_of(id)?;
+        self.revision += 1;
+        self.revisions.remove(id);
+        // A gap after the message is now after the one before it
+        if let MessageId::Ack(message_id) = id {
+            if self.gaps.remove(message_id) {
+                if let Some(prev) = self.messages.keys().take(index).rev().find_map(MessageId::id) {
+                    self.gaps.insert(prev);
+                }
+            }
+        }
+        self.messages.shift_remove_index(index).map(|(_, message)| message)
+    }
+
+    /// Replaces a message that wasn't acknowledged yet with its acknowledged version, in the same
+    /// place. Keeps the acknowledged message if it was already loaded.
+    ///
+    /// Returns `false` if the unacknowledged message isn't loaded.
+    pub fn acknowledge(&mut self, transaction_id: u64, message_id: u64, message: Message) -> bool {
+        let index = match self.messages.get_index_of(&MessageId::Unack(transaction_id)) {
+            Some(index) => index,
+            None => return false,
+        };
+        let ack_id = MessageId::Ack(message_id);
+        self.revisions.remove(&MessageId::Unack(transaction_id));
+        if self.messages.contains_key(&ack_id) {
+            self.revision += 1;
+            self.messages.shift_remove_index(index);
+        } else {
+            self.messages.insert(ack_id, message);
+            self.messages.swap_remove_index(index);
+            self.changed(ack_id);
+        }
+        true
+    }
+
+    pub fn clear(&mut self) {
+        self.messages.clear();
+        self.gaps.clear();
+        self.revision += 1;
+        self.revisions.clear();
+    }
+
+    /// Adds a run of messages that follow each other in history, oldest first.
+    ///
+    /// `follows` and `precedes` are the messages the run is known to come right after or right
+    /// before, like the message a page of history was fetched from. Otherwise the run is put next to
+    /// the loaded messages it overlaps with, or by the time it was sent. Gaps are recorded wherever
+    /// the run doesn't connect to the messages around it, and removed where it fills them.
+    ///
+    /// This takes time linear in the number of loaded messages, or in the size of the run if it
+    /// only adds messages after the others.
+    pub fn merge(&mut self, run: Vec<(u64, Message)>, follows: Option<u64>, precedes: Option<u64>) {
+        let (first, last, first_key) = match (run.first(), run.last()) {
+            (Some((first, message)), Some((last, _))) => (*first, *last, (message.timestamp, *first)),
+            _ => return,
+        };
+        let in_run = run.iter().map(|(id, _)| MessageId::Ack(*id)).collect::<AHashSet<_>>();
+        self.revision += 1;
+        for id in &in_run {
+            self.revisions.insert(*id, self.revision);
+        }
+
+        let old = std::mem::take(&mut self.messages);
+        let len = old.len();
+        let gaps = &self.gaps;
+        let position = |id: u64| old.get_index_of(&MessageId::Ack(id));
+        // Whether `a` was right before `b`, with nothing missing between them
+        let adjacent = |a: u64, b: u64| !gaps.contains(&a) && position(a).map_or(false, |i| position(b) == Some(i + 1));
+
+        let insert_at = precedes
+            .and_then(position)
+            .or_else(|| follows.and_then(position).map(|index| index + 1))
+            .or_else(|| old.keys().position(|id| in_run.contains(id)))
+            .unwrap_or_else(|| {
+                old.iter()
+                    .position(|(id, message)| match id {
+                        MessageId::Ack(id) => (message.timestamp, *id) > first_key,
+                        MessageId::Unack(_) => true,
+                    })
+                    .unwrap_or(len)
+            });
+        let prev = old
+            .keys()
+            .take(insert_at)
+            .rev()
+            .filter(|id| !in_run.contains(id))
+            .find_map(MessageId::id);
+        let next = old
+            .keys()
+            .skip(insert_at)
+            .filter(|id| !in_run.contains(id))
+            .find_map(MessageId::id);
+        let gap_before = prev.map_or(false, |prev| follows != Some(prev) && !adjacent(prev, first));
+        let gap_after = next.map_or(false, |next| precedes != Some(next) && !adjacent(last, next));
+        let overlaps = old.keys().any(|id| in_run.contains(id));
+
+        let run = run.into_iter().map(|(id, message)| (MessageId::Ack(id), message));
+        self.messages = if !overlaps && insert_at == len {
+            let mut messages = old;
+            messages.extend(run);
+            messages
+        } else {
+            let mut messages = IndexMap::with_capacity_and_hasher(len + in_run.len(), Default::default());
+            let mut run = Some(run);
+            for (index, (id, message)) in old.into_iter().enumerate() {
+                if index == insert_at {
+                    messages.extend(run.take().into_iter().flatten());
+                }
+                if !in_run.contains(&id) {
+                    messages.insert(id, message);
+                }
+            }
+            messages.extend(run.into_iter().flatten());
+            messages
+        };
+
+        self.gaps.retain(|id| !in_run.contains(&MessageId::Ack(*id)));
+        if let Some(prev) = prev {
+            if gap_before {
+                self.gaps.insert(prev);
+            } else {
+                self.gaps.remove(&prev);
+            }
+        }
+        if gap_after {
+            self.gaps.insert(last);
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a Messages {
+    type Item = (&'a MessageId, &'a Message);
+    type IntoIter = Iter<'a, MessageId, Message>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.messages.iter()
+    }
+}
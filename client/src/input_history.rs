@@ -0,0 +1,66 @@
+//! Messages the user sent in each channel, so they can be recalled in the composer.
+
+use ahash::AHashMap;
+
+/// How many sent messages are remembered per channel.
+pub const MAX_ENTRIES: usize = 100;
+
+/// Sent messages of every channel, keyed by guild ID and channel ID, oldest first.
+#[derive(Debug, Clone, Default)]
+pub struct InputHistory {
+    sent: AHashMap<(u64, u64), Vec<String>>,
+    /// Index of the recalled entry, and what the composer had before browsing started.
+    browsing: Option<(usize, String)>,
+}
+
+impl InputHistory {
+    /// Remembers a sent message. Sending the same message twice in a row only remembers it once.
+    pub fn push(&mut self, guild_id: u64, channel_id: u64, text: String) {
+        self.browsing = None;
+        let entries = self.sent.entry((guild_id, channel_id)).or_default();
+        if entries.last() != Some(&text) {
+            entries.push(text);
+        }
+        if entries.len() > MAX_ENTRIES {
+            entries.drain(..entries.len() - MAX_ENTRIES);
+        }
+    }
+
+    /// Recalls the message sent before the one currently recalled.
+    ///
+    /// `current` is what the composer has, which is given back by [`InputHistory::newer`] once
+    /// browsing gets past the newest message.
+    pub fn older(&mut self, guild_id: u64, channel_id: u64, current: &str) -> Option<&str> {
+        let entries = self.sent.get(&(guild_id, channel_id))?;
+        let index = match &self.browsing {
+            Some((index, _)) => index.checked_sub(1)?,
+            None => entries.len().checked_sub(1)?,
+        };
+        let stash = self
+            .browsing
+            .take()
+            .map_or_else(|| current.to_string(), |(_, stash)| stash);
+        self.browsing = Some((index, stash));
+        entries.get(index).map(String::as_str)
+    }
+
+    /// Recalls the message sent after the one currently recalled, or what the composer had before
+    /// browsing once past the newest message.
+    pub fn newer(&mut self, guild_id: u64, channel_id: u64) -> Option<String> {
+        let (index, stash) = self.browsing.take()?;
+        let entries = self.sent.get(&(guild_id, channel_id))?;
+        match entries.get(index + 1) {
+            Some(entry) => {
+                let entry = entry.clone();
+                self.browsing = Some((index + 1, stash));
+                Some(entry)
+            }
+            None => Some(stash),
+        }
+    }
+
+    /// Stops browsing, for example when the user switches to another channel.
+    pub fn stop_browsing(&mut self) {
+        self.browsing = None;
+    }
+}
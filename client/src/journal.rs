@@ -0,0 +1,570 @@
+//! Recording of everything that changes [`Client`] state, so that bugs can be reproduced
+//! by replaying a journal into a fresh client without a homeserver.
+//!
+//! Both what the homeserver sends (events, history pages, replies) and local changes (sending,
+//! reacting, editing and marking messages read) are recorded, along with the user the client is
+//! logged in as. State loaded from disk before or while recording, like the outbox and read
+//! markers of a previous run, is not part of the journal, so a replay starts without it.
+//!
+//! A journal is a JSONL file: every line is a [`JournalEntry`], with protobuf payloads
+//! encoded as base64.
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::Path,
+    time::Duration,
+};
+
+use harmony_rust_sdk::api::{
+    chat::{
+        self, get_channel_messages_request::Direction, Event, GetChannelMessagesResponse, Message as HarmonyMessage,
+        MessageWithId,
+    },
+    emote,
+    exports::prost::Message as _,
+    profile,
+};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    error::{ClientError, ClientResult},
+    message::{Message, MessageId},
+    Client, PostProcessEvent, SendMessageResult,
+};
+
+/// Recording is enabled if this environment variable is set.
+pub const RECORD_ENV_VAR: &str = "LOQUI_RECORD_EVENTS";
+
+pub fn recording_enabled() -> bool {
+    std::env::var_os(RECORD_ENV_VAR).is_some()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    Chat,
+    Profile,
+    Emote,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum JournalEntry {
+    /// An event passed to [`Client::process_event`].
+    Event {
+        /// Milliseconds since the UNIX epoch.
+        time: u64,
+        source: EventKind,
+        data: String,
+    },
+    /// A history page passed to [`Client::process_get_message_history_response`].
+    History {
+        time: u64,
+        guild_id: u64,
+        channel_id: u64,
+        message_id: u64,
+        direction: i32,
+        data: String,
+    },
+    /// A message passed to [`Client::process_reply_message`].
+    Reply {
+        time: u64,
+        guild_id: u64,
+        channel_id: u64,
+        message_id: u64,
+        data: String,
+    },
+    /// The user the client was logged in as when recording started.
+    Session { time: u64, user_id: Option<u64> },
+    /// A message passed to [`Client::send_msg_cmd`].
+    Send {
+        time: u64,
+        guild_id: u64,
+        channel_id: u64,
+        transaction_id: u64,
+        data: String,
+    },
+    /// A result passed to [`Client::process_send_result`].
+    SendResult {
+        time: u64,
+        guild_id: u64,
+        channel_id: u64,
+        transaction_id: u64,
+        /// Milliseconds.
+        retry_after: u64,
+        message_id: Option<u64>,
+        data: String,
+    },
+    /// A message sent again by [`Client::process_send_result`] after it failed.
+    ///
+    /// Replaying the failed `SendResult` before it already sends the message again, so this is only
+    /// kept to show when it was, and does nothing when applied.
+    Retry { time: u64, transaction_id: u64 },
+    /// A message removed with [`Client::discard_outbox_message`].
+    Discard { time: u64, transaction_id: u64 },
+    /// A reaction toggled with [`Client::toggle_reaction`].
+    Reaction {
+        time: u64,
+        guild_id: u64,
+        channel_id: u64,
+        message_id: u64,
+        image_id: String,
+        name: String,
+    },
+    /// Whether we reacted, recorded with [`Client::set_reacted`].
+    Reacted {
+        time: u64,
+        guild_id: u64,
+        channel_id: u64,
+        message_id: u64,
+        image_id: String,
+        reacted: bool,
+    },
+    /// An edit started with [`Client::edit_msg_cmd`].
+    Edit {
+        time: u64,
+        guild_id: u64,
+        channel_id: u64,
+        message_id: u64,
+        new_content: String,
+    },
+    /// An edit passed to [`Client::process_edit_result`].
+    EditDone {
+        time: u64,
+        guild_id: u64,
+        channel_id: u64,
+        message_id: u64,
+    },
+    /// A guild marked as read with [`Client::mark_guild_read`], or one of its channels with
+    /// [`Client::mark_channel_read`].
+    MarkRead {
+        time: u64,
+        guild_id: u64,
+        channel_id: Option<u64>,
+    },
+    /// Messages marked as unread with [`Client::mark_unread_from`].
+    MarkUnread {
+        time: u64,
+        guild_id: u64,
+        channel_id: u64,
+        message_id: u64,
+    },
+}
+
+fn now() -> u64 {
+    std::time::UNIX_EPOCH.elapsed().unwrap_or_default().as_millis() as u64
+}
+
+fn decode_err(err: impl std::fmt::Display) -> ClientError {
+    ClientError::Custom(format!("couldn't decode journal entry: {}", err))
+}
+
+fn encode_message(message: &Message) -> String {
+    base64::encode(HarmonyMessage::from(message.clone()).encode_to_vec())
+}
+
+fn decode_message(data: String) -> ClientResult<Message> {
+    let data = base64::decode(data).map_err(decode_err)?;
+    Ok(HarmonyMessage::decode(data.as_slice()).map_err(decode_err)?.into())
+}
+
+impl JournalEntry {
+    pub fn event(event: &Event) -> Self {
+        let (source, data) = match event {
+            Event::Chat(ev) => (
+                EventKind::Chat,
+                chat::StreamEvent {
+                    event: Some(ev.clone()),
+                }
+                .encode_to_vec(),
+            ),
+            Event::Profile(ev) => (
+                EventKind::Profile,
+                profile::StreamEvent {
+                    event: Some(ev.clone()),
+                }
+                .encode_to_vec(),
+            ),
+            Event::Emote(ev) => (
+                EventKind::Emote,
+                emote::StreamEvent {
+                    event: Some(ev.clone()),
+                }
+                .encode_to_vec(),
+            ),
+        };
+
+        JournalEntry::Event {
+            time: now(),
+            source,
+            data: base64::encode(data),
+        }
+    }
+
+    pub fn history(
+        guild_id: u64,
+        channel_id: u64,
+        message_id: u64,
+        messages: &[(u64, HarmonyMessage)],
+        reached_top: bool,
+        direction: Direction,
+    ) -> Self {
+        let response = GetChannelMessagesResponse {
+            reached_top,
+            messages: messages
+                .iter()
+                .map(|(message_id, message)| MessageWithId {
+                    message_id: *message_id,
+                    message: Some(message.clone()),
+                })
+                .collect(),
+        };
+
+        JournalEntry::History {
+            time: now(),
+            guild_id,
+            channel_id,
+            message_id,
+            direction: direction.into(),
+            data: base64::encode(response.encode_to_vec()),
+        }
+    }
+
+    pub fn reply(guild_id: u64, channel_id: u64, message_id: u64, message: &HarmonyMessage) -> Self {
+        JournalEntry::Reply {
+            time: now(),
+            guild_id,
+            channel_id,
+            message_id,
+            data: base64::encode(message.encode_to_vec()),
+        }
+    }
+
+    pub fn session(user_id: Option<u64>) -> Self {
+        JournalEntry::Session { time: now(), user_id }
+    }
+
+    pub fn send(guild_id: u64, channel_id: u64, transaction_id: u64, message: &Message) -> Self {
+        JournalEntry::Send {
+            time: now(),
+            guild_id,
+            channel_id,
+            transaction_id,
+            data: encode_message(message),
+        }
+    }
+
+    pub fn send_result(
+        &(guild_id, channel_id, transaction_id, ref message, retry_after, message_id): &SendMessageResult,
+    ) -> Self {
+        JournalEntry::SendResult {
+            time: now(),
+            guild_id,
+            channel_id,
+            transaction_id,
+            retry_after: retry_after.as_millis() as u64,
+            message_id,
+            data: encode_message(message),
+        }
+    }
+
+    pub fn retry(transaction_id: u64) -> Self {
+        JournalEntry::Retry {
+            time: now(),
+            transaction_id,
+        }
+    }
+
+    pub fn discard(transaction_id: u64) -> Self {
+        JournalEntry::Discard {
+            time: now(),
+            transaction_id,
+        }
+    }
+
+    pub fn reaction(guild_id: u64, channel_id: u64, message_id: u64, image_id: &str, name: &str) -> Self {
+        JournalEntry::Reaction {
+            time: now(),
+            guild_id,
+            channel_id,
+            message_id,
+            image_id: image_id.to_string(),
+            name: name.to_string(),
+        }
+    }
+
+    pub fn reacted(guild_id: u64, channel_id: u64, message_id: u64, image_id: &str, reacted: bool) -> Self {
+        JournalEntry::Reacted {
+            time: now(),
+            guild_id,
+            channel_id,
+            message_id,
+            image_id: image_id.to_string(),
+            reacted,
+        }
+    }
+
+    pub fn edit(guild_id: u64, channel_id: u64, message_id: u64, new_content: &str) -> Self {
+        JournalEntry::Edit {
+            time: now(),
+            guild_id,
+            channel_id,
+            message_id,
+            new_content: new_content.to_string(),
+        }
+    }
+
+    pub fn edit_done(guild_id: u64, channel_id: u64, message_id: u64) -> Self {
+        JournalEntry::EditDone {
+            time: now(),
+            guild_id,
+            channel_id,
+            message_id,
+        }
+    }
+
+    pub fn mark_read(guild_id: u64, channel_id: Option<u64>) -> Self {
+        JournalEntry::MarkRead {
+            time: now(),
+            guild_id,
+            channel_id,
+        }
+    }
+
+    pub fn mark_unread(guild_id: u64, channel_id: u64, message_id: u64) -> Self {
+        JournalEntry::MarkUnread {
+            time: now(),
+            guild_id,
+            channel_id,
+            message_id,
+        }
+    }
+
+    /// Applies this entry to a client, the same way it was applied when it was recorded.
+    ///
+    /// Nothing is sent to the homeserver or saved to disk; local changes only change the client's state.
+    pub fn apply(self, client: &mut Client) -> ClientResult<Vec<PostProcessEvent>> {
+        let replaying = std::mem::replace(&mut client.replaying, true);
+        let result = self.apply_replaying(client);
+        client.replaying = replaying;
+        result
+    }
+
+    fn apply_replaying(self, client: &mut Client) -> ClientResult<Vec<PostProcessEvent>> {
+        let post = match self {
+            JournalEntry::Event { source, data, .. } => {
+                let data = base64::decode(data).map_err(decode_err)?;
+                let event = match source {
+                    EventKind::Chat => chat::StreamEvent::decode(data.as_slice())
+                        .map_err(decode_err)?
+                        .event
+                        .map(Event::Chat),
+                    EventKind::Profile => profile::StreamEvent::decode(data.as_slice())
+                        .map_err(decode_err)?
+                        .event
+                        .map(Event::Profile),
+                    EventKind::Emote => emote::StreamEvent::decode(data.as_slice())
+                        .map_err(decode_err)?
+                        .event
+                        .map(Event::Emote),
+                };
+                event.map_or_else(Vec::new, |event| client.process_event(event))
+            }
+            JournalEntry::History {
+                guild_id,
+                channel_id,
+                message_id,
+                direction,
+                data,
+                ..
+            } => {
+                let data = base64::decode(data).map_err(decode_err)?;
+                let response = GetChannelMessagesResponse::decode(data.as_slice()).map_err(decode_err)?;
+                let direction = Direction::from_i32(direction).unwrap_or(Direction::BeforeUnspecified);
+                client.process_get_message_history_response(
+                    guild_id,
+                    channel_id,
+                    message_id,
+                    response
+                        .messages
+                        .into_iter()
+                        .flat_map(|m| Some((m.message_id, m.message?)))
+                        .collect(),
+                    response.reached_top,
+                    direction,
+                )
+            }
+            JournalEntry::Reply {
+                guild_id,
+                channel_id,
+                message_id,
+                data,
+                ..
+            } => {
+                let data = base64::decode(data).map_err(decode_err)?;
+                let message = HarmonyMessage::decode(data.as_slice()).map_err(decode_err)?;
+                client.process_reply_message(guild_id, channel_id, message_id, message)
+            }
+            JournalEntry::Session { user_id, .. } => {
+                client.user_id = user_id;
+                Vec::new()
+            }
+            // The returned futures are what talks to the homeserver, dropping them keeps only the local changes
+            JournalEntry::Send {
+                guild_id,
+                channel_id,
+                transaction_id,
+                data,
+                ..
+            } => {
+                let message = decode_message(data)?;
+                drop(client.send_msg_cmd(
+                    guild_id,
+                    channel_id,
+                    Duration::from_secs(0),
+                    MessageId::Unack(transaction_id),
+                    message,
+                ));
+                Vec::new()
+            }
+            JournalEntry::SendResult {
+                guild_id,
+                channel_id,
+                transaction_id,
+                retry_after,
+                message_id,
+                data,
+                ..
+            } => {
+                let message = decode_message(data)?;
+                drop(client.process_send_result((
+                    guild_id,
+                    channel_id,
+                    transaction_id,
+                    message,
+                    Duration::from_millis(retry_after),
+                    message_id,
+                )));
+                Vec::new()
+            }
+            JournalEntry::Retry { .. } => Vec::new(),
+            JournalEntry::Discard { transaction_id, .. } => {
+                client.discard_outbox_message(transaction_id);
+                Vec::new()
+            }
+            JournalEntry::Reaction {
+                guild_id,
+                channel_id,
+                message_id,
+                image_id,
+                name,
+                ..
+            } => {
+                client.toggle_reaction(guild_id, channel_id, message_id, &image_id, &name);
+                Vec::new()
+            }
+            JournalEntry::Reacted {
+                guild_id,
+                channel_id,
+                message_id,
+                image_id,
+                reacted,
+                ..
+            } => {
+                client.set_reacted(guild_id, channel_id, message_id, &image_id, reacted);
+                Vec::new()
+            }
+            JournalEntry::Edit {
+                guild_id,
+                channel_id,
+                message_id,
+                new_content,
+                ..
+            } => {
+                drop(client.edit_msg_cmd(guild_id, channel_id, message_id, new_content));
+                Vec::new()
+            }
+            JournalEntry::EditDone {
+                guild_id,
+                channel_id,
+                message_id,
+                ..
+            } => {
+                client.process_edit_result(guild_id, channel_id, message_id);
+                Vec::new()
+            }
+            JournalEntry::MarkRead {
+                guild_id, channel_id, ..
+            } => {
+                match channel_id {
+                    Some(channel_id) => client.mark_channel_read(guild_id, channel_id),
+                    None => client.mark_guild_read(guild_id),
+                }
+                Vec::new()
+            }
+            JournalEntry::MarkUnread {
+                guild_id,
+                channel_id,
+                message_id,
+                ..
+            } => {
+                client.mark_unread_from(guild_id, channel_id, message_id);
+                Vec::new()
+            }
+        };
+
+        Ok(post)
+    }
+}
+
+/// Appends journal entries to a file, flushing after every entry so nothing is lost on a crash.
+#[derive(Debug)]
+pub struct Recorder {
+    writer: BufWriter<File>,
+}
+
+impl Recorder {
+    pub fn create(path: &Path) -> ClientResult<Self> {
+        let file = std::fs::OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            writer: BufWriter::new(file),
+        })
+    }
+
+    pub fn record(&mut self, entry: &JournalEntry) -> ClientResult<()> {
+        serde_json::to_writer(&mut self.writer, entry)
+            .map_err(|err| ClientError::Custom(format!("couldn't write journal entry: {}", err)))?;
+        self.writer.write_all(b"\n")?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Reads every entry of a journal, in the order they were recorded.
+pub fn read_journal(path: &Path) -> ClientResult<Vec<JournalEntry>> {
+    BufReader::new(File::open(path)?)
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| line.as_ref().map_or(true, |line| !line.trim().is_empty()))
+        .map(|(index, line)| {
+            serde_json::from_str(&line?).map_err(|err| {
+                ClientError::Custom(format!(
+                    "invalid journal entry on line {} of {}: {}",
+                    index + 1,
+                    path.display(),
+                    err
+                ))
+            })
+        })
+        .collect()
+}
+
+/// Feeds a whole journal into a client, returning the post process events it produced.
+pub fn replay(client: &mut Client, entries: Vec<JournalEntry>) -> ClientResult<Vec<PostProcessEvent>> {
+    let mut post = Vec::new();
+    for entry in entries {
+        post.extend(entry.apply(client)?);
+    }
+    Ok(post)
+}
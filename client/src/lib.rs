@@ -1,16 +1,32 @@
 #![allow(clippy::field_reassign_with_default)]
 
+pub mod bookmarks;
+pub mod cache;
 pub mod channel;
+pub mod commands;
 pub mod content;
+pub mod diff;
+pub mod drafts;
 pub mod emotes;
 pub mod error;
+pub mod formatting;
 pub mod guild;
+pub mod highlight;
+pub mod history;
+pub mod input_history;
+pub mod journal;
 pub mod member;
 pub mod message;
+pub mod outbox;
+pub mod read_markers;
 pub mod role;
+pub mod search;
+pub mod viewport;
 
+use bookmarks::{Bookmark, Bookmarks};
 use bool_ext::BoolExt;
 use channel::Channel;
+use drafts::Drafts;
 use emotes::EmotePacks;
 use guild::Guild;
 pub use harmony_rust_sdk::{
@@ -24,7 +40,8 @@ use harmony_rust_sdk::{
             color,
             get_channel_messages_request::Direction,
             stream_event::{Event as ChatEvent, *},
-            ChannelKind, DeleteMessageRequest, Event, FormattedText, Message as HarmonyMessage, Permission, Role,
+            AddReactionRequest, ChannelKind, DeleteMessageRequest, Event, FormattedText, GetMessageRequest,
+            Message as HarmonyMessage, Permission, RemoveReactionRequest, Role,
         },
         emote::{stream_event::Event as EmoteEvent, *},
         exports::hrpc::exports::futures_util::FutureExt,
@@ -33,6 +50,7 @@ use harmony_rust_sdk::{
     },
     client::api::{
         chat::{
+            channel::GetChannelMessages,
             message::{SendMessage, UpdateMessageTextRequest},
             EventSource,
         },
@@ -40,16 +58,23 @@ use harmony_rust_sdk::{
         rest::FileId,
     },
 };
+use journal::{JournalEntry, Recorder};
 
+use chrono::{Local, NaiveDateTime};
 use content::ContentStore;
 use error::{ClientError, ClientResult};
 use member::{Member, Members};
 use message::{Attachment, Content, Embed, MessageId};
+use outbox::{Outbox, SendState};
+use read_markers::ReadMarker;
+use search::SearchIndex;
 use serde::{Deserialize, Serialize};
 use smol_str::SmolStr;
 use std::{
+    collections::VecDeque,
     fmt::{self, Debug, Display, Formatter},
     future::Future,
+    path::PathBuf,
     str::FromStr,
     sync::Arc,
     time::{Duration, Instant},
@@ -64,7 +89,7 @@ use self::{
 };
 
 pub type IndexMap<K, V> = indexmap::IndexMap<K, V, ahash::RandomState>;
-pub use ahash::AHashMap;
+pub use ahash::{AHashMap, AHashSet};
 pub use bool_ext;
 pub use linemd;
 pub use smol_str;
@@ -132,6 +157,62 @@ pub enum PostProcessEvent {
     FetchEmotes(u64),
 }
 
+/// State of the events socket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionStatus {
+    Connecting,
+    Connected,
+    /// The socket was lost, and we will try to connect again after `retry_after`.
+    Reconnecting {
+        attempt: u32,
+        retry_after: Duration,
+    },
+}
+
+impl ConnectionStatus {
+    /// How long to wait before the given reconnection attempt.
+    pub fn backoff(attempt: u32) -> Duration {
+        const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+        Duration::from_secs(1)
+            .checked_mul(2_u32.saturating_pow(attempt))
+            .map_or(MAX_BACKOFF, |backoff| backoff.min(MAX_BACKOFF))
+    }
+}
+
+impl Display for ConnectionStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ConnectionStatus::Connecting => write!(f, "Connecting..."),
+            ConnectionStatus::Connected => write!(f, "Connected"),
+            ConnectionStatus::Reconnecting { attempt, retry_after } => write!(
+                f,
+                "Connection lost, reconnecting in {}s (attempt {})",
+                retry_after.as_secs(),
+                attempt + 1
+            ),
+        }
+    }
+}
+
+/// How many unknown events are kept around for the debug view.
+pub const MAX_UNKNOWN_EVENTS: usize = 100;
+
+/// An event this client doesn't know how to handle, most likely from a newer homeserver.
+#[derive(Debug, Clone)]
+pub struct UnknownEvent {
+    pub received_at: NaiveDateTime,
+    pub description: String,
+}
+
+/// The guild ID, channel ID, transaction ID, message, next retry delay and (if it was sent) message ID
+/// of a message sent with [`Client::send_msg_cmd`].
+pub type SendMessageResult = (u64, u64, u64, Message, Duration, Option<u64>);
+
+/// Whether we had reacted before a reaction was toggled with [`Client::toggle_reaction_cmd`] (`None` if it
+/// wasn't known), and whether we reacted after, unless the homeserver refused the change.
+pub type ReactionToggleResult = (Option<bool>, ClientResult<bool>);
+
 #[derive(Clone)]
 pub struct Client {
     inner: InnerClient,
@@ -140,7 +221,22 @@ pub struct Client {
     pub user_id: Option<u64>,
     pub link_datas: AHashMap<Uri, FetchLinkData>,
     pub emote_packs: EmotePacks,
+    pub search_index: SearchIndex,
+    /// Unknown events, oldest first.
+    pub unknown_events: VecDeque<UnknownEvent>,
+    pub connection_status: ConnectionStatus,
+    /// Messages we sent that weren't acknowledged yet.
+    pub outbox: Outbox,
+    /// Messages the user saved.
+    pub bookmarks: Bookmarks,
+    /// What the user was writing in each channel.
+    pub drafts: Drafts,
+    /// Saved read markers of channels that weren't added to the client yet.
+    pending_read_markers: AHashMap<(u64, u64), ReadMarker>,
     content_store: Arc<ContentStore>,
+    recorder: Option<Arc<std::sync::Mutex<Recorder>>>,
+    /// Whether a journal is being replayed into this client, in which case nothing is saved to disk.
+    replaying: bool,
 }
 
 impl Debug for Client {
@@ -169,6 +265,15 @@ impl Client {
             inner: InnerClient::new(homeserver_url, session).await?,
             link_datas: AHashMap::new(),
             emote_packs: EmotePacks::default(),
+            search_index: SearchIndex::default(),
+            unknown_events: VecDeque::new(),
+            connection_status: ConnectionStatus::Connecting,
+            outbox: Outbox::default(),
+            bookmarks: Bookmarks::default(),
+            drafts: Drafts::default(),
+            pending_read_markers: AHashMap::new(),
+            recorder: None,
+            replaying: false,
         })
     }
 
@@ -247,6 +352,155 @@ impl Client {
         self.members.get_mut(&user_id)
     }
 
+    /// Starts recording everything that changes this client's state to a new journal.
+    ///
+    /// Returns the path of the journal.
+    pub fn start_recording(&mut self) -> ClientResult<PathBuf> {
+        let path = self.content_store.new_journal_path();
+        self.recorder = Some(Arc::new(std::sync::Mutex::new(Recorder::create(&path)?)));
+        let user_id = self.user_id;
+        self.record(|| JournalEntry::session(user_id));
+        Ok(path)
+    }
+
+    fn record(&self, make_entry: impl FnOnce() -> JournalEntry) {
+        if let Some(recorder) = self.recorder.as_ref() {
+            let entry = make_entry();
+            let result = match recorder.lock() {
+                Ok(mut recorder) => recorder.record(&entry),
+                Err(_) => Err(ClientError::Custom("journal recorder was poisoned".to_string())),
+            };
+            if let Err(err) = result {
+                tracing::error!("couldn't record journal entry: {}", err);
+            }
+        }
+    }
+
+    /// The user whose data is saved to disk. Nothing is saved while replaying a journal.
+    fn saving_user_id(&self) -> Option<u64> {
+        self.user_id.filter(|_| !self.replaying)
+    }
+
+    fn message_cache_path(&self, guild_id: u64, channel_id: u64) -> PathBuf {
+        self.content_store
+            .message_cache_path(self.inner.homeserver_url().to_string().as_str(), guild_id, channel_id)
+    }
+
+    /// Writes the newest acknowledged messages of a channel to the message cache.
+    ///
+    /// Returns `None` if the channel doesn't exist or no messages were loaded for it.
+    pub fn save_channel_cache(&self, guild_id: u64, channel_id: u64) -> Option<impl Future<Output = ClientResult<()>>> {
+        let channel = self.guilds.get(&guild_id)?.channels.get(&channel_id)?;
+        (channel.reached_bottom && channel.messages.keys().any(MessageId::is_ack)).then(|| {
+            let data = cache::encode_messages(&channel.messages);
+            let path = self.message_cache_path(guild_id, channel_id);
+            async move { tokio::fs::write(path, data).await.map_err(Into::into) }
+        })
+    }
+
+    /// Writes the message caches of every channel we have loaded messages for.
+    pub fn save_all_channel_caches(&self) -> impl Future<Output = ()> {
+        let writes = self
+            .guilds
+            .iter()
+            .flat_map(|(guild_id, guild)| {
+                guild
+                    .channels
+                    .keys()
+                    .flat_map(move |channel_id| self.save_channel_cache(*guild_id, *channel_id))
+            })
+            .collect::<Vec<_>>();
+
+        async move {
+            for write in writes {
+                if let Err(err) = write.await {
+                    tracing::error!("couldn't write message cache: {}", err);
+                }
+            }
+        }
+    }
+
+    /// Reads the message cache of a channel, if there is one.
+    ///
+    /// The messages are returned newest first, see [`Client::process_cached_messages`].
+    pub fn load_channel_cache(
+        &self,
+        guild_id: u64,
+        channel_id: u64,
+    ) -> impl Future<Output = ClientResult<Option<Vec<(u64, Message)>>>> {
+        let path = self.message_cache_path(guild_id, channel_id);
+
+        async move {
+            match tokio::fs::read(&path).await {
+                Ok(raw) => cache::decode_messages(&raw).map(Some),
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                Err(err) => Err(err.into()),
+            }
+        }
+    }
+
+    /// The newest acknowledged message of every channel that has loaded messages.
+    pub fn newest_acked_messages(&self) -> Vec<(u64, u64, u64)> {
+        self.guilds
+            .iter()
+            .flat_map(|(guild_id, guild)| {
+                guild.channels.iter().flat_map(move |(channel_id, channel)| {
+                    // Channels showing older messages get newer ones as the user scrolls down
+                    if !channel.reached_bottom {
+                        return None;
+                    }
+                    let message_id = channel.messages.keys().rev().find_map(MessageId::id)?;
+                    Some((*guild_id, *channel_id, message_id))
+                })
+            })
+            .collect()
+    }
+
+    /// Fetches every message sent after the given message, oldest first, and whether they go up to
+    /// the newest message.
+    pub fn get_messages_after_cmd(
+        &self,
+        guild_id: u64,
+        channel_id: u64,
+        message_id: u64,
+    ) -> impl Future<Output = ClientResult<(Vec<(u64, HarmonyMessage)>, bool)>> {
+        // Upper limit so we don't fetch the whole channel if we were gone for a long time
+        const MAX_PAGES: usize = 20;
+
+        let inner = self.inner_arc();
+
+        async move {
+            let mut messages = Vec::new();
+            let mut seen = AHashSet::new();
+            let mut after = message_id;
+
+            for _ in 0..MAX_PAGES {
+                let page = inner
+                    .call(
+                        GetChannelMessages::new(guild_id, channel_id)
+                            .with_message_id(after)
+                            .with_direction(Direction::After),
+                    )
+                    .await?
+                    .messages
+                    .into_iter()
+                    .flat_map(|m| Some((m.message_id, m.message?)))
+                    .filter(|(id, _)| seen.insert(*id))
+                    .collect::<Vec<_>>();
+
+                if page.is_empty() {
+                    return Ok((messages, true));
+                }
+
+                messages.extend(page);
+                messages.sort_unstable_by_key(|(id, message): &(u64, HarmonyMessage)| (message.created_at, *id));
+                after = messages.last().map_or(after, |(id, _)| *id);
+            }
+
+            Ok((messages, false))
+        }
+    }
+
     pub fn get_emote_name(&self, image_id: &str) -> Option<&str> {
         self.emote_packs
             .values()
@@ -262,6 +516,9 @@ impl Client {
             .flatten()
     }
 
+    /// Sends a message, adding it to the outbox until it is acknowledged.
+    ///
+    /// `message_id` must be an unacknowledged ID; it is reused when the message is sent again.
     pub fn send_msg_cmd(
         &mut self,
         guild_id: u64,
@@ -269,43 +526,416 @@ impl Client {
         retry_after: Duration,
         message_id: MessageId,
         message: Message,
-    ) -> Option<impl Future<Output = (u64, u64, u64, Message, Duration, Option<u64>)>> {
+    ) -> impl Future<Output = SendMessageResult> {
+        let echo_id = message_id.transaction_id().unwrap();
+        self.record(|| JournalEntry::send(guild_id, channel_id, echo_id, &message));
+        self.send_outbox_message(guild_id, channel_id, retry_after, echo_id, message)
+    }
+
+    /// Sends a message without recording it, for [`Client::send_msg_cmd`] and retries.
+    fn send_outbox_message(
+        &mut self,
+        guild_id: u64,
+        channel_id: u64,
+        retry_after: Duration,
+        echo_id: u64,
+        message: Message,
+    ) -> impl Future<Output = SendMessageResult> {
+        let message_id = MessageId::Unack(echo_id);
+        let is_new = self.outbox.get(echo_id).is_none();
+        self.outbox.push(echo_id, guild_id, channel_id, message.clone());
+        if is_new {
+            self.save_outbox();
+        }
         if let Some(channel) = self.get_channel(guild_id, channel_id) {
-            if retry_after.as_secs() == 0 {
+            // Don't make a channel look loaded if its messages weren't fetched yet, or add the message
+            // after older ones; it will get the message when they are [ref:outbox_after_history]
+            let is_loaded = (channel.reached_top || !channel.messages.is_empty()) && channel.reached_bottom;
+            if is_loaded && !channel.messages.contains_key(&message_id) {
                 channel.messages.insert(message_id, message.clone());
             }
+        }
 
-            let inner = self.inner().clone();
+        let inner = self.inner().clone();
+        let content = message.content.to_harmony(&self.members, &self.emote_packs);
 
-            Some(async move {
-                tokio::time::sleep(retry_after).await;
+        async move {
+            tokio::time::sleep(retry_after).await;
 
-                let echo_id = message_id.transaction_id().unwrap();
-                let msg = SendMessage::new(guild_id, channel_id)
-                    .with_content(harmony_rust_sdk::api::chat::Content {
-                        content: Some(message.content.clone().into()),
-                    })
-                    .with_in_reply_to(message.reply_to)
-                    .with_echo_id(echo_id)
-                    .with_overrides(message.overrides.clone().map(Into::into));
+            let msg = SendMessage::new(guild_id, channel_id)
+                .with_content(harmony_rust_sdk::api::chat::Content { content: Some(content) })
+                .with_in_reply_to(message.reply_to)
+                .with_echo_id(echo_id)
+                .with_overrides(message.overrides.clone().map(Into::into));
 
-                let send_result = inner.call(msg).await;
-                (
+            let send_result = inner.call(msg).await;
+            (
+                guild_id,
+                channel_id,
+                echo_id,
+                message,
+                send_result
+                    .is_err()
+                    .map_or(retry_after, || retry_after + Duration::from_secs(1)),
+                send_result
+                    .map(|resp| resp.message_id)
+                    .map_err(|err| tracing::error!("error occured when sending message: {}", err))
+                    .ok(),
+            )
+        }
+    }
+
+    /// Handles the result of [`Client::send_msg_cmd`].
+    ///
+    /// If sending failed and the message wasn't tried too many times yet, returns a future
+    /// that sends it again.
+    pub fn process_send_result(
+        &mut self,
+        result: SendMessageResult,
+    ) -> Option<impl Future<Output = SendMessageResult>> {
+        self.record(|| JournalEntry::send_result(&result));
+        let (guild_id, channel_id, echo_id, message, retry_after, result) = result;
+        match result {
+            Some(message_id) => {
+                if self.outbox.remove(echo_id).is_some() {
+                    self.save_outbox();
+                }
+                // The echo event may not arrive if the socket is down, so acknowledge it ourselves
+                if let Some(channel) = self.get_channel(guild_id, channel_id) {
+                    channel.messages.acknowledge(echo_id, message_id, message);
+                }
+                None
+            }
+            None => match self.outbox.send_failed(echo_id)? {
+                SendState::Sending { .. } => {
+                    self.record(|| JournalEntry::retry(echo_id));
+                    Some(self.send_outbox_message(guild_id, channel_id, retry_after, echo_id, message))
+                }
+                SendState::Failed => {
+                    self.save_outbox();
+                    None
+                }
+            },
+        }
+    }
+
+    /// Sends a failed message again.
+    pub fn retry_outbox_message(&mut self, transaction_id: u64) -> Option<impl Future<Output = SendMessageResult>> {
+        let entry = self
+            .outbox
+            .get(transaction_id)
+            .filter(|entry| entry.state == SendState::Failed)?;
+        let (guild_id, channel_id, message) = (entry.guild_id, entry.channel_id, entry.message.clone());
+        Some(self.send_msg_cmd(
+            guild_id,
+            channel_id,
+            Duration::from_secs(0),
+            MessageId::Unack(transaction_id),
+            message,
+        ))
+    }
+
+    /// Removes a message from the outbox without sending it.
+    pub fn discard_outbox_message(&mut self, transaction_id: u64) {
+        self.record(|| JournalEntry::discard(transaction_id));
+        if let Some(entry) = self.outbox.remove(transaction_id) {
+            self.save_outbox();
+            if let Some(channel) = self.get_channel(entry.guild_id, entry.channel_id) {
+                channel.messages.remove(&MessageId::Unack(transaction_id));
+            }
+        }
+    }
+
+    /// Sends the messages in the outbox again, one after another so they stay in order.
+    ///
+    /// If `only_failed` is set, messages that are currently being sent are left alone.
+    pub fn resend_outbox(&mut self, only_failed: bool) -> impl Future<Output = Vec<SendMessageResult>> {
+        let to_send = self
+            .outbox
+            .iter()
+            .filter(|(_, entry)| !only_failed || entry.state == SendState::Failed)
+            .map(|(transaction_id, entry)| (transaction_id, entry.guild_id, entry.channel_id, entry.message.clone()))
+            .collect::<Vec<_>>();
+        let sends = to_send
+            .into_iter()
+            .map(|(transaction_id, guild_id, channel_id, message)| {
+                self.send_msg_cmd(
                     guild_id,
                     channel_id,
-                    echo_id,
+                    Duration::from_secs(0),
+                    MessageId::Unack(transaction_id),
                     message,
-                    send_result
-                        .is_err()
-                        .map_or(retry_after, || retry_after + Duration::from_secs(1)),
-                    send_result
-                        .map(|resp| resp.message_id)
-                        .map_err(|err| tracing::error!("error occured when sending message: {}", err))
-                        .ok(),
                 )
             })
+            .collect::<Vec<_>>();
+
+        async move {
+            let mut results = Vec::with_capacity(sends.len());
+            for send in sends {
+                results.push(send.await);
+            }
+            results
+        }
+    }
+
+    fn outbox_path(&self) -> Option<PathBuf> {
+        let user_id = self.saving_user_id()?;
+        Some(
+            self.content_store
+                .outbox_path(self.inner.homeserver_url().to_string().as_str(), user_id),
+        )
+    }
+
+    /// Writes the outbox to disk, removing the file if nothing is left to send.
+    pub fn save_outbox(&self) {
+        let path = match self.outbox_path() {
+            Some(path) => path,
+            None => return,
+        };
+        let result = if self.outbox.is_empty() {
+            match std::fs::remove_file(&path) {
+                Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err),
+                _ => Ok(()),
+            }
         } else {
-            None
+            std::fs::write(&path, self.outbox.encode())
+        };
+        if let Err(err) = result {
+            tracing::error!("couldn't save outbox to {}: {}", path.display(), err);
+        }
+    }
+
+    /// Loads the outbox saved by a previous run, replacing the current one.
+    pub fn load_outbox(&mut self) -> ClientResult<()> {
+        let path = match self.outbox_path() {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        self.outbox = match std::fs::read(&path) {
+            Ok(raw) => Outbox::decode(&raw)?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Outbox::default(),
+            Err(err) => return Err(err.into()),
+        };
+        Ok(())
+    }
+
+    fn read_markers_path(&self) -> Option<PathBuf> {
+        let user_id = self.saving_user_id()?;
+        Some(
+            self.content_store
+                .read_markers_path(self.inner.homeserver_url().to_string().as_str(), user_id),
+        )
+    }
+
+    /// Writes the read markers of every channel to disk.
+    pub fn save_read_markers(&self) {
+        if let Some(path) = self.read_markers_path() {
+            if let Err(err) = std::fs::write(&path, read_markers::encode(&self.guilds, &self.pending_read_markers)) {
+                tracing::error!("couldn't save read markers to {}: {}", path.display(), err);
+            }
+        }
+    }
+
+    /// Loads the read markers saved by a previous run. They are applied to channels as they are added.
+    pub fn load_read_markers(&mut self) -> ClientResult<()> {
+        let path = match self.read_markers_path() {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let mut markers = match std::fs::read(&path) {
+            Ok(raw) => read_markers::decode(&raw)?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => AHashMap::new(),
+            Err(err) => return Err(err.into()),
+        };
+        for (guild_id, guild) in self.guilds.iter_mut() {
+            for (channel_id, channel) in guild.channels.iter_mut() {
+                if let Some(marker) = markers.remove(&(*guild_id, *channel_id)) {
+                    channel.read_marker = marker;
+                }
+            }
+        }
+        self.pending_read_markers = markers;
+        Ok(())
+    }
+
+    /// Marks every channel in a guild as read.
+    pub fn mark_guild_read(&mut self, guild_id: u64) {
+        self.record(|| JournalEntry::mark_read(guild_id, None));
+        if let Some(guild) = self.get_guild(guild_id) {
+            guild.channels.values_mut().for_each(Channel::mark_read);
+            self.save_read_markers();
+        }
+    }
+
+    /// Marks a channel as read.
+    pub fn mark_channel_read(&mut self, guild_id: u64, channel_id: u64) {
+        self.record(|| JournalEntry::mark_read(guild_id, Some(channel_id)));
+        if let Some(channel) = self.get_channel(guild_id, channel_id) {
+            channel.mark_read();
+            self.save_read_markers();
+        }
+    }
+
+    /// Marks a message and every message after it as unread.
+    pub fn mark_unread_from(&mut self, guild_id: u64, channel_id: u64, message_id: u64) {
+        self.record(|| JournalEntry::mark_unread(guild_id, channel_id, message_id));
+        let user_id = self.user_id.unwrap_or(0);
+        if let Some(channel) = self.get_channel(guild_id, channel_id) {
+            let pos = match channel.messages.get_index_of(&MessageId::Ack(message_id)) {
+                Some(pos) => pos,
+                None => return,
+            };
+            let mut marker = ReadMarker {
+                last_read: channel.messages.keys().take(pos).rev().find_map(MessageId::id),
+                ..ReadMarker::default()
+            };
+            for (_, message) in channel.messages.iter().skip(pos).filter(|(id, _)| id.is_ack()) {
+                let mentions = message
+                    .content
+                    .as_text()
+                    .map_or(false, |text| mentions_user(text, user_id));
+                marker.add_unread(mentions);
+            }
+            channel.read_marker = marker;
+            self.save_read_markers();
+        }
+    }
+
+    fn bookmarks_path(&self) -> Option<PathBuf> {
+        let user_id = self.saving_user_id()?;
+        Some(
+            self.content_store
+                .bookmarks_path(self.inner.homeserver_url().to_string().as_str(), user_id),
+        )
+    }
+
+    /// Writes the bookmarks to disk.
+    pub fn save_bookmarks(&self) {
+        if let Some(path) = self.bookmarks_path() {
+            if let Err(err) = std::fs::write(&path, self.bookmarks.encode()) {
+                tracing::error!("couldn't save bookmarks to {}: {}", path.display(), err);
+            }
+        }
+    }
+
+    /// Loads the bookmarks saved by a previous run, replacing the current ones.
+    pub fn load_bookmarks(&mut self) -> ClientResult<()> {
+        let path = match self.bookmarks_path() {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        self.bookmarks = match std::fs::read(&path) {
+            Ok(raw) => Bookmarks::decode(&raw)?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Bookmarks::default(),
+            Err(err) => return Err(err.into()),
+        };
+        Ok(())
+    }
+
+    fn drafts_path(&self) -> Option<PathBuf> {
+        let user_id = self.saving_user_id()?;
+        Some(
+            self.content_store
+                .drafts_path(self.inner.homeserver_url().to_string().as_str(), user_id),
+        )
+    }
+
+    /// Writes the drafts to disk.
+    pub fn save_drafts(&self) {
+        if let Some(path) = self.drafts_path() {
+            if let Err(err) = std::fs::write(&path, self.drafts.encode()) {
+                tracing::error!("couldn't save drafts to {}: {}", path.display(), err);
+            }
+        }
+    }
+
+    /// Loads the drafts saved by a previous run, replacing the current ones.
+    pub fn load_drafts(&mut self) -> ClientResult<()> {
+        let path = match self.drafts_path() {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        self.drafts = match std::fs::read(&path) {
+            Ok(raw) => Drafts::decode(&raw)?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Drafts::default(),
+            Err(err) => return Err(err.into()),
+        };
+        Ok(())
+    }
+
+    /// Saves a loaded message as a bookmark, with a snapshot of its content.
+    ///
+    /// Returns `false` if the message isn't loaded or is already saved.
+    pub fn add_bookmark(&mut self, guild_id: u64, channel_id: u64, message_id: u64) -> bool {
+        let message = match self
+            .guilds
+            .get(&guild_id)
+            .and_then(|g| g.channels.get(&channel_id))
+            .and_then(|c| c.messages.get(&MessageId::Ack(message_id)))
+        {
+            Some(message) => message,
+            None => return false,
+        };
+        let snapshot = match &message.content {
+            Content::Text(text) => text.text.clone(),
+            Content::Files(attachments) => attachments
+                .iter()
+                .map(|a| a.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", "),
+            Content::Embeds(embeds) => embeds.iter().map(|e| e.title.as_str()).collect::<Vec<_>>().join(", "),
+            Content::System(system) => system.describe(&self.members),
+        };
+        let sender_name = message
+            .overrides
+            .as_ref()
+            .and_then(|ov| ov.name.clone())
+            .or_else(|| self.members.get(&message.sender).map(|m| m.username.to_string()))
+            .unwrap_or_default();
+        let bookmark = Bookmark {
+            guild_id,
+            channel_id,
+            message_id,
+            sender: message.sender,
+            sender_name,
+            timestamp: message.timestamp.timestamp(),
+            snapshot,
+            note: String::new(),
+        };
+        let added = self.bookmarks.add(bookmark);
+        if added {
+            self.save_bookmarks();
+        }
+        added
+    }
+
+    pub fn remove_bookmark(&mut self, guild_id: u64, channel_id: u64, message_id: u64) {
+        if self.bookmarks.remove(guild_id, channel_id, message_id).is_some() {
+            self.save_bookmarks();
+        }
+    }
+
+    pub fn set_bookmark_note(&mut self, guild_id: u64, channel_id: u64, message_id: u64, note: String) {
+        if self.bookmarks.set_note(guild_id, channel_id, message_id, note) {
+            self.save_bookmarks();
+        }
+    }
+
+    /// Adds outbox messages for a channel that aren't shown yet to the end of its messages.
+    /// [tag:outbox_after_history]
+    fn show_outbox_messages(&mut self, guild_id: u64, channel_id: u64) {
+        let pending = self
+            .outbox
+            .iter()
+            .filter(|(_, entry)| entry.guild_id == guild_id && entry.channel_id == channel_id)
+            .map(|(transaction_id, entry)| (MessageId::Unack(transaction_id), entry.message.clone()))
+            .collect::<Vec<_>>();
+        if let Some(channel) = self.get_channel(guild_id, channel_id).filter(|c| c.reached_bottom) {
+            for (message_id, message) in pending {
+                if !channel.messages.contains_key(&message_id) {
+                    channel.messages.insert(message_id, message);
+                }
+            }
         }
     }
 
@@ -330,13 +960,30 @@ impl Client {
         }
     }
 
+    /// Edits the text of a message, showing the new text as being edited until
+    /// [`Client::process_edit_result`] is called.
     pub fn edit_msg_cmd(
-        &self,
+        &mut self,
         guild_id: u64,
         channel_id: u64,
         message_id: u64,
         new_content: String,
     ) -> impl Future<Output = (u64, u64, u64, Option<Box<ClientError>>)> {
+        self.record(|| JournalEntry::edit(guild_id, channel_id, message_id, &new_content));
+        let mut markdown = FormattedText::default();
+        if let Some(channel) = self.get_channel(guild_id, channel_id) {
+            let id = MessageId::Ack(message_id);
+            if let Some(msg) = channel.messages.get_mut(&id) {
+                msg.being_edited = Some(new_content.clone());
+                if let Content::Text(text) = &msg.content {
+                    markdown = text.clone();
+                }
+                channel.messages.changed(id);
+            }
+        }
+        // Formats that have no markdown syntax, like colors, are kept over the text that wasn't edited
+        let markdown = formatting::with_edited_text(&markdown, new_content);
+
         let fut = self.inner().chat().update_message_text(
             (UpdateMessageTextRequest {
                 guild_id,
@@ -344,7 +991,7 @@ impl Client {
                 message_id,
                 ..Default::default()
             })
-            .with_new_content(FormattedText::default().with_text(new_content)),
+            .with_new_content(formatting::to_harmony(&markdown, &self.members, &self.emote_packs)),
         );
 
         fut.map(move |result| {
@@ -357,7 +1004,164 @@ impl Client {
         })
     }
 
+    /// Handles an edit made with [`Client::edit_msg_cmd`] being done, whether it succeeded or not.
+    pub fn process_edit_result(&mut self, guild_id: u64, channel_id: u64, message_id: u64) {
+        self.record(|| JournalEntry::edit_done(guild_id, channel_id, message_id));
+        if let Some(channel) = self.get_channel(guild_id, channel_id) {
+            let id = MessageId::Ack(message_id);
+            // The text is shown as it was before the edit again, unless the edit was received already
+            let was_edited = channel
+                .messages
+                .get_mut(&id)
+                .and_then(|msg| msg.being_edited.take())
+                .is_some();
+            if was_edited {
+                channel.messages.changed(id);
+            }
+        }
+    }
+
+    /// Adds our reaction to a loaded message, or removes it if we already reacted with this emote,
+    /// without telling the homeserver.
+    ///
+    /// Returns whether we had reacted before, or `None` if the message isn't loaded or it isn't known
+    /// whether we reacted, in which case nothing is changed.
+    pub fn toggle_reaction(
+        &mut self,
+        guild_id: u64,
+        channel_id: u64,
+        message_id: u64,
+        image_id: &str,
+        name: &str,
+    ) -> Option<bool> {
+        self.record(|| JournalEntry::reaction(guild_id, channel_id, message_id, image_id, name));
+        let messages = &mut self.get_channel(guild_id, channel_id)?.messages;
+        let id = MessageId::Ack(message_id);
+        let message = messages.get_mut(&id)?;
+        let had_reactions = !message.reactions.is_empty();
+        let reacted = message.toggle_reaction(image_id, name);
+        // Only the row of reactions showing up or going away changes how the message is shown
+        if message.reactions.is_empty() == had_reactions {
+            messages.changed(id);
+        }
+        reacted
+    }
+
+    /// Records whether we reacted to a loaded message with an emote, once the homeserver told us.
+    pub fn set_reacted(&mut self, guild_id: u64, channel_id: u64, message_id: u64, image_id: &str, reacted: bool) {
+        self.record(|| JournalEntry::reacted(guild_id, channel_id, message_id, image_id, reacted));
+        if let Some(message) = self
+            .get_channel(guild_id, channel_id)
+            .and_then(|c| c.messages.get_mut(&MessageId::Ack(message_id)))
+        {
+            message.set_reacted(image_id, reacted);
+        }
+    }
+
+    /// Adds our reaction to a message, or removes it if we already reacted with this emote.
+    ///
+    /// If we know whether we reacted, the message is updated right away. Otherwise, since the homeserver
+    /// doesn't say who reacted, our reaction is removed first: if the count goes down we had reacted,
+    /// if it doesn't we hadn't and it is added instead. The result has to be handled with
+    /// [`Client::process_reaction_toggle_result`].
+    pub fn toggle_reaction_cmd(
+        &mut self,
+        guild_id: u64,
+        channel_id: u64,
+        message_id: u64,
+        image_id: SmolStr,
+        name: SmolStr,
+    ) -> Option<impl Future<Output = ReactionToggleResult>> {
+        let count = self
+            .get_channel(guild_id, channel_id)?
+            .messages
+            .get(&MessageId::Ack(message_id))?
+            .reactions
+            .iter()
+            .find(|r| r.image_id == image_id)
+            .map_or(0, |r| r.count);
+        let was_reacted = self.toggle_reaction(guild_id, channel_id, message_id, &image_id, &name);
+        let inner = self.inner().clone();
+
+        Some(async move {
+            let remove = RemoveReactionRequest {
+                guild_id,
+                channel_id,
+                message_id,
+                image_id: image_id.to_string(),
+            };
+            let add = AddReactionRequest {
+                guild_id,
+                channel_id,
+                message_id,
+                emote: Some(Emote {
+                    image_id: image_id.to_string(),
+                    name: name.into(),
+                }),
+            };
+
+            let result = match was_reacted {
+                Some(true) => inner.call(remove).await.map(|_| false),
+                Some(false) => inner.call(add).await.map(|_| true),
+                None => match inner.call(remove).await {
+                    Ok(_) => {
+                        let count_after = inner
+                            .call(GetMessageRequest {
+                                guild_id,
+                                channel_id,
+                                message_id,
+                            })
+                            .await
+                            .map(|response| {
+                                response
+                                    .message
+                                    .into_iter()
+                                    .flat_map(|message| message.reactions)
+                                    .find(|r| r.emote.as_ref().map_or(false, |e| e.image_id == image_id.as_str()))
+                                    .map_or(0, |r| r.count)
+                            });
+                        match count_after {
+                            Ok(count_after) if count_after < count => Ok(false),
+                            Ok(_) => inner.call(add).await.map(|_| true),
+                            Err(err) => Err(err),
+                        }
+                    }
+                    // The homeserver wouldn't remove a reaction we didn't make
+                    Err(_) => inner.call(add).await.map(|_| true),
+                },
+            };
+            (was_reacted, result.map_err(Into::into))
+        })
+    }
+
+    /// Handles the result of [`Client::toggle_reaction_cmd`], returning the error if the homeserver
+    /// refused the change.
+    pub fn process_reaction_toggle_result(
+        &mut self,
+        guild_id: u64,
+        channel_id: u64,
+        message_id: u64,
+        image_id: &str,
+        name: &str,
+        (was_reacted, result): ReactionToggleResult,
+    ) -> Option<ClientError> {
+        match (was_reacted, result) {
+            (None, Ok(reacted)) => {
+                self.set_reacted(guild_id, channel_id, message_id, image_id, reacted);
+                None
+            }
+            (Some(_), Ok(_)) => None,
+            // Undo the change made right away, since the homeserver didn't accept it
+            (Some(_), Err(err)) => {
+                self.toggle_reaction(guild_id, channel_id, message_id, image_id, name);
+                Some(err)
+            }
+            (None, Err(err)) => Some(err),
+        }
+    }
+
     pub fn process_event(&mut self, event: Event) -> Vec<PostProcessEvent> {
+        self.record(|| JournalEntry::event(&event));
         let mut post = Vec::new();
 
         match event {
@@ -385,68 +1189,75 @@ impl Client {
                     let channel_id = message_sent.channel_id;
                     let message_id = message_sent.message_id;
 
+                    if echo_id.map_or(false, |id| self.outbox.remove(id).is_some()) {
+                        self.save_outbox();
+                    }
+
                     if let Some(message) = message_sent.message {
                         if let Some(guild) = self.guilds.get_mut(&guild_id) {
                             if let Some(channel) = guild.channels.get_mut(&channel_id) {
                                 let message = Message::from(message);
 
                                 message.post_process(&mut post, guild_id, channel_id);
+                                self.search_index
+                                    .index_message(guild_id, channel_id, message_id, &message);
+
+                                let is_new = !channel.messages.contains_key(&MessageId::Ack(message_id));
+                                let current_user_id = self.user_id.unwrap_or(0);
+                                let mentions_user = match &message.content {
+                                    Content::Text(text) => mentions_user(&text.text, current_user_id),
+                                    _ => false,
+                                };
 
                                 if let Content::Text(text) = &message.content {
-                                    if !channel.looking_at_channel {
-                                        use byte_writer::Writer;
-                                        use std::fmt::Write;
-
-                                        let current_user_id = self.user_id.unwrap_or(0);
-
-                                        let mut pattern_arr = [b'0'; 23];
-                                        write!(Writer(&mut pattern_arr), "<@{}>", current_user_id).unwrap();
-
-                                        if text.contains(
-                                            (unsafe { std::str::from_utf8_unchecked(&pattern_arr) })
-                                                .trim_end_matches(|c| c != '>'),
-                                        ) {
-                                            let member_name = self
-                                                .members
-                                                .get(&message.sender)
-                                                .map_or("unknown", |m| m.username.as_str());
-                                            post.push(PostProcessEvent::SendNotification {
-                                                unread_message: false,
-                                                mention: true,
-                                                title: format!("{} | #{}", guild.name, channel.name),
-                                                content: format!(
-                                                    "@{}: {}",
-                                                    member_name,
-                                                    render_text(text, &self.members, &self.emote_packs)
-                                                ),
-                                            });
-                                        }
+                                    if !channel.looking_at_channel && mentions_user {
+                                        let member_name = self
+                                            .members
+                                            .get(&message.sender)
+                                            .map_or("unknown", |m| m.username.as_str());
+                                        post.push(PostProcessEvent::SendNotification {
+                                            unread_message: false,
+                                            mention: true,
+                                            title: format!("{} | #{}", guild.name, channel.name),
+                                            content: format!(
+                                                "@{}: {}",
+                                                member_name,
+                                                render_text(&text.text, &self.members, &self.emote_packs)
+                                            ),
+                                        });
                                     }
                                 }
 
-                                if let Some(msg_index) = echo_id
-                                    .map(|id| channel.messages.get_index_of(&MessageId::Unack(id)))
-                                    .flatten()
-                                {
-                                    channel.messages.insert(MessageId::Ack(message_id), message);
-                                    channel
-                                        .messages
-                                        .swap_indices(msg_index, channel.messages.len().saturating_sub(1));
-                                    channel.messages.pop();
-                                } else if let Some(msg) = channel.messages.get_mut(&MessageId::Ack(message_id)) {
+                                if is_new {
+                                    let marker = &mut channel.read_marker;
+                                    // Messages we send, and ones arriving in the channel we're reading, are read
+                                    // unless the user marked something in the channel as unread
+                                    if message.sender == current_user_id
+                                        || (channel.looking_at_channel && !marker.has_unread())
+                                    {
+                                        marker.read_up_to(Some(message_id));
+                                    } else {
+                                        marker.add_unread(mentions_user);
+                                    }
+                                }
+
+                                let shown_echo_id =
+                                    echo_id.filter(|id| channel.messages.contains_key(&MessageId::Unack(*id)));
+                                if let Some(msg) = channel.messages.get_mut(&MessageId::Ack(message_id)) {
                                     *msg = message;
-                                } else {
+                                    channel.messages.changed(MessageId::Ack(message_id));
+                                    if let Some(echo_id) = shown_echo_id {
+                                        channel.messages.remove(&MessageId::Unack(echo_id));
+                                    }
+                                } else if let Some(echo_id) = shown_echo_id {
+                                    channel.messages.acknowledge(echo_id, message_id, message);
+                                } else if channel.reached_bottom {
                                     channel.messages.insert(MessageId::Ack(message_id), message);
                                 }
 
-                                let disp = channel.messages.len();
-                                if channel.looking_at_message >= disp.saturating_sub(32) {
-                                    channel.looking_at_message = disp.saturating_sub(1);
+                                if channel.reached_bottom && channel.viewport.is_at_bottom() {
                                     post.push(PostProcessEvent::GoToFirstMsgOnChannel(channel_id));
                                 }
-                                if !channel.looking_at_channel {
-                                    channel.has_unread = true;
-                                }
                             }
                         }
                     }
@@ -459,16 +1270,54 @@ impl Client {
                     if let Some(channel) = self.get_channel(guild_id, channel_id) {
                         channel.messages.remove(&MessageId::Ack(message_id));
                     }
+                    self.search_index.remove_message(guild_id, channel_id, message_id);
                 }
                 ChatEvent::EditedMessage(message_updated) => {
                     let guild_id = message_updated.guild_id;
                     let channel_id = message_updated.channel_id;
 
-                    if let Some(channel) = self.get_channel(guild_id, channel_id) {
-                        if let Some(msg) = channel.messages.get_mut(&MessageId::Ack(message_updated.message_id)) {
-                            msg.content =
-                                Content::Text(message_updated.new_content.map_or_else(String::new, |f| f.text));
-                            msg.post_process(&mut post, guild_id, channel_id);
+                    if let Some(guild) = self.guilds.get_mut(&guild_id) {
+                        if let Some(channel) = guild.channels.get_mut(&channel_id) {
+                            let id = MessageId::Ack(message_updated.message_id);
+                            if let Some(msg) = channel.messages.get_mut(&id) {
+                                msg.edit(
+                                    message_updated
+                                        .new_content
+                                        .map_or_else(FormattedText::default, formatting::from_harmony),
+                                    NaiveDateTime::from_timestamp(message_updated.edited_at as i64, 0),
+                                );
+                                msg.post_process(&mut post, guild_id, channel_id);
+                                self.search_index
+                                    .index_message(guild_id, channel_id, message_updated.message_id, msg);
+                                channel.messages.changed(id);
+                            }
+                        }
+                    }
+                }
+                ChatEvent::ReactionUpdated(ReactionUpdated {
+                    guild_id,
+                    channel_id,
+                    message_id,
+                    reaction,
+                }) => {
+                    if let Some(reaction) = reaction {
+                        if let Some(channel) = self.get_channel(guild_id, channel_id) {
+                            let id = MessageId::Ack(message_id);
+                            if let Some(msg) = channel.messages.get_mut(&id) {
+                                let reaction = message::Reaction::from(reaction);
+                                if !reaction.is_unicode() {
+                                    post.push(PostProcessEvent::FetchThumbnail(Attachment {
+                                        kind: "image".into(),
+                                        name: "emote".into(),
+                                        ..Attachment::new_unknown(FileId::Id(reaction.image_id.to_string()))
+                                    }));
+                                }
+                                let had_reactions = !msg.reactions.is_empty();
+                                msg.update_reaction(reaction);
+                                if msg.reactions.is_empty() == had_reactions {
+                                    channel.messages.changed(id);
+                                }
+                            }
                         }
                     }
                 }
@@ -476,6 +1325,9 @@ impl Client {
                     if let Some(guild) = self.get_guild(guild_id) {
                         guild.channels.remove(&channel_id);
                     }
+                    self.search_index.remove_messages_in(guild_id, Some(channel_id));
+                    self.outbox.remove_in(guild_id, Some(channel_id));
+                    self.save_outbox();
                 }
                 ChatEvent::EditedChannel(ChannelUpdated {
                     guild_id,
@@ -528,11 +1380,14 @@ impl Client {
                                 is_category: kind == i32::from(ChannelKind::Category),
                                 name: name.into(),
                                 loading_messages_history: false,
-                                looking_at_message: 0,
+                                viewport: Default::default(),
                                 messages: Default::default(),
-                                last_known_message_id: 0,
                                 reached_top: false,
-                                has_unread: false,
+                                reached_bottom: true,
+                                read_marker: self
+                                    .pending_read_markers
+                                    .remove(&(guild_id, channel_id))
+                                    .unwrap_or_default(),
                                 looking_at_channel: false,
                                 perms: Vec::new(),
                                 init_fetching: false,
@@ -592,9 +1447,15 @@ impl Client {
                     homeserver: _,
                 }) => {
                     self.guilds.remove(&guild_id);
+                    self.search_index.remove_messages_in(guild_id, None);
+                    self.outbox.remove_in(guild_id, None);
+                    self.save_outbox();
                 }
                 ChatEvent::DeletedGuild(GuildDeleted { guild_id }) => {
                     self.guilds.remove(&guild_id);
+                    self.search_index.remove_messages_in(guild_id, None);
+                    self.outbox.remove_in(guild_id, None);
+                    self.save_outbox();
                 }
                 ChatEvent::EditedGuild(GuildUpdated {
                     guild_id,
@@ -697,7 +1558,7 @@ impl Client {
                         }
                     });
                 }
-                _ => panic!(),
+                ev => self.push_unknown_event(format!("{:?}", ev)),
             },
             Event::Profile(ev) => match ev {
                 ProfileEvent::ProfileUpdated(ProfileUpdated {
@@ -781,6 +1642,17 @@ impl Client {
         post
     }
 
+    fn push_unknown_event(&mut self, description: String) {
+        tracing::warn!("received unknown event: {}", description);
+        if self.unknown_events.len() >= MAX_UNKNOWN_EVENTS {
+            self.unknown_events.pop_front();
+        }
+        self.unknown_events.push_back(UnknownEvent {
+            received_at: Local::now().naive_local(),
+            description,
+        });
+    }
+
     pub fn process_reply_message(
         &mut self,
         guild_id: u64,
@@ -788,18 +1660,24 @@ impl Client {
         message_id: u64,
         message: HarmonyMessage,
     ) -> Vec<PostProcessEvent> {
+        self.record(|| JournalEntry::reply(guild_id, channel_id, message_id, &message));
         let mut post = Vec::new();
 
-        if let Some(channel) = self.get_channel(guild_id, channel_id) {
+        if let Some(channel) = self
+            .guilds
+            .get_mut(&guild_id)
+            .and_then(|g| g.channels.get_mut(&channel_id))
+        {
             let message: Message = message.into();
             message.post_process(&mut post, guild_id, channel_id);
-            let message_id = MessageId::Ack(message_id);
-            if channel.messages.contains_key(&message_id) {
-                channel.messages.insert(message_id, message);
-            } else {
-                channel.messages.reverse();
-                channel.messages.insert(message_id, message);
-                channel.messages.reverse();
+            self.search_index
+                .index_message(guild_id, channel_id, message_id, &message);
+            match channel.messages.get_mut(&MessageId::Ack(message_id)) {
+                Some(loaded) => {
+                    *loaded = message;
+                    channel.messages.changed(MessageId::Ack(message_id));
+                }
+                None => channel.messages.merge(vec![(message_id, message)], None, None),
             }
         }
 
@@ -815,103 +1693,148 @@ impl Client {
         reached_top: bool,
         direction: Direction,
     ) -> Vec<PostProcessEvent> {
+        self.record(|| JournalEntry::history(guild_id, channel_id, message_id, &messages, reached_top, direction));
         let mut post = Vec::new();
 
-        if let Some(channel) = self.get_channel(guild_id, channel_id) {
-            let mut messages: IndexMap<_, _> = messages
+        if let Some(channel) = self
+            .guilds
+            .get_mut(&guild_id)
+            .and_then(|g| g.channels.get_mut(&channel_id))
+        {
+            let mut messages = messages
                 .into_iter()
-                .map(|(id, msg)| (MessageId::Ack(id), Message::from(msg)))
-                .collect();
+                .map(|(id, msg)| (id, Message::from(msg)))
+                .collect::<Vec<_>>();
 
-            messages.values().for_each(|m| {
+            let search_index = &mut self.search_index;
+            messages.iter().for_each(|(id, m)| {
                 m.post_process(&mut post, guild_id, channel_id);
+                search_index.index_message(guild_id, channel_id, *id, m);
             });
 
-            let msg_pos = channel.messages.get_index_of(&MessageId::Ack(message_id));
-            let process_before = |mut pos: usize,
-                                  messages: IndexMap<MessageId, Message>,
-                                  chan_messages: &mut Vec<(MessageId, Message)>| {
-                for message in messages {
-                    if chan_messages.get(pos).map_or(true, |(id, _)| message.0.eq(id)) {
-                        continue;
-                    }
-                    if let Some(at) = chan_messages.iter().position(|(id, _)| message.0.eq(id)) {
-                        if at < pos {
-                            pos -= 1;
-                        }
-                        chan_messages.remove(at);
+            // Messages fetched after the ones we have arrived while we weren't connected
+            if direction == Direction::After && channel.reached_bottom && !channel.looking_at_channel {
+                let user_id = self.user_id.unwrap_or(0);
+                for (id, message) in &messages {
+                    if message.sender != user_id && !channel.messages.contains_key(&MessageId::Ack(*id)) {
+                        let mentions = message
+                            .content
+                            .as_text()
+                            .map_or(false, |text| mentions_user(text, user_id));
+                        channel.read_marker.add_unread(mentions);
                     }
-                    chan_messages.insert(pos, message);
                 }
-            };
-            let process_after = |mut pos: usize,
-                                 messages: IndexMap<MessageId, Message>,
-                                 chan_messages: &mut Vec<(MessageId, Message)>| {
-                pos += 1;
-                for message in messages {
-                    if chan_messages.get(pos).map_or(true, |(id, _)| message.0.eq(id)) {
-                        pos += 1;
-                        continue;
-                    }
-                    if let Some(at) = chan_messages.iter().position(|(id, _)| message.0.eq(id)) {
-                        if at < pos {
-                            pos -= 1;
-                        }
-                        chan_messages.remove(at);
-                    }
-                    chan_messages.insert(pos, message);
-                    pos += 1;
-                }
-            };
+            }
 
+            let newest_message_id = channel.newest_ack_message_id();
             match direction {
                 Direction::BeforeUnspecified => {
-                    let last_message_id = messages.keys().last().copied();
-                    match msg_pos.or_else(|| {
-                        channel
-                            .messages
-                            .get_index_of(&MessageId::Ack(channel.last_known_message_id))
-                    }) {
-                        Some(pos) => {
-                            let mut chan_messages = channel.messages.drain(..).collect::<Vec<_>>();
-                            process_before(pos, messages, &mut chan_messages);
-                            channel.messages = chan_messages.into_iter().collect();
-                        }
-                        None => {
-                            messages.reverse();
-                            channel.messages.extend(messages);
-                        }
-                    }
-                    if let Some(MessageId::Ack(id)) = last_message_id {
-                        channel.last_known_message_id = id;
+                    // Pages going back in history are newest first
+                    messages.reverse();
+                    channel
+                        .messages
+                        .merge(messages, None, (message_id != 0).then(|| message_id));
+                    // The newest messages, which may have been fetched to go back to the present from older ones
+                    if message_id == 0 {
+                        channel.reached_bottom = true;
                     }
                 }
-                Direction::After => match msg_pos {
-                    Some(pos) => {
-                        let mut chan_messages = channel.messages.drain(..).collect::<Vec<_>>();
-                        process_after(pos, messages, &mut chan_messages);
-                        channel.messages = chan_messages.into_iter().collect();
-                    }
-                    None => {
-                        channel.messages.extend(messages);
-                    }
-                },
-                Direction::Around => match msg_pos {
-                    Some(pos) => {
-                        let mut chan_messages = channel.messages.drain(..).collect::<Vec<_>>();
-                        let message_pos = messages.get_index_of(&MessageId::Ack(message_id)).unwrap();
-                        process_after(pos, messages.drain(message_pos + 1..).collect(), &mut chan_messages);
-                        process_before(pos, messages.drain(..message_pos).collect(), &mut chan_messages);
-                        channel.messages = chan_messages.into_iter().collect();
+                Direction::After => {
+                    if messages.is_empty() {
+                        // Nothing is newer, so nothing is missing after the message
+                        channel.messages.close_gap_after(message_id);
+                        if newest_message_id == Some(message_id) {
+                            channel.reached_bottom = true;
+                        }
                     }
-                    None => {
-                        messages.extend(channel.messages.drain(..));
-                        channel.messages = messages;
+                    channel.messages.merge(messages, Some(message_id), None);
+                }
+                Direction::Around => {
+                    channel.messages.merge(messages, None, None);
+                    // Newer messages than the ones around the message we jumped to may not be loaded
+                    if channel.newest_ack_message_id() != newest_message_id {
+                        channel.reached_bottom = false;
                     }
-                },
+                }
+            }
+            if direction != Direction::After {
+                channel.reached_top |= reached_top;
             }
-            channel.reached_top = reached_top;
         }
+        self.show_outbox_messages(guild_id, channel_id);
+
+        post
+    }
+
+    /// Processes the messages fetched with [`Client::get_messages_after_cmd`].
+    pub fn process_messages_after(
+        &mut self,
+        guild_id: u64,
+        channel_id: u64,
+        message_id: u64,
+        messages: Vec<(u64, HarmonyMessage)>,
+        reached_end: bool,
+    ) -> Vec<PostProcessEvent> {
+        let newest_message_id = messages.last().map_or(message_id, |(id, _)| *id);
+        let mut post = self.process_get_message_history_response(
+            guild_id,
+            channel_id,
+            message_id,
+            messages,
+            false,
+            Direction::After,
+        );
+        if !reached_end {
+            // Not every newer message was fetched, the rest is fetched as the user scrolls down
+            self.get_channel(guild_id, channel_id)
+                .and_do(|c| c.reached_bottom = false);
+        } else if newest_message_id != message_id {
+            // Nothing comes after the newest fetched message, like an empty page after it would tell
+            post.extend(self.process_get_message_history_response(
+                guild_id,
+                channel_id,
+                newest_message_id,
+                Vec::new(),
+                false,
+                Direction::After,
+            ));
+        }
+        post
+    }
+
+    /// Shows the messages read from the message cache of a channel, newest first.
+    ///
+    /// The cache may be out of date, so it doesn't tell whether the top or bottom of history is
+    /// reached; the history fetched from the homeserver does. Newer messages are missing after the
+    /// cached ones until they are fetched with [`Client::get_messages_after_cmd`]. Like the rest of
+    /// what is read from disk, cached messages aren't recorded in the journal.
+    pub fn process_cached_messages(
+        &mut self,
+        guild_id: u64,
+        channel_id: u64,
+        mut messages: Vec<(u64, Message)>,
+    ) -> Vec<PostProcessEvent> {
+        let mut post = Vec::new();
+
+        if let Some(channel) = self
+            .guilds
+            .get_mut(&guild_id)
+            .and_then(|g| g.channels.get_mut(&channel_id))
+        {
+            let search_index = &mut self.search_index;
+            messages.iter().for_each(|(id, m)| {
+                m.post_process(&mut post, guild_id, channel_id);
+                search_index.index_message(guild_id, channel_id, *id, m);
+            });
+
+            messages.reverse();
+            let newest_message_id = messages.last().map(|(id, _)| *id);
+            channel.messages.merge(messages, None, None);
+            if let Some(message_id) = newest_message_id {
+                channel.messages.open_gap_after(message_id);
+            }
+        }
+        self.show_outbox_messages(guild_id, channel_id);
 
         post
     }
@@ -927,6 +1850,17 @@ impl Client {
     }
 }
 
+/// Whether a message text mentions the user with the given ID.
+pub fn mentions_user(text: &str, user_id: u64) -> bool {
+    use byte_writer::Writer;
+    use std::fmt::Write;
+
+    let mut pattern_arr = [b'0'; 23];
+    write!(Writer(&mut pattern_arr), "<@{}>", user_id).unwrap();
+
+    text.contains((unsafe { std::str::from_utf8_unchecked(&pattern_arr) }).trim_end_matches(|c| c != '>'))
+}
+
 pub fn render_text(textt: &str, members: &Members, emote_packs: &EmotePacks) -> String {
     // TODO: this is horribly inefficient
     let mut text = textt.to_string();
@@ -1036,12 +1970,35 @@ use linemd::{
 pub enum HarmonyToken<'a> {
     Emote(&'a str),
     Mention(u64),
+    /// Text between `||`, hidden until it is clicked.
+    Spoiler(&'a str),
+    /// Text between `~~`.
+    Strikethrough(&'a str),
+    /// A `> ` at the start of a line, which makes the line a block quote.
+    Quote,
 }
 
 impl<'a> HarmonyToken<'a> {
     pub fn parse(value: &'a &str, at: usize) -> Option<AtToken<'a, HarmonyToken<'a>>> {
-        let nat = value.consume_char_if(at, |c| c == '<')?;
+        if let Some(nat) = value.consume_char_if(at, |c| c == '<') {
+            Self::parse_angled(value, nat)
+        } else if let Some(nat) = Self::consume_pair(value, at, '|') {
+            Self::parse_until(value, nat, "||", HarmonyToken::Spoiler)
+        } else if let Some(nat) = Self::consume_pair(value, at, '~') {
+            Self::parse_until(value, nat, "~~", HarmonyToken::Strikethrough)
+        } else if value
+            .get(..at)
+            .map_or(false, |before| before.is_empty() || before.ends_with('\n'))
+        {
+            let nat = value.consume_char_if(at, |c| c == '>')?;
+            let nat = value.consume_char_if(nat, |c| c == ' ')?;
+            Some((Token::Custom(HarmonyToken::Quote), nat))
+        } else {
+            None
+        }
+    }
 
+    fn parse_angled(value: &'a &str, nat: usize) -> Option<AtToken<'a, HarmonyToken<'a>>> {
         if let Some(nat) = value.consume_char_if(nat, |c| c == '@') {
             value
                 .consume_while(nat, |c| c != '>')
@@ -1063,4 +2020,24 @@ impl<'a> HarmonyToken<'a> {
             None
         }
     }
+
+    fn consume_pair(value: &'a &str, at: usize, delimiter: char) -> Option<usize> {
+        let nat = value.consume_char_if(at, |c| c == delimiter)?;
+        value.consume_char_if(nat, |c| c == delimiter)
+    }
+
+    /// Parses the text up to `end` into a token, which must not be empty.
+    fn parse_until(
+        value: &'a &str,
+        nat: usize,
+        end: &str,
+        token: fn(&'a str) -> HarmonyToken<'a>,
+    ) -> Option<AtToken<'a, HarmonyToken<'a>>> {
+        value
+            .consume_until_str(nat, end)
+            .ok()
+            .flatten()
+            .filter(|(text, _)| !text.trim().is_empty())
+            .map(|(text, nat)| (Token::Custom(token(text)), nat + end.len()))
+    }
 }
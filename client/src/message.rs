@@ -4,6 +4,7 @@ use harmony_rust_sdk::{
         chat::{
             self, color, content, embed, overrides::Reason, FormattedText, Message as HarmonyMessage, Minithumbnail,
         },
+        emote::Emote,
         exports::hrpc::exports::http::Uri,
         Hmc,
     },
@@ -14,11 +15,9 @@ use rand::Rng;
 use smol_str::SmolStr;
 use std::{str::FromStr, time::UNIX_EPOCH};
 
-use crate::{HarmonyToken, IndexMap};
+use crate::HarmonyToken;
 
-use super::{content::MAX_THUMB_SIZE, post_heading, PostProcessEvent};
-
-pub type Messages = IndexMap<MessageId, Message>;
+use super::{content::MAX_THUMB_SIZE, emotes::EmotePacks, formatting, member::Members, post_heading, PostProcessEvent};
 
 #[derive(Debug, Clone)]
 pub struct EmbedField {
@@ -192,16 +191,86 @@ impl Default for MessageId {
 
 #[derive(Debug, Clone)]
 pub enum Content {
-    Text(String),
+    /// Markdown, with the formats that can't be written in markdown. See [`crate::formatting`].
+    Text(FormattedText),
     Files(Vec<Attachment>),
     Embeds(Vec<Embed>),
+    System(SystemMessage),
+}
+
+/// Content sent by the homeserver itself, shown as a system line instead of a regular message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SystemMessage {
+    InviteAccepted { invitee_id: u64, inviter_id: u64 },
+    InviteRejected { invitee_id: u64, inviter_id: u64 },
+    RoomUpgradedToGuild { upgraded_by: u64 },
+}
+
+impl SystemMessage {
+    /// Users mentioned by this message.
+    pub fn user_ids(&self) -> Vec<u64> {
+        match *self {
+            SystemMessage::InviteAccepted { invitee_id, inviter_id }
+            | SystemMessage::InviteRejected { invitee_id, inviter_id } => vec![invitee_id, inviter_id],
+            SystemMessage::RoomUpgradedToGuild { upgraded_by } => vec![upgraded_by],
+        }
+    }
+
+    /// A human readable description, using the usernames in `members` where known.
+    pub fn describe(&self, members: &Members) -> String {
+        let name = |user_id: u64| {
+            members
+                .get(&user_id)
+                .map_or_else(|| user_id.to_string(), |m| m.username.to_string())
+        };
+        match *self {
+            SystemMessage::InviteAccepted { invitee_id, inviter_id } => {
+                format!("{} accepted an invite from {}", name(invitee_id), name(inviter_id))
+            }
+            SystemMessage::InviteRejected { invitee_id, inviter_id } => {
+                format!("{} rejected an invite from {}", name(invitee_id), name(inviter_id))
+            }
+            SystemMessage::RoomUpgradedToGuild { upgraded_by } => {
+                format!("{} upgraded this room to a guild", name(upgraded_by))
+            }
+        }
+    }
+}
+
+impl Content {
+    /// Text content written in markdown.
+    pub fn text(markdown: impl Into<String>) -> Self {
+        Content::Text(FormattedText::default().with_text(markdown.into()))
+    }
+
+    /// The markdown of text content.
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            Content::Text(text) => Some(&text.text),
+            _ => None,
+        }
+    }
+
+    /// Converts the content to send it, writing mentions and emotes with their names.
+    pub fn to_harmony(&self, members: &Members, emote_packs: &EmotePacks) -> content::Content {
+        match self {
+            Content::Text(text) => content::Content::TextMessage(content::TextContent {
+                content: Some(formatting::to_harmony(text, members, emote_packs)),
+            }),
+            other => other.clone().into(),
+        }
+    }
 }
 
 impl From<Content> for content::Content {
     fn from(c: Content) -> content::Content {
         match c {
             Content::Text(content) => content::Content::TextMessage(content::TextContent {
-                content: Some(FormattedText::default().with_text(content)),
+                content: Some(formatting::to_harmony(
+                    &content,
+                    &Members::default(),
+                    &EmotePacks::default(),
+                )),
             }),
             Content::Embeds(embeds) => content::Content::EmbedMessage(content::EmbedContent {
                 embeds: embeds.into_iter().map(Into::into).collect(),
@@ -209,6 +278,15 @@ impl From<Content> for content::Content {
             Content::Files(attachments) => content::Content::AttachmentMessage(content::AttachmentContent {
                 files: attachments.into_iter().map(Into::into).collect(),
             }),
+            Content::System(SystemMessage::InviteAccepted { invitee_id, inviter_id }) => {
+                content::Content::InviteAccepted(content::InviteAccepted { invitee_id, inviter_id })
+            }
+            Content::System(SystemMessage::InviteRejected { invitee_id, inviter_id }) => {
+                content::Content::InviteRejected(content::InviteRejected { invitee_id, inviter_id })
+            }
+            Content::System(SystemMessage::RoomUpgradedToGuild { upgraded_by }) => {
+                content::Content::RoomUpgradedToGuild(content::RoomUpgradedToGuild { upgraded_by })
+            }
         }
     }
 }
@@ -216,7 +294,10 @@ impl From<Content> for content::Content {
 impl From<content::Content> for Content {
     fn from(content: content::Content) -> Self {
         match content {
-            content::Content::TextMessage(text) => Self::Text(text.content.map_or_else(String::new, |f| f.text)),
+            content::Content::TextMessage(text) => Self::Text(
+                text.content
+                    .map_or_else(FormattedText::default, formatting::from_harmony),
+            ),
             content::Content::AttachmentMessage(files) => Self::Files(
                 files
                     .files
@@ -232,9 +313,15 @@ impl From<content::Content> for Content {
                     .flat_map(Attachment::from_harmony_photo)
                     .collect(),
             ),
-            content::Content::InviteRejected(_) => todo!(),
-            content::Content::InviteAccepted(_) => todo!(),
-            content::Content::RoomUpgradedToGuild(_) => todo!(),
+            content::Content::InviteRejected(content::InviteRejected { invitee_id, inviter_id }) => {
+                Self::System(SystemMessage::InviteRejected { invitee_id, inviter_id })
+            }
+            content::Content::InviteAccepted(content::InviteAccepted { invitee_id, inviter_id }) => {
+                Self::System(SystemMessage::InviteAccepted { invitee_id, inviter_id })
+            }
+            content::Content::RoomUpgradedToGuild(content::RoomUpgradedToGuild { upgraded_by }) => {
+                Self::System(SystemMessage::RoomUpgradedToGuild { upgraded_by })
+            }
         }
     }
 }
@@ -245,6 +332,56 @@ impl Default for Content {
     }
 }
 
+/// A reaction to a message, with either a custom emote or a unicode emoji.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reaction {
+    /// Image ID of the emote, which identifies the reaction. Unicode reactions use the emoji itself.
+    pub image_id: SmolStr,
+    pub name: SmolStr,
+    pub count: u32,
+    /// Whether the current user reacted with this, or `None` if it isn't known: the homeserver
+    /// doesn't say who reacted, so this is only known for reactions the user changed.
+    pub reacted: Option<bool>,
+}
+
+impl Reaction {
+    pub fn is_unicode(&self) -> bool {
+        self.image_id == self.name
+    }
+}
+
+impl From<chat::Reaction> for Reaction {
+    fn from(reaction: chat::Reaction) -> Self {
+        let emote = reaction.emote.unwrap_or_default();
+        Reaction {
+            image_id: emote.image_id.into(),
+            name: emote.name.into(),
+            count: reaction.count,
+            reacted: None,
+        }
+    }
+}
+
+impl From<Reaction> for chat::Reaction {
+    fn from(reaction: Reaction) -> Self {
+        chat::Reaction {
+            emote: Some(Emote {
+                image_id: reaction.image_id.into(),
+                name: reaction.name.into(),
+            }),
+            count: reaction.count,
+        }
+    }
+}
+
+/// A version of a message's text that was replaced by an edit.
+#[derive(Debug, Clone)]
+pub struct Revision {
+    pub text: FormattedText,
+    /// When this version was sent or, if it was an edit itself, when it was edited.
+    pub written_at: NaiveDateTime,
+}
+
 #[derive(Debug, Clone)]
 pub struct Message {
     pub content: Content,
@@ -253,9 +390,91 @@ pub struct Message {
     pub overrides: Option<Override>,
     pub being_edited: Option<String>,
     pub reply_to: Option<u64>,
+    pub reactions: Vec<Reaction>,
+    /// When the message was last edited.
+    pub edited_at: Option<NaiveDateTime>,
+    /// Versions of the text seen before the current one, oldest first.
+    pub revisions: Vec<Revision>,
 }
 
 impl Message {
+    /// Replaces the text of the message, keeping the current one as a revision.
+    pub fn edit(&mut self, text: FormattedText, edited_at: NaiveDateTime) {
+        if let Content::Text(old) = std::mem::replace(&mut self.content, Content::Text(text)) {
+            self.revisions.push(Revision {
+                text: old,
+                written_at: self.edited_at.unwrap_or(self.timestamp),
+            });
+        }
+        self.edited_at = Some(edited_at);
+    }
+
+    /// Every known version of the text with when it was written, oldest first, ending with the current one.
+    pub fn text_history(&self) -> Vec<(&FormattedText, NaiveDateTime)> {
+        let mut history = self
+            .revisions
+            .iter()
+            .map(|revision| (&revision.text, revision.written_at))
+            .collect::<Vec<_>>();
+        if let Content::Text(text) = &self.content {
+            history.push((text, self.edited_at.unwrap_or(self.timestamp)));
+        }
+        history
+    }
+
+    /// Replaces a reaction with its new state from the homeserver, keeping whether we reacted.
+    pub fn update_reaction(&mut self, mut reaction: Reaction) {
+        match self.reactions.iter().position(|r| r.image_id == reaction.image_id) {
+            Some(pos) if reaction.count == 0 => {
+                self.reactions.remove(pos);
+            }
+            Some(pos) => {
+                reaction.reacted = self.reactions[pos].reacted;
+                self.reactions[pos] = reaction;
+            }
+            None if reaction.count > 0 => self.reactions.push(reaction),
+            None => {}
+        }
+    }
+
+    /// Adds or removes our reaction, returning whether we reacted before.
+    ///
+    /// Returns `None` without changing anything if it isn't known whether we reacted.
+    pub fn toggle_reaction(&mut self, image_id: &str, name: &str) -> Option<bool> {
+        match self.reactions.iter().position(|r| r.image_id == image_id) {
+            Some(pos) => {
+                let reaction = &mut self.reactions[pos];
+                let was_reacted = reaction.reacted?;
+                reaction.reacted = Some(!was_reacted);
+                if was_reacted {
+                    reaction.count = reaction.count.saturating_sub(1);
+                    if reaction.count == 0 {
+                        self.reactions.remove(pos);
+                    }
+                } else {
+                    reaction.count += 1;
+                }
+                Some(was_reacted)
+            }
+            None => {
+                self.reactions.push(Reaction {
+                    image_id: image_id.into(),
+                    name: name.into(),
+                    count: 1,
+                    reacted: Some(true),
+                });
+                Some(false)
+            }
+        }
+    }
+
+    /// Records whether we reacted with an emote, once the homeserver told us.
+    pub fn set_reacted(&mut self, image_id: &str, reacted: bool) {
+        if let Some(reaction) = self.reactions.iter_mut().find(|r| r.image_id == image_id) {
+            reaction.reacted = Some(reacted);
+        }
+    }
+
     pub fn post_process(&self, post: &mut Vec<PostProcessEvent>, guild_id: u64, channel_id: u64) {
         if let Some(message_id) = self.reply_to.filter(|id| id != &0) {
             post.push(PostProcessEvent::FetchMessage {
@@ -264,6 +483,13 @@ impl Message {
                 message_id,
             });
         }
+        for reaction in self.reactions.iter().filter(|r| !r.is_unicode()) {
+            post.push(PostProcessEvent::FetchThumbnail(Attachment {
+                kind: "image".into(),
+                name: "emote".into(),
+                ..Attachment::new_unknown(FileId::Id(reaction.image_id.to_string()))
+            }));
+        }
         if let Some(id) = self.overrides.as_ref().and_then(|ov| ov.avatar_url.clone()) {
             post.push(PostProcessEvent::FetchThumbnail(Attachment {
                 kind: "image".into(),
@@ -282,16 +508,21 @@ impl Message {
             Content::Embeds(embeds) => {
                 post_heading(post, embeds);
             }
+            Content::System(system) => {
+                post.extend(system.user_ids().into_iter().map(PostProcessEvent::FetchProfile));
+            }
             Content::Text(text) => {
                 post.extend(
-                    text.split_whitespace()
+                    text.text
+                        .split_whitespace()
                         .map(|a| a.trim_end_matches('>').trim_start_matches('<').parse::<Uri>())
                         .flatten()
                         .filter(|url| matches!(url.scheme_str(), Some("http" | "https")))
                         .map(PostProcessEvent::FetchLinkMetadata),
                 );
                 post.extend(
-                    text.as_str()
+                    text.text
+                        .as_str()
                         .parse_md_custom(HarmonyToken::parse)
                         .into_iter()
                         .flat_map(|tok| {
@@ -323,6 +554,9 @@ impl Default for Message {
             overrides: None,
             being_edited: None,
             reply_to: None,
+            reactions: Vec::new(),
+            edited_at: None,
+            revisions: Vec::new(),
         }
     }
 }
@@ -369,6 +603,25 @@ impl From<chat::Embed> for Embed {
     }
 }
 
+impl From<Message> for HarmonyMessage {
+    fn from(message: Message) -> Self {
+        HarmonyMessage {
+            author_id: message.sender,
+            created_at: message.timestamp.timestamp() as u64,
+            in_reply_to: message.reply_to,
+            overrides: message.overrides.map(Into::into),
+            content: Some(chat::Content {
+                content: Some(message.content.into()),
+            }),
+            reactions: message.reactions.into_iter().map(Into::into).collect(),
+            edited_at: message.edited_at.map(|edited_at| edited_at.timestamp() as u64),
+            // Everything read back in `From<HarmonyMessage>` is set above; revisions can't be part of a
+            // Harmony message, the message cache keeps them next to it
+            ..Default::default()
+        }
+    }
+}
+
 impl From<HarmonyMessage> for Message {
     fn from(message: HarmonyMessage) -> Self {
         Message {
@@ -382,6 +635,11 @@ impl From<HarmonyMessage> for Message {
             timestamp: { NaiveDateTime::from_timestamp(message.created_at as i64, 0) },
             overrides: message.overrides.map(From::from),
             being_edited: None,
+            reactions: message.reactions.into_iter().map(From::from).collect(),
+            edited_at: message
+                .edited_at
+                .map(|edited_at| NaiveDateTime::from_timestamp(edited_at as i64, 0)),
+            revisions: Vec::new(),
         }
     }
 }
@@ -0,0 +1,152 @@
+//! Messages that were sent but not acknowledged by the homeserver yet.
+//!
+//! The outbox is written to disk whenever it changes, so unsent messages survive a restart
+//! and are sent again the next time the client starts.
+
+use harmony_rust_sdk::api::{chat::Message as HarmonyMessage, exports::prost::Message as _};
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    error::{ClientError, ClientResult},
+    message::Message,
+};
+
+/// How many times sending a message is tried before it is marked as failed.
+pub const MAX_SEND_ATTEMPTS: u32 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SendState {
+    /// The message is being sent, after failing `attempt` times before.
+    Sending { attempt: u32 },
+    /// Sending failed too many times, the user has to retry or discard the message.
+    Failed,
+}
+
+#[derive(Debug, Clone)]
+pub struct OutboxEntry {
+    pub guild_id: u64,
+    pub channel_id: u64,
+    pub message: Message,
+    pub state: SendState,
+}
+
+/// Unacknowledged messages keyed by their transaction ID, in the order they were sent.
+#[derive(Debug, Clone, Default)]
+pub struct Outbox {
+    entries: IndexMap<u64, OutboxEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedEntry {
+    transaction_id: u64,
+    guild_id: u64,
+    channel_id: u64,
+    failed: bool,
+    /// Base64 encoded protobuf message.
+    message: String,
+}
+
+impl Outbox {
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, transaction_id: u64) -> Option<&OutboxEntry> {
+        self.entries.get(&transaction_id)
+    }
+
+    pub fn state(&self, transaction_id: u64) -> Option<SendState> {
+        self.get(transaction_id).map(|entry| entry.state)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (u64, &OutboxEntry)> + '_ {
+        self.entries.iter().map(|(id, entry)| (*id, entry))
+    }
+
+    /// Adds a message to the outbox, or marks it as being sent again if it's already there.
+    pub fn push(&mut self, transaction_id: u64, guild_id: u64, channel_id: u64, message: Message) {
+        match self.entries.get_mut(&transaction_id) {
+            Some(entry) => {
+                if entry.state == SendState::Failed {
+                    entry.state = SendState::Sending { attempt: 0 };
+                }
+            }
+            None => {
+                self.entries.insert(
+                    transaction_id,
+                    OutboxEntry {
+                        guild_id,
+                        channel_id,
+                        message,
+                        state: SendState::Sending { attempt: 0 },
+                    },
+                );
+            }
+        }
+    }
+
+    pub fn remove(&mut self, transaction_id: u64) -> Option<OutboxEntry> {
+        self.entries.shift_remove(&transaction_id)
+    }
+
+    /// Removes every message for a guild, or only for one of its channels.
+    pub fn remove_in(&mut self, guild_id: u64, channel_id: Option<u64>) {
+        self.entries
+            .retain(|_, entry| entry.guild_id != guild_id || channel_id.map_or(false, |id| entry.channel_id != id));
+    }
+
+    /// Records a failed attempt to send a message, returning its new state.
+    pub fn send_failed(&mut self, transaction_id: u64) -> Option<SendState> {
+        let entry = self.entries.get_mut(&transaction_id)?;
+        entry.state = match entry.state {
+            SendState::Sending { attempt } if attempt + 1 < MAX_SEND_ATTEMPTS => {
+                SendState::Sending { attempt: attempt + 1 }
+            }
+            _ => SendState::Failed,
+        };
+        Some(entry.state)
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let saved = self
+            .entries
+            .iter()
+            .map(|(transaction_id, entry)| SavedEntry {
+                transaction_id: *transaction_id,
+                guild_id: entry.guild_id,
+                channel_id: entry.channel_id,
+                failed: entry.state == SendState::Failed,
+                message: base64::encode(HarmonyMessage::from(entry.message.clone()).encode_to_vec()),
+            })
+            .collect::<Vec<_>>();
+        // Serializing plain structs to a Vec can't fail
+        serde_json::to_vec(&saved).unwrap()
+    }
+
+    /// Decodes an outbox created by [`Outbox::encode`].
+    pub fn decode(raw: &[u8]) -> ClientResult<Self> {
+        let decode_err = |err: &dyn std::fmt::Display| ClientError::Custom(format!("couldn't decode outbox: {}", err));
+
+        let saved: Vec<SavedEntry> = serde_json::from_slice(raw).map_err(|err| decode_err(&err))?;
+        let mut entries = IndexMap::with_capacity(saved.len());
+        for entry in saved {
+            let data = base64::decode(&entry.message).map_err(|err| decode_err(&err))?;
+            let message = HarmonyMessage::decode(data.as_slice()).map_err(|err| decode_err(&err))?;
+            entries.insert(
+                entry.transaction_id,
+                OutboxEntry {
+                    guild_id: entry.guild_id,
+                    channel_id: entry.channel_id,
+                    message: message.into(),
+                    state: if entry.failed {
+                        SendState::Failed
+                    } else {
+                        SendState::Sending { attempt: 0 }
+                    },
+                },
+            );
+        }
+        Ok(Self { entries })
+    }
+}
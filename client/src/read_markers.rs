@@ -0,0 +1,97 @@
+//! Tracks which messages the user has read in each channel.
+//!
+//! Read markers are saved to disk so unread and mention counts survive a restart.
+
+use ahash::AHashMap;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    error::{ClientError, ClientResult},
+    guild::Guilds,
+};
+
+/// What the user has read in a channel.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReadMarker {
+    /// The newest message the user has read.
+    pub last_read: Option<u64>,
+    /// How many messages arrived after [`ReadMarker::last_read`].
+    pub unread_count: u32,
+    /// How many of the unread messages mention the user.
+    pub mention_count: u32,
+}
+
+impl ReadMarker {
+    pub fn has_unread(&self) -> bool {
+        self.unread_count > 0
+    }
+
+    /// Marks everything up to (and including) `message_id` as read.
+    pub fn read_up_to(&mut self, message_id: Option<u64>) {
+        if message_id.is_some() {
+            self.last_read = message_id;
+        }
+        self.unread_count = 0;
+        self.mention_count = 0;
+    }
+
+    /// Counts a message that arrived after the last read one.
+    pub fn add_unread(&mut self, mentions_user: bool) {
+        self.unread_count += 1;
+        if mentions_user {
+            self.mention_count += 1;
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedMarker {
+    guild_id: u64,
+    channel_id: u64,
+    last_read: Option<u64>,
+    unread_count: u32,
+    mention_count: u32,
+}
+
+/// Encodes the read markers of every channel in `guilds`, plus `pending` markers for channels
+/// that weren't added to the client yet.
+pub fn encode(guilds: &Guilds, pending: &AHashMap<(u64, u64), ReadMarker>) -> Vec<u8> {
+    let from_guilds = guilds.iter().flat_map(|(guild_id, guild)| {
+        guild
+            .channels
+            .iter()
+            .map(move |(channel_id, channel)| ((*guild_id, *channel_id), &channel.read_marker))
+    });
+    let saved = from_guilds
+        .chain(pending.iter().map(|(ids, marker)| (*ids, marker)))
+        .filter(|(_, marker)| marker.last_read.is_some() || marker.has_unread())
+        .map(|((guild_id, channel_id), marker)| SavedMarker {
+            guild_id,
+            channel_id,
+            last_read: marker.last_read,
+            unread_count: marker.unread_count,
+            mention_count: marker.mention_count,
+        })
+        .collect::<Vec<_>>();
+    // Serializing plain structs to a Vec can't fail
+    serde_json::to_vec(&saved).unwrap()
+}
+
+/// Decodes read markers created by [`encode`], keyed by guild ID and channel ID.
+pub fn decode(raw: &[u8]) -> ClientResult<AHashMap<(u64, u64), ReadMarker>> {
+    let saved: Vec<SavedMarker> = serde_json::from_slice(raw)
+        .map_err(|err| ClientError::Custom(format!("couldn't decode read markers: {}", err)))?;
+    Ok(saved
+        .into_iter()
+        .map(|marker| {
+            (
+                (marker.guild_id, marker.channel_id),
+                ReadMarker {
+                    last_read: marker.last_read,
+                    unread_count: marker.unread_count,
+                    mention_count: marker.mention_count,
+                },
+            )
+        })
+        .collect())
+}
@@ -0,0 +1,192 @@
+use ahash::AHashSet;
+use chrono::{NaiveDate, NaiveDateTime};
+use smol_str::SmolStr;
+
+use super::{
+    message::{Content, Message},
+    AHashMap,
+};
+
+/// (guild id, channel id, message id)
+type MessageKey = (u64, u64, u64);
+
+/// Filters that narrow down search results.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SearchFilter {
+    pub from_user: Option<u64>,
+    /// (guild id, channel id)
+    pub in_channel: Option<(u64, u64)>,
+    /// Inclusive start date.
+    pub since: Option<NaiveDate>,
+    /// Inclusive end date.
+    pub until: Option<NaiveDate>,
+}
+
+impl SearchFilter {
+    fn matches(&self, key: &MessageKey, message: &IndexedMessage) -> bool {
+        let date = message.timestamp.date();
+
+        self.from_user.map_or(true, |id| message.sender == id)
+            && self
+                .in_channel
+                .map_or(true, |(guild_id, channel_id)| key.0 == guild_id && key.1 == channel_id)
+            && self.since.map_or(true, |since| date >= since)
+            && self.until.map_or(true, |until| date <= until)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SearchHit {
+    pub guild_id: u64,
+    pub channel_id: u64,
+    pub message_id: u64,
+    pub sender: u64,
+    pub timestamp: NaiveDateTime,
+    pub text: String,
+}
+
+#[derive(Debug, Clone)]
+struct IndexedMessage {
+    sender: u64,
+    timestamp: NaiveDateTime,
+    text: String,
+}
+
+/// An in-memory inverted index over every acknowledged message the client has seen.
+#[derive(Debug, Default, Clone)]
+pub struct SearchIndex {
+    messages: AHashMap<MessageKey, IndexedMessage>,
+    terms: AHashMap<SmolStr, AHashSet<MessageKey>>,
+}
+
+impl SearchIndex {
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    /// Adds a message to the index, replacing it if it was already indexed.
+    pub fn index_message(&mut self, guild_id: u64, channel_id: u64, message_id: u64, message: &Message) {
+        let key = (guild_id, channel_id, message_id);
+        self.remove_message(guild_id, channel_id, message_id);
+
+        let text = searchable_text(&message.content);
+        for term in tokenize(&text) {
+            self.terms.entry(term).or_default().insert(key);
+        }
+        self.messages.insert(
+            key,
+            IndexedMessage {
+                sender: message.sender,
+                timestamp: message.timestamp,
+                text,
+            },
+        );
+    }
+
+    pub fn remove_message(&mut self, guild_id: u64, channel_id: u64, message_id: u64) {
+        let key = (guild_id, channel_id, message_id);
+        if let Some(indexed) = self.messages.remove(&key) {
+            for term in tokenize(&indexed.text) {
+                if let Some(keys) = self.terms.get_mut(&term) {
+                    keys.remove(&key);
+                    if keys.is_empty() {
+                        self.terms.remove(&term);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Removes every message of a channel, or of a whole guild if `channel_id` is `None`.
+    pub fn remove_messages_in(&mut self, guild_id: u64, channel_id: Option<u64>) {
+        let keys = self
+            .messages
+            .keys()
+            .filter(|key| key.0 == guild_id && channel_id.map_or(true, |id| key.1 == id))
+            .copied()
+            .collect::<Vec<_>>();
+
+        for (guild_id, channel_id, message_id) in keys {
+            self.remove_message(guild_id, channel_id, message_id);
+        }
+    }
+
+    /// Returns messages that contain every term of the query, newest first.
+    ///
+    /// The last term of the query is matched as a prefix, so results update while typing.
+    /// An empty query matches every message that passes the filter.
+    pub fn search(&self, query: &str, filter: &SearchFilter, limit: usize) -> Vec<SearchHit> {
+        let query_terms = tokenize(query).collect::<Vec<_>>();
+
+        let mut candidates: Option<AHashSet<MessageKey>> = None;
+        for (index, query_term) in query_terms.iter().enumerate() {
+            let is_last = index == query_terms.len() - 1;
+            let mut matching = AHashSet::new();
+            if is_last {
+                for (term, keys) in self.terms.iter() {
+                    if term.starts_with(query_term.as_str()) {
+                        matching.extend(keys.iter().copied());
+                    }
+                }
+            } else if let Some(keys) = self.terms.get(query_term) {
+                matching.extend(keys.iter().copied());
+            }
+
+            candidates = Some(match candidates {
+                Some(candidates) => candidates.intersection(&matching).copied().collect(),
+                None => matching,
+            });
+        }
+
+        let mut hits = match candidates {
+            Some(keys) => keys
+                .into_iter()
+                .flat_map(|key| self.messages.get(&key).map(|message| (key, message)))
+                .filter(|(key, message)| filter.matches(key, message))
+                .collect::<Vec<_>>(),
+            None => self
+                .messages
+                .iter()
+                .map(|(key, message)| (*key, message))
+                .filter(|(key, message)| filter.matches(key, message))
+                .collect::<Vec<_>>(),
+        };
+
+        hits.sort_unstable_by(|(a_key, a), (b_key, b)| b.timestamp.cmp(&a.timestamp).then(b_key.2.cmp(&a_key.2)));
+        hits.into_iter()
+            .take(limit)
+            .map(|((guild_id, channel_id, message_id), message)| SearchHit {
+                guild_id,
+                channel_id,
+                message_id,
+                sender: message.sender,
+                timestamp: message.timestamp,
+                text: message.text.clone(),
+            })
+            .collect()
+    }
+}
+
+/// Text that is searchable for some content: text, attachment names and embed titles.
+fn searchable_text(content: &Content) -> String {
+    match content {
+        Content::Text(text) => text.text.clone(),
+        Content::Files(attachments) => attachments
+            .iter()
+            .map(|a| a.name.as_str())
+            .collect::<Vec<_>>()
+            .join(" "),
+        Content::Embeds(embeds) => embeds.iter().map(|e| e.title.as_str()).collect::<Vec<_>>().join(" "),
+        Content::System(_) => String::new(),
+    }
+}
+
+fn tokenize(text: &str) -> impl Iterator<Item = SmolStr> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|term| !term.is_empty())
+        .map(|term| term.to_lowercase().into())
+}
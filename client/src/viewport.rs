@@ -0,0 +1,219 @@
+//! Which messages of a channel's history are in view, so only those have to be laid out.
+
+use std::ops::Range;
+
+use ahash::AHashMap;
+
+use super::{
+    history::Messages,
+    message::{Message, MessageId},
+};
+
+/// How far past the edges of the view messages are still laid out, in pixels, so scrolling a bit
+/// doesn't show empty space before the next layout.
+pub const OVERSCAN: f32 = 600.0;
+
+/// How close to the end the view has to be, in pixels, to follow the newest messages.
+const BOTTOM_SLACK: f32 = 2.0;
+
+/// Where the event history of a channel is scrolled to.
+///
+/// Positions are in pixels, computed from estimated heights of the messages (see [`Heights`]):
+/// only the messages in view are laid out, with empty space standing for the ones around them. The
+/// view is anchored to a message instead of a position, so it stays on the same message when older
+/// ones are added before.
+#[derive(Debug, Clone)]
+pub struct Viewport {
+    /// The message at the top of the view, and how far below its top the view starts.
+    anchor: Option<(MessageId, f32)>,
+    /// Whether the view follows the newest messages.
+    at_bottom: bool,
+    /// How many times the view was moved other than by scrolling it.
+    moves: u64,
+}
+
+impl Default for Viewport {
+    fn default() -> Self {
+        Self {
+            anchor: None,
+            at_bottom: true,
+            moves: 0,
+        }
+    }
+}
+
+/// Estimated heights of the messages of a channel, kept between layouts.
+///
+/// Messages are only estimated again once they change, or all of them when the width of the view
+/// does, so laying out and scrolling don't go through every message.
+#[derive(Debug, Clone)]
+pub struct Heights {
+    width: Option<f32>,
+    /// The revision of the messages the heights are for, see [`Messages::revision`].
+    revision: Option<u64>,
+    /// Height of each message, with the revision of the message it was estimated at.
+    estimated: AHashMap<MessageId, (u64, f32)>,
+    /// Where each message starts, followed by where the last one ends.
+    tops: Vec<f32>,
+    /// How many times the heights changed.
+    version: u64,
+}
+
+impl Default for Heights {
+    fn default() -> Self {
+        Self {
+            width: None,
+            revision: None,
+            estimated: AHashMap::new(),
+            tops: vec![0.0],
+            version: 0,
+        }
+    }
+}
+
+impl Heights {
+    /// Brings the heights up to date with `messages`, laid out `width` pixels wide.
+    ///
+    /// `estimate` gives the height of a message that changed, and `spacing` the space above a
+    /// message, given the one before it.
+    pub fn update(
+        &mut self,
+        messages: &Messages,
+        width: f32,
+        mut estimate: impl FnMut(&Message) -> f32,
+        mut spacing: impl FnMut(Option<&Message>, &Message) -> f32,
+    ) {
+        if self.width == Some(width) && self.revision == Some(messages.revision()) {
+            return;
+        }
+        if self.width != Some(width) {
+            self.estimated.clear();
+            self.width = Some(width);
+        }
+
+        let mut estimated = AHashMap::with_capacity(messages.len());
+        let mut top = 0.0;
+        let mut previous = None;
+        self.tops.clear();
+        self.tops.push(top);
+        for (id, message) in messages {
+            let revision = messages.revision_of(id);
+            let height = match self.estimated.get(id) {
+                Some((estimated_at, height)) if *estimated_at == revision => *height,
+                _ => estimate(message),
+            };
+            estimated.insert(*id, (revision, height));
+            top += spacing(previous, message) + height;
+            self.tops.push(top);
+            previous = Some(message);
+        }
+        self.estimated = estimated;
+        self.revision = Some(messages.revision());
+        self.version += 1;
+    }
+
+    /// Changes whenever the heights do.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    fn total(&self) -> f32 {
+        self.tops[self.tops.len() - 1]
+    }
+
+    /// Number of messages there are heights for.
+    fn len(&self) -> usize {
+        self.tops.len() - 1
+    }
+}
+
+/// Messages to lay out for a view, see [`Viewport::visible`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Visible {
+    /// Indices of the messages to lay out.
+    pub range: Range<usize>,
+    /// Height of the messages before the range.
+    pub space_before: f32,
+    /// Height of the messages after the range.
+    pub space_after: f32,
+    /// Where the view is scrolled to, as a fraction of how far it can be scrolled.
+    pub scroll: f32,
+}
+
+impl Viewport {
+    /// Whether the view follows the newest messages.
+    pub fn is_at_bottom(&self) -> bool {
+        self.at_bottom
+    }
+
+    /// The message at the top of the view, unless the view follows the newest messages.
+    pub fn anchor(&self) -> Option<MessageId> {
+        self.anchor.filter(|_| !self.at_bottom).map(|(id, _)| id)
+    }
+
+    /// How many times the view was moved other than by scrolling it, like with
+    /// [`Viewport::scroll_to`]. The scroll position only has to be set when this or the heights of
+    /// the messages change, so it doesn't fight the user scrolling.
+    pub fn moves(&self) -> u64 {
+        self.moves
+    }
+
+    /// Follows the newest messages again.
+    pub fn scroll_to_bottom(&mut self) {
+        self.anchor = None;
+        self.at_bottom = true;
+        self.moves += 1;
+    }
+
+    /// Puts a message at the top of the view.
+    pub fn scroll_to(&mut self, message_id: MessageId) {
+        self.anchor = Some((message_id, 0.0));
+        self.at_bottom = false;
+        self.moves += 1;
+    }
+
+    /// Position of the top of the view.
+    fn offset(&self, messages: &Messages, heights: &Heights, view_height: f32) -> f32 {
+        let max_offset = (heights.total() - view_height).max(0.0);
+        if self.at_bottom {
+            return max_offset;
+        }
+        self.anchor
+            .and_then(|(id, below)| Some(heights.tops.get(messages.get_index_of(&id)?)? + below))
+            .map_or(max_offset, |offset| offset.clamp(0.0, max_offset))
+    }
+
+    /// Moves the view after it was scrolled to `scroll`, a fraction of how far it can be scrolled.
+    ///
+    /// `heights` are the estimated heights of `messages`, and `view_height` the height of the view.
+    pub fn scrolled(&mut self, messages: &Messages, heights: &Heights, view_height: f32, scroll: f32) {
+        let tops = &heights.tops;
+        let max_offset = (heights.total() - view_height).max(0.0);
+        let offset = scroll.clamp(0.0, 1.0) * max_offset;
+
+        self.at_bottom = offset >= max_offset - BOTTOM_SLACK;
+        let index = tops[1..].partition_point(|bottom| *bottom <= offset);
+        self.anchor = messages.get_index(index).map(|(id, _)| (*id, offset - tops[index]));
+    }
+
+    /// The messages to lay out for the view.
+    ///
+    /// `heights` are the estimated heights of `messages`, and `view_height` the height of the view.
+    pub fn visible(&self, messages: &Messages, heights: &Heights, view_height: f32) -> Visible {
+        let tops = &heights.tops;
+        let total = heights.total();
+        let max_offset = (total - view_height).max(0.0);
+        let offset = self.offset(messages, heights, view_height);
+
+        let start = tops[1..].partition_point(|bottom| *bottom <= offset - OVERSCAN);
+        let end = tops[..heights.len()]
+            .partition_point(|top| *top < offset + view_height + OVERSCAN)
+            .max(start);
+        Visible {
+            range: start..end,
+            space_before: tops[start],
+            space_after: total - tops[end],
+            scroll: if max_offset > 0.0 { offset / max_offset } else { 1.0 },
+        }
+    }
+}
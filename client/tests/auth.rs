@@ -0,0 +1,82 @@
+mod mock;
+
+use client::{
+    harmony_rust_sdk::{
+        api::auth::{auth_step::Step, next_step_request::form_fields::Field},
+        client::{api::auth::AuthStepResponse, AuthStatus},
+    },
+    Client,
+};
+use mock::MockHomeserver;
+
+#[tokio::test]
+async fn login_walks_auth_steps() {
+    let mock = MockHomeserver::start().await;
+    let client = Client::new(mock.url(), None, mock::temp_content_store()).await.unwrap();
+    let inner = client.inner_arc();
+
+    inner.begin_auth().await.unwrap();
+    let step = inner
+        .next_auth_step(AuthStepResponse::Initial)
+        .await
+        .unwrap()
+        .and_then(|s| s.step)
+        .and_then(|s| s.step);
+    match step {
+        Some(Step::Choice(choice)) => assert_eq!(choice.options, vec!["login".to_string()]),
+        step => panic!("expected a choice, got {:?}", step),
+    }
+
+    let step = inner
+        .next_auth_step(AuthStepResponse::Choice("login".to_string()))
+        .await
+        .unwrap()
+        .and_then(|s| s.step)
+        .and_then(|s| s.step);
+    match step {
+        Some(Step::Form(form)) => assert_eq!(form.fields.len(), 2),
+        step => panic!("expected a form, got {:?}", step),
+    }
+
+    inner
+        .next_auth_step(AuthStepResponse::form(vec![
+            Field::String(mock::EMAIL.to_string()),
+            Field::Bytes(mock::PASSWORD.as_bytes().to_vec()),
+        ]))
+        .await
+        .unwrap();
+
+    match client.auth_status() {
+        AuthStatus::Complete(session) => {
+            assert_eq!(session.user_id, mock::USER_ID);
+            assert_eq!(session.session_token, mock::SESSION_TOKEN);
+        }
+        status => panic!("expected auth to be complete, got {:?}", status),
+    }
+}
+
+#[tokio::test]
+async fn wrong_password_restarts_auth() {
+    let mock = MockHomeserver::start().await;
+    let client = Client::new(mock.url(), None, mock::temp_content_store()).await.unwrap();
+    let inner = client.inner_arc();
+
+    inner.begin_auth().await.unwrap();
+    inner.next_auth_step(AuthStepResponse::Initial).await.unwrap();
+    inner
+        .next_auth_step(AuthStepResponse::Choice("login".to_string()))
+        .await
+        .unwrap();
+    let step = inner
+        .next_auth_step(AuthStepResponse::form(vec![
+            Field::String(mock::EMAIL.to_string()),
+            Field::Bytes(b"wrong".to_vec()),
+        ]))
+        .await
+        .unwrap()
+        .and_then(|s| s.step)
+        .and_then(|s| s.step);
+
+    assert!(matches!(step, Some(Step::Choice(_))));
+    assert!(!matches!(client.auth_status(), AuthStatus::Complete(_)));
+}
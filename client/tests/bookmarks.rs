@@ -0,0 +1,45 @@
+mod mock;
+
+use mock::MockHomeserver;
+
+const GUILD_ID: u64 = 1;
+const CHANNEL_ID: u64 = 2;
+
+#[tokio::test]
+async fn bookmarks_survive_restart() {
+    let mock = MockHomeserver::start().await;
+    mock.add_guild(GUILD_ID, CHANNEL_ID, 0);
+    let content_store = mock::temp_content_store();
+
+    let mut client = mock.logged_in_client_with(content_store.clone()).await;
+    for &(message_id, text) in &[(10, "remember this"), (11, "and this")] {
+        client.process_event(mock::message_sent(
+            GUILD_ID,
+            CHANNEL_ID,
+            message_id,
+            mock::text_message(text, message_id),
+        ));
+    }
+
+    assert!(client.add_bookmark(GUILD_ID, CHANNEL_ID, 10));
+    assert!(
+        !client.add_bookmark(GUILD_ID, CHANNEL_ID, 10),
+        "a message is only saved once"
+    );
+    assert!(
+        !client.add_bookmark(GUILD_ID, CHANNEL_ID, 99),
+        "unknown messages can't be saved"
+    );
+    assert!(client.add_bookmark(GUILD_ID, CHANNEL_ID, 11));
+    client.set_bookmark_note(GUILD_ID, CHANNEL_ID, 10, "for later".to_string());
+    client.remove_bookmark(GUILD_ID, CHANNEL_ID, 11);
+    drop(client);
+
+    let mut client = mock.logged_in_client_with(content_store).await;
+    client.load_bookmarks().unwrap();
+    let bookmarks = client.bookmarks.iter().collect::<Vec<_>>();
+    assert_eq!(bookmarks.len(), 1);
+    assert_eq!(bookmarks[0].message_id, 10);
+    assert_eq!(bookmarks[0].snapshot, "remember this");
+    assert_eq!(bookmarks[0].note, "for later");
+}
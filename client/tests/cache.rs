@@ -0,0 +1,93 @@
+mod mock;
+
+use chrono::NaiveDateTime;
+use client::{
+    cache::{decode_messages, encode_messages},
+    harmony_rust_sdk::api::chat::FormattedText,
+    history::Messages,
+    message::{Message, MessageId},
+};
+use mock::MockHomeserver;
+
+const GUILD_ID: u64 = 1;
+const CHANNEL_ID: u64 = 2;
+
+#[test]
+fn unsent_messages_arent_cached() {
+    let mut messages = Messages::default();
+    messages.merge(mock::messages(1..10), None, None);
+    messages.insert(MessageId::Unack(1), Message::default());
+
+    let cached = decode_messages(&encode_messages(&messages)).unwrap();
+    assert_eq!(
+        cached.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+        (1..10).rev().collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn history_is_cached_up_to_a_gap() {
+    let mut messages = Messages::default();
+    messages.merge(mock::messages(1..5), None, None);
+    messages.merge(mock::messages(10..15), None, None);
+
+    let cached = decode_messages(&encode_messages(&messages)).unwrap();
+    assert_eq!(cached.len(), 5);
+}
+
+#[test]
+fn edited_messages_keep_their_earlier_versions() {
+    let mut messages = Messages::default();
+    let mut message = mock::message(mock::USER_ID, 1, "first");
+    message.edit(
+        FormattedText::default().with_text("second".to_string()),
+        NaiveDateTime::from_timestamp(2, 0),
+    );
+    messages.merge(vec![(1, message)], None, None);
+
+    let cached = decode_messages(&encode_messages(&messages)).unwrap();
+    let (_, message) = &cached[0];
+    assert_eq!(message.edited_at, Some(NaiveDateTime::from_timestamp(2, 0)));
+    let history = message
+        .text_history()
+        .into_iter()
+        .map(|(text, written_at)| (text.text.as_str(), written_at.timestamp()))
+        .collect::<Vec<_>>();
+    assert_eq!(history, [("first", 1), ("second", 2)]);
+}
+
+#[tokio::test]
+async fn cached_messages_dont_reach_either_end_of_history() {
+    let mock = MockHomeserver::start().await;
+    mock.add_guild(GUILD_ID, CHANNEL_ID, 10);
+    let mut client = mock.logged_in_client().await;
+
+    // The cache has the 5 oldest messages, newest first
+    let cached = mock.state.lock().unwrap().guilds[0].channels[0].messages[..5]
+        .iter()
+        .rev()
+        .map(|(id, message)| (*id, Message::from(message.clone())))
+        .collect::<Vec<_>>();
+    let newest_cached = cached[0].0;
+    client.process_cached_messages(GUILD_ID, CHANNEL_ID, cached);
+
+    let channel = client.get_channel(GUILD_ID, CHANNEL_ID).unwrap();
+    assert!(!channel.reached_top);
+    assert!(!channel.reached_bottom);
+    assert!(channel.messages.has_gap_after(newest_cached));
+    assert_eq!(channel.missing_after(4), Some(newest_cached));
+
+    let (newer, reached_end) = client
+        .get_messages_after_cmd(GUILD_ID, CHANNEL_ID, newest_cached)
+        .await
+        .unwrap();
+    assert!(reached_end);
+    client.process_messages_after(GUILD_ID, CHANNEL_ID, newest_cached, newer, reached_end);
+
+    let channel = client.get_channel(GUILD_ID, CHANNEL_ID).unwrap();
+    assert_eq!(channel.messages.len(), 10);
+    assert!(!channel.messages.has_gap_after(newest_cached));
+    assert!(channel.reached_bottom);
+    // Only fetching older history can tell there is none
+    assert!(!channel.reached_top);
+}
@@ -0,0 +1,50 @@
+use client::{
+    commands::{completions, parse, SlashCommand, COMMANDS},
+    harmony_rust_sdk::api::profile::UserStatus,
+};
+
+#[test]
+fn commands_are_parsed_with_their_arguments() {
+    assert!(parse("hello /me").is_none(), "only text starting with / is a command");
+    assert_eq!(
+        parse("/me waves").unwrap().unwrap(),
+        SlashCommand::Say("*waves*".to_string())
+    );
+    assert_eq!(
+        parse("/status dnd").unwrap().unwrap(),
+        SlashCommand::SetStatus(UserStatus::DoNotDisturb)
+    );
+    assert_eq!(
+        parse("/search  some words ").unwrap().unwrap(),
+        SlashCommand::Search("some words".to_string())
+    );
+    assert_eq!(
+        parse("//not a command").unwrap().unwrap(),
+        SlashCommand::Say("/not a command".to_string())
+    );
+}
+
+#[test]
+fn bad_commands_are_errors() {
+    assert!(parse("/frobnicate").unwrap().is_err(), "unknown commands aren't sent");
+    assert!(parse("/status away").unwrap().is_err());
+    assert!(parse("/nick").unwrap().is_err(), "missing argument");
+    assert!(parse("/leave now").unwrap().is_err(), "unexpected argument");
+    assert!(
+        parse("/topic announcements").unwrap().is_err(),
+        "topics aren't supported, so the channel isn't renamed instead"
+    );
+}
+
+#[test]
+fn completions_follow_the_command() {
+    assert_eq!(completions("/s", 0, "/s"), vec!["shrug", "status", "search"]);
+    assert_eq!(completions("/status i", 8, "i"), vec!["idle"]);
+    assert!(completions("/nick a", 6, "a").is_empty(), "nick has no argument values");
+    assert!(completions("s", 0, "s").is_empty());
+    assert_eq!(
+        completions("/", 0, "/").len(),
+        COMMANDS.len(),
+        "every command is offered"
+    );
+}
@@ -0,0 +1,75 @@
+mod mock;
+
+use client::{
+    drafts::{Draft, Drafts},
+    harmony_rust_sdk::client::api::rest::FileId,
+    message::Attachment,
+};
+use mock::MockHomeserver;
+
+const GUILD_ID: u64 = 1;
+const CHANNEL_ID: u64 = 2;
+
+#[tokio::test]
+async fn drafts_survive_restart() {
+    let mock = MockHomeserver::start().await;
+    mock.add_guild(GUILD_ID, CHANNEL_ID, 0);
+    let content_store = mock::temp_content_store();
+
+    let mut client = mock.logged_in_client_with(content_store.clone()).await;
+    let attachment = Attachment {
+        name: "cat.png".to_string(),
+        kind: "image/png".to_string(),
+        size: 1024,
+        ..Attachment::new_unknown(FileId::Id("cat".to_string()))
+    };
+    client.drafts.set(
+        GUILD_ID,
+        CHANNEL_ID,
+        Draft {
+            text: "half written".to_string(),
+            reply_to: Some(10),
+            attachments: vec![attachment],
+        },
+    );
+    client.save_drafts();
+    drop(client);
+
+    let mut client = mock.logged_in_client_with(content_store).await;
+    client.load_drafts().unwrap();
+    assert!(client.drafts.has_draft(GUILD_ID, CHANNEL_ID));
+    let draft = client.drafts.get(GUILD_ID, CHANNEL_ID).unwrap();
+    assert_eq!(draft.text, "half written");
+    assert_eq!(draft.reply_to, Some(10));
+    assert_eq!(draft.attachments.len(), 1);
+    assert_eq!(draft.attachments[0].name, "cat.png");
+    assert_eq!(draft.attachments[0].size, 1024);
+}
+
+#[test]
+fn empty_drafts_are_removed() {
+    let mut drafts = Drafts::default();
+    drafts.set(
+        GUILD_ID,
+        CHANNEL_ID,
+        Draft {
+            text: "hello".to_string(),
+            ..Default::default()
+        },
+    );
+    assert!(drafts.has_draft(GUILD_ID, CHANNEL_ID));
+
+    drafts.set(
+        GUILD_ID,
+        CHANNEL_ID,
+        Draft {
+            text: "  ".to_string(),
+            ..Default::default()
+        },
+    );
+    assert!(!drafts.has_draft(GUILD_ID, CHANNEL_ID));
+    assert!(Drafts::decode(&drafts.encode())
+        .unwrap()
+        .get(GUILD_ID, CHANNEL_ID)
+        .is_none());
+}
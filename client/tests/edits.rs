@@ -0,0 +1,66 @@
+mod mock;
+
+use client::{
+    diff::{diff_words, Change},
+    harmony_rust_sdk::api::chat::{
+        stream_event::{Event as ChatEvent, MessageUpdated},
+        Event, FormattedText,
+    },
+    message::MessageId,
+};
+use mock::MockHomeserver;
+
+const GUILD_ID: u64 = 1;
+const CHANNEL_ID: u64 = 2;
+
+#[tokio::test]
+async fn edits_keep_earlier_versions() {
+    let mock = MockHomeserver::start().await;
+    mock.add_guild(GUILD_ID, CHANNEL_ID, 0);
+    let mut client = mock.logged_in_client().await;
+
+    client.process_event(mock::message_sent(
+        GUILD_ID,
+        CHANNEL_ID,
+        10,
+        mock::text_message("hello wrld", 100),
+    ));
+    for &(text, edited_at) in &[("hello world", 200), ("hello there world", 300)] {
+        client.process_event(Event::Chat(ChatEvent::EditedMessage(MessageUpdated {
+            guild_id: GUILD_ID,
+            channel_id: CHANNEL_ID,
+            message_id: 10,
+            edited_at,
+            new_content: Some(FormattedText::default().with_text(text.to_string())),
+            ..Default::default()
+        })));
+    }
+
+    let message = &client.get_channel(GUILD_ID, CHANNEL_ID).unwrap().messages[&MessageId::Ack(10)];
+    assert_eq!(message.edited_at.map(|at| at.timestamp()), Some(300));
+    let history = message
+        .text_history()
+        .into_iter()
+        .map(|(text, written_at)| (text.text.as_str(), written_at.timestamp()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        history,
+        vec![("hello wrld", 100), ("hello world", 200), ("hello there world", 300)]
+    );
+}
+
+#[test]
+fn diff_is_word_level() {
+    assert_eq!(
+        diff_words("the quick fox", "the slow fox jumps"),
+        vec![
+            Change::Same("the "),
+            Change::Removed("quick"),
+            Change::Added("slow"),
+            Change::Same(" fox"),
+            Change::Added(" jumps"),
+        ]
+    );
+    assert_eq!(diff_words("same text", "same text"), vec![Change::Same("same text")]);
+    assert_eq!(diff_words("", "new"), vec![Change::Added("new")]);
+}
@@ -0,0 +1,163 @@
+use client::{
+    emotes::EmotePacks,
+    formatting::{emphasis_at, from_harmony, style_at, to_harmony, with_edited_text, FormatStyle, TextColor},
+    harmony_rust_sdk::api::chat::{
+        format::{self, color::Kind},
+        Format, FormattedText,
+    },
+    member::Members,
+};
+
+fn span(start: u32, length: u32, format: format::Format) -> Format {
+    Format {
+        start,
+        length,
+        format: Some(format),
+    }
+}
+
+#[test]
+fn received_formats_become_markdown() {
+    let received = FormattedText {
+        text: "hi @bob look".to_string(),
+        format: vec![
+            span(0, 2, format::Format::Bold(format::Bold {})),
+            span(3, 4, format::Format::UserMention(format::UserMention { user_id: 42 })),
+            span(
+                8,
+                4,
+                format::Format::Color(format::Color {
+                    kind: Kind::Warning as i32,
+                }),
+            ),
+        ],
+    };
+
+    let markdown = from_harmony(received);
+    assert_eq!(markdown.text, "**hi** <@42> look");
+    // The color is kept, over the same text in the markdown
+    assert_eq!(markdown.format.len(), 1);
+    let (start, length) = (markdown.format[0].start as usize, markdown.format[0].length as usize);
+    assert_eq!(&markdown.text[start..start + length], "look");
+    assert_eq!(
+        style_at(&markdown.format, start),
+        Some(FormatStyle::Color(TextColor::Warning))
+    );
+    assert_eq!(style_at(&markdown.format, 0), None);
+}
+
+#[test]
+fn crossing_formats_are_kept_instead_of_nesting_markdown() {
+    let bold = span(0, 5, format::Format::Bold(format::Bold {}));
+    let italic = span(3, 5, format::Format::Italic(format::Italic {}));
+    let received = FormattedText {
+        text: "abcdefgh".to_string(),
+        format: vec![bold.clone(), italic.clone()],
+    };
+
+    let markdown = from_harmony(received);
+    assert_eq!(markdown.text, "**abcde**fgh");
+    assert_eq!(
+        markdown.format,
+        vec![span(5, 7, format::Format::Italic(format::Italic {}))]
+    );
+    assert_eq!(emphasis_at(&markdown.format, 4), (false, false));
+    assert_eq!(emphasis_at(&markdown.format, 5), (false, true));
+    assert_eq!(emphasis_at(&markdown.format, 11), (false, true));
+
+    let sent = to_harmony(&markdown, &Members::default(), &EmotePacks::default());
+    assert_eq!(sent.text, "abcdefgh");
+    assert_eq!(sent.format, vec![bold, italic]);
+}
+
+#[test]
+fn sent_markdown_becomes_formats() {
+    let markdown = FormattedText::default().with_text("**bold** and `code`".to_string());
+
+    let sent = to_harmony(&markdown, &Members::default(), &EmotePacks::default());
+    assert_eq!(sent.text, "bold and code");
+    assert_eq!(
+        sent.format,
+        vec![
+            span(0, 4, format::Format::Bold(format::Bold {})),
+            span(9, 4, format::Format::Monospace(format::Monospace {})),
+        ]
+    );
+}
+
+#[test]
+fn markdown_survives_a_round_trip() {
+    for text in &[
+        "plain text",
+        "**bold *and italic* text** <@42>",
+        "emote <:image-id:> and `code`",
+        "```rust\nfn main() {}\n```",
+        "unclosed **bold and `code",
+    ] {
+        let markdown = FormattedText::default().with_text(text.to_string());
+        let sent = to_harmony(&markdown, &Members::default(), &EmotePacks::default());
+        assert_eq!(from_harmony(sent).text, *text);
+    }
+}
+
+#[test]
+fn markers_only_format_text_they_are_next_to() {
+    for text in &[
+        "2 * 3 * 4",
+        "file_*_name*",
+        "a ** b ** c",
+        "snake*case*word",
+        "ends with *",
+        "` spaced `",
+    ] {
+        let markdown = FormattedText::default().with_text(text.to_string());
+        let sent = to_harmony(&markdown, &Members::default(), &EmotePacks::default());
+        assert_eq!(sent.text, *text);
+        assert!(sent.format.is_empty(), "{} was formatted", text);
+    }
+
+    let markdown = FormattedText::default().with_text("(*a*) **b**, `c`".to_string());
+    let sent = to_harmony(&markdown, &Members::default(), &EmotePacks::default());
+    assert_eq!(sent.text, "(a) b, c");
+    assert_eq!(
+        sent.format,
+        vec![
+            span(1, 1, format::Format::Italic(format::Italic {})),
+            span(4, 1, format::Format::Bold(format::Bold {})),
+            span(7, 1, format::Format::Monospace(format::Monospace {})),
+        ]
+    );
+}
+
+#[test]
+fn editing_keeps_formats_over_unchanged_text() {
+    let warning = || {
+        format::Format::Color(format::Color {
+            kind: Kind::Warning as i32,
+        })
+    };
+    let markdown = FormattedText {
+        text: "hello world, bye".to_string(),
+        format: vec![span(0, 5, warning()), span(6, 5, warning()), span(13, 3, warning())],
+    };
+
+    let edited = with_edited_text(&markdown, "hello big world, bye".to_string());
+    assert_eq!(edited.text, "hello big world, bye");
+    let covered = edited
+        .format
+        .iter()
+        .map(|f| &edited.text[f.start as usize..(f.start + f.length) as usize])
+        .collect::<Vec<_>>();
+    assert_eq!(covered, vec!["hello", "world", "bye"]);
+
+    // A format over replaced text covers what replaced it, and is dropped if it was removed
+    let edited = with_edited_text(&markdown, "hello there, bye".to_string());
+    let covered = edited
+        .format
+        .iter()
+        .map(|f| &edited.text[f.start as usize..(f.start + f.length) as usize])
+        .collect::<Vec<_>>();
+    assert_eq!(covered, vec!["hello", "there", "bye"]);
+    let edited = with_edited_text(&markdown, "hello, bye".to_string());
+    assert_eq!(edited.format.len(), 2);
+}
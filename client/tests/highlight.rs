@@ -0,0 +1,32 @@
+use client::highlight::{highlight, Highlight};
+
+#[test]
+fn rust_code_is_highlighted() {
+    let lines = highlight("let x = \"a \\\" b\"; // note\nfn f() -> u8 { 42 }", Some("rust"));
+    assert_eq!(
+        lines[0],
+        vec![
+            (Highlight::Keyword, "let"),
+            (Highlight::Plain, " x = "),
+            (Highlight::String, "\"a \\\" b\""),
+            (Highlight::Plain, "; "),
+            (Highlight::Comment, "// note"),
+        ]
+    );
+    assert!(lines[1].contains(&(Highlight::Keyword, "fn")));
+    assert!(lines[1].contains(&(Highlight::Number, "42")));
+}
+
+#[test]
+fn block_comments_span_lines() {
+    let lines = highlight("a /* one\ntwo */ b", Some("c"));
+    assert_eq!(lines[0], vec![(Highlight::Plain, "a "), (Highlight::Comment, "/* one")]);
+    assert_eq!(lines[1], vec![(Highlight::Comment, "two */"), (Highlight::Plain, " b")]);
+}
+
+#[test]
+fn unknown_languages_are_plain() {
+    let lines = highlight("let x = 1;", Some("brainfudge"));
+    assert_eq!(lines, vec![vec![(Highlight::Plain, "let x = 1;")]]);
+    assert_eq!(highlight("let x = 1;", None), lines);
+}
@@ -0,0 +1,56 @@
+mod mock;
+
+use client::{history::Messages, message::MessageId};
+
+fn ids(messages: &Messages) -> Vec<u64> {
+    messages.keys().filter_map(MessageId::id).collect()
+}
+
+#[test]
+fn runs_that_dont_connect_leave_gaps() {
+    let mut messages = Messages::default();
+    messages.merge(mock::messages(20..30), None, None);
+    messages.merge(mock::messages(1..5), None, None);
+    messages.merge(mock::messages(10..15), None, None);
+
+    assert_eq!(
+        ids(&messages),
+        [1..5, 10..15, 20..30].iter().cloned().flatten().collect::<Vec<_>>()
+    );
+    assert!(messages.has_gap_after(4));
+    assert!(messages.has_gap_after(14));
+    assert!(!messages.has_gap_after(29));
+    assert!(messages.has_gap_from(5));
+    assert!(!messages.has_gap_from(9));
+}
+
+#[test]
+fn pages_fetched_next_to_a_message_fill_gaps() {
+    let mut messages = Messages::default();
+    messages.merge(mock::messages(1..5), None, None);
+    messages.merge(mock::messages(20..30), None, None);
+
+    // A page after a message connects to it, but not to what comes next until they overlap
+    messages.merge(mock::messages(5..10), Some(4), None);
+    assert!(!messages.has_gap_after(4));
+    assert!(messages.has_gap_after(9));
+    messages.merge(mock::messages(15..20), None, Some(20));
+    assert!(messages.has_gap_after(9));
+    assert!(!messages.has_gap_after(19));
+    messages.merge(mock::messages(10..17), Some(9), None);
+
+    assert_eq!(ids(&messages), (1..30).collect::<Vec<_>>());
+    assert!(!messages.has_gap_from(0));
+}
+
+#[test]
+fn removing_a_message_keeps_the_gap_after_it() {
+    let mut messages = Messages::default();
+    messages.merge(mock::messages(1..5), None, None);
+    messages.merge(mock::messages(10..15), None, None);
+
+    messages.remove(&MessageId::Ack(4));
+    assert!(messages.has_gap_after(3));
+    messages.close_gap_after(3);
+    assert!(!messages.has_gap_from(0));
+}
@@ -0,0 +1,41 @@
+use client::input_history::{InputHistory, MAX_ENTRIES};
+
+const GUILD_ID: u64 = 1;
+const CHANNEL_ID: u64 = 2;
+const OTHER_CHANNEL_ID: u64 = 3;
+
+#[test]
+fn browsing_goes_back_to_what_was_typed() {
+    let mut history = InputHistory::default();
+    history.push(GUILD_ID, CHANNEL_ID, "first".to_string());
+    history.push(GUILD_ID, CHANNEL_ID, "second".to_string());
+    history.push(GUILD_ID, OTHER_CHANNEL_ID, "elsewhere".to_string());
+
+    assert_eq!(history.older(GUILD_ID, CHANNEL_ID, "typing"), Some("second"));
+    assert_eq!(history.older(GUILD_ID, CHANNEL_ID, "second"), Some("first"));
+    assert_eq!(
+        history.older(GUILD_ID, CHANNEL_ID, "first"),
+        None,
+        "there is nothing older"
+    );
+    assert_eq!(history.newer(GUILD_ID, CHANNEL_ID).as_deref(), Some("second"));
+    assert_eq!(history.newer(GUILD_ID, CHANNEL_ID).as_deref(), Some("typing"));
+    assert_eq!(history.newer(GUILD_ID, CHANNEL_ID), None, "browsing stopped");
+}
+
+#[test]
+fn repeated_and_old_messages_are_dropped() {
+    let mut history = InputHistory::default();
+    for i in 0..=MAX_ENTRIES {
+        history.push(GUILD_ID, CHANNEL_ID, i.to_string());
+        history.push(GUILD_ID, CHANNEL_ID, i.to_string());
+    }
+
+    let mut recalled = Vec::new();
+    while let Some(text) = history.older(GUILD_ID, CHANNEL_ID, "") {
+        recalled.push(text.to_string());
+    }
+    assert_eq!(recalled.len(), MAX_ENTRIES);
+    assert_eq!(recalled.first().map(String::as_str), Some("100"));
+    assert_eq!(recalled.last().map(String::as_str), Some("1"));
+}
@@ -0,0 +1,190 @@
+mod mock;
+
+use std::time::Duration;
+
+use client::{
+    harmony_rust_sdk::api::chat::{
+        get_channel_messages_request::Direction,
+        stream_event::{ChannelCreated, Event as ChatEvent, GuildAddedToList},
+        ChannelKind, Event,
+    },
+    journal,
+    message::{Content, Message, MessageId},
+    outbox::SendState,
+    read_markers::ReadMarker,
+    Client,
+};
+use mock::{temp_content_store, text_message, MockHomeserver, USER_ID};
+
+const GUILD_ID: u64 = 1;
+const CHANNEL_ID: u64 = 2;
+const THUMBS_UP: &str = "👍";
+
+/// (message ID, text, text being edited, reactions as (image ID, count, reacted))
+type MessageSummary = (
+    MessageId,
+    Option<String>,
+    Option<String>,
+    Vec<(String, u32, Option<bool>)>,
+);
+
+/// The parts of a channel's state a replay has to reproduce.
+fn summary(client: &mut Client) -> (Vec<MessageSummary>, ReadMarker, bool, bool) {
+    let channel = client.get_channel(GUILD_ID, CHANNEL_ID).unwrap();
+    let messages = channel
+        .messages
+        .iter()
+        .map(|(id, message)| {
+            (
+                *id,
+                message.content.as_text().map(str::to_string),
+                message.being_edited.clone(),
+                message
+                    .reactions
+                    .iter()
+                    .map(|r| (r.image_id.to_string(), r.count, r.reacted))
+                    .collect(),
+            )
+        })
+        .collect();
+    (
+        messages,
+        channel.read_marker,
+        channel.reached_top,
+        channel.reached_bottom,
+    )
+}
+
+#[tokio::test]
+async fn replaying_a_journal_gives_the_same_state() {
+    let mock = MockHomeserver::start().await;
+    mock.add_guild(GUILD_ID, CHANNEL_ID, 3);
+    let history = mock.state.lock().unwrap().guilds[0].channels[0].messages.clone();
+
+    let mut client = Client::new(mock.url(), Some(mock.session().into()), temp_content_store())
+        .await
+        .unwrap();
+    client.user_id = Some(USER_ID);
+    let journal_path = client.start_recording().unwrap();
+
+    // What the homeserver sends
+    client.process_event(Event::Chat(ChatEvent::GuildAddedToList(GuildAddedToList {
+        guild_id: GUILD_ID,
+        homeserver: String::new(),
+    })));
+    client.process_event(Event::Chat(ChatEvent::CreatedChannel(ChannelCreated {
+        guild_id: GUILD_ID,
+        channel_id: CHANNEL_ID,
+        name: "general".to_string(),
+        kind: ChannelKind::TextUnspecified.into(),
+        ..Default::default()
+    })));
+    client.process_get_message_history_response(
+        GUILD_ID,
+        CHANNEL_ID,
+        0,
+        history.iter().rev().cloned().collect(),
+        true,
+        Direction::BeforeUnspecified,
+    );
+    let mention = text_message(&format!("hey <@{}>", USER_ID), 10);
+    client.process_event(mock::message_sent(
+        GUILD_ID,
+        CHANNEL_ID,
+        5000,
+        client::harmony_rust_sdk::api::chat::Message {
+            author_id: USER_ID + 1,
+            ..mention
+        },
+    ));
+
+    // What the user does
+    let message = Message {
+        content: Content::text("sent from here"),
+        sender: USER_ID,
+        ..Default::default()
+    };
+    let send = client.send_msg_cmd(
+        GUILD_ID,
+        CHANNEL_ID,
+        Duration::from_secs(0),
+        MessageId::default(),
+        message,
+    );
+    let result = send.await;
+    assert!(client.process_send_result(result).is_none());
+    let (first_id, second_id) = (history[0].0, history[1].0);
+    let react = client
+        .toggle_reaction_cmd(GUILD_ID, CHANNEL_ID, first_id, THUMBS_UP.into(), THUMBS_UP.into())
+        .unwrap();
+    let result = react.await;
+    let err = client.process_reaction_toggle_result(GUILD_ID, CHANNEL_ID, first_id, THUMBS_UP, THUMBS_UP, result);
+    assert!(err.is_none());
+    drop(client.edit_msg_cmd(GUILD_ID, CHANNEL_ID, second_id, "edited".to_string()));
+    client.mark_unread_from(GUILD_ID, CHANNEL_ID, second_id);
+
+    let expected = summary(&mut client);
+    assert_eq!(expected.1.mention_count, 1, "the mention should count as unread");
+    drop(client);
+
+    let entries = journal::read_journal(&journal_path).unwrap();
+    let mut replayed = Client::new(mock.url(), None, temp_content_store()).await.unwrap();
+    journal::replay(&mut replayed, entries).unwrap();
+
+    assert_eq!(replayed.user_id, Some(USER_ID));
+    assert_eq!(summary(&mut replayed), expected);
+    assert!(replayed.outbox.is_empty());
+}
+
+#[tokio::test]
+async fn replaying_retries_doesnt_retry_them_again() {
+    let mock = MockHomeserver::start().await;
+    mock.add_guild(GUILD_ID, CHANNEL_ID, 0);
+
+    let mut client = Client::new(mock.url(), Some(mock.session().into()), temp_content_store())
+        .await
+        .unwrap();
+    client.user_id = Some(USER_ID);
+    let journal_path = client.start_recording().unwrap();
+
+    let message = Message {
+        content: Content::text("unlucky"),
+        sender: USER_ID,
+        ..Default::default()
+    };
+    let message_id = MessageId::default();
+    let transaction_id = message_id.transaction_id().unwrap();
+    drop(client.send_msg_cmd(
+        GUILD_ID,
+        CHANNEL_ID,
+        Duration::from_secs(0),
+        message_id,
+        message.clone(),
+    ));
+    // Sending failed twice, so it is tried a third time
+    for _ in 0..2 {
+        let failed = (
+            GUILD_ID,
+            CHANNEL_ID,
+            transaction_id,
+            message.clone(),
+            Duration::from_secs(0),
+            None,
+        );
+        assert!(client.process_send_result(failed).is_some());
+    }
+    let state = client.outbox.state(transaction_id);
+    assert_eq!(state, Some(SendState::Sending { attempt: 2 }));
+    drop(client);
+
+    let entries = journal::read_journal(&journal_path).unwrap();
+    let content_store = temp_content_store();
+    let mut replayed = Client::new(mock.url(), None, content_store.clone()).await.unwrap();
+    journal::replay(&mut replayed, entries).unwrap();
+    assert_eq!(replayed.outbox.state(transaction_id), state);
+
+    // The replay is over, so changes are saved again
+    replayed.save_outbox();
+    let homeserver = replayed.inner().homeserver_url().to_string();
+    assert!(content_store.outbox_path(&homeserver, USER_ID).exists());
+}
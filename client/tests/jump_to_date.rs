@@ -0,0 +1,143 @@
+mod mock;
+
+use chrono::NaiveDateTime;
+use client::{
+    channel::{fetch_first_message_since, DateLocation, DateSearch, DateSearchStep},
+    harmony_rust_sdk::{
+        api::chat::get_channel_messages_request::Direction, client::api::chat::channel::GetChannelMessages,
+    },
+    message::MessageId,
+};
+use mock::MockHomeserver;
+
+const GUILD_ID: u64 = 1;
+const CHANNEL_ID: u64 = 2;
+
+/// Message `index` of a channel made by [`MockHomeserver::add_guild`], which was written at second `index + 1`.
+fn message_id_at(mock: &MockHomeserver, index: usize) -> u64 {
+    mock.state.lock().unwrap().guilds[0].channels[0].messages[index].0
+}
+
+fn at(secs: i64) -> NaiveDateTime {
+    NaiveDateTime::from_timestamp(secs, 0)
+}
+
+/// Runs a date search to the end, returning what it found and how many pages it fetched.
+async fn search(client: &client::Client, since: NaiveDateTime, before: Option<u64>) -> (Option<u64>, usize) {
+    let mut search = DateSearch::new(since, before);
+    for pages in 1.. {
+        match fetch_first_message_since(client.inner(), GUILD_ID, CHANNEL_ID, search)
+            .await
+            .unwrap()
+        {
+            DateSearchStep::Found(message_id) => return (message_id, pages),
+            DateSearchStep::Narrowed(narrowed) => {
+                assert!(narrowed.reached.is_some());
+                search = narrowed;
+            }
+        }
+    }
+    unreachable!()
+}
+
+#[tokio::test]
+async fn finds_first_message_since_in_unloaded_history() {
+    let mock = MockHomeserver::start().await;
+    mock.add_guild(GUILD_ID, CHANNEL_ID, mock::PAGE_SIZE * 3 + 10);
+    let client = mock.logged_in_client().await;
+
+    // Three pages back from the newest message
+    let (found, _) = search(&client, at(6), None).await;
+    assert_eq!(found, Some(message_id_at(&mock, 5)));
+
+    // Before the first message
+    let (found, _) = search(&client, at(0), None).await;
+    assert_eq!(found, Some(message_id_at(&mock, 0)));
+
+    // In the middle of a page
+    let (found, _) = search(&client, at(mock::PAGE_SIZE as i64 + 20), None).await;
+    assert_eq!(found, Some(message_id_at(&mock, mock::PAGE_SIZE + 19)));
+
+    // Starting from a known message only looks at older ones
+    let before = message_id_at(&mock, 100);
+    let (found, _) = search(&client, at(1000), Some(before)).await;
+    assert_eq!(found, Some(before));
+
+    // Every message is older
+    let (found, pages) = search(&client, at(1000), None).await;
+    assert_eq!(found, None);
+    assert_eq!(pages, 1);
+}
+
+#[tokio::test]
+async fn search_halves_history_with_each_page() {
+    let pages = 64;
+    let mock = MockHomeserver::start().await;
+    mock.add_guild(GUILD_ID, CHANNEL_ID, mock::PAGE_SIZE * pages);
+    let client = mock.logged_in_client().await;
+    let newest_id = message_id_at(&mock, mock::PAGE_SIZE * pages - 1);
+
+    for &index in &[
+        0,
+        1,
+        mock::PAGE_SIZE * 10 + 3,
+        mock::PAGE_SIZE * pages / 2,
+        mock::PAGE_SIZE * (pages - 2),
+    ] {
+        let (found, fetched) = search(&client, at(index as i64 + 1), None).await;
+        assert_eq!(found, Some(message_id_at(&mock, index)));
+        // The newest page, then at most one page for each halving of the message ids
+        assert!(
+            fetched <= 2 + (newest_id as f64).log2().ceil() as usize,
+            "{} pages",
+            fetched
+        );
+    }
+}
+
+#[tokio::test]
+async fn locates_date_in_loaded_messages() {
+    let mock = MockHomeserver::start().await;
+    mock.add_guild(GUILD_ID, CHANNEL_ID, mock::PAGE_SIZE * 2);
+    let mut client = mock.logged_in_client().await;
+
+    let response = client
+        .inner()
+        .call(GetChannelMessages::new(GUILD_ID, CHANNEL_ID))
+        .await
+        .unwrap();
+    let messages = response
+        .messages
+        .into_iter()
+        .flat_map(|m| Some((m.message_id, m.message?)))
+        .collect();
+    client.process_get_message_history_response(
+        GUILD_ID,
+        CHANNEL_ID,
+        0,
+        messages,
+        response.reached_top,
+        Direction::BeforeUnspecified,
+    );
+
+    let channel = client.get_channel(GUILD_ID, CHANNEL_ID).unwrap();
+    let newest_page_start = mock::PAGE_SIZE;
+    assert_eq!(
+        channel.locate_date(at(newest_page_start as i64 + 10)),
+        DateLocation::Loaded(MessageId::Ack(message_id_at(&mock, newest_page_start + 9)))
+    );
+    assert_eq!(channel.locate_date(at(1000)), DateLocation::Latest);
+    // Older messages aren't loaded, so they have to be fetched
+    assert_eq!(
+        channel.locate_date(at(1)),
+        DateLocation::NotLoaded {
+            oldest_loaded: Some(message_id_at(&mock, newest_page_start))
+        }
+    );
+
+    channel.reached_top = true;
+    assert_eq!(
+        channel.locate_date(at(1)),
+        DateLocation::Loaded(MessageId::Ack(message_id_at(&mock, newest_page_start)))
+    );
+}
@@ -0,0 +1,38 @@
+use client::{
+    linemd::{parser::Token, Parser},
+    HarmonyToken,
+};
+
+fn custom<'a>(tokens: &[Token<'a, HarmonyToken<'a>>]) -> Vec<HarmonyToken<'a>> {
+    tokens
+        .iter()
+        .filter_map(|tok| match tok {
+            Token::Custom(tok) => Some(tok.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn spoilers_and_strikethrough_are_parsed() {
+    let text = "the ||butler|| did ~~not~~ do it";
+    let tokens = text.parse_md_custom(HarmonyToken::parse);
+    assert_eq!(
+        custom(&tokens),
+        vec![HarmonyToken::Spoiler("butler"), HarmonyToken::Strikethrough("not")]
+    );
+}
+
+#[test]
+fn empty_spoilers_are_text() {
+    let text = "a |||| b";
+    let tokens = text.parse_md_custom(HarmonyToken::parse);
+    assert!(custom(&tokens).is_empty());
+}
+
+#[test]
+fn quotes_only_start_lines() {
+    let text = "> quoted\nnot > quoted\n> again";
+    let tokens = text.parse_md_custom(HarmonyToken::parse);
+    assert_eq!(custom(&tokens), vec![HarmonyToken::Quote, HarmonyToken::Quote]);
+}
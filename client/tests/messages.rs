@@ -0,0 +1,298 @@
+mod mock;
+
+use std::time::Duration;
+
+use client::{
+    harmony_rust_sdk::{
+        api::chat::{
+            get_channel_messages_request::Direction,
+            stream_event::{Event as ChatEvent, MessageSent},
+            Event, EventSource,
+        },
+        client::api::chat::channel::GetChannelMessages,
+    },
+    message::{Content, Message, MessageId},
+    Client,
+};
+use mock::MockHomeserver;
+
+const GUILD_ID: u64 = 1;
+const CHANNEL_ID: u64 = 2;
+
+/// Fetches a page of history like the UI does, and gives it to the client.
+async fn load_page(client: &mut Client, message_id: u64, direction: Direction) {
+    let mut request = GetChannelMessages::new(GUILD_ID, CHANNEL_ID).with_direction(direction);
+    if message_id != 0 {
+        request = request.with_message_id(message_id);
+    }
+    let response = client.inner().call(request).await.unwrap();
+    let messages = response
+        .messages
+        .into_iter()
+        .flat_map(|m| Some((m.message_id, m.message?)))
+        .collect();
+    let reached_top = match direction {
+        Direction::After => client.get_channel(GUILD_ID, CHANNEL_ID).unwrap().reached_top,
+        _ => response.reached_top,
+    };
+    client.process_get_message_history_response(GUILD_ID, CHANNEL_ID, message_id, messages, reached_top, direction);
+}
+
+fn mock_message_ids(mock: &MockHomeserver) -> Vec<MessageId> {
+    mock.state.lock().unwrap().guilds[0].channels[0]
+        .messages
+        .iter()
+        .map(|(id, _)| MessageId::Ack(*id))
+        .collect()
+}
+
+fn loaded_message_ids(client: &mut Client) -> Vec<MessageId> {
+    let channel = client.get_channel(GUILD_ID, CHANNEL_ID).unwrap();
+    channel.messages.keys().copied().collect()
+}
+
+#[tokio::test]
+async fn sent_message_is_acknowledged_by_echo() {
+    let mock = MockHomeserver::start().await;
+    mock.add_guild(GUILD_ID, CHANNEL_ID, 0);
+    let mut client = mock.logged_in_client().await;
+
+    let (mut tx, mut rx) = client.inner().subscribe_events(false).await.unwrap().split();
+    tx.add_source(EventSource::Guild(GUILD_ID)).await.unwrap();
+
+    // An empty channel whose history was already fetched
+    client.get_channel(GUILD_ID, CHANNEL_ID).unwrap().reached_top = true;
+
+    let message_id = MessageId::default();
+    let message = Message {
+        content: Content::text("hello"),
+        sender: mock::USER_ID,
+        ..Default::default()
+    };
+    let send = client.send_msg_cmd(GUILD_ID, CHANNEL_ID, Duration::from_secs(0), message_id, message);
+    assert!(client
+        .get_channel(GUILD_ID, CHANNEL_ID)
+        .unwrap()
+        .messages
+        .contains_key(&message_id));
+    assert!(client.outbox.get(message_id.transaction_id().unwrap()).is_some());
+
+    let (_, _, echo_id, _, _, acked_id) = send.await;
+    assert_eq!(Some(echo_id), message_id.transaction_id());
+    let acked_id = acked_id.expect("homeserver should have accepted the message");
+
+    let event = tokio::time::timeout(Duration::from_secs(5), rx.get_event())
+        .await
+        .expect("echo should arrive on the event stream")
+        .unwrap()
+        .unwrap();
+    client.process_event(event);
+
+    assert!(client.outbox.is_empty());
+    let messages = &client.get_channel(GUILD_ID, CHANNEL_ID).unwrap().messages;
+    assert!(!messages.contains_key(&message_id));
+    match messages.get(&MessageId::Ack(acked_id)).map(|m| &m.content) {
+        Some(Content::Text(text)) => assert_eq!(text.text, "hello"),
+        content => panic!("expected the acknowledged text message, got {:?}", content),
+    }
+}
+
+#[tokio::test]
+async fn outbox_is_resent_after_restart() {
+    let mock = MockHomeserver::start().await;
+    mock.add_guild(GUILD_ID, CHANNEL_ID, 0);
+    let content_store = mock::temp_content_store();
+
+    let mut client = mock.logged_in_client_with(content_store.clone()).await;
+    for text in &["first", "second"] {
+        let message = Message {
+            content: Content::text(*text),
+            sender: mock::USER_ID,
+            ..Default::default()
+        };
+        let transaction_id = MessageId::default().transaction_id().unwrap();
+        client.outbox.push(transaction_id, GUILD_ID, CHANNEL_ID, message);
+    }
+    client.save_outbox();
+    drop(client);
+
+    let mut client = mock.logged_in_client_with(content_store).await;
+    client.load_outbox().unwrap();
+    assert_eq!(client.outbox.iter().count(), 2);
+
+    for result in client.resend_outbox(false).await {
+        assert!(client.process_send_result(result).is_none());
+    }
+    assert!(client.outbox.is_empty());
+
+    let sent = mock.state.lock().unwrap().guilds[0].channels[0]
+        .messages
+        .iter()
+        .map(|(_, message)| match Message::from(message.clone()).content {
+            Content::Text(text) => text.text,
+            content => panic!("expected a text message, got {:?}", content),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(sent, vec!["first".to_string(), "second".to_string()]);
+
+    // Nothing is left to send, so a new client starts with an empty outbox
+    client.load_outbox().unwrap();
+    assert!(client.outbox.is_empty());
+}
+
+#[tokio::test]
+async fn history_pagination_reaches_top_in_order() {
+    let mock = MockHomeserver::start().await;
+    mock.add_guild(GUILD_ID, CHANNEL_ID, mock::PAGE_SIZE * 2 + 20);
+    let mut client = mock.logged_in_client().await;
+
+    let mut before = 0;
+    let mut pages = 0;
+    loop {
+        let mut request = GetChannelMessages::new(GUILD_ID, CHANNEL_ID).with_direction(Direction::BeforeUnspecified);
+        if before != 0 {
+            request = request.with_message_id(before);
+        }
+        let response = client.inner().call(request).await.unwrap();
+        let messages = response
+            .messages
+            .into_iter()
+            .flat_map(|m| Some((m.message_id, m.message?)))
+            .collect::<Vec<_>>();
+        let oldest = messages.last().map(|(id, _)| *id);
+        client.process_get_message_history_response(
+            GUILD_ID,
+            CHANNEL_ID,
+            before,
+            messages,
+            response.reached_top,
+            Direction::BeforeUnspecified,
+        );
+        pages += 1;
+
+        if response.reached_top {
+            break;
+        }
+        before = oldest.expect("a page that didn't reach the top can't be empty");
+        assert!(pages < 10, "pagination never reached the top");
+    }
+
+    assert_eq!(pages, 3);
+    let channel = client.get_channel(GUILD_ID, CHANNEL_ID).unwrap();
+    assert!(channel.reached_top);
+
+    let expected = mock.state.lock().unwrap().guilds[0].channels[0]
+        .messages
+        .iter()
+        .map(|(id, _)| MessageId::Ack(*id))
+        .collect::<Vec<_>>();
+    let got = channel.messages.keys().copied().collect::<Vec<_>>();
+    assert_eq!(got, expected);
+}
+
+#[tokio::test]
+async fn uploaded_file_can_be_downloaded() {
+    use client::harmony_rust_sdk::client::api::rest::{download_extract_file, upload_extract_id, FileId};
+
+    let mock = MockHomeserver::start().await;
+    let client = mock.logged_in_client().await;
+
+    let data = b"not really a png".to_vec();
+    let id = upload_extract_id(
+        client.inner(),
+        "test.png".to_string(),
+        "image/png".to_string(),
+        data.clone(),
+    )
+    .await
+    .unwrap();
+
+    let file = download_extract_file(client.inner(), FileId::Id(id)).await.unwrap();
+    assert_eq!(file.name(), "test.png");
+    assert_eq!(&file.data()[..], data.as_slice());
+}
+
+#[tokio::test]
+async fn jumping_to_older_messages_leaves_a_gap_that_newer_pages_fill() {
+    let mock = MockHomeserver::start().await;
+    mock.add_guild(GUILD_ID, CHANNEL_ID, mock::PAGE_SIZE * 3);
+    let mut client = mock.logged_in_client().await;
+    let all = mock_message_ids(&mock);
+
+    load_page(&mut client, 0, Direction::BeforeUnspecified).await;
+
+    // The messages around the one jumped to are kept apart from the newest ones
+    let jumped_to = all[10].id().unwrap();
+    load_page(&mut client, jumped_to, Direction::Around).await;
+    let around_end = 10 + mock::PAGE_SIZE / 2 + 1;
+    let newest_start = all.len() - mock::PAGE_SIZE;
+    let expected = all[..around_end]
+        .iter()
+        .chain(&all[newest_start..])
+        .copied()
+        .collect::<Vec<_>>();
+    assert_eq!(loaded_message_ids(&mut client), expected);
+    let channel = client.get_channel(GUILD_ID, CHANNEL_ID).unwrap();
+    assert!(channel.reached_bottom);
+    assert!(channel.reached_top);
+    let last_around = all[around_end - 1].id().unwrap();
+    assert!(channel.messages.has_gap_after(last_around));
+    assert_eq!(channel.missing_after(around_end - 1), Some(last_around));
+    assert_eq!(channel.missing_after(around_end), None);
+
+    // Scrolling down fetches newer pages until the gap is filled
+    let mut pages = 0;
+    loop {
+        let channel = client.get_channel(GUILD_ID, CHANNEL_ID).unwrap();
+        let gap = (0..channel.messages.len()).find_map(|index| {
+            let message_id = channel.messages.get_index(index)?.0.id()?;
+            channel.messages.has_gap_after(message_id).then(|| message_id)
+        });
+        match gap {
+            Some(message_id) => load_page(&mut client, message_id, Direction::After).await,
+            None => break,
+        }
+        pages += 1;
+        assert!(pages < 10, "newer pages never filled the gap");
+    }
+    assert_eq!(pages, 2);
+    assert_eq!(loaded_message_ids(&mut client), all);
+}
+
+#[tokio::test]
+async fn older_pages_fill_the_gap_before_the_newest_messages() {
+    let mock = MockHomeserver::start().await;
+    mock.add_guild(GUILD_ID, CHANNEL_ID, mock::PAGE_SIZE * 3);
+    let mut client = mock.logged_in_client().await;
+    let all = mock_message_ids(&mock);
+
+    // Nothing newer than the messages jumped to is known in a channel that wasn't loaded
+    load_page(&mut client, all[10].id().unwrap(), Direction::Around).await;
+    assert!(!client.get_channel(GUILD_ID, CHANNEL_ID).unwrap().reached_bottom);
+
+    // Going back to the present keeps them, with the history between missing
+    load_page(&mut client, 0, Direction::BeforeUnspecified).await;
+    let around_end = 10 + mock::PAGE_SIZE / 2 + 1;
+    let newest_start = all.len() - mock::PAGE_SIZE;
+    let channel = client.get_channel(GUILD_ID, CHANNEL_ID).unwrap();
+    assert!(channel.reached_bottom);
+    assert_eq!(
+        channel.missing_before(around_end),
+        Some(all[newest_start].id().unwrap())
+    );
+    assert_eq!(channel.missing_before(around_end - 1), None);
+
+    // Scrolling up from the newest messages fetches the missing ones before them
+    let mut pages = 0;
+    while let Some(before) = client
+        .get_channel(GUILD_ID, CHANNEL_ID)
+        .unwrap()
+        .missing_before(around_end)
+    {
+        load_page(&mut client, before, Direction::BeforeUnspecified).await;
+        pages += 1;
+        assert!(pages < 10, "older pages never filled the gap");
+    }
+    assert_eq!(pages, 2);
+    assert_eq!(loaded_message_ids(&mut client), all);
+}
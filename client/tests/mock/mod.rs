@@ -0,0 +1,753 @@
+//! An in-process fake Harmony homeserver for integration tests.
+//!
+//! It speaks just enough hRPC for `InnerClient`: unary calls are protobuf bodies POSTed to
+//! `/<package>.<Service>/<Method>`, `StreamEvents` is a websocket of protobuf frames, and
+//! files go through the `/_harmony/media` REST endpoints.
+
+#![allow(dead_code)]
+
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    net::SocketAddr,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::UNIX_EPOCH,
+};
+
+use chrono::NaiveDateTime;
+use client::{
+    content::ContentStore,
+    harmony_rust_sdk::api::{
+        auth::{
+            auth_step::{self, form::FormField, Choice as AuthChoice, Form as AuthForm},
+            next_step_request::{self, form_fields::Field},
+            AuthStep, BeginAuthResponse, NextStepRequest, NextStepResponse, Session as AuthSession,
+        },
+        chat::{
+            get_channel_messages_request::Direction,
+            stream_event::{ChannelCreated, Event as ChatEvent, GuildAddedToList, MessageSent, ReactionUpdated},
+            stream_events_response, AddReactionRequest, AddReactionResponse, Channel, ChannelKind, ChannelWithId,
+            Event, GetChannelMessagesRequest, GetChannelMessagesResponse, GetGuildChannelsRequest,
+            GetGuildChannelsResponse, GetGuildListResponse, GetGuildRequest, GetGuildResponse, GetMessageRequest,
+            GetMessageResponse, Guild, GuildListEntry, Message as HarmonyMessage, MessageWithId,
+            QueryHasPermissionResponse, Reaction, RemoveReactionRequest, RemoveReactionResponse, SendMessageRequest,
+            SendMessageResponse, StreamEvent as ChatStreamEvent, StreamEventsResponse,
+        },
+        emote::Emote,
+        exports::prost::Message as _,
+        profile::{GetProfileRequest, GetProfileResponse, Profile},
+    },
+    message::{Content as MessageContent, Message},
+    Client, Uri,
+};
+use futures_util::{SinkExt, StreamExt};
+use hyper::{
+    header::{HeaderValue, CONNECTION, CONTENT_TYPE, UPGRADE},
+    service::{make_service_fn, service_fn},
+    Body, Request, Response, Server, StatusCode,
+};
+use tokio::sync::broadcast;
+use tokio_tungstenite::{
+    tungstenite::{handshake::derive_accept_key, protocol::Role, Message as WsMessage},
+    WebSocketStream,
+};
+
+pub const USER_ID: u64 = 1;
+pub const USER_NAME: &str = "tester";
+pub const EMAIL: &str = "tester@example.org";
+pub const PASSWORD: &str = "password";
+pub const SESSION_TOKEN: &str = "mock-session-token";
+
+/// How many messages a history page contains at most.
+pub const PAGE_SIZE: usize = 50;
+
+#[derive(Debug, Clone)]
+pub struct MockChannel {
+    pub channel_id: u64,
+    pub name: String,
+    /// Oldest first.
+    pub messages: Vec<(u64, HarmonyMessage)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MockGuild {
+    pub guild_id: u64,
+    pub name: String,
+    pub channels: Vec<MockChannel>,
+}
+
+#[derive(Debug, Default)]
+pub struct State {
+    pub guilds: Vec<MockGuild>,
+    /// File id -> (name, mimetype, data)
+    pub files: HashMap<String, (String, String, Vec<u8>)>,
+    next_id: u64,
+}
+
+impl State {
+    fn next_id(&mut self) -> u64 {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    fn channel_mut(&mut self, guild_id: u64, channel_id: u64) -> Option<&mut MockChannel> {
+        self.guilds
+            .iter_mut()
+            .find(|g| g.guild_id == guild_id)?
+            .channels
+            .iter_mut()
+            .find(|c| c.channel_id == channel_id)
+    }
+}
+
+pub struct MockHomeserver {
+    addr: SocketAddr,
+    pub state: Arc<Mutex<State>>,
+    events: broadcast::Sender<StreamEventsResponse>,
+}
+
+impl MockHomeserver {
+    /// Starts a homeserver on a random localhost port.
+    pub async fn start() -> Self {
+        let state = Arc::new(Mutex::new(State {
+            next_id: 1000,
+            ..Default::default()
+        }));
+        let (events, _) = broadcast::channel(256);
+
+        let service_state = state.clone();
+        let service_events = events.clone();
+        let make_service = make_service_fn(move |_| {
+            let state = service_state.clone();
+            let events = service_events.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req| {
+                    let state = state.clone();
+                    let events = events.clone();
+                    async move { Ok::<_, Infallible>(handle(req, state, events).await) }
+                }))
+            }
+        });
+
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+
+        Self { addr, state, events }
+    }
+
+    pub fn url(&self) -> Uri {
+        format!("http://{}", self.addr).parse().unwrap()
+    }
+
+    /// A session for the mock user, as if they logged in before.
+    pub fn session(&self) -> client::Session {
+        client::Session {
+            session_token: SESSION_TOKEN.into(),
+            user_name: USER_NAME.into(),
+            user_id: USER_ID.to_string().into(),
+            homeserver: self.url().to_string().into(),
+        }
+    }
+
+    /// Adds a guild with one text channel that has `message_count` messages from the mock user.
+    pub fn add_guild(&self, guild_id: u64, channel_id: u64, message_count: usize) {
+        let mut state = self.state.lock().unwrap();
+        let messages = (0..message_count)
+            .map(|index| {
+                let id = state.next_id();
+                (id, text_message(&format!("message {}", index), index as u64 + 1))
+            })
+            .collect();
+        state.guilds.push(MockGuild {
+            guild_id,
+            name: format!("guild {}", guild_id),
+            channels: vec![MockChannel {
+                channel_id,
+                name: "general".to_string(),
+                messages,
+            }],
+        });
+    }
+
+    /// A client that is logged in as the mock user, with every guild and channel loaded.
+    pub async fn logged_in_client(&self) -> Client {
+        self.logged_in_client_with(temp_content_store()).await
+    }
+
+    /// Like [`MockHomeserver::logged_in_client`], but uses the given content store.
+    pub async fn logged_in_client_with(&self, content_store: Arc<ContentStore>) -> Client {
+        let mut client = Client::new(self.url(), Some(self.session().into()), content_store)
+            .await
+            .unwrap();
+        client.user_id = Some(USER_ID);
+
+        let guilds = self
+            .state
+            .lock()
+            .unwrap()
+            .guilds
+            .iter()
+            .map(|g| (g.guild_id, g.name.clone(), g.channels.clone()))
+            .collect::<Vec<_>>();
+        for (guild_id, name, channels) in guilds {
+            client.process_event(Event::Chat(ChatEvent::GuildAddedToList(GuildAddedToList {
+                guild_id,
+                homeserver: String::new(),
+            })));
+            client.get_guild(guild_id).unwrap().name = name;
+            for channel in channels {
+                client.process_event(Event::Chat(ChatEvent::CreatedChannel(ChannelCreated {
+                    guild_id,
+                    channel_id: channel.channel_id,
+                    name: channel.name,
+                    kind: ChannelKind::TextUnspecified.into(),
+                    ..Default::default()
+                })));
+            }
+        }
+
+        client
+    }
+
+    /// Sends a chat event to every connected event stream.
+    pub fn push_chat_event(&self, event: ChatEvent) {
+        let _ = self.events.send(StreamEventsResponse {
+            event: Some(stream_events_response::Event::Chat(ChatStreamEvent {
+                event: Some(event),
+            })),
+        });
+    }
+}
+
+/// A content store in a fresh temporary directory.
+pub fn temp_content_store() -> Arc<ContentStore> {
+    let root: PathBuf = std::env::temp_dir().join(format!("loqui-test-{}", rand_suffix()));
+    let content_store = ContentStore::from_root(root);
+    content_store.create_req_dirs().unwrap();
+    Arc::new(content_store)
+}
+
+fn rand_suffix() -> u128 {
+    UNIX_EPOCH.elapsed().unwrap().as_nanos()
+}
+
+pub fn text_message(text: &str, created_at: u64) -> HarmonyMessage {
+    use client::harmony_rust_sdk::api::chat::{content, Content, FormattedText};
+
+    HarmonyMessage {
+        author_id: USER_ID,
+        created_at,
+        content: Some(Content {
+            content: Some(content::Content::TextMessage(content::TextContent {
+                content: Some(FormattedText::default().with_text(text.to_string())),
+            })),
+        }),
+        ..Default::default()
+    }
+}
+
+/// The event of a message being sent in a channel.
+pub fn message_sent(guild_id: u64, channel_id: u64, message_id: u64, message: HarmonyMessage) -> Event {
+    Event::Chat(ChatEvent::SentMessage(MessageSent {
+        guild_id,
+        channel_id,
+        message_id,
+        message: Some(message),
+        ..Default::default()
+    }))
+}
+
+/// A loaded text message, written at the second of its id.
+pub fn message(sender: u64, id: u64, text: &str) -> Message {
+    Message {
+        content: MessageContent::text(text),
+        sender,
+        timestamp: NaiveDateTime::from_timestamp(id as i64, 0),
+        ..Default::default()
+    }
+}
+
+/// Loaded messages with the given ids, each written at the second of its id.
+pub fn messages(ids: impl IntoIterator<Item = u64>) -> Vec<(u64, Message)> {
+    ids.into_iter()
+        .map(|id| {
+            let message = Message {
+                timestamp: NaiveDateTime::from_timestamp(id as i64, 0),
+                ..Default::default()
+            };
+            (id, message)
+        })
+        .collect()
+}
+
+fn hrpc_response(message: impl client::harmony_rust_sdk::api::exports::prost::Message) -> Response<Body> {
+    let mut response = Response::new(Body::from(message.encode_to_vec()));
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static("application/hrpc"));
+    response
+}
+
+fn status(code: StatusCode) -> Response<Body> {
+    let mut response = Response::new(Body::empty());
+    *response.status_mut() = code;
+    response
+}
+
+async fn decode<T: client::harmony_rust_sdk::api::exports::prost::Message + Default>(req: Request<Body>) -> Option<T> {
+    let body = hyper::body::to_bytes(req.into_body()).await.ok()?;
+    T::decode(body).ok()
+}
+
+fn is_authorized(req: &Request<Body>) -> bool {
+    let token = req
+        .headers()
+        .get("authorization")
+        .or_else(|| req.headers().get("sec-websocket-protocol"))
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    token
+        .split(|c: char| c == ' ' || c == ',')
+        .any(|part| part == SESSION_TOKEN)
+}
+
+async fn handle(
+    req: Request<Body>,
+    state: Arc<Mutex<State>>,
+    events: broadcast::Sender<StreamEventsResponse>,
+) -> Response<Body> {
+    let path = req.uri().path().to_string();
+
+    if !path.starts_with("/protocol.auth.v1.AuthService/") && !is_authorized(&req) {
+        return status(StatusCode::UNAUTHORIZED);
+    }
+
+    match path.as_str() {
+        "/protocol.auth.v1.AuthService/BeginAuth" => hrpc_response(BeginAuthResponse {
+            auth_id: "mock-auth".to_string(),
+        }),
+        "/protocol.auth.v1.AuthService/NextStep" => match decode::<NextStepRequest>(req).await {
+            Some(request) => hrpc_response(NextStepResponse {
+                step: Some(next_auth_step(request)),
+            }),
+            None => status(StatusCode::BAD_REQUEST),
+        },
+        "/protocol.chat.v1.ChatService/GetGuildList" => {
+            let state = state.lock().unwrap();
+            hrpc_response(GetGuildListResponse {
+                guilds: state
+                    .guilds
+                    .iter()
+                    .map(|g| GuildListEntry {
+                        guild_id: g.guild_id,
+                        server_id: String::new(),
+                    })
+                    .collect(),
+            })
+        }
+        "/protocol.chat.v1.ChatService/GetGuild" => {
+            let request = match decode::<GetGuildRequest>(req).await {
+                Some(request) => request,
+                None => return status(StatusCode::BAD_REQUEST),
+            };
+            let state = state.lock().unwrap();
+            match state.guilds.iter().find(|g| g.guild_id == request.guild_id) {
+                Some(guild) => hrpc_response(GetGuildResponse {
+                    guild: Some(Guild {
+                        name: guild.name.clone(),
+                        owner_ids: vec![USER_ID],
+                        ..Default::default()
+                    }),
+                }),
+                None => status(StatusCode::NOT_FOUND),
+            }
+        }
+        "/protocol.chat.v1.ChatService/GetGuildChannels" => {
+            let request = match decode::<GetGuildChannelsRequest>(req).await {
+                Some(request) => request,
+                None => return status(StatusCode::BAD_REQUEST),
+            };
+            let state = state.lock().unwrap();
+            match state.guilds.iter().find(|g| g.guild_id == request.guild_id) {
+                Some(guild) => hrpc_response(GetGuildChannelsResponse {
+                    channels: guild
+                        .channels
+                        .iter()
+                        .map(|c| ChannelWithId {
+                            channel_id: c.channel_id,
+                            channel: Some(Channel {
+                                channel_name: c.name.clone(),
+                                kind: ChannelKind::TextUnspecified.into(),
+                                ..Default::default()
+                            }),
+                        })
+                        .collect(),
+                }),
+                None => status(StatusCode::NOT_FOUND),
+            }
+        }
+        "/protocol.chat.v1.ChatService/GetChannelMessages" => {
+            let request = match decode::<GetChannelMessagesRequest>(req).await {
+                Some(request) => request,
+                None => return status(StatusCode::BAD_REQUEST),
+            };
+            let mut state = state.lock().unwrap();
+            match state.channel_mut(request.guild_id, request.channel_id) {
+                Some(channel) => hrpc_response(history_page(channel, &request)),
+                None => status(StatusCode::NOT_FOUND),
+            }
+        }
+        "/protocol.chat.v1.ChatService/GetMessage" => {
+            let request = match decode::<GetMessageRequest>(req).await {
+                Some(request) => request,
+                None => return status(StatusCode::BAD_REQUEST),
+            };
+            let mut state = state.lock().unwrap();
+            let message = state
+                .channel_mut(request.guild_id, request.channel_id)
+                .and_then(|c| c.messages.iter().find(|(id, _)| *id == request.message_id))
+                .map(|(_, message)| message.clone());
+            match message {
+                Some(message) => hrpc_response(GetMessageResponse { message: Some(message) }),
+                None => status(StatusCode::NOT_FOUND),
+            }
+        }
+        "/protocol.chat.v1.ChatService/SendMessage" => {
+            let request = match decode::<SendMessageRequest>(req).await {
+                Some(request) => request,
+                None => return status(StatusCode::BAD_REQUEST),
+            };
+            let mut state = state.lock().unwrap();
+            let message_id = state.next_id();
+            let message = HarmonyMessage {
+                author_id: USER_ID,
+                created_at: UNIX_EPOCH.elapsed().unwrap().as_secs(),
+                content: request.content,
+                in_reply_to: request.in_reply_to,
+                overrides: request.overrides,
+                ..Default::default()
+            };
+            match state.channel_mut(request.guild_id, request.channel_id) {
+                Some(channel) => {
+                    channel.messages.push((message_id, message.clone()));
+                    let _ = events.send(StreamEventsResponse {
+                        event: Some(stream_events_response::Event::Chat(ChatStreamEvent {
+                            event: Some(ChatEvent::SentMessage(MessageSent {
+                                echo_id: request.echo_id,
+                                guild_id: request.guild_id,
+                                channel_id: request.channel_id,
+                                message_id,
+                                message: Some(message),
+                            })),
+                        })),
+                    });
+                    hrpc_response(SendMessageResponse { message_id })
+                }
+                None => status(StatusCode::NOT_FOUND),
+            }
+        }
+        "/protocol.chat.v1.ChatService/AddReaction" => {
+            let request = match decode::<AddReactionRequest>(req).await {
+                Some(request) => request,
+                None => return status(StatusCode::BAD_REQUEST),
+            };
+            let emote = request.emote.unwrap_or_default();
+            let mut state = state.lock().unwrap();
+            let ids = (request.guild_id, request.channel_id, request.message_id);
+            if change_reaction(&mut state, &events, ids, emote, 1) {
+                hrpc_response(AddReactionResponse {})
+            } else {
+                status(StatusCode::NOT_FOUND)
+            }
+        }
+        "/protocol.chat.v1.ChatService/RemoveReaction" => {
+            let request = match decode::<RemoveReactionRequest>(req).await {
+                Some(request) => request,
+                None => return status(StatusCode::BAD_REQUEST),
+            };
+            let emote = Emote {
+                image_id: request.image_id,
+                ..Default::default()
+            };
+            let mut state = state.lock().unwrap();
+            let ids = (request.guild_id, request.channel_id, request.message_id);
+            if change_reaction(&mut state, &events, ids, emote, -1) {
+                hrpc_response(RemoveReactionResponse {})
+            } else {
+                status(StatusCode::NOT_FOUND)
+            }
+        }
+        "/protocol.chat.v1.ChatService/QueryHasPermission" => hrpc_response(QueryHasPermissionResponse { ok: true }),
+        "/protocol.chat.v1.ChatService/StreamEvents" => stream_events(req, events),
+        "/protocol.profile.v1.ProfileService/GetProfile" => {
+            let request = match decode::<GetProfileRequest>(req).await {
+                Some(request) => request,
+                None => return status(StatusCode::BAD_REQUEST),
+            };
+            hrpc_response(GetProfileResponse {
+                profile: Some(Profile {
+                    user_name: if request.user_id == USER_ID {
+                        USER_NAME.to_string()
+                    } else {
+                        format!("user {}", request.user_id)
+                    },
+                    ..Default::default()
+                }),
+            })
+        }
+        "/protocol.profile.v1.ProfileService/UpdateProfile" => status(StatusCode::OK),
+        "/_harmony/media/upload" => upload(req, state).await,
+        _ if path.starts_with("/_harmony/media/download/") => {
+            let id = path.trim_start_matches("/_harmony/media/download/");
+            let state = state.lock().unwrap();
+            match state.files.get(id) {
+                Some((name, mimetype, data)) => Response::builder()
+                    .header(CONTENT_TYPE, mimetype.as_str())
+                    .header("content-disposition", format!("inline; filename={}", name))
+                    .body(Body::from(data.clone()))
+                    .unwrap(),
+                None => status(StatusCode::NOT_FOUND),
+            }
+        }
+        _ => status(StatusCode::NOT_FOUND),
+    }
+}
+
+/// Adds `delta` to a message's reaction count and broadcasts the new count.
+///
+/// Returns `false` if the message doesn't exist.
+fn change_reaction(
+    state: &mut State,
+    events: &broadcast::Sender<StreamEventsResponse>,
+    (guild_id, channel_id, message_id): (u64, u64, u64),
+    emote: Emote,
+    delta: i32,
+) -> bool {
+    let message = match state
+        .channel_mut(guild_id, channel_id)
+        .and_then(|c| c.messages.iter_mut().find(|(id, _)| *id == message_id))
+    {
+        Some((_, message)) => message,
+        None => return false,
+    };
+    let pos = message
+        .reactions
+        .iter()
+        .position(|r| r.emote.as_ref().map(|e| e.image_id.as_str()) == Some(emote.image_id.as_str()));
+    let reaction = match pos {
+        Some(pos) => &mut message.reactions[pos],
+        None => {
+            message.reactions.push(Reaction {
+                emote: Some(emote),
+                count: 0,
+            });
+            message.reactions.last_mut().unwrap()
+        }
+    };
+    reaction.count = (reaction.count as i32 + delta).max(0) as u32;
+    let reaction = reaction.clone();
+    message.reactions.retain(|r| r.count > 0);
+
+    let _ = events.send(StreamEventsResponse {
+        event: Some(stream_events_response::Event::Chat(ChatStreamEvent {
+            event: Some(ChatEvent::ReactionUpdated(ReactionUpdated {
+                guild_id,
+                channel_id,
+                message_id,
+                reaction: Some(reaction),
+            })),
+        })),
+    });
+    true
+}
+
+/// Login is a "login" choice followed by an email / password form.
+fn next_auth_step(request: NextStepRequest) -> AuthStep {
+    let step = match request.step {
+        None => auth_step::Step::Choice(AuthChoice {
+            title: "initial".to_string(),
+            options: vec!["login".to_string()],
+        }),
+        Some(next_step_request::Step::Choice(choice)) if choice.choice == "login" => auth_step::Step::Form(AuthForm {
+            title: "login".to_string(),
+            fields: vec![
+                FormField {
+                    name: "email".to_string(),
+                    r#type: "email".to_string(),
+                },
+                FormField {
+                    name: "password".to_string(),
+                    r#type: "password".to_string(),
+                },
+            ],
+        }),
+        Some(next_step_request::Step::Form(form)) => {
+            let values = form.fields.into_iter().flat_map(|f| f.field).collect::<Vec<_>>();
+            let valid = matches!(
+                values.as_slice(),
+                [Field::String(email), Field::Bytes(password)] if email == EMAIL && password == PASSWORD.as_bytes()
+            );
+            if valid {
+                auth_step::Step::Session(AuthSession {
+                    user_id: USER_ID,
+                    session_token: SESSION_TOKEN.to_string(),
+                })
+            } else {
+                return next_auth_step(NextStepRequest::default());
+            }
+        }
+        Some(_) => return next_auth_step(NextStepRequest::default()),
+    };
+
+    AuthStep {
+        can_go_back: false,
+        step: Some(step),
+        ..Default::default()
+    }
+}
+
+/// Pages through a channel's history like a homeserver does: `Before` pages are newest first,
+/// `After` and `Around` pages are oldest first.
+fn history_page(channel: &MockChannel, request: &GetChannelMessagesRequest) -> GetChannelMessagesResponse {
+    let messages = &channel.messages;
+    let position = request
+        .message_id
+        .filter(|id| *id != 0)
+        .and_then(|id| messages.iter().position(|(message_id, _)| *message_id == id));
+    let direction = request
+        .direction
+        .and_then(Direction::from_i32)
+        .unwrap_or(Direction::BeforeUnspecified);
+
+    let (range, newest_first) = match direction {
+        // Like a homeserver, history before an id doesn't need a message with that id, since ids grow
+        // with time
+        Direction::BeforeUnspecified => {
+            let end = request.message_id.filter(|id| *id != 0).map_or(messages.len(), |id| {
+                messages.partition_point(|(message_id, _)| *message_id < id)
+            });
+            (end.saturating_sub(PAGE_SIZE)..end, true)
+        }
+        Direction::After => {
+            let start = position.map_or(messages.len(), |pos| pos + 1);
+            (start..(start + PAGE_SIZE).min(messages.len()), false)
+        }
+        Direction::Around => {
+            let pos = position.unwrap_or(messages.len().saturating_sub(1));
+            let start = pos.saturating_sub(PAGE_SIZE / 2);
+            (start..(pos + PAGE_SIZE / 2 + 1).min(messages.len()), false)
+        }
+    };
+
+    let reached_top = range.start == 0;
+    let mut page = messages[range]
+        .iter()
+        .map(|(message_id, message)| MessageWithId {
+            message_id: *message_id,
+            message: Some(message.clone()),
+        })
+        .collect::<Vec<_>>();
+    if newest_first {
+        page.reverse();
+    }
+
+    GetChannelMessagesResponse {
+        reached_top,
+        messages: page,
+    }
+}
+
+fn stream_events(req: Request<Body>, events: broadcast::Sender<StreamEventsResponse>) -> Response<Body> {
+    let key = match req.headers().get("sec-websocket-key") {
+        Some(key) => derive_accept_key(key.as_bytes()),
+        None => return status(StatusCode::BAD_REQUEST),
+    };
+    let protocol = req.headers().get("sec-websocket-protocol").cloned();
+    let mut rx = events.subscribe();
+
+    tokio::spawn(async move {
+        let upgraded = match hyper::upgrade::on(req).await {
+            Ok(upgraded) => upgraded,
+            Err(_) => return,
+        };
+        let mut socket = WebSocketStream::from_raw_socket(upgraded, Role::Server, None).await;
+
+        loop {
+            tokio::select! {
+                // Subscriptions are ignored, every event goes to every stream
+                incoming = socket.next() => match incoming {
+                    Some(Ok(WsMessage::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => {}
+                },
+                event = rx.recv() => match event {
+                    Ok(event) => {
+                        if socket.send(WsMessage::Binary(event.encode_to_vec())).await.is_err() {
+                            break;
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Lagged(_)) => {}
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+            }
+        }
+    });
+
+    let mut response = status(StatusCode::SWITCHING_PROTOCOLS);
+    let headers = response.headers_mut();
+    headers.insert(CONNECTION, HeaderValue::from_static("upgrade"));
+    headers.insert(UPGRADE, HeaderValue::from_static("websocket"));
+    headers.insert("sec-websocket-accept", HeaderValue::from_str(&key).unwrap());
+    if let Some(protocol) = protocol {
+        headers.insert("sec-websocket-protocol", protocol);
+    }
+    response
+}
+
+/// Takes the first file of a multipart upload and stores it.
+async fn upload(req: Request<Body>, state: Arc<Mutex<State>>) -> Response<Body> {
+    let query = req.uri().query().unwrap_or_default().to_string();
+    let boundary = req
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split("boundary=").nth(1))
+        .map(|boundary| format!("--{}", boundary.trim_matches('"')));
+    let body = match hyper::body::to_bytes(req.into_body()).await {
+        Ok(body) => body.to_vec(),
+        Err(_) => return status(StatusCode::BAD_REQUEST),
+    };
+
+    let data = match boundary {
+        Some(boundary) => {
+            let find = |haystack: &[u8], needle: &[u8]| haystack.windows(needle.len()).position(|w| w == needle);
+            let headers_end = match find(&body, b"\r\n\r\n") {
+                Some(pos) => pos + 4,
+                None => return status(StatusCode::BAD_REQUEST),
+            };
+            let rest = &body[headers_end..];
+            let end = find(rest, format!("\r\n{}", boundary).as_bytes()).unwrap_or(rest.len());
+            rest[..end].to_vec()
+        }
+        None => body,
+    };
+
+    let param = |name: &str| {
+        query
+            .split('&')
+            .find_map(|pair| pair.strip_prefix(name)?.strip_prefix('='))
+            .map(|value| client::urlencoding::decode(value).map_or_else(|_| value.to_string(), |v| v.into_owned()))
+    };
+    let name = param("filename").unwrap_or_else(|| "unknown".to_string());
+    let mimetype = param("contentType").unwrap_or_else(|| "application/octet-stream".to_string());
+
+    let mut state = state.lock().unwrap();
+    let id = format!("file{}", state.next_id());
+    state.files.insert(id.clone(), (name, mimetype, data));
+
+    Response::builder()
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(format!("{{\"id\":\"{}\"}}", id)))
+        .unwrap()
+}
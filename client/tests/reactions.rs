@@ -0,0 +1,176 @@
+mod mock;
+
+use std::time::Duration;
+
+use client::{
+    harmony_rust_sdk::api::{
+        chat::{
+            stream_event::{Event as ChatEvent, ReactionUpdated},
+            Event, EventSource, Reaction,
+        },
+        emote::Emote,
+    },
+    message::MessageId,
+    Client,
+};
+use mock::MockHomeserver;
+
+const GUILD_ID: u64 = 1;
+const CHANNEL_ID: u64 = 2;
+const THUMBS_UP: &str = "👍";
+
+/// Starts a client that has the only message of the mock channel loaded.
+async fn client_with_message(mock: &MockHomeserver) -> (Client, u64) {
+    mock.add_guild(GUILD_ID, CHANNEL_ID, 1);
+    let mut client = mock.logged_in_client().await;
+
+    let (message_id, message) = mock.state.lock().unwrap().guilds[0].channels[0].messages[0].clone();
+    client.process_event(mock::message_sent(GUILD_ID, CHANNEL_ID, message_id, message));
+
+    (client, message_id)
+}
+
+fn reactions(client: &mut Client, message_id: u64) -> Vec<client::message::Reaction> {
+    client
+        .get_channel(GUILD_ID, CHANNEL_ID)
+        .unwrap()
+        .messages
+        .get(&MessageId::Ack(message_id))
+        .unwrap()
+        .reactions
+        .clone()
+}
+
+#[tokio::test]
+async fn reaction_updates_change_counts() {
+    let mock = MockHomeserver::start().await;
+    let (mut client, message_id) = client_with_message(&mock).await;
+
+    let update = |count| {
+        Event::Chat(ChatEvent::ReactionUpdated(ReactionUpdated {
+            guild_id: GUILD_ID,
+            channel_id: CHANNEL_ID,
+            message_id,
+            reaction: Some(Reaction {
+                emote: Some(Emote {
+                    image_id: "emote-id".to_string(),
+                    name: "party".to_string(),
+                }),
+                count,
+            }),
+        }))
+    };
+
+    let post = client.process_event(update(2));
+    assert!(!post.is_empty(), "the custom emote's image should be fetched");
+    let reaction = &reactions(&mut client, message_id)[0];
+    assert_eq!(reaction.count, 2);
+    assert!(!reaction.is_unicode());
+
+    client.process_event(update(0));
+    assert!(reactions(&mut client, message_id).is_empty());
+}
+
+#[tokio::test]
+async fn only_the_first_and_last_reaction_change_how_a_message_is_shown() {
+    let mock = MockHomeserver::start().await;
+    let (mut client, message_id) = client_with_message(&mock).await;
+    let revision = |client: &mut Client| {
+        client
+            .get_channel(GUILD_ID, CHANNEL_ID)
+            .unwrap()
+            .messages
+            .revision_of(&MessageId::Ack(message_id))
+    };
+
+    let before = revision(&mut client);
+    client.toggle_reaction(GUILD_ID, CHANNEL_ID, message_id, THUMBS_UP, THUMBS_UP);
+    let first = revision(&mut client);
+    assert_ne!(first, before);
+
+    // Reacting again only changes the count
+    client.set_reacted(GUILD_ID, CHANNEL_ID, message_id, THUMBS_UP, false);
+    client.toggle_reaction(GUILD_ID, CHANNEL_ID, message_id, THUMBS_UP, THUMBS_UP);
+    assert_eq!(revision(&mut client), first);
+
+    client.toggle_reaction(GUILD_ID, CHANNEL_ID, message_id, THUMBS_UP, THUMBS_UP);
+    assert_eq!(revision(&mut client), first);
+    client.set_reacted(GUILD_ID, CHANNEL_ID, message_id, THUMBS_UP, true);
+    client.toggle_reaction(GUILD_ID, CHANNEL_ID, message_id, THUMBS_UP, THUMBS_UP);
+    assert!(reactions(&mut client, message_id).is_empty());
+    assert_ne!(revision(&mut client), first);
+}
+
+#[tokio::test]
+async fn toggling_a_reaction_is_applied_right_away_and_confirmed() {
+    let mock = MockHomeserver::start().await;
+    let (mut client, message_id) = client_with_message(&mock).await;
+
+    let (mut tx, mut rx) = client.inner().subscribe_events(false).await.unwrap().split();
+    tx.add_source(EventSource::Guild(GUILD_ID)).await.unwrap();
+
+    let add = client
+        .toggle_reaction_cmd(GUILD_ID, CHANNEL_ID, message_id, THUMBS_UP.into(), THUMBS_UP.into())
+        .unwrap();
+    let reaction = reactions(&mut client, message_id)[0].clone();
+    assert_eq!(reaction.reacted, Some(true));
+    assert_eq!(reaction.count, 1);
+    let (was_reacted, result) = add.await;
+    assert_eq!(was_reacted, Some(false));
+    assert!(result.unwrap());
+
+    // The homeserver's count replaces ours, but it doesn't know that we were the one reacting
+    let event = tokio::time::timeout(Duration::from_secs(5), rx.get_event())
+        .await
+        .expect("reaction update should arrive on the event stream")
+        .unwrap()
+        .unwrap();
+    client.process_event(event);
+    assert_eq!(reactions(&mut client, message_id), vec![reaction]);
+
+    let remove = client
+        .toggle_reaction_cmd(GUILD_ID, CHANNEL_ID, message_id, THUMBS_UP.into(), THUMBS_UP.into())
+        .unwrap();
+    assert!(reactions(&mut client, message_id).is_empty());
+    assert!(!remove.await.1.unwrap());
+}
+
+#[tokio::test]
+async fn toggling_a_reaction_we_may_not_have_made_removes_it_if_the_count_goes_down() {
+    let mock = MockHomeserver::start().await;
+    let (mut client, message_id) = client_with_message(&mock).await;
+
+    // A reaction loaded from the homeserver, which doesn't say whether we reacted
+    let reaction = Reaction {
+        emote: Some(Emote {
+            image_id: THUMBS_UP.to_string(),
+            name: THUMBS_UP.to_string(),
+        }),
+        count: 2,
+    };
+    mock.state.lock().unwrap().guilds[0].channels[0].messages[0]
+        .1
+        .reactions
+        .push(reaction.clone());
+    client.process_event(Event::Chat(ChatEvent::ReactionUpdated(ReactionUpdated {
+        guild_id: GUILD_ID,
+        channel_id: CHANNEL_ID,
+        message_id,
+        reaction: Some(reaction),
+    })));
+    assert_eq!(reactions(&mut client, message_id)[0].reacted, None);
+
+    // Nothing changes until the homeserver answers
+    let toggle = client
+        .toggle_reaction_cmd(GUILD_ID, CHANNEL_ID, message_id, THUMBS_UP.into(), THUMBS_UP.into())
+        .unwrap();
+    assert_eq!(reactions(&mut client, message_id)[0].count, 2);
+    let result = toggle.await;
+    assert_eq!(result.0, None);
+    let err = client.process_reaction_toggle_result(GUILD_ID, CHANNEL_ID, message_id, THUMBS_UP, THUMBS_UP, result);
+    assert!(err.is_none());
+
+    assert_eq!(reactions(&mut client, message_id)[0].reacted, Some(false));
+    let remaining = mock.state.lock().unwrap().guilds[0].channels[0].messages[0].1.reactions[0].count;
+    assert_eq!(remaining, 1);
+}
@@ -0,0 +1,63 @@
+mod mock;
+
+use client::harmony_rust_sdk::api::chat::Event;
+use mock::MockHomeserver;
+
+const GUILD_ID: u64 = 1;
+const CHANNEL_ID: u64 = 2;
+const OTHER_USER_ID: u64 = 99;
+
+fn message_from(sender: u64, message_id: u64, text: &str) -> Event {
+    let mut message = mock::text_message(text, message_id);
+    message.author_id = sender;
+    mock::message_sent(GUILD_ID, CHANNEL_ID, message_id, message)
+}
+
+#[tokio::test]
+async fn unread_and_mentions_are_counted() {
+    let mock = MockHomeserver::start().await;
+    mock.add_guild(GUILD_ID, CHANNEL_ID, 0);
+    let mut client = mock.logged_in_client().await;
+
+    client.process_event(message_from(OTHER_USER_ID, 10, "hello"));
+    client.process_event(message_from(OTHER_USER_ID, 11, &format!("hey <@{}>", mock::USER_ID)));
+    let marker = client.get_channel(GUILD_ID, CHANNEL_ID).unwrap().read_marker;
+    assert_eq!(marker.unread_count, 2);
+    assert_eq!(marker.mention_count, 1);
+
+    // Sending a message means everything before it was read
+    client.process_event(message_from(mock::USER_ID, 12, "reply"));
+    let marker = client.get_channel(GUILD_ID, CHANNEL_ID).unwrap().read_marker;
+    assert!(!marker.has_unread());
+    assert_eq!(marker.last_read, Some(12));
+
+    client.process_event(message_from(OTHER_USER_ID, 13, "more"));
+    client.mark_guild_read(GUILD_ID);
+    let marker = client.get_channel(GUILD_ID, CHANNEL_ID).unwrap().read_marker;
+    assert!(!marker.has_unread());
+    assert_eq!(marker.last_read, Some(13));
+}
+
+#[tokio::test]
+async fn read_markers_survive_restart() {
+    let mock = MockHomeserver::start().await;
+    mock.add_guild(GUILD_ID, CHANNEL_ID, 0);
+    let content_store = mock::temp_content_store();
+
+    let mut client = mock.logged_in_client_with(content_store.clone()).await;
+    client.process_event(message_from(OTHER_USER_ID, 10, "first"));
+    client.process_event(message_from(OTHER_USER_ID, 11, &format!("<@{}> second", mock::USER_ID)));
+    client.process_event(message_from(OTHER_USER_ID, 12, "third"));
+    client.mark_channel_read(GUILD_ID, CHANNEL_ID);
+    client.mark_unread_from(GUILD_ID, CHANNEL_ID, 11);
+
+    let marker = client.get_channel(GUILD_ID, CHANNEL_ID).unwrap().read_marker;
+    assert_eq!(marker.last_read, Some(10));
+    assert_eq!(marker.unread_count, 2);
+    assert_eq!(marker.mention_count, 1);
+    drop(client);
+
+    let mut client = mock.logged_in_client_with(content_store).await;
+    client.load_read_markers().unwrap();
+    assert_eq!(client.get_channel(GUILD_ID, CHANNEL_ID).unwrap().read_marker, marker);
+}
@@ -0,0 +1,71 @@
+mod mock;
+
+use std::time::Duration;
+
+use client::harmony_rust_sdk::api::chat::{
+    stream_event::{Event as ChatEvent, PermissionUpdated, RoleCreated, RoleUpdated},
+    EventSource,
+};
+use mock::MockHomeserver;
+
+const GUILD_ID: u64 = 1;
+const CHANNEL_ID: u64 = 2;
+const ROLE_ID: u64 = 3;
+
+#[tokio::test]
+async fn role_and_permission_events_update_guild() {
+    let mock = MockHomeserver::start().await;
+    mock.add_guild(GUILD_ID, CHANNEL_ID, 0);
+    let mut client = mock.logged_in_client().await;
+
+    let (mut tx, mut rx) = client.inner().subscribe_events(false).await.unwrap().split();
+    tx.add_source(EventSource::Guild(GUILD_ID)).await.unwrap();
+
+    mock.push_chat_event(ChatEvent::RoleCreated(RoleCreated {
+        guild_id: GUILD_ID,
+        role_id: ROLE_ID,
+        name: "moderator".to_string(),
+        color: 0x00ff00,
+        hoist: true,
+        pingable: false,
+    }));
+    mock.push_chat_event(ChatEvent::RoleUpdated(RoleUpdated {
+        guild_id: GUILD_ID,
+        role_id: ROLE_ID,
+        new_pingable: Some(true),
+        ..Default::default()
+    }));
+    mock.push_chat_event(ChatEvent::PermissionUpdated(PermissionUpdated {
+        guild_id: GUILD_ID,
+        channel_id: None,
+        query: "messages.send".to_string(),
+        ok: true,
+    }));
+    mock.push_chat_event(ChatEvent::PermissionUpdated(PermissionUpdated {
+        guild_id: GUILD_ID,
+        channel_id: Some(CHANNEL_ID),
+        query: "messages.manage.delete".to_string(),
+        ok: false,
+    }));
+
+    for _ in 0..4 {
+        let event = tokio::time::timeout(Duration::from_secs(5), rx.get_event())
+            .await
+            .expect("event should arrive on the event stream")
+            .unwrap()
+            .unwrap();
+        client.process_event(event);
+    }
+
+    let guild = client.get_guild(GUILD_ID).unwrap();
+    let role = guild.roles.get(&ROLE_ID).expect("role should have been created");
+    assert_eq!(role.name, "moderator");
+    assert_eq!(role.color, [0, 255, 0]);
+    assert!(role.hoist);
+    assert!(role.pingable);
+    assert!(guild.has_perm("messages.send"));
+
+    let channel = guild.channels.get(&CHANNEL_ID).unwrap();
+    assert!(!channel.has_perm("messages.manage.delete"));
+    assert_eq!(channel.perms.len(), 1);
+}
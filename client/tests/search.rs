@@ -0,0 +1,88 @@
+mod mock;
+
+use chrono::NaiveDate;
+use client::search::{SearchFilter, SearchIndex};
+
+const GUILD: u64 = 1;
+const CHANNEL: u64 = 2;
+const OTHER_CHANNEL: u64 = 3;
+
+fn ids(index: &SearchIndex, query: &str, filter: &SearchFilter) -> Vec<u64> {
+    index
+        .search(query, filter, 100)
+        .into_iter()
+        .map(|hit| hit.message_id)
+        .collect()
+}
+
+fn index() -> SearchIndex {
+    let mut index = SearchIndex::default();
+    index.index_message(GUILD, CHANNEL, 1, &mock::message(10, 1, "Hello, WORLD!"));
+    index.index_message(GUILD, CHANNEL, 2, &mock::message(11, 2, "the world is big"));
+    index.index_message(GUILD, OTHER_CHANNEL, 3, &mock::message(10, 3, "worldwide hello"));
+    index
+}
+
+#[test]
+fn queries_ignore_case_and_punctuation() {
+    let index = index();
+    let all = SearchFilter::default();
+
+    assert_eq!(ids(&index, "hello world", &all), [3, 1]);
+    assert_eq!(ids(&index, "HELLO, world!!", &all), [3, 1]);
+    assert_eq!(ids(&index, "  ...  ", &all), [3, 2, 1]);
+    assert!(ids(&index, "hello-there", &all).is_empty());
+}
+
+#[test]
+fn only_the_last_term_matches_as_a_prefix() {
+    let index = index();
+    let all = SearchFilter::default();
+
+    assert_eq!(ids(&index, "wor", &all), [3, 2, 1]);
+    assert_eq!(ids(&index, "hello wor", &all), [3, 1]);
+    // Earlier terms have to match a whole word
+    assert!(ids(&index, "hel world", &all).is_empty());
+}
+
+#[test]
+fn filters_narrow_down_results() {
+    let index = index();
+
+    let from_user = SearchFilter {
+        from_user: Some(11),
+        ..Default::default()
+    };
+    assert_eq!(ids(&index, "world", &from_user), [2]);
+
+    let in_channel = SearchFilter {
+        in_channel: Some((GUILD, OTHER_CHANNEL)),
+        ..Default::default()
+    };
+    assert_eq!(ids(&index, "", &in_channel), [3]);
+
+    let since = SearchFilter {
+        since: Some(NaiveDate::from_ymd(1970, 1, 2)),
+        ..Default::default()
+    };
+    assert!(ids(&index, "", &since).is_empty());
+}
+
+#[test]
+fn removed_and_edited_messages_dont_show_up() {
+    let mut index = index();
+    let all = SearchFilter::default();
+
+    index.index_message(GUILD, CHANNEL, 1, &mock::message(10, 1, "goodbye"));
+    assert_eq!(ids(&index, "hello", &all), [3]);
+    assert_eq!(ids(&index, "goodbye", &all), [1]);
+    assert_eq!(index.len(), 3);
+
+    index.remove_message(GUILD, CHANNEL, 1);
+    assert!(ids(&index, "goodbye", &all).is_empty());
+
+    index.remove_messages_in(GUILD, Some(OTHER_CHANNEL));
+    assert_eq!(ids(&index, "", &all), [2]);
+    index.remove_messages_in(GUILD, None);
+    assert!(index.is_empty());
+}
@@ -0,0 +1,58 @@
+mod mock;
+
+use client::{channel::Thread, message::MessageId, Client};
+use mock::MockHomeserver;
+
+const GUILD_ID: u64 = 1;
+const CHANNEL_ID: u64 = 2;
+
+fn send(client: &mut Client, message_id: u64, reply_to: Option<u64>) {
+    let mut message = mock::text_message(&format!("message {}", message_id), message_id);
+    message.in_reply_to = reply_to;
+    client.process_event(mock::message_sent(GUILD_ID, CHANNEL_ID, message_id, message));
+}
+
+#[tokio::test]
+async fn thread_has_parents_and_reply_tree() {
+    let mock = MockHomeserver::start().await;
+    mock.add_guild(GUILD_ID, CHANNEL_ID, 0);
+    let mut client = mock.logged_in_client().await;
+
+    // 10 <- 11 <- 12 <- (13 <- 15), 14
+    send(&mut client, 10, None);
+    send(&mut client, 11, Some(10));
+    send(&mut client, 12, Some(11));
+    send(&mut client, 13, Some(12));
+    send(&mut client, 14, Some(12));
+    send(&mut client, 15, Some(13));
+    send(&mut client, 16, None);
+
+    let thread = client.get_channel(GUILD_ID, CHANNEL_ID).unwrap().thread(12);
+    assert_eq!(
+        thread,
+        Thread {
+            parents: vec![10, 11],
+            missing_parent: None,
+            replies: vec![
+                (MessageId::Ack(13), 1),
+                (MessageId::Ack(15), 2),
+                (MessageId::Ack(14), 1)
+            ],
+        }
+    );
+}
+
+#[tokio::test]
+async fn thread_reports_unloaded_parent() {
+    let mock = MockHomeserver::start().await;
+    mock.add_guild(GUILD_ID, CHANNEL_ID, 0);
+    let mut client = mock.logged_in_client().await;
+
+    send(&mut client, 21, Some(20));
+    send(&mut client, 22, Some(21));
+
+    let thread = client.get_channel(GUILD_ID, CHANNEL_ID).unwrap().thread(22);
+    assert_eq!(thread.parents, vec![21]);
+    assert_eq!(thread.missing_parent, Some(20));
+    assert!(thread.replies.is_empty());
+}
@@ -0,0 +1,144 @@
+mod mock;
+
+use client::{
+    history::Messages,
+    message::MessageId,
+    viewport::{Heights, Viewport, OVERSCAN},
+};
+
+const MESSAGE_HEIGHT: f32 = 50.0;
+const VIEW_WIDTH: f32 = 800.0;
+const VIEW_HEIGHT: f32 = 500.0;
+
+fn heights(messages: &Messages) -> Heights {
+    let mut heights = Heights::default();
+    heights.update(messages, VIEW_WIDTH, |_| MESSAGE_HEIGHT, |_, _| 0.0);
+    heights
+}
+
+#[test]
+fn only_messages_in_view_are_laid_out() {
+    let mut loaded = Messages::default();
+    loaded.merge(mock::messages(1..=1000), None, None);
+    let heights = heights(&loaded);
+    let total = MESSAGE_HEIGHT * 1000.0;
+
+    // A new view follows the newest messages
+    let viewport = Viewport::default();
+    let visible = viewport.visible(&loaded, &heights, VIEW_HEIGHT);
+    let shown = ((VIEW_HEIGHT + OVERSCAN) / MESSAGE_HEIGHT) as usize;
+    assert_eq!(visible.range, 1000 - shown..1000);
+    assert_eq!(visible.space_before, total - MESSAGE_HEIGHT * shown as f32);
+    assert_eq!(visible.space_after, 0.0);
+    assert_eq!(visible.scroll, 1.0);
+
+    // Halfway through, there is space on both sides
+    let mut viewport = Viewport::default();
+    viewport.scrolled(&loaded, &heights, VIEW_HEIGHT, 0.5);
+    assert!(!viewport.is_at_bottom());
+    let visible = viewport.visible(&loaded, &heights, VIEW_HEIGHT);
+    assert!(visible.range.len() < 50);
+    assert!(visible.space_before > 0.0 && visible.space_after > 0.0);
+    let laid_out = MESSAGE_HEIGHT * visible.range.len() as f32;
+    assert_eq!(visible.space_before + laid_out + visible.space_after, total);
+    assert!((visible.scroll - 0.5).abs() < 0.001);
+
+    // Scrolling to the end follows the newest messages again
+    viewport.scrolled(&loaded, &heights, VIEW_HEIGHT, 1.0);
+    assert!(viewport.is_at_bottom());
+    assert_eq!(viewport.anchor(), None);
+}
+
+#[test]
+fn view_stays_on_its_message_when_older_ones_are_added() {
+    let mut loaded = Messages::default();
+    loaded.merge(mock::messages(501..=1000), None, None);
+    let mut viewport = Viewport::default();
+    viewport.scrolled(&loaded, &heights(&loaded), VIEW_HEIGHT, 0.0);
+    let anchor = viewport.anchor();
+    assert_eq!(anchor, Some(MessageId::Ack(501)));
+
+    let before = viewport.visible(&loaded, &heights(&loaded), VIEW_HEIGHT);
+    assert_eq!(before.range.start, 0);
+    assert_eq!(before.scroll, 0.0);
+
+    // The older messages are laid out above, and the view is scrolled down by as much as they add
+    loaded.merge(mock::messages(1..=500), None, Some(501));
+    let after = viewport.visible(&loaded, &heights(&loaded), VIEW_HEIGHT);
+    let overscanned = (OVERSCAN / MESSAGE_HEIGHT) as usize;
+    assert_eq!(viewport.anchor(), anchor);
+    assert_eq!(after.range, 500 - overscanned..before.range.end + 500);
+    assert_eq!(after.space_before + OVERSCAN, MESSAGE_HEIGHT * 500.0);
+    let max_offset = MESSAGE_HEIGHT * 1000.0 - VIEW_HEIGHT;
+    assert!((after.scroll - MESSAGE_HEIGHT * 500.0 / max_offset).abs() < 0.001);
+}
+
+#[test]
+fn jumping_to_a_message_puts_it_at_the_top() {
+    let mut loaded = Messages::default();
+    loaded.merge(mock::messages(1..=1000), None, None);
+    let heights = heights(&loaded);
+
+    let mut viewport = Viewport::default();
+    viewport.scroll_to(MessageId::Ack(300));
+    let visible = viewport.visible(&loaded, &heights, VIEW_HEIGHT);
+    let overscanned = (OVERSCAN / MESSAGE_HEIGHT) as usize;
+    assert_eq!(visible.range.start, 299 - overscanned);
+    assert_eq!(visible.space_before, MESSAGE_HEIGHT * (299 - overscanned) as f32);
+
+    viewport.scroll_to_bottom();
+    assert!(viewport.is_at_bottom());
+}
+
+#[test]
+fn heights_are_only_estimated_again_when_needed() {
+    let mut loaded = Messages::default();
+    loaded.merge(mock::messages(1..=100), None, None);
+    let mut heights = Heights::default();
+    let mut estimated = 0;
+    let mut update = |heights: &mut Heights, loaded: &Messages, width: f32| {
+        heights.update(
+            loaded,
+            width,
+            |_| {
+                estimated += 1;
+                MESSAGE_HEIGHT
+            },
+            |_, _| 0.0,
+        );
+        std::mem::take(&mut estimated)
+    };
+
+    assert_eq!(update(&mut heights, &loaded, VIEW_WIDTH), 100);
+    let version = heights.version();
+    assert_eq!(update(&mut heights, &loaded, VIEW_WIDTH), 0);
+    assert_eq!(heights.version(), version, "nothing changed");
+
+    // Only new and changed messages are estimated
+    loaded.merge(mock::messages(101..=110), Some(100), None);
+    loaded.get_mut(&MessageId::Ack(50)).unwrap().being_edited = Some("edited".to_string());
+    loaded.changed(MessageId::Ack(50));
+    assert_eq!(update(&mut heights, &loaded, VIEW_WIDTH), 11);
+    assert_ne!(heights.version(), version);
+
+    // Text wraps differently in another width
+    assert_eq!(update(&mut heights, &loaded, VIEW_WIDTH / 2.0), 110);
+}
+
+#[test]
+fn scrolling_doesnt_move_the_view() {
+    let mut loaded = Messages::default();
+    loaded.merge(mock::messages(1..=1000), None, None);
+    let heights = heights(&loaded);
+
+    let mut viewport = Viewport::default();
+    let moves = viewport.moves();
+    viewport.scrolled(&loaded, &heights, VIEW_HEIGHT, 0.5);
+    assert_eq!(viewport.moves(), moves, "the view is already where it was scrolled");
+
+    viewport.scroll_to(MessageId::Ack(300));
+    assert_ne!(viewport.moves(), moves);
+    let moves = viewport.moves();
+    viewport.scroll_to_bottom();
+    assert_ne!(viewport.moves(), moves);
+}
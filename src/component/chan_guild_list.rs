@@ -1,22 +1,26 @@
 use crate::{
     client::{
         channel::Channels,
+        drafts::Drafts,
         guild::{Guild, Guilds},
     },
     component::*,
     label,
-    screen::{main::Message, truncate_string},
+    screen::{main::Message, truncate_string, AccountInfo, AccountKey},
     space,
     style::{Theme, AVATAR_WIDTH, DEF_SIZE, PADDING, SPACING},
 };
 
 use client::{bool_ext::BoolExt, channel::Channel};
 use iced::{tooltip::Position, Tooltip};
+use std::ops::Not;
 
 /// Builds a room list.
 #[allow(clippy::too_many_arguments)]
 pub fn build_channel_list<'a>(
     channels: &Channels,
+    guild_id: u64,
+    drafts: &Drafts,
     current_channel_id: Option<u64>,
     state: &'a mut scrollable::State,
     buttons_state: &'a mut [button::State],
@@ -25,7 +29,7 @@ pub fn build_channel_list<'a>(
 ) -> Element<'a, Message> {
     type Item<'a, 'b> = ((&'b u64, &'b Channel), &'a mut button::State);
     let process_item = |mut list: Scrollable<'a, Message>, ((channel_id, channel), button_state): Item<'a, '_>| {
-        let read_color = channel.has_unread.then(|| theme.user_theme.text).unwrap_or(Color {
+        let read_color = channel.has_unread().then(|| theme.user_theme.text).unwrap_or(Color {
             r: theme.user_theme.dimmed_text.r * 0.7,
             g: theme.user_theme.dimmed_text.g * 0.7,
             b: theme.user_theme.dimmed_text.b * 0.7,
@@ -38,6 +42,23 @@ pub fn build_channel_list<'a>(
             .is_category
             .and_do(|| content_widgets.push(space!(w = SPACING).into()));
         content_widgets.push(label!(truncate_string(&channel.name, 17)).size(DEF_SIZE - 2).into());
+        if drafts.has_draft(guild_id, *channel_id) && current_channel_id != Some(*channel_id) {
+            content_widgets.push(space!(w = SPACING).into());
+            content_widgets.push(
+                icon(Icon::Pencil)
+                    .size(DEF_SIZE - 4)
+                    .color(theme.user_theme.dimmed_text)
+                    .into(),
+            );
+        }
+        if let Some(badge) = unread_badge(
+            channel.read_marker.unread_count,
+            channel.read_marker.mention_count,
+            theme,
+        ) {
+            content_widgets.push(space!(w+).into());
+            content_widgets.push(badge);
+        }
 
         let mut but = Button::new(
             button_state,
@@ -90,7 +111,9 @@ pub fn build_guild_list<'a>(
     type Item<'a, 'b> = ((&'b u64, &'b Guild), (usize, &'a mut button::State));
     let process_item = |mut list: Scrollable<'a, Message>, ((guild_id, guild), (index, button_state)): Item<'a, '_>| {
         let mk_but = |state: &'a mut button::State, content: Element<'a, Message>| {
-            let theme = if guild.channels.values().any(|c| c.has_unread) {
+            let theme = if guild.channels.values().any(|c| c.read_marker.mention_count > 0) {
+                theme.border_color(theme.user_theme.mention_color)
+            } else if guild.channels.values().any(Channel::has_unread) {
                 theme.border_color(Color::WHITE)
             } else {
                 *theme
@@ -144,6 +167,16 @@ pub fn build_guild_list<'a>(
                 .style(theme.secondary()),
         );
 
+        let (unread_count, mention_count) = guild.channels.values().fold((0, 0), |(unread, mentions), c| {
+            (
+                unread + c.read_marker.unread_count,
+                mentions + c.read_marker.mention_count,
+            )
+        });
+        if let Some(badge) = unread_badge(unread_count, mention_count, theme) {
+            list = list.push(Container::new(badge).width(length!(+)).center_x());
+        }
+
         if index < buttons_state_len - 1 {
             list = list.push(Rule::horizontal(SPACING).style(theme.secondary()));
         }
@@ -170,3 +203,62 @@ pub fn build_guild_list<'a>(
         .fold(list_init, process_item)
         .into()
 }
+
+/// Builds the list of connected accounts, shown above the guild list.
+pub fn build_account_list<'a>(
+    accounts: Vec<AccountInfo>,
+    buttons_state: &'a mut [button::State],
+    on_button_press: fn(AccountKey) -> Message,
+    theme: &Theme,
+) -> Element<'a, Message> {
+    let mut list = Column::new().spacing(SPACING).padding(PADDING / 4);
+
+    for (account, button_state) in accounts.into_iter().zip(buttons_state.iter_mut()) {
+        let initial = account.label.chars().next().unwrap_or('u').to_ascii_uppercase();
+        let but_theme = if account.is_current {
+            theme.border_color(theme.user_theme.accent)
+        } else {
+            *theme
+        };
+        let mut but = Button::new(
+            button_state,
+            fill_container(label!(initial).size(DEF_SIZE + 4)).style(but_theme.border_width(0.0)),
+        )
+        .width(length!(+))
+        .height(length!(= 40))
+        .style(but_theme.secondary().border_width(2.0));
+
+        // Mentions of the shown account are already in the guild list
+        let badge = account
+            .is_current
+            .not()
+            .then(|| unread_badge(0, account.mention_count, theme))
+            .flatten();
+        if !account.is_current {
+            but = but.on_press(on_button_press(account.key));
+        }
+
+        list = list.push(
+            Tooltip::new(but, account.label, Position::Right)
+                .gap(PADDING / 2)
+                .style(theme.secondary()),
+        );
+        if let Some(badge) = badge {
+            list = list.push(Container::new(badge).width(length!(+)).center_x());
+        }
+    }
+
+    list.into()
+}
+
+/// A small count of unread messages, showing mentions instead if there are any.
+fn unread_badge<'a>(unread_count: u32, mention_count: u32, theme: &Theme) -> Option<Element<'a, Message>> {
+    let (text, color) = if mention_count > 0 {
+        (format!("@{}", mention_count), theme.user_theme.mention_color)
+    } else if unread_count > 0 {
+        (unread_count.to_string(), theme.user_theme.dimmed_text)
+    } else {
+        return None;
+    };
+    Some(label!(text).size(DEF_SIZE - 4).color(color).into())
+}
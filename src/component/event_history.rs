@@ -5,11 +5,11 @@ use crate::{
         channel::Channel,
         content::ContentStore,
         member::Members,
-        message::{Content as IcyContent, EmbedHeading},
+        message::{Content as IcyContent, Embed, EmbedHeading},
     },
     color,
     component::*,
-    label,
+    label, label_button,
     screen::{
         main::{Message, Mode},
         scale_down, truncate_string,
@@ -21,29 +21,50 @@ use crate::{
     },
     IOSEVKA,
 };
-use chrono::{Datelike, TimeZone};
+use chrono::{Datelike, Local, NaiveDateTime, TimeZone};
 use client::{
     bool_ext::BoolExt,
+    formatting::{emphasis_at, style_at, FormatStyle},
     guild::Guild,
     harmony_rust_sdk::api::{
-        chat::overrides::Reason, mediaproxy::fetch_link_metadata_response::Data as FetchLinkData, profile::UserStatus,
+        chat::{overrides::Reason, Format},
+        mediaproxy::fetch_link_metadata_response::Data as FetchLinkData,
+        profile::UserStatus,
     },
+    highlight::{highlight, Highlight},
     linemd::{
         parser::{Text, Token},
         Parser,
     },
+    mentions_user,
     message::{Attachment, MessageId},
-    Client, HarmonyToken, OptionExt, Uri,
+    outbox::SendState,
+    smol_str::SmolStr,
+    viewport::{Heights, Viewport},
+    AHashSet, Client, HarmonyToken, OptionExt, Uri,
 };
 use iced::{rule::FillMode, Font, Tooltip};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+/// Unicode reactions that are always offered in the message menu.
+const DEFAULT_REACTIONS: [&str; 4] = ["👍", "❤️", "😂", "🎉"];
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum MessageMenuOption {
     Copy(MessageId),
     CopyMessageId(u64),
     Reply(u64),
     Edit(u64),
     Delete(u64),
+    MarkUnread(u64),
+    Save(u64),
+    OpenThread(u64),
+    React {
+        message_id: u64,
+        image_id: SmolStr,
+        name: SmolStr,
+        /// `None` if it isn't known whether we reacted.
+        reacted: Option<bool>,
+    },
 }
 
 impl Display for MessageMenuOption {
@@ -54,13 +75,33 @@ impl Display for MessageMenuOption {
             MessageMenuOption::Reply(_) => "reply",
             MessageMenuOption::Edit(_) => "edit",
             MessageMenuOption::Delete(_) => "delete",
+            MessageMenuOption::MarkUnread(_) => "mark unread",
+            MessageMenuOption::Save(_) => "save",
+            MessageMenuOption::OpenThread(_) => "view thread",
+            MessageMenuOption::React {
+                image_id,
+                name,
+                reacted,
+                ..
+            } => {
+                let action = match reacted {
+                    Some(true) => "unreact",
+                    Some(false) => "react",
+                    None => "react / unreact",
+                };
+                return if image_id == name {
+                    write!(f, "{} {}", action, name)
+                } else {
+                    write!(f, "{} :{}:", action, name)
+                };
+            }
         };
         f.write_str(text)
     }
 }
 
-pub const SHOWN_MSGS_LIMIT: usize = 32;
-pub type EventHistoryButsState = [(
+/// Button states of each message that is laid out.
+pub type EventHistoryButsState = Vec<(
     Vec<button::State>,
     button::State,
     button::State,
@@ -69,13 +110,591 @@ pub type EventHistoryButsState = [(
     Vec<button::State>,
     Vec<button::State>,
     pick_list::State<MessageMenuOption>,
-); SHOWN_MSGS_LIMIT];
+    [button::State; 2],
+    Vec<button::State>,
+    button::State,
+    button::State,
+)>;
 
 const MSG_LR_PADDING: u16 = AVATAR_WIDTH / 4;
 const RIGHT_TIMESTAMP_PADDING: u16 = MSG_LR_PADDING;
 const LEFT_TIMESTAMP_PADDING: u16 = MSG_LR_PADDING + (MSG_LR_PADDING / 4);
 const TIMESTAMP_WIDTH: u16 = DEF_SIZE * 2 + RIGHT_TIMESTAMP_PADDING + LEFT_TIMESTAMP_PADDING;
 
+/// Builds a fenced code block, highlighted for the language in its info string.
+///
+/// `plain_color` is used for code that isn't highlighted.
+fn build_code_block<'a>(
+    code: &str,
+    language: Option<&str>,
+    plain_color: Color,
+    copy_but_state: &'a mut button::State,
+    theme: &Theme,
+) -> Element<'a, Message> {
+    let lines = highlight(code, language)
+        .into_iter()
+        .map(|pieces| {
+            if pieces.is_empty() {
+                // Keep empty lines from collapsing
+                return label!(" ").font(IOSEVKA).size(MESSAGE_SIZE).into();
+            }
+            let pieces = pieces
+                .into_iter()
+                .map(|(highlight, piece)| {
+                    let color = match highlight {
+                        Highlight::Plain => plain_color,
+                        _ => theme.highlight_color(highlight),
+                    };
+                    label!(piece.replace('\t', "    "))
+                        .font(IOSEVKA)
+                        .size(MESSAGE_SIZE)
+                        .color(color)
+                        .into()
+                })
+                .collect();
+            Row::with_children(pieces).into()
+        })
+        .collect();
+
+    let copy_button = Tooltip::new(
+        Button::new(copy_but_state, icon(Icon::Clipboard).size(MESSAGE_SIZE - 2))
+            .style(theme.secondary())
+            .padding(PADDING / 4)
+            .on_press(Message::CopyToClipboard(code.to_string())),
+        "Copy code",
+        iced::tooltip::Position::Top,
+    )
+    .size(MESSAGE_SIZE)
+    .gap(PADDING / 2)
+    .style(theme);
+    let header = Row::with_children(vec![
+        label!(language.filter(|l| !l.is_empty()).unwrap_or("code"))
+            .size(MESSAGE_SIZE - 4)
+            .color(theme.user_theme.dimmed_text)
+            .into(),
+        space!(w+).into(),
+        copy_button.into(),
+    ])
+    .align_items(Align::Center);
+
+    let mut bg_color = theme.user_theme.primary_bg;
+    bg_color.r *= 1.6;
+    bg_color.g *= 1.6;
+    bg_color.b *= 1.6;
+    Container::new(
+        Column::with_children(vec![header.into(), Column::with_children(lines).into()])
+            .spacing(SPACING / 2)
+            .align_items(Align::Start),
+    )
+    .padding(PADDING / 2)
+    .style(theme.border_width(0.0).background_color(bg_color))
+    .into()
+}
+
+/// Renders the markdown of a message's text, one widget for each line.
+///
+/// `formats` are the formats over the markdown that can't be written in it. `message_id` is `None`
+/// for text that isn't sent yet, like the composer preview; its spoilers are always shown.
+#[allow(clippy::too_many_arguments)]
+pub fn build_message_text<'a>(
+    source: &str,
+    formats: &[Format],
+    message_id: Option<MessageId>,
+    revealed_spoilers: &AHashSet<(MessageId, usize)>,
+    color: Color,
+    has_mention: bool,
+    thumbnail_cache: &ThumbnailCache,
+    client: &Client,
+    guild: &Guild,
+    members: &Members,
+    buts_state: &'a mut Vec<button::State>,
+    theme: &Theme,
+) -> Vec<Element<'a, Message>> {
+    let tokens = source.parse_md_custom(HarmonyToken::parse);
+    let mut widgets = Vec::with_capacity(tokens.len());
+
+    let is_emotes_until_line_break = |at: usize| {
+        tokens
+            .iter()
+            .skip(at)
+            .take_while(|tok| !matches!(tok, Token::LineBreak))
+            .all(|tok| matches!(tok, Token::Custom(HarmonyToken::Emote(_))))
+    };
+    let mut only_emotes = is_emotes_until_line_break(0);
+    let mut line_widgets = Vec::with_capacity(5);
+    // Headings make the rest of their line bigger
+    let mut line_size = MESSAGE_SIZE;
+    let mut is_quote = false;
+    let mk_line = |line_widgets: &mut Vec<Element<'a, Message>>, is_quote: bool| -> Element<'a, Message> {
+        let line = Row::with_children(line_widgets.drain(..).collect()).align_items(Align::Center);
+        if is_quote {
+            Row::with_children(vec![
+                label!("▍")
+                    .size(MESSAGE_SIZE)
+                    .color(theme.user_theme.dimmed_text)
+                    .into(),
+                line.into(),
+            ])
+            .align_items(Align::Center)
+            .into()
+        } else {
+            line.into()
+        }
+    };
+    let mk_text_elem = |text: &Text, size: u16| -> Element<Message> {
+        let Text {
+            value,
+            code,
+            bold,
+            italic,
+            ..
+        } = text;
+        // There are no bold or italic variants of our fonts, so emphasis is only shown with color and
+        // size, see the README
+        let offset = (value.as_ptr() as usize).checked_sub(source.as_ptr() as usize);
+        let (kept_bold, kept_italic) = offset.map_or((false, false), |offset| emphasis_at(formats, offset));
+        let (bold, italic) = (*bold || kept_bold, *italic || kept_italic);
+        let mut text_color = match offset.and_then(|offset| style_at(formats, offset)) {
+            Some(FormatStyle::Color(text_color)) => theme.text_color(text_color),
+            Some(FormatStyle::RoleMention(role_id)) => guild
+                .roles
+                .get(&role_id)
+                .map_or(color, |role| tuple_to_iced_color(role.color)),
+            None => color,
+        };
+        if bold {
+            text_color.r = (text_color.r * 1.3).min(1.0);
+            text_color.g = (text_color.g * 1.3).min(1.0);
+            text_color.b = (text_color.b * 1.3).min(1.0);
+        }
+        if italic {
+            text_color.a *= 0.75;
+        }
+        let size = bold.some(size + 1).unwrap_or(size);
+        let mut text = label!(value.trim()).color(text_color).size(size);
+        if *code {
+            text = text.font(IOSEVKA);
+            let mut bg_color = theme.user_theme.primary_bg;
+            bg_color.r *= 1.6;
+            bg_color.g *= 1.6;
+            bg_color.b *= 1.6;
+            Container::new(text)
+                .style(theme.border_width(0.0).background_color(bg_color))
+                .into()
+        } else {
+            text.into()
+        }
+    };
+
+    buts_state.resize_with(tokens.len(), Default::default);
+    for ((at, token), but_state) in tokens.iter().enumerate().zip(buts_state.iter_mut()) {
+        match token {
+            Token::Custom(tok) => match tok {
+                HarmonyToken::Emote(id) => match thumbnail_cache.emotes.get(&FileId::Id(id.to_string())) {
+                    Some(handle) => {
+                        let tooltip = |content, state| {
+                            Tooltip::new(
+                                Button::new(state, content)
+                                    .style(theme)
+                                    .padding(0)
+                                    .on_press(Message::CopyToClipboard(id.to_string())),
+                                format!(
+                                    "Click to copy ID of {}",
+                                    truncate_string(client.get_emote_name(id).unwrap_or(id), 10)
+                                ),
+                                iced::tooltip::Position::Top,
+                            )
+                            .size(MESSAGE_SIZE)
+                            .gap(PADDING / 2)
+                            .style(theme)
+                            .into()
+                        };
+                        if only_emotes {
+                            line_widgets.push(tooltip(
+                                Image::new(handle.clone()).width(length!(= 48)).height(length!( = 48)),
+                                but_state,
+                            ));
+                        } else {
+                            line_widgets.push(tooltip(
+                                Image::new(handle.clone())
+                                    .width(length!(= MESSAGE_SIZE + 4))
+                                    .height(length!( = MESSAGE_SIZE + 4)),
+                                but_state,
+                            ));
+                            line_widgets.push(label!(" ").into());
+                        }
+                    }
+                    None => {
+                        line_widgets.push(label!(format!("<:{}:> ", id)).size(MESSAGE_SIZE).color(color).into());
+                    }
+                },
+                HarmonyToken::Mention(id) => {
+                    let member_name = members.get(id).map_or_else(|| "unknown user", |m| m.username.as_str());
+                    let role_color = guild
+                        .highest_role_for_member(*id)
+                        .map_or(theme.user_theme.text, |(_, role)| tuple_to_iced_color(role.color));
+
+                    line_widgets.push(
+                        Button::new(
+                            but_state,
+                            label!(format!("@{}", member_name)).size(MESSAGE_SIZE).color(role_color),
+                        )
+                        .padding([2, 3])
+                        .height(length!(= MESSAGE_SIZE + 4))
+                        .style({
+                            let theme = theme.background_color(Color { a: 0.1, ..role_color });
+                            has_mention.then(|| theme.border_width(0.0)).unwrap_or(theme)
+                        })
+                        .on_press(Message::SelectedMember(*id))
+                        .into(),
+                    );
+                    line_widgets.push(label!(" ").into());
+                }
+                HarmonyToken::Spoiler(text) => {
+                    let revealed = message_id.map_or(true, |id| revealed_spoilers.contains(&(id, at)));
+                    let dimmed = theme.user_theme.dimmed_text;
+                    let spoiler: Element<Message> = if revealed {
+                        Container::new(label!(*text).size(line_size).color(color))
+                            .padding([2, 3])
+                            .style(theme.border_width(0.0).background_color(Color { a: 0.15, ..dimmed }))
+                            .into()
+                    } else {
+                        // Hide the text by drawing it with the same color as its background
+                        let mut button = Button::new(but_state, label!(*text).size(line_size).color(dimmed))
+                            .padding([2, 3])
+                            .style(theme.border_width(0.0).background_color(dimmed));
+                        if let Some(id) = message_id {
+                            button = button.on_press(Message::RevealSpoiler(id, at));
+                        }
+                        Tooltip::new(button, "Click to reveal spoiler", iced::tooltip::Position::Top)
+                            .size(MESSAGE_SIZE)
+                            .gap(PADDING / 2)
+                            .style(theme)
+                            .into()
+                    };
+                    line_widgets.push(spoiler);
+                    line_widgets.push(label!(" ").into());
+                }
+                HarmonyToken::Strikethrough(text) => {
+                    // Text can't be drawn with a line through it, so struck text is only dimmed
+                    line_widgets.push(
+                        label!(text.trim())
+                            .size(line_size)
+                            .color(theme.user_theme.dimmed_text)
+                            .into(),
+                    );
+                    line_widgets.push(label!(" ").into());
+                }
+                HarmonyToken::Quote => is_quote = true,
+            },
+            Token::Text(text) => {
+                line_widgets.push(mk_text_elem(text, line_size));
+                line_widgets.push(label!(" ").into());
+            }
+            Token::Url { name, url, .. } => {
+                let url = *url;
+                let color = theme.user_theme.accent;
+                let color = Color {
+                    r: color.r * 1.35,
+                    g: color.g * 1.35,
+                    b: color.b * 1.35,
+                    a: color.a,
+                };
+                let label = label!(name.as_ref().map_or(url, |text| text.value))
+                    .color(color)
+                    .size(MESSAGE_SIZE);
+                line_widgets.push(
+                    Tooltip::new(
+                        Button::new(but_state, label)
+                            .padding([2, 3])
+                            .style({
+                                let theme = theme.background_color(Color { a: 0.1, ..color });
+                                has_mention.then(|| theme.border_width(0.0)).unwrap_or(theme)
+                            })
+                            .on_press(Message::OpenUrl(url.into()))
+                            .height(length!(= MESSAGE_SIZE + 4)),
+                        format!("Go to {}", url),
+                        iced::tooltip::Position::Top,
+                    )
+                    .size(MESSAGE_SIZE)
+                    .style(theme)
+                    .gap(PADDING / 2)
+                    .into(),
+                );
+                line_widgets.push(label!(" ").into());
+            }
+            Token::Header(depth) => {
+                line_size = MESSAGE_SIZE + 2 * 4_u16.saturating_sub(*depth as u16);
+            }
+            Token::ListItem(number) => {
+                let prefix = match number {
+                    Some(num) => label!(format!("{}. ", num)),
+                    None => label!("• "),
+                };
+                line_widgets.push(space!(w = PADDING / 2).into());
+                line_widgets.push(prefix.size(MESSAGE_SIZE).color(color).into());
+            }
+            Token::CodeFence { code, attrs } => {
+                only_emotes = is_emotes_until_line_break(at);
+                widgets.push(mk_line(&mut line_widgets, is_quote));
+                widgets.push(build_code_block(code, *attrs, color, but_state, theme));
+            }
+            Token::LineBreak => {
+                only_emotes = is_emotes_until_line_break(at);
+                widgets.push(mk_line(&mut line_widgets, is_quote));
+                line_size = MESSAGE_SIZE;
+                is_quote = false;
+            }
+        }
+    }
+
+    widgets.push(mk_line(&mut line_widgets, is_quote));
+
+    widgets
+}
+
+/// Builds an embed, with header and footer buttons opening their URL with `on_url`.
+pub fn build_embed<'a, M: Clone + 'a>(
+    embed: &Embed,
+    thumbnail_cache: &ThumbnailCache,
+    h_embed_but: &'a mut button::State,
+    f_embed_but: &'a mut button::State,
+    on_url: fn(SmolStr) -> M,
+    theme: &Theme,
+) -> Element<'a, M> {
+    let put_heading = |embed_widgets: &mut Vec<Element<'a, M>>, h: &EmbedHeading, state: &'a mut button::State| {
+        let mut heading = Vec::with_capacity(3);
+
+        if let Some(img_url) = &h.icon {
+            if let Some(handle) = thumbnail_cache.thumbnails.get(img_url) {
+                heading.push(
+                    Image::new(handle.clone())
+                        .height(length!(=24))
+                        .width(length!(=24))
+                        .into(),
+                );
+            }
+        }
+
+        heading.push(label!(&h.text).size(DEF_SIZE + 2).into());
+        if let Some(subtext) = h.subtext.as_deref() {
+            heading.push(label!(subtext).size(DEF_SIZE - 6).color(color!(200, 200, 200)).into());
+        }
+
+        let mut but = Button::new(state, row(heading).padding(0).spacing(SPACING)).style(theme.embed());
+
+        if let Some(url) = h.url.clone() {
+            but = but.on_press(on_url(url));
+        }
+
+        embed_widgets.push(but.into());
+    };
+
+    let mut embed_widgets = Vec::with_capacity(5);
+
+    if let Some(h) = &embed.header {
+        put_heading(&mut embed_widgets, h, h_embed_but);
+    }
+
+    embed_widgets.push(label!(&embed.title).size(DEF_SIZE + 2).into());
+    if let Some(body) = embed.body.as_deref() {
+        embed_widgets.push(label!(body).color(color!(220, 220, 220)).size(DEF_SIZE - 2).into());
+    }
+
+    for f in &embed.fields {
+        // TODO: handle presentation
+        let mut field = Vec::with_capacity(3);
+
+        field.push(label!(&f.title).size(DEF_SIZE - 1).into());
+        if let Some(subtitle) = f.subtitle.as_deref() {
+            field.push(label!(subtitle).size(DEF_SIZE - 3).into());
+        }
+        if let Some(body) = f.body.as_deref() {
+            field.push(label!(body).color(color!(220, 220, 220)).size(DEF_SIZE - 3).into());
+        }
+
+        embed_widgets.push(
+            Container::new(
+                column(field)
+                    .padding(PADDING / 4)
+                    .spacing(SPACING / 4)
+                    .align_items(Align::Start),
+            )
+            .style(theme)
+            .into(),
+        );
+    }
+
+    if let Some(h) = &embed.footer {
+        put_heading(&mut embed_widgets, h, f_embed_but);
+    }
+
+    let mut theme = theme.secondary();
+    if let Some(color) = embed.color {
+        theme = theme.border_color(tuple_to_iced_color(color));
+    }
+
+    Container::new(
+        column(embed_widgets)
+            .padding(PADDING / 2)
+            .spacing(SPACING / 2)
+            .align_items(Align::Start),
+    )
+    .style(theme)
+    .into()
+}
+
+/// Row standing for messages that weren't loaded after a message, with a button to load them.
+fn build_gap_row<'a>(message_id: u64, state: &'a mut button::State, theme: &Theme) -> Element<'a, Message> {
+    Row::with_children(vec![
+        Rule::horizontal(SPACING).style(theme.secondary()).into(),
+        Button::new(state, label!("load missing messages").size(MESSAGE_SIZE))
+            .padding(PADDING / 4)
+            .style(theme.secondary())
+            .on_press(Message::LoadMissingMessages(message_id))
+            .into(),
+        Rule::horizontal(SPACING).style(theme.secondary()).into(),
+    ])
+    .spacing(SPACING)
+    .align_items(Align::Center)
+    .into()
+}
+
+/// Height an embed is estimated to take, see [`HistoryLayout`].
+const EMBED_HEIGHT: f32 = 160.0;
+
+/// Roughly how tall `text` is once laid out `width` pixels wide as message text.
+pub fn estimate_text_height(text: &str, width: f32) -> f32 {
+    let chars_per_line = (width / (f32::from(MESSAGE_SIZE) * 0.55)).max(1.0);
+    let lines = text
+        .lines()
+        .map(|line| (line.chars().count() as f32 / chars_per_line).ceil().max(1.0))
+        .sum::<f32>();
+    lines.max(1.0) * f32::from(MESSAGE_SIZE) * 1.4
+}
+
+/// Roughly how tall a message is once laid out in an event history `width` pixels wide.
+fn estimate_height(message: &client::message::Message, width: f32) -> f32 {
+    let line_height = f32::from(MESSAGE_SIZE) * 1.4;
+    let text_width = (width - f32::from(TIMESTAMP_WIDTH + PADDING * 2 + SPACING)).max(f32::from(MESSAGE_SIZE));
+    let mut height = match &message.content {
+        // Edits are shown as soon as they are sent
+        IcyContent::Text(text) => {
+            estimate_text_height(message.being_edited.as_deref().unwrap_or(&text.text), text_width)
+        }
+        IcyContent::Files(attachments) => attachments
+            .iter()
+            .map(|attachment| {
+                attachment
+                    .resolution
+                    .map_or(line_height * 2.0, |(w, h)| scale_down(w, h, 400).1 as f32)
+            })
+            .sum(),
+        IcyContent::Embeds(_) => EMBED_HEIGHT,
+        IcyContent::System(_) => line_height,
+    };
+    if message.reply_to.is_some() {
+        height += line_height;
+    }
+    if !message.reactions.is_empty() {
+        height += f32::from(MESSAGE_SIZE + PADDING);
+    }
+    height
+}
+
+/// Whether two messages were written on different days, in local time, which separates their groups
+/// with the date.
+fn is_other_day(last: NaiveDateTime, message: NaiveDateTime) -> bool {
+    Local.from_utc_datetime(&last).day() != Local.from_utc_datetime(&message).day()
+}
+
+/// Whether the time between the last message and this one puts them in different groups, even if
+/// they have the same sender.
+fn splits_group(last: NaiveDateTime, message: NaiveDateTime) -> bool {
+    last.signed_duration_since(message) > chrono::Duration::minutes(5)
+}
+
+/// Space above a message, which is taller if it starts a group.
+fn spacing(previous: Option<&client::message::Message>, message: &client::message::Message) -> f32 {
+    let group_spacing = f32::from(AVATAR_WIDTH + PADDING * 2 + SPACING * 3);
+    let previous = match previous {
+        // Groups end at system messages
+        Some(previous) if !matches!(previous.content, IcyContent::System(_)) => previous,
+        _ => return group_spacing,
+    };
+    // Same as the groups made by `build_event_history`, which show who sent them
+    let previous_name = previous.overrides.as_ref().and_then(|ov| ov.name.as_deref());
+    let name = message.overrides.as_ref().and_then(|ov| ov.name.as_deref());
+    if previous.sender != message.sender || previous_name != name {
+        group_spacing
+    } else if is_other_day(previous.timestamp, message.timestamp) {
+        group_spacing + f32::from(DATE_SEPERATOR_SIZE + SPACING * 3)
+    } else if splits_group(previous.timestamp, message.timestamp) {
+        group_spacing
+    } else {
+        f32::from(SPACING)
+    }
+}
+
+/// Layout of the event history of the current channel, kept between views.
+///
+/// Only the messages in view are laid out, and estimated heights stand for the others. Being off
+/// only makes the scrollbar a bit off, and it is corrected as messages come into view.
+#[derive(Debug, Default, Clone)]
+pub struct HistoryLayout {
+    /// The guild and channel the layout is for.
+    channel: Option<(u64, u64)>,
+    heights: Heights,
+    /// The moves of the viewport and version of the heights the scroll position was last set for.
+    snapped: Option<(u64, u64)>,
+}
+
+impl HistoryLayout {
+    /// Brings the estimated heights up to date with the messages of a channel, shown in an event
+    /// history `width` pixels wide.
+    pub fn update(&mut self, guild_id: u64, channel_id: u64, channel: &Channel, width: f32) -> &Heights {
+        if self.channel != Some((guild_id, channel_id)) {
+            *self = HistoryLayout {
+                channel: Some((guild_id, channel_id)),
+                ..Default::default()
+            };
+        }
+        self.heights.update(
+            &channel.messages,
+            width,
+            |message| estimate_height(message, width),
+            spacing,
+        );
+        &self.heights
+    }
+
+    /// Records that the event history is scrolled to where the viewport is, since the user
+    /// scrolled it there.
+    pub fn scrolled(&mut self, viewport: &Viewport) {
+        self.snapped = Some((viewport.moves(), self.heights.version()));
+    }
+
+    /// Whether the scroll position has to be set to where the viewport is, because the viewport
+    /// was moved or the place of its message changed since it last was.
+    fn needs_snap(&mut self, viewport: &Viewport) -> bool {
+        let key = (viewport.moves(), self.heights.version());
+        self.snapped.replace(key) != Some(key)
+    }
+}
+
+/// Empty space standing for messages that aren't laid out.
+fn build_space<'a>(height: f32) -> Element<'a, Message> {
+    // Lengths can't be longer than `u16::MAX`, so the space is split
+    let mut remaining = height.max(0.0) as u32;
+    let mut spaces = Vec::with_capacity(1);
+    while remaining > 0 {
+        let part = remaining.min(u32::from(u16::MAX));
+        spaces.push(space!(h = part as u16).into());
+        remaining -= part;
+    }
+    Column::with_children(spaces).into()
+}
+
 #[allow(clippy::mutable_key_type)]
 #[allow(clippy::too_many_arguments)]
 pub fn build_event_history<'a>(
@@ -86,35 +705,38 @@ pub fn build_event_history<'a>(
     channel: &Channel,
     members: &Members,
     current_user_id: u64,
-    looking_at_message: usize,
+    layout: &mut HistoryLayout,
+    view_height: f32,
+    new_messages_after: Option<u64>,
+    revealed_spoilers: &AHashSet<(MessageId, usize)>,
     scrollable_state: &'a mut scrollable::State,
     buts_sate: &'a mut EventHistoryButsState,
     mode: Mode,
     theme: &Theme,
 ) -> Element<'a, Message> {
+    // Only the messages in view are laid out. The scroll position follows the message the view is
+    // anchored to, so the view stays on it when older messages are added
+    let visible = channel
+        .viewport
+        .visible(&channel.messages, &layout.heights, view_height);
+    if layout.needs_snap(&channel.viewport) {
+        scrollable_state.snap_to(visible.scroll);
+    }
     let mut event_history = Scrollable::new(scrollable_state)
         .on_scroll(Message::MessageHistoryScrolled)
         .width(length!(+))
         .height(length!(+))
         .style(theme)
         .align_items(Align::Start)
-        .spacing(SPACING * 2);
+        .spacing(SPACING * 2)
+        .push(build_space(visible.space_before));
 
-    let timeline_range_end = looking_at_message
-        .saturating_add(SHOWN_MSGS_LIMIT)
-        .min(channel.messages.len());
-    let timeline_range_start = timeline_range_end.saturating_sub(SHOWN_MSGS_LIMIT);
-    let timeline_range_start = channel
-        .messages
-        .get_index_of(&MessageId::Ack(channel.last_known_message_id))
-        .map_or(timeline_range_start, |last_message_pos| {
-            timeline_range_start.max(last_message_pos)
-        });
     let mut displayable_events = channel
         .messages
         .iter()
-        .skip(timeline_range_start)
-        .take(timeline_range_end - timeline_range_start);
+        .skip(visible.range.start)
+        .take(visible.range.len());
+    buts_sate.resize_with(visible.range.len(), Default::default);
 
     let timezone = chrono::Local::now().timezone();
 
@@ -126,7 +748,13 @@ pub fn build_event_history<'a>(
     let mut last_timestamp = timezone.from_utc_datetime(&first_message.timestamp);
     let mut last_sender_id = None;
     let mut last_sender_name = None;
-    let mut message_group = Vec::with_capacity(SHOWN_MSGS_LIMIT);
+    let mut message_group = Vec::with_capacity(visible.range.len());
+    let mut previous_message_id = visible
+        .range
+        .start
+        .checked_sub(1)
+        .and_then(|pos| channel.messages.get_index(pos))
+        .and_then(|(id, _)| id.id());
 
     let push_to_msg_group = |msg_group: &mut Vec<Element<'a, Message>>| {
         let mut content = Vec::with_capacity(msg_group.len());
@@ -158,9 +786,14 @@ pub fn build_event_history<'a>(
             message_buts_state,
             external_url_states,
             menu_list_state,
+            outbox_buts_state,
+            reaction_buts_state,
+            edited_but_state,
+            load_missing_but_state,
         ),
     ) in (std::iter::once((first_message_id, first_message)).chain(displayable_events)).zip(buts_sate.iter_mut())
     {
+        let gap_after = message_id.id().filter(|id| channel.messages.has_gap_after(*id));
         let id_to_use = message_id
             .is_ack()
             .not()
@@ -168,6 +801,52 @@ pub fn build_event_history<'a>(
             .unwrap_or(message.sender);
 
         let message_timestamp = timezone.from_utc_datetime(&message.timestamp);
+
+        let is_first_unread = new_messages_after.is_some() && previous_message_id == new_messages_after;
+        previous_message_id = message_id.id();
+        if is_first_unread {
+            if message_group.is_empty().not() {
+                event_history = event_history.push(push_to_msg_group(&mut message_group));
+            }
+            let separator = Row::with_children(vec![
+                Rule::horizontal(SPACING).style(theme.secondary()).into(),
+                label!("new messages")
+                    .size(MESSAGE_SIZE)
+                    .color(theme.user_theme.mention_color)
+                    .into(),
+                Rule::horizontal(SPACING).style(theme.secondary()).into(),
+            ])
+            .spacing(SPACING)
+            .align_items(Align::Center);
+            event_history = event_history.push(separator);
+            last_sender_id = None;
+            last_sender_name = None;
+        }
+
+        if let IcyContent::System(system) = &message.content {
+            if message_group.is_empty().not() {
+                event_history = event_history.push(push_to_msg_group(&mut message_group));
+            }
+            let system_line = fill_container(
+                label!(format!(
+                    "[{}] {}",
+                    message_timestamp.format("%H:%M"),
+                    system.describe(members)
+                ))
+                .size(MESSAGE_SIZE)
+                .color(theme.user_theme.dimmed_text),
+            )
+            .height(length!(-));
+            event_history = event_history.push(system_line);
+            if let Some(message_id) = gap_after {
+                event_history = event_history.push(build_gap_row(message_id, load_missing_but_state, theme));
+            }
+            last_sender_id = None;
+            last_sender_name = None;
+            last_timestamp = message_timestamp;
+            continue;
+        }
+
         let member = members.get(&id_to_use);
         let name_to_use = member.map_or("unknown member", |member| member.username.as_str());
         let sender_status = member.map_or(UserStatus::OfflineUnspecified, |m| m.status);
@@ -273,7 +952,7 @@ pub fn build_event_history<'a>(
                 event_history = event_history.push(push_to_msg_group(&mut message_group));
             }
             message_group.push(sender_body_creator(avatar_but_state));
-        } else if message_timestamp.day() != last_timestamp.day() {
+        } else if is_other_day(last_timestamp.naive_utc(), message.timestamp) {
             let date_time_seperator = fill_container(
                 label!(message_timestamp.format("[%d %B %Y]").to_string())
                     .size(DATE_SEPERATOR_SIZE)
@@ -287,219 +966,46 @@ pub fn build_event_history<'a>(
                 Rule::horizontal(SPACING).style(theme.border_width(2.0).border_radius(0.0).padded(FillMode::Full)),
             );
             message_group.push(sender_body_creator(avatar_but_state));
-        } else if message_group.is_empty().not()
-            && last_timestamp.signed_duration_since(message_timestamp) > chrono::Duration::minutes(5)
-        {
+        } else if message_group.is_empty().not() && splits_group(last_timestamp.naive_utc(), message.timestamp) {
             event_history = event_history.push(push_to_msg_group(&mut message_group));
             message_group.push(sender_body_creator(avatar_but_state));
         }
 
         let mut message_body_widgets = Vec::with_capacity(2);
 
-        let msg_text = message.being_edited.as_deref().or_else(|| {
-            if let IcyContent::Text(text) = &message.content {
-                Some(text)
-            } else {
-                None
-            }
-        });
+        // Formats don't apply to the text of an edit that isn't confirmed yet
+        let msg_text = match (&message.being_edited, &message.content) {
+            (Some(text), _) => Some((text.as_str(), &[][..])),
+            (None, IcyContent::Text(text)) => Some((text.text.as_str(), text.format.as_slice())),
+            _ => None,
+        };
 
-        if let Some(textt) = msg_text {
-            let tokens = textt.parse_md_custom(HarmonyToken::parse);
-            let mut widgets = Vec::with_capacity(tokens.len());
+        if let Some((textt, formats)) = msg_text {
             let color = theme.user_theme.text;
             let color = message_id
                 .is_ack()
                 .not()
                 .then(|| color!(. color.r * 0.6, color.g * 0.6, color.b * 0.6))
                 .unwrap_or_else(|| {
-                    // Safety: unwrap is safe since we check if it's acked beforehand
-                    (Mode::EditingMessage(message_id.id().unwrap()) == mode)
+                    (message_id.id().map(Mode::EditingMessage) == Some(mode))
                         .then(|| theme.user_theme.error)
                         .unwrap_or(color)
                 });
 
-            let is_emotes_until_line_break = |at: usize| {
-                tokens
-                    .iter()
-                    .skip(at)
-                    .take_while(|tok| !matches!(tok, Token::LineBreak))
-                    .all(|tok| matches!(tok, Token::Custom(HarmonyToken::Emote(_))))
-            };
-            let has_mention = tokens.iter().any(|tok| {
-                if let Token::Custom(HarmonyToken::Mention(id)) = tok {
-                    current_user_id.eq(id)
-                } else {
-                    false
-                }
-            });
-            let mut only_emotes = is_emotes_until_line_break(0);
-            let mut line_widgets = Vec::with_capacity(5);
-            let mk_text_elem = |text: &Text| -> Element<Message> {
-                let Text { value, code, .. } = text;
-                let mut text = label!(value.trim()).color(color).size(MESSAGE_SIZE);
-                if *code {
-                    text = text.font(IOSEVKA);
-                    let mut bg_color = theme.user_theme.primary_bg;
-                    bg_color.r *= 1.6;
-                    bg_color.g *= 1.6;
-                    bg_color.b *= 1.6;
-                    Container::new(text)
-                        .style(theme.border_width(0.0).background_color(bg_color))
-                        .into()
-                } else {
-                    text.into()
-                }
-            };
-
-            message_buts_state.resize_with(tokens.len(), Default::default);
-            for ((at, token), but_state) in tokens.iter().enumerate().zip(message_buts_state.iter_mut()) {
-                match token {
-                    Token::Custom(tok) => match tok {
-                        HarmonyToken::Emote(id) => match thumbnail_cache.emotes.get(&FileId::Id(id.to_string())) {
-                            Some(handle) => {
-                                let tooltip = |content, state| {
-                                    Tooltip::new(
-                                        Button::new(state, content)
-                                            .style(theme)
-                                            .padding(0)
-                                            .on_press(Message::CopyToClipboard(id.to_string())),
-                                        format!(
-                                            "Click to copy ID of {}",
-                                            truncate_string(client.get_emote_name(id).unwrap_or(id), 10)
-                                        ),
-                                        iced::tooltip::Position::Top,
-                                    )
-                                    .size(MESSAGE_SIZE)
-                                    .gap(PADDING / 2)
-                                    .style(theme)
-                                    .into()
-                                };
-                                if only_emotes {
-                                    line_widgets.push(tooltip(
-                                        Image::new(handle.clone()).width(length!(= 48)).height(length!( = 48)),
-                                        but_state,
-                                    ));
-                                } else {
-                                    line_widgets.push(tooltip(
-                                        Image::new(handle.clone())
-                                            .width(length!(= MESSAGE_SIZE + 4))
-                                            .height(length!( = MESSAGE_SIZE + 4)),
-                                        but_state,
-                                    ));
-                                    line_widgets.push(label!(" ").into());
-                                }
-                            }
-                            None => {
-                                line_widgets
-                                    .push(label!(format!("<:{}:> ", id)).size(MESSAGE_SIZE).color(color).into());
-                            }
-                        },
-                        HarmonyToken::Mention(id) => {
-                            let member_name = members.get(id).map_or_else(|| "unknown user", |m| m.username.as_str());
-                            let role_color = guild
-                                .highest_role_for_member(*id)
-                                .map_or(theme.user_theme.text, |(_, role)| tuple_to_iced_color(role.color));
-
-                            line_widgets.push(
-                                Button::new(
-                                    but_state,
-                                    label!(format!("@{}", member_name)).size(MESSAGE_SIZE).color(role_color),
-                                )
-                                .padding([2, 3])
-                                .height(length!(= MESSAGE_SIZE + 4))
-                                .style({
-                                    let theme = theme.background_color(Color { a: 0.1, ..role_color });
-                                    has_mention.then(|| theme.border_width(0.0)).unwrap_or(theme)
-                                })
-                                .on_press(Message::SelectedMember(*id))
-                                .into(),
-                            );
-                            line_widgets.push(label!(" ").into());
-                        }
-                    },
-                    Token::Text(text) => {
-                        line_widgets.push(mk_text_elem(text));
-                        line_widgets.push(label!(" ").into());
-                    }
-                    Token::Url { name, url, .. } => {
-                        let url = *url;
-                        let color = theme.user_theme.accent;
-                        let color = Color {
-                            r: color.r * 1.35,
-                            g: color.g * 1.35,
-                            b: color.b * 1.35,
-                            a: color.a,
-                        };
-                        let label = label!(name.as_ref().map_or(url, |text| text.value))
-                            .color(color)
-                            .size(MESSAGE_SIZE);
-                        line_widgets.push(
-                            Tooltip::new(
-                                Button::new(but_state, label)
-                                    .padding([2, 3])
-                                    .style({
-                                        let theme = theme.background_color(Color { a: 0.1, ..color });
-                                        has_mention.then(|| theme.border_width(0.0)).unwrap_or(theme)
-                                    })
-                                    .on_press(Message::OpenUrl(url.into()))
-                                    .height(length!(= MESSAGE_SIZE + 4)),
-                                format!("Go to {}", url),
-                                iced::tooltip::Position::Top,
-                            )
-                            .size(MESSAGE_SIZE)
-                            .style(theme)
-                            .gap(PADDING / 2)
-                            .into(),
-                        );
-                        line_widgets.push(label!(" ").into());
-                    }
-                    Token::Header(depth) => {
-                        line_widgets.push(
-                            label!((0..*depth + 1)
-                                .enumerate()
-                                .map(|(at, _)| if at == *depth { ' ' } else { '#' })
-                                .collect::<String>())
-                            .color(color)
-                            .size(MESSAGE_SIZE)
-                            .into(),
-                        );
-                    }
-                    Token::ListItem(number) => {
-                        let prefix = match number {
-                            Some(num) => label!(format!("{}. ", num)),
-                            None => label!(". "),
-                        };
-                        line_widgets.push(prefix.size(MESSAGE_SIZE).color(color).into());
-                    }
-                    Token::CodeFence { code, .. } => {
-                        only_emotes = is_emotes_until_line_break(at);
-                        widgets.push(
-                            Row::with_children(line_widgets.drain(..).collect())
-                                .align_items(Align::Center)
-                                .into(),
-                        );
-                        line_widgets.push(mk_text_elem(&Text {
-                            value: code,
-                            code: true,
-                            ..Default::default()
-                        }));
-                    }
-                    Token::LineBreak => {
-                        only_emotes = is_emotes_until_line_break(at);
-                        widgets.push(
-                            Row::with_children(line_widgets.drain(..).collect())
-                                .align_items(Align::Center)
-                                .into(),
-                        );
-                    }
-                }
-            }
-
-            widgets.push(
-                Row::with_children(line_widgets.drain(..).collect())
-                    .align_items(Align::Center)
-                    .into(),
+            let has_mention = mentions_user(textt, current_user_id);
+            let widgets = build_message_text(
+                textt,
+                formats,
+                Some(*message_id),
+                revealed_spoilers,
+                color,
+                has_mention,
+                thumbnail_cache,
+                client,
+                guild,
+                members,
+                message_buts_state,
+                theme,
             );
 
             let (container_theme, container_padding) = mention_container_style(has_mention, theme);
@@ -512,6 +1018,30 @@ pub fn build_event_history<'a>(
                     .into(),
             );
 
+            if let (Some(edited_at), Some(id), None) = (message.edited_at, message_id.id(), &message.being_edited) {
+                let edited_at = timezone.from_utc_datetime(&edited_at);
+                let marker = Button::new(
+                    edited_but_state,
+                    label!("(edited)")
+                        .size(MESSAGE_TIMESTAMP_SIZE)
+                        .color(theme.user_theme.dimmed_text),
+                )
+                .padding(0)
+                .style(theme.border_width(0.0).background_color(Color::TRANSPARENT))
+                .on_press(Message::ShowRevisions(id));
+                message_body_widgets.push(
+                    Tooltip::new(
+                        marker,
+                        format!("Edited at {}", edited_at.format("%d %B %Y %H:%M")),
+                        iced::tooltip::Position::Top,
+                    )
+                    .size(MESSAGE_SIZE)
+                    .gap(PADDING / 2)
+                    .style(theme)
+                    .into(),
+                );
+            }
+
             let urls = textt
                 .split_whitespace()
                 .map(|a| a.trim_end_matches('>').trim_start_matches('<').parse::<Uri>())
@@ -646,88 +1176,14 @@ pub fn build_event_history<'a>(
         if let IcyContent::Embeds(embeds) = &message.content {
             // TODO: show multiple embeds
             if let Some(embed) = embeds.first() {
-                let put_heading =
-                    |embed_widgets: &mut Vec<Element<'a, Message>>, h: &EmbedHeading, state: &'a mut button::State| {
-                        let mut heading = Vec::with_capacity(3);
-
-                        if let Some(img_url) = &h.icon {
-                            if let Some(handle) = thumbnail_cache.thumbnails.get(img_url) {
-                                heading.push(
-                                    Image::new(handle.clone())
-                                        .height(length!(=24))
-                                        .width(length!(=24))
-                                        .into(),
-                                );
-                            }
-                        }
-
-                        heading.push(label!(&h.text).size(DEF_SIZE + 2).into());
-                        if let Some(subtext) = h.subtext.as_deref() {
-                            heading.push(label!(subtext).size(DEF_SIZE - 6).color(color!(200, 200, 200)).into());
-                        }
-
-                        let mut but = Button::new(state, row(heading).padding(0).spacing(SPACING)).style(theme.embed());
-
-                        if let Some(url) = h.url.clone() {
-                            but = but.on_press(Message::OpenUrl(url));
-                        }
-
-                        embed_widgets.push(but.into());
-                    };
-
-                let mut embed_widgets = Vec::with_capacity(5);
-
-                if let Some(h) = &embed.header {
-                    put_heading(&mut embed_widgets, h, h_embed_but);
-                }
-
-                embed_widgets.push(label!(&embed.title).size(DEF_SIZE + 2).into());
-                if let Some(body) = embed.body.as_deref() {
-                    embed_widgets.push(label!(body).color(color!(220, 220, 220)).size(DEF_SIZE - 2).into());
-                }
-
-                for f in &embed.fields {
-                    // TODO: handle presentation
-                    let mut field = Vec::with_capacity(3);
-
-                    field.push(label!(&f.title).size(DEF_SIZE - 1).into());
-                    if let Some(subtitle) = f.subtitle.as_deref() {
-                        field.push(label!(subtitle).size(DEF_SIZE - 3).into());
-                    }
-                    if let Some(body) = f.body.as_deref() {
-                        field.push(label!(body).color(color!(220, 220, 220)).size(DEF_SIZE - 3).into());
-                    }
-
-                    embed_widgets.push(
-                        Container::new(
-                            column(field)
-                                .padding(PADDING / 4)
-                                .spacing(SPACING / 4)
-                                .align_items(Align::Start),
-                        )
-                        .style(theme)
-                        .into(),
-                    );
-                }
-
-                if let Some(h) = &embed.footer {
-                    put_heading(&mut embed_widgets, h, f_embed_but);
-                }
-
-                let mut theme = theme.secondary();
-                if let Some(color) = embed.color {
-                    theme = theme.border_color(tuple_to_iced_color(color));
-                }
-                message_body_widgets.push(
-                    Container::new(
-                        column(embed_widgets)
-                            .padding(PADDING / 2)
-                            .spacing(SPACING / 2)
-                            .align_items(Align::Start),
-                    )
-                    .style(theme)
-                    .into(),
-                );
+                message_body_widgets.push(build_embed(
+                    embed,
+                    thumbnail_cache,
+                    h_embed_but,
+                    f_embed_but,
+                    Message::OpenUrl,
+                    theme,
+                ));
             }
         }
 
@@ -788,6 +1244,55 @@ pub fn build_event_history<'a>(
             }
         }
 
+        if let (Some(message_id), false) = (message_id.id(), message.reactions.is_empty()) {
+            reaction_buts_state.resize_with(message.reactions.len(), Default::default);
+            let chips = message
+                .reactions
+                .iter()
+                .zip(reaction_buts_state.iter_mut())
+                .map(|(reaction, state)| {
+                    let emote_image = reaction
+                        .is_unicode()
+                        .not()
+                        .then(|| thumbnail_cache.emotes.get(&FileId::Id(reaction.image_id.to_string())))
+                        .flatten();
+                    let emote: Element<Message> = match emote_image {
+                        Some(handle) => Image::new(handle.clone())
+                            .width(length!(= MESSAGE_SIZE + 4))
+                            .height(length!(= MESSAGE_SIZE + 4))
+                            .into(),
+                        None if reaction.is_unicode() => label!(reaction.name.as_str()).size(MESSAGE_SIZE).into(),
+                        None => label!(":{}:", reaction.name).size(MESSAGE_SIZE).into(),
+                    };
+                    let content = Row::with_children(vec![
+                        emote,
+                        label!(reaction.count.to_string()).size(MESSAGE_SIZE).into(),
+                    ])
+                    .spacing(SPACING)
+                    .align_items(Align::Center);
+                    let mut chip_theme = theme.secondary().border_width(2.0);
+                    if reaction.reacted == Some(true) {
+                        chip_theme = chip_theme.border_color(theme.user_theme.accent);
+                    }
+                    Button::new(state, content)
+                        .padding([PADDING / 8, PADDING / 4])
+                        .style(chip_theme)
+                        .on_press(Message::ToggleReaction {
+                            message_id,
+                            image_id: reaction.image_id.clone(),
+                            name: reaction.name.clone(),
+                        })
+                        .into()
+                })
+                .collect();
+            message_body_widgets.push(
+                Row::with_children(chips)
+                    .spacing(SPACING)
+                    .align_items(Align::Center)
+                    .into(),
+            );
+        }
+
         let msg_body = Column::with_children(message_body_widgets)
             .align_items(Align::Start)
             .spacing(MSG_LR_PADDING);
@@ -798,19 +1303,38 @@ pub fn build_event_history<'a>(
             channel.messages.get(&id).map(|m| (id, m))
         });
 
-        let mut options = Vec::with_capacity(4);
+        let mut options = Vec::with_capacity(12);
         if matches!(message.content, IcyContent::Text(_)) {
             options.push(MessageMenuOption::Copy(*message_id));
         }
         if let Some(id) = message_id.id() {
             options.push(MessageMenuOption::Reply(id));
+            options.push(MessageMenuOption::OpenThread(id));
             options.push(MessageMenuOption::CopyMessageId(id));
+            options.push(MessageMenuOption::MarkUnread(id));
+            options.push(MessageMenuOption::Save(id));
             if msg_text.is_some() && current_user_id == message.sender {
                 options.push(MessageMenuOption::Edit(id));
             }
             if current_user_id == message.sender {
                 options.push(MessageMenuOption::Delete(id));
             }
+            let defaults = DEFAULT_REACTIONS
+                .iter()
+                .filter(|emoji| message.reactions.iter().all(|r| r.image_id != **emoji))
+                .map(|emoji| MessageMenuOption::React {
+                    message_id: id,
+                    image_id: SmolStr::new(emoji),
+                    name: SmolStr::new(emoji),
+                    reacted: Some(false),
+                });
+            let existing = message.reactions.iter().map(|r| MessageMenuOption::React {
+                message_id: id,
+                image_id: r.image_id.clone(),
+                name: r.name.clone(),
+                reacted: r.reacted,
+            });
+            options.extend(existing.chain(defaults));
         }
 
         let timestamp_formatted = message_timestamp.format("%H:%M").to_string();
@@ -861,14 +1385,67 @@ pub fn build_event_history<'a>(
                 .into(),
         );
 
+        // Show whether our unacknowledged messages are still being sent
+        if let Some(state) = message_id.transaction_id().and_then(|id| client.outbox.state(id)) {
+            let status: Element<Message> = match state {
+                SendState::Sending { attempt } => {
+                    let text = if attempt == 0 {
+                        "sending...".to_string()
+                    } else {
+                        format!("sending... (retry {})", attempt)
+                    };
+                    label!(text)
+                        .size(MESSAGE_TIMESTAMP_SIZE)
+                        .color(theme.user_theme.dimmed_text)
+                        .into()
+                }
+                SendState::Failed => {
+                    let transaction_id = message_id
+                        .transaction_id()
+                        .expect("only unacknowledged messages are in the outbox");
+                    let [retry_but_state, discard_but_state] = outbox_buts_state;
+                    Row::with_children(vec![
+                        label!("failed to send")
+                            .size(MESSAGE_TIMESTAMP_SIZE)
+                            .color(theme.user_theme.error)
+                            .into(),
+                        label_button!(retry_but_state, "retry")
+                            .on_press(Message::RetrySend(transaction_id))
+                            .style(theme.secondary())
+                            .into(),
+                        label_button!(discard_but_state, "discard")
+                            .on_press(Message::DiscardSend(transaction_id))
+                            .style(theme.secondary())
+                            .into(),
+                    ])
+                    .spacing(SPACING)
+                    .align_items(Align::Center)
+                    .into()
+                }
+            };
+            message_col.push(
+                Row::with_children(vec![space!(w = TIMESTAMP_WIDTH + SPACING).into(), status])
+                    .align_items(Align::Center)
+                    .into(),
+            );
+        }
+
         message_group.push(Column::with_children(message_col).align_items(Align::Start).into());
 
         last_sender_id = Some(id_to_use);
         last_sender_name = Some(sender_display_name);
         last_timestamp = message_timestamp;
+
+        // Messages after this one weren't loaded, so the next one doesn't continue its group
+        if let Some(message_id) = gap_after {
+            event_history = event_history.push(push_to_msg_group(&mut message_group));
+            event_history = event_history.push(build_gap_row(message_id, load_missing_but_state, theme));
+            last_sender_id = None;
+            last_sender_name = None;
+        }
     }
     if message_group.is_empty().not() {
         event_history = event_history.push(push_to_msg_group(&mut message_group));
     }
-    event_history.into()
+    event_history.push(build_space(visible.space_after)).into()
 }
@@ -1,5 +1,6 @@
 pub mod chan_guild_list;
 pub mod event_history;
+pub mod thread;
 
 pub use crate::{color, label, space};
 use crate::{
@@ -29,9 +30,20 @@ use super::style::{PADDING, SPACING};
 
 pub trait CursorExt {
     fn get_word_at_cursor<'a>(&self, message: &'a str) -> Option<(&'a str, usize, usize)>;
+    /// Byte range of the selected text in `message`, which is empty at the cursor if nothing is selected.
+    fn selected_range(&self, message: &str) -> (usize, usize);
 }
 
 impl CursorExt for text_input::State {
+    fn selected_range(&self, message: &str) -> (usize, usize) {
+        let (start, end) = match self.cursor().state(&Value::new(message)) {
+            State::Index(index) => (index, index),
+            State::Selection { start, end } => (start.min(end), start.max(end)),
+        };
+        let offset = |index: usize| message.char_indices().nth(index).map_or(message.len(), |(at, _)| at);
+        (offset(start), offset(end))
+    }
+
     fn get_word_at_cursor<'a>(&self, message: &'a str) -> Option<(&'a str, usize, usize)> {
         let index = match self.cursor().state(&Value::new(message)) {
             State::Index(index) => index,
@@ -106,7 +118,9 @@ pub fn make_reply_message<'a, M: Clone + 'a>(
 
             let author = label!(format!("@{}", author_name)).color(color).size(MESSAGE_SIZE - 3);
             let content_label = match &reply_message.content {
-                IcyContent::Text(text) => render_text(&text.replace('\n', " "), &client.members, &client.emote_packs),
+                IcyContent::Text(text) => {
+                    render_text(&text.text.replace('\n', " "), &client.members, &client.emote_packs)
+                }
                 IcyContent::Files(files) => files.iter().map(|f| &f.name).enumerate().fold(
                     String::from("sent file(s): "),
                     |mut names, (index, name)| {
@@ -118,6 +132,7 @@ pub fn make_reply_message<'a, M: Clone + 'a>(
                     },
                 ),
                 IcyContent::Embeds(_) => "sent an embed".to_string(),
+                IcyContent::System(system) => system.describe(&client.members),
             };
             let content = label!(truncate_string(&content_label, 100))
                 .size(MESSAGE_SIZE - 4)
@@ -0,0 +1,109 @@
+use crate::{
+    client::channel::Channel,
+    component::*,
+    label,
+    screen::main::Message,
+    space,
+    style::{Theme, MESSAGE_SIZE, PADDING, SPACING},
+};
+
+use client::{message::MessageId, Client};
+
+/// Builds the side panel that shows the reply thread of a message.
+#[allow(clippy::too_many_arguments)]
+pub fn build_thread_panel<'a>(
+    client: &Client,
+    channel: &Channel,
+    message_id: u64,
+    composer_message: &str,
+    can_send: bool,
+    scrollable_state: &'a mut scrollable::State,
+    buttons_state: &'a mut Vec<button::State>,
+    close_but_state: &'a mut button::State,
+    composer_state: &'a mut text_input::State,
+    theme: &Theme,
+) -> Element<'a, Message> {
+    let thread = channel.thread(message_id);
+
+    // Parents, then the message itself, then its replies
+    let entries = thread
+        .parents
+        .iter()
+        .map(|id| (MessageId::Ack(*id), 0))
+        .chain(std::iter::once((MessageId::Ack(message_id), 0)))
+        .chain(thread.replies.iter().copied())
+        .collect::<Vec<_>>();
+    buttons_state.resize_with(entries.len(), Default::default);
+
+    let mut list = Scrollable::new(scrollable_state)
+        .width(length!(+))
+        .height(length!(+))
+        .style(theme)
+        .align_items(Align::Start)
+        .spacing(SPACING);
+    if thread.missing_parent.is_some() {
+        list = list.push(
+            label!("Loading earlier messages...")
+                .size(MESSAGE_SIZE - 2)
+                .color(theme.user_theme.dimmed_text),
+        );
+    }
+    for ((id, depth), state) in entries.into_iter().zip(buttons_state.iter_mut()) {
+        let reply = make_reply_message(
+            channel.messages.get(&id).map(|m| (id, m)),
+            client,
+            theme,
+            Message::GotoReply,
+            state,
+        );
+        let entry: Element<Message> = if id == MessageId::Ack(message_id) {
+            Container::new(reply)
+                .padding(PADDING / 4)
+                .style(theme.border_width(2.0).border_color(theme.user_theme.accent))
+                .into()
+        } else {
+            reply.into()
+        };
+        list = list.push(
+            Row::with_children(vec![space!(w = depth as u16 * PADDING).into(), entry]).align_items(Align::Center),
+        );
+    }
+
+    let header = Row::with_children(vec![
+        label!("Thread").size(MESSAGE_SIZE + 2).into(),
+        space!(w+).into(),
+        Button::new(close_but_state, icon(Icon::X))
+            .style(theme)
+            .padding(PADDING / 4)
+            .on_press(Message::CloseThread)
+            .into(),
+    ])
+    .align_items(Align::Center);
+
+    let composer: Element<Message> = if can_send {
+        TextInput::new(
+            composer_state,
+            "Reply in thread...",
+            composer_message,
+            Message::ThreadComposerChanged,
+        )
+        .padding((PADDING / 4) * 3)
+        .size(MESSAGE_SIZE)
+        .style(theme.secondary().border_width(2.0))
+        .on_submit(Message::SendThreadMessage)
+        .width(length!(+))
+        .into()
+    } else {
+        label!("You don't have permission to send a message here")
+            .size(MESSAGE_SIZE)
+            .color(theme.user_theme.dimmed_text)
+            .into()
+    };
+
+    Column::with_children(vec![header.into(), list.into(), composer])
+        .padding(PADDING / 2)
+        .spacing(SPACING * 2)
+        .width(length!(+))
+        .height(length!(+))
+        .into()
+}
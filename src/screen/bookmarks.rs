@@ -0,0 +1,205 @@
+use chrono::{NaiveDateTime, TimeZone};
+use client::error::ClientError;
+
+use super::{sub_escape_pop_screen, truncate_string, Message as TopLevelMessage, Screen as TopLevelScreen};
+
+use crate::{
+    client::Client,
+    component::*,
+    label, label_button, length, space,
+    style::{Theme, DEF_SIZE, MESSAGE_SIZE, PADDING, SPACING},
+};
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    NoteChanged(usize, String),
+    /// Saves the note that is being edited.
+    SaveNote,
+    GoToBookmark(usize),
+    RemoveBookmark(usize),
+    GoBack,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct BookmarksScreen {
+    bookmarks_state: scrollable::State,
+    /// Go to, remove and note input states for each bookmark.
+    bookmarks_buts_state: Vec<(button::State, button::State, text_input::State)>,
+    back_but_state: button::State,
+    /// The bookmark whose note is being edited, and the edited note.
+    editing_note: Option<(usize, String)>,
+    error_text: String,
+}
+
+impl BookmarksScreen {
+    pub fn view(&mut self, theme: &Theme, client: &Client) -> Element<Message> {
+        let mut bookmarks = Scrollable::new(&mut self.bookmarks_state)
+            .align_items(Align::Start)
+            .height(length!(+))
+            .width(length!(+))
+            .padding(PADDING)
+            .spacing(SPACING)
+            .style(theme);
+
+        let timezone = chrono::Local::now().timezone();
+        self.bookmarks_buts_state
+            .resize_with(client.bookmarks.len(), Default::default);
+        for (index, (bookmark, (goto_state, remove_state, note_state))) in client
+            .bookmarks
+            .iter()
+            .zip(self.bookmarks_buts_state.iter_mut())
+            .enumerate()
+        {
+            let guild = client.guilds.get(&bookmark.guild_id);
+            let guild_name = guild.map_or("unknown guild", |g| g.name.as_str());
+            let channel_name = guild
+                .and_then(|g| g.channels.get(&bookmark.channel_id))
+                .map_or("unknown channel", |c| c.name.as_str());
+            let sender_name = client
+                .members
+                .get(&bookmark.sender)
+                .map_or(bookmark.sender_name.as_str(), |m| m.username.as_str());
+            let timestamp = timezone.from_utc_datetime(&NaiveDateTime::from_timestamp(bookmark.timestamp, 0));
+
+            let content = column(vec![
+                label!(
+                    "{} #{} | {} | {}",
+                    truncate_string(guild_name, 24),
+                    truncate_string(channel_name, 24),
+                    truncate_string(sender_name, 24),
+                    timestamp.format("%d %B %Y %H:%M")
+                )
+                .size(MESSAGE_SIZE - 4)
+                .color(theme.user_theme.dimmed_text)
+                .into(),
+                label!(truncate_string(&bookmark.snapshot, 200))
+                    .size(MESSAGE_SIZE)
+                    .into(),
+            ]);
+
+            let note = match &self.editing_note {
+                Some((editing, note)) if *editing == index => note.as_str(),
+                _ => bookmark.note.as_str(),
+            };
+            let note_input = TextInput::new(note_state, "Add a note...", note, move |note| {
+                Message::NoteChanged(index, note)
+            })
+            .on_submit(Message::SaveNote)
+            .padding(PADDING / 4)
+            .size(MESSAGE_SIZE - 2)
+            .width(length!(+))
+            .style(theme);
+
+            let entry = column(vec![
+                Button::new(goto_state, content)
+                    .width(length!(+))
+                    .on_press(Message::GoToBookmark(index))
+                    .style(theme.secondary())
+                    .into(),
+                row(vec![
+                    note_input.into(),
+                    label_button!(remove_state, "Remove")
+                        .on_press(Message::RemoveBookmark(index))
+                        .style(theme.secondary())
+                        .into(),
+                ])
+                .width(length!(+))
+                .into(),
+            ])
+            .align_items(Align::Start)
+            .width(length!(+));
+            bookmarks = bookmarks.push(entry);
+        }
+
+        let status = if !self.error_text.is_empty() {
+            label!(&self.error_text).color(theme.user_theme.error)
+        } else if client.bookmarks.is_empty() {
+            label!("No saved messages, use \"save\" in a message's menu to save it")
+        } else {
+            label!("{} saved messages", client.bookmarks.len())
+        };
+
+        let back = label_button!(&mut self.back_but_state, "Back")
+            .on_press(Message::GoBack)
+            .style(theme);
+
+        let panel = column(vec![
+            label!("Bookmarks").size(DEF_SIZE + 4).into(),
+            status.into(),
+            bookmarks.into(),
+            row(vec![
+                space!(w % 4).into(),
+                back.width(length!(% 2)).into(),
+                space!(w % 4).into(),
+            ])
+            .into(),
+        ]);
+
+        fill_container(panel).style(theme).into()
+    }
+
+    pub fn update(&mut self, msg: Message, client: &mut Client) -> Command<TopLevelMessage> {
+        let ids_of = |client: &Client, index: usize| {
+            client
+                .bookmarks
+                .iter()
+                .nth(index)
+                .map(|b| (b.guild_id, b.channel_id, b.message_id))
+        };
+
+        match msg {
+            Message::NoteChanged(index, note) => {
+                // Switching to another bookmark's note keeps what was written in the previous one
+                if matches!(self.editing_note, Some((editing, _)) if editing != index) {
+                    self.update(Message::SaveNote, client);
+                }
+                self.editing_note = Some((index, note));
+            }
+            Message::SaveNote => {
+                if let Some((index, note)) = self.editing_note.take() {
+                    if let Some((guild_id, channel_id, message_id)) = ids_of(client, index) {
+                        client.set_bookmark_note(guild_id, channel_id, message_id, note);
+                    }
+                }
+            }
+            Message::GoToBookmark(index) => {
+                if let Some((guild_id, channel_id, message_id)) = ids_of(client, index) {
+                    self.update(Message::SaveNote, client);
+                    return Command::perform(
+                        async move {
+                            TopLevelMessage::JumpToMessage {
+                                guild_id,
+                                channel_id,
+                                message_id,
+                            }
+                        },
+                        std::convert::identity,
+                    );
+                }
+            }
+            Message::RemoveBookmark(index) => {
+                if let Some((guild_id, channel_id, message_id)) = ids_of(client, index) {
+                    // Indices after the removed bookmark change, so save the edit before that
+                    self.update(Message::SaveNote, client);
+                    client.remove_bookmark(guild_id, channel_id, message_id);
+                }
+            }
+            Message::GoBack => {
+                self.update(Message::SaveNote, client);
+                return TopLevelScreen::pop_screen_cmd();
+            }
+        }
+
+        Command::none()
+    }
+
+    pub fn subscription(&self) -> Subscription<TopLevelMessage> {
+        sub_escape_pop_screen()
+    }
+
+    pub fn on_error(&mut self, error: ClientError) -> Command<TopLevelMessage> {
+        self.error_text = error.to_string();
+
+        Command::none()
+    }
+}
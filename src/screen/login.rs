@@ -473,6 +473,11 @@ impl LoginScreen {
                         .then(|| TopLevelMessage::login(Message::Focus(FocusDirection::Before)))
                         .unwrap_or_else(|| TopLevelMessage::login(Message::Focus(FocusDirection::After))),
                 ),
+                // Go back to the main screen when adding another account
+                Event::Keyboard(Ke::KeyPressed {
+                    key_code: KeyCode::Escape,
+                    ..
+                }) => Some(TopLevelMessage::PopScreen),
                 _ => None,
             }
         })
@@ -9,9 +9,13 @@ use std::{
 
 use super::{Message as TopLevelMessage, Screen as TopLevelScreen};
 use channel::GetChannelMessages;
+use chrono::{Local, NaiveDate, NaiveDateTime, TimeZone};
 use client::{
     bool_ext::BoolExt,
+    channel::{fetch_first_message_since, DateLocation, DateSearch, DateSearchStep},
+    commands::{self, SlashCommand},
     content,
+    drafts::Draft,
     error::ClientResult,
     harmony_rust_sdk::{
         api::{
@@ -19,8 +23,8 @@ use client::{
                 all_permissions::{MESSAGES_SEND, ROLES_GET, ROLES_USER_MANAGE},
                 get_channel_messages_request::Direction,
                 stream_event::{ChannelCreated, Event as ChatEvent, MemberJoined, RoleCreated, UserRolesUpdated},
-                Event, GetChannelMessagesResponse, GetGuildChannelsRequest, GetGuildMembersRequest,
-                GetGuildRolesRequest, GetUserRolesRequest, LeaveGuildRequest, TypingRequest,
+                Event, GetGuildChannelsRequest, GetGuildMembersRequest, GetGuildRolesRequest, GetUserRolesRequest,
+                JoinGuildRequest, LeaveGuildRequest, TypingRequest,
             },
             profile::UserStatus,
             rest::FileId,
@@ -31,44 +35,82 @@ use client::{
             exports::reqwest::StatusCode,
         },
     },
+    input_history::InputHistory,
     message::MessageId,
     render_text,
     smol_str::SmolStr,
     tracing::error,
-    IndexMap, OptionExt,
+    AHashSet, IndexMap, OptionExt,
 };
 use iced::{futures::future::ready, rule::FillMode, Tooltip};
-use iced_aw::{modal, Modal};
+use iced_aw::{date_picker, modal, DatePicker, Modal};
 
-use chan_guild_list::build_guild_list;
+use chan_guild_list::{build_account_list, build_guild_list};
+use embed_composer::EmbedComposerModal;
 use help::HelpModal;
 use image_viewer::ImageViewerModal;
 use logout::LogoutModal;
 use profile_edit::ProfileEditModal;
+use revisions::RevisionsModal;
+use unknown_events::UnknownEventsModal;
 
 use crate::{
     client::{
         error::ClientError,
         message::{Attachment, Content as IcyContent, Message as IcyMessage},
-        Client,
+        Client, ConnectionStatus, PostProcessEvent,
     },
     component::{
-        event_history::{EventHistoryButsState, MessageMenuOption, SHOWN_MSGS_LIMIT},
+        event_history::{
+            build_message_text, estimate_text_height, EventHistoryButsState, HistoryLayout, MessageMenuOption,
+        },
+        thread::build_thread_panel,
         *,
     },
     label, label_button, length,
-    screen::{map_send_msg, map_to_nothing, select_files, truncate_string, ClientExt, ResultExt},
+    screen::{
+        map_send_msg, map_to_nothing, select_files, truncate_string, AccountInfo, AccountKey, ClientExt, LoginScreen,
+        ResultExt,
+    },
     space,
     style::{tuple_to_iced_color, Theme, AVATAR_WIDTH, DEF_SIZE, MESSAGE_SIZE, PADDING, SPACING},
 };
 
 use self::quick_switcher::QuickSwitcherModal;
 
+pub mod embed_composer;
 pub mod help;
 pub mod image_viewer;
 pub mod logout;
 pub mod profile_edit;
 pub mod quick_switcher;
+pub mod revisions;
+pub mod unknown_events;
+
+/// Size the window starts at, see `main`.
+const DEFAULT_WINDOW_SIZE: (u32, u32) = (1280, 720);
+/// Width of the guild list on the left.
+const GUILD_LIST_WIDTH: u16 = 64;
+/// Width of the channel list, and of the member list on the other side.
+const SIDE_PANEL_WIDTH: u16 = 220;
+const THREAD_PANEL_WIDTH: u16 = 320;
+/// Size of the icons in the channel header and next to the composer.
+const ICON_SIZE: u16 = (PADDING / 4) * 3 + MESSAGE_SIZE;
+/// Height of the rows above and below the event history, like the channel header and the composer.
+///
+/// The event history is laid out in the space these leave, and iced doesn't tell how big a widget is,
+/// so they are given fixed heights to know it.
+const BAR_HEIGHT: u16 = ICON_SIZE + PADDING * 2;
+/// Height of the list of who is typing, below the event history.
+const TYPING_HEIGHT: u16 = 14;
+/// Height of the error and connection status bars at the top of the window.
+const STATUS_BAR_HEIGHT: u16 = DEF_SIZE + PADDING;
+
+/// Shown in place of line breaks in the composer, which is a single line text input.
+///
+/// The iced text input can't show more than one line, so the whole message is edited on one line
+/// with its line breaks marked. Typing the marker itself also adds a line break.
+const NEWLINE_MARKER: &str = "↵";
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Mode {
@@ -86,9 +128,12 @@ impl Default for Mode {
 pub enum ProfileMenuOption {
     EditProfile,
     ManageEmotes,
+    SearchMessages,
+    Bookmarks,
     Help,
+    UnknownEvents,
     Logout,
-    SwitchAccount,
+    AddAccount,
     CopyToken,
     Exit,
 }
@@ -98,9 +143,12 @@ impl Display for ProfileMenuOption {
         let w = match self {
             ProfileMenuOption::EditProfile => "Edit Profile",
             ProfileMenuOption::ManageEmotes => "Manage Emotes",
+            ProfileMenuOption::SearchMessages => "Search Messages",
+            ProfileMenuOption::Bookmarks => "Bookmarks",
             ProfileMenuOption::Help => "Help",
+            ProfileMenuOption::UnknownEvents => "Unknown Events",
             ProfileMenuOption::Logout => "Logout",
-            ProfileMenuOption::SwitchAccount => "Switch Account",
+            ProfileMenuOption::AddAccount => "Add Account",
             ProfileMenuOption::CopyToken => "Copy Token",
             ProfileMenuOption::Exit => "Exit",
         };
@@ -112,6 +160,8 @@ impl Display for ProfileMenuOption {
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum GuildMenuOption {
     EditGuild,
+    MarkGuildRead,
+    MarkChannelRead,
     LeaveGuild,
 }
 
@@ -119,6 +169,8 @@ impl Display for GuildMenuOption {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         let w = match self {
             GuildMenuOption::EditGuild => "Edit Guild",
+            GuildMenuOption::MarkGuildRead => "Mark Guild Read",
+            GuildMenuOption::MarkChannelRead => "Mark Channel Read",
             GuildMenuOption::LeaveGuild => "Leave Guild",
         };
 
@@ -130,6 +182,10 @@ impl Display for GuildMenuOption {
 pub enum Message {
     FocusComposer(char),
     EditLastMessage,
+    /// Puts an earlier (`true`) or later (`false`) sent message of the current channel in the composer.
+    RecallSentMessage(bool),
+    /// Sent when the user presses or releases Shift.
+    ShiftHeld(bool),
     QuickSwitch,
     ChangeMode(Mode),
     ClearError,
@@ -152,7 +208,14 @@ pub enum Message {
     },
     /// Sent when user makes a change to the message they are composing.
     ComposerMessageChanged(String),
+    /// Removes an uploaded file from the message the user is composing.
+    RemoveAttachment(usize),
     ScrollToBottom(u64),
+    /// Fetches the newest messages of the current channel again, to leave older messages that were
+    /// jumped to.
+    JumpToPresent,
+    /// Fetches the messages of the current channel that weren't loaded after the given message.
+    LoadMissingMessages(u64),
     OpenContent {
         attachment: Attachment,
         is_thumbnail: bool,
@@ -165,10 +228,14 @@ pub enum Message {
     OpenUrl(SmolStr),
     /// Sent when the user selects a different guild.
     GuildChanged(u64),
+    /// Sent when the user picks another account in the account switcher.
+    SwitchAccount(AccountKey),
     /// Sent when the user selects a different channel.
     ChannelChanged(u64),
     /// Sent when the user scrolls the message history.
     MessageHistoryScrolled(f32),
+    /// Sent when the window is resized, with its new width and height.
+    WindowResized(u32, u32),
     /// Sent when the user selects a menu entry from the profile menu (top right of the screen).
     SelectedAppMenuOption(ProfileMenuOption),
     /// Sent when the user selects a menu entry from the guild menu (top left of the screen).
@@ -183,16 +250,62 @@ pub enum Message {
     QuickSwitchMsg(quick_switcher::Message),
     ProfileEditMsg(profile_edit::Message),
     HelpModal(help::Message),
+    UnknownEventsModal(unknown_events::Message),
+    RevisionsModal(revisions::Message),
+    EmbedComposerMsg(embed_composer::Message),
+    /// Opens the embed composer for the current channel.
+    OpenEmbedComposer,
+    /// Shows the earlier versions of an edited message in the current channel.
+    ShowRevisions(u64),
     /// Sent when the user clicks the `+` button (guild discovery)
     OpenCreateJoinGuild,
     /// Sent when the user picks a new status
     ChangeUserStatus(UserStatus),
     GotoReply(MessageId),
+    /// Shows or hides the date picker in the channel header.
+    ShowDatePicker(bool),
+    /// Shows the first message of the current channel written on or after the date.
+    JumpToDate(NaiveDate),
+    /// Sent when a history page was searched for the date of a [`Message::JumpToDate`].
+    DateSearched {
+        guild_id: u64,
+        channel_id: u64,
+        since: NaiveDateTime,
+        step: DateSearchStep,
+    },
+    CancelDateSearch,
+    OpenSearch,
+    /// Switches to the given channel and shows the message, fetching it if it isn't loaded.
+    JumpToMessage {
+        guild_id: u64,
+        channel_id: u64,
+        message_id: u64,
+    },
     ClearReply,
     NextBeforeGuild(bool),
     NextBeforeChannel(bool),
     CopyToClipboard(String),
     MessageMenuSelected(MessageMenuOption),
+    /// Shows the reply thread of a message in the current channel.
+    OpenThread(u64),
+    CloseThread,
+    ThreadComposerChanged(String),
+    /// Sends the thread composer's message as a reply to the thread's message.
+    SendThreadMessage,
+    /// Adds our reaction to a message in the current channel, or removes it if we already reacted.
+    ToggleReaction {
+        message_id: u64,
+        image_id: SmolStr,
+        name: SmolStr,
+    },
+    /// Shows a spoiler of a message in the current channel.
+    RevealSpoiler(MessageId, usize),
+    /// Shows or hides the rendered preview of the message being composed.
+    TogglePreview,
+    /// Sends a message that failed to be sent again.
+    RetrySend(u64),
+    /// Removes a message that failed to be sent from the outbox.
+    DiscardSend(u64),
     AutoCompleteBefore,
     AutoCompleteNext,
     AutoComplete,
@@ -204,10 +317,21 @@ pub struct MainScreen {
     event_history_state: scrollable::State,
     history_buts_sate: EventHistoryButsState,
     send_file_but_state: button::State,
+    send_embed_but_state: button::State,
+    jump_to_date_but_state: button::State,
+    date_picker_state: date_picker::State,
+    cancel_date_search_but_state: button::State,
+    attachment_buts_state: Vec<button::State>,
     composer_state: text_input::State,
     goto_reply_state: button::State,
     clear_reply_state: button::State,
     scroll_to_bottom_but_state: button::State,
+    thread_scroll_state: scrollable::State,
+    thread_buts_state: Vec<button::State>,
+    close_thread_but_state: button::State,
+    thread_composer_state: text_input::State,
+    preview_but_state: button::State,
+    preview_buts_state: Vec<button::State>,
     before_after_completion_items: (Option<SmolStr>, Option<SmolStr>),
     completion_current: Option<SmolStr>,
 
@@ -216,6 +340,7 @@ pub struct MainScreen {
     menu_state: pick_list::State<ProfileMenuOption>,
     guilds_list_state: scrollable::State,
     guilds_buts_state: Vec<button::State>,
+    accounts_buts_state: Vec<button::State>,
     channels_list_state: scrollable::State,
     channels_buts_state: Vec<button::State>,
     members_buts_state: Vec<button::State>,
@@ -228,6 +353,9 @@ pub struct MainScreen {
     quick_switcher_modal: modal::State<QuickSwitcherModal>,
     profile_edit_modal: modal::State<ProfileEditModal>,
     help_modal: modal::State<HelpModal>,
+    unknown_events_modal: modal::State<UnknownEventsModal>,
+    revisions_modal: modal::State<RevisionsModal>,
+    embed_composer_modal: modal::State<EmbedComposerModal>,
 
     /// A map of the last channel we have looked in each guild we are in
     guild_last_channels: IndexMap<u64, u64>,
@@ -238,6 +366,23 @@ pub struct MainScreen {
     /// The message the user is currently typing.
     message: String,
     reply_to: Option<u64>,
+    /// Files uploaded to be sent with the message the user is typing.
+    attachments: Vec<Attachment>,
+    /// Messages sent in each channel, recalled with Ctrl + Up / Down.
+    input_history: InputHistory,
+    /// Whether Shift is held, so that Shift + Enter adds a line instead of sending.
+    shift_held: bool,
+    /// The message whose reply thread is shown in the side panel.
+    thread_of: Option<u64>,
+    /// The message the user is typing in the thread panel.
+    thread_message: String,
+    /// Whether the message being composed is shown rendered above the composer.
+    previewing: bool,
+    /// Spoilers the user revealed in the current channel, by message and token position.
+    revealed_spoilers: AHashSet<(MessageId, usize)>,
+    /// The last message that was read when the current channel was opened, the "new messages"
+    /// separator is shown after it.
+    new_messages_after: Option<u64>,
     /// The last error in string form.
     error_text: String,
     /// Error "popup" close button state
@@ -245,6 +390,50 @@ pub struct MainScreen {
     /// Current mode
     mode: Mode,
     prev_scroll_perc: f32,
+    /// Size of the window, once it was resized.
+    window_size: Option<(u32, u32)>,
+    /// Size the event history was last laid out at.
+    history_size: Option<(f32, f32)>,
+    history_layout: HistoryLayout,
+    /// The search for the date picked to jump to, while its history is fetched.
+    date_search: Option<DateSearchState>,
+}
+
+/// A search for the first message of a channel written on or after a date, which fetches history
+/// one page at a time until it gets there or is cancelled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct DateSearchState {
+    guild_id: u64,
+    channel_id: u64,
+    date: NaiveDate,
+    since: NaiveDateTime,
+    /// When the oldest message fetched so far was written.
+    reached: Option<NaiveDateTime>,
+}
+
+/// The rows of the message area around the event history, and how tall they are together, since the
+/// event history is given the height they leave.
+#[derive(Default)]
+struct MessageAreaRows<'a> {
+    widgets: Vec<Element<'a, Message>>,
+    height: f32,
+}
+
+impl<'a> MessageAreaRows<'a> {
+    /// Adds a row, which has to be laid out `height` pixels tall.
+    fn push(&mut self, row: impl Into<Element<'a, Message>>, height: f32) {
+        self.widgets.push(row.into());
+        self.height += height;
+    }
+
+    /// Adds a line between rows, which takes no height.
+    fn push_separator(&mut self, theme: &Theme) {
+        self.widgets.push(
+            Rule::horizontal(0)
+                .style(theme.border_width(2.0).border_radius(0.0).padded(FillMode::Full))
+                .into(),
+        );
+    }
 }
 
 impl MainScreen {
@@ -252,6 +441,7 @@ impl MainScreen {
         &'a mut self,
         theme: &'a Theme,
         client: &'a Client,
+        accounts: Vec<AccountInfo>,
         thumbnail_cache: &'a ThumbnailCache,
     ) -> Element<'a, Message> {
         let guilds = &client.guilds;
@@ -272,8 +462,27 @@ impl MainScreen {
             theme,
         );
 
+        // Only show the account switcher if there is something to switch to
+        let guild_list = if accounts.len() > 1 {
+            self.accounts_buts_state.resize_with(accounts.len(), Default::default);
+            let account_list = build_account_list(
+                accounts,
+                self.accounts_buts_state.as_mut_slice(),
+                Message::SwitchAccount,
+                theme,
+            );
+            Column::with_children(vec![
+                account_list,
+                Rule::horizontal(SPACING).style(theme.secondary()).into(),
+                guild_list,
+            ])
+            .into()
+        } else {
+            guild_list
+        };
+
         let guild_list = Container::new(guild_list)
-            .width(length!(= 64))
+            .width(length!(= GUILD_LIST_WIDTH))
             .height(length!(+))
             .style(theme);
 
@@ -292,8 +501,11 @@ impl MainScreen {
             vec![
                 ProfileMenuOption::EditProfile,
                 ProfileMenuOption::ManageEmotes,
+                ProfileMenuOption::SearchMessages,
+                ProfileMenuOption::Bookmarks,
                 ProfileMenuOption::Help,
-                ProfileMenuOption::SwitchAccount,
+                ProfileMenuOption::UnknownEvents,
+                ProfileMenuOption::AddAccount,
                 ProfileMenuOption::CopyToken,
                 ProfileMenuOption::Logout,
                 ProfileMenuOption::Exit,
@@ -427,7 +639,11 @@ impl MainScreen {
             );
 
             // [tag:guild_menu_entry]
-            let channel_menu_entries = vec![GuildMenuOption::EditGuild, GuildMenuOption::LeaveGuild];
+            let mut channel_menu_entries = vec![GuildMenuOption::EditGuild, GuildMenuOption::MarkGuildRead];
+            if self.current_channel_id.is_some() {
+                channel_menu_entries.push(GuildMenuOption::MarkChannelRead);
+            }
+            channel_menu_entries.push(GuildMenuOption::LeaveGuild);
 
             let channel_menu = PickList::new(
                 &mut self.channel_menu_state,
@@ -449,6 +665,8 @@ impl MainScreen {
             } else {
                 build_channel_list(
                     &guild.channels,
+                    guild_id,
+                    &client.drafts,
                     self.current_channel_id,
                     &mut self.channels_list_state,
                     &mut self.channels_buts_state,
@@ -459,7 +677,7 @@ impl MainScreen {
 
             screen_widgets.push(
                 Container::new(Column::with_children(vec![channel_menu.into(), channels_list]))
-                    .width(length!(= 220))
+                    .width(length!(= SIDE_PANEL_WIDTH))
                     .height(length!(+))
                     .style(theme)
                     .into(),
@@ -470,44 +688,130 @@ impl MainScreen {
                 .as_ref()
                 .and_then(|id| Some((guild.channels.get(id)?, *id)))
             {
-                let message_history_list = build_event_history(
-                    client.content_store(),
-                    thumbnail_cache,
-                    client,
-                    guild,
-                    channel,
-                    &client.members,
-                    current_user_id,
-                    channel.looking_at_message,
-                    &mut self.event_history_state,
-                    &mut self.history_buts_sate,
-                    self.mode,
-                    theme,
+                let icon_size = ICON_SIZE;
+                let (window_width, window_height) = self.window_size.unwrap_or(DEFAULT_WINDOW_SIZE);
+                let mut side_panels_width = GUILD_LIST_WIDTH + SIDE_PANEL_WIDTH * 2;
+                if self.thread_of.is_some() {
+                    side_panels_width += THREAD_PANEL_WIDTH;
+                }
+                let history_width = window_width.saturating_sub(u32::from(side_panels_width)) as f32;
+                let jump_to_date = Tooltip::new(
+                    Button::new(&mut self.jump_to_date_but_state, icon(Icon::Calendar).size(icon_size))
+                        .style(theme.secondary())
+                        .padding(PADDING / 4)
+                        .on_press(Message::ShowDatePicker(true)),
+                    "Jump to date",
+                    iced::tooltip::Position::Left,
+                )
+                .style(theme);
+                let mut channel_header_widgets = vec![
+                    icon(Icon::Hash).size(icon_size).into(),
+                    label!(channel.name.as_str()).into(),
+                    space!(w+).into(),
+                ];
+                if let Some(search) = self
+                    .date_search
+                    .filter(|search| (search.guild_id, search.channel_id) == (guild_id, channel_id))
+                {
+                    let progress = match search.reached {
+                        Some(reached) => format!(
+                            "Looking for {}, got to {}",
+                            search.date,
+                            Local.from_utc_datetime(&reached).date().naive_local()
+                        ),
+                        None => format!("Looking for {}", search.date),
+                    };
+                    channel_header_widgets.push(label!(progress).size(MESSAGE_SIZE).into());
+                    channel_header_widgets.push(
+                        Button::new(
+                            &mut self.cancel_date_search_but_state,
+                            label!("Cancel").size(MESSAGE_SIZE),
+                        )
+                        .style(theme.secondary())
+                        .padding(PADDING / 4)
+                        .on_press(Message::CancelDateSearch)
+                        .into(),
+                    );
+                }
+                channel_header_widgets.push(
+                    DatePicker::new(
+                        &mut self.date_picker_state,
+                        jump_to_date,
+                        Message::ShowDatePicker(false),
+                        |date| Message::JumpToDate(date.into()),
+                    )
+                    .style(theme)
+                    .into(),
                 );
-
-                let icon_size = (PADDING / 4) * 3 + MESSAGE_SIZE;
-
-                let mk_seperator = || {
-                    Rule::horizontal(0)
-                        .style(theme.border_width(2.0).border_radius(0.0).padded(FillMode::Full))
-                        .into()
-                };
-                let mut message_area_widgets = Vec::with_capacity(8);
-                message_area_widgets.push(message_history_list);
-                message_area_widgets.push(mk_seperator());
+                let channel_header = Container::new(
+                    Row::with_children(channel_header_widgets)
+                        .spacing(SPACING)
+                        .align_items(Align::Center),
+                )
+                .width(length!(+))
+                .height(length!(= BAR_HEIGHT))
+                .center_y()
+                .padding(PADDING / 2);
+
+                // The event history is put between these once the height left for it is known
+                let mut rows = MessageAreaRows::default();
+                rows.push(channel_header, f32::from(BAR_HEIGHT));
+                rows.push_separator(theme);
+                rows.push_separator(theme);
                 if !channel.uploading_files.is_empty() {
                     let widgets = std::iter::once("Uploading files: ")
                         .chain(channel.uploading_files.iter().map(String::as_str))
                         .map(|label| label!(label).size(MESSAGE_SIZE).into())
                         .collect();
-                    message_area_widgets.push(
+                    rows.push(
                         Container::new(Row::with_children(widgets).align_items(Align::Center).spacing(SPACING))
                             .center_y()
                             .center_x()
-                            .padding(PADDING / 2)
+                            .height(length!(= BAR_HEIGHT))
+                            .padding(PADDING / 2),
+                        f32::from(BAR_HEIGHT),
+                    );
+                    rows.push_separator(theme);
+                }
+                if self.mode == Mode::Normal && !self.attachments.is_empty() {
+                    self.attachment_buts_state
+                        .resize_with(self.attachments.len(), Default::default);
+                    let mut widgets = Vec::with_capacity(self.attachments.len() + 1);
+                    widgets.push(label!("Attached files: ").size(MESSAGE_SIZE).into());
+                    for (index, (attachment, state)) in self
+                        .attachments
+                        .iter()
+                        .zip(self.attachment_buts_state.iter_mut())
+                        .enumerate()
+                    {
+                        let content = Row::with_children(vec![
+                            label!(truncate_string(&attachment.name, 24)).size(MESSAGE_SIZE).into(),
+                            icon(Icon::X).size(MESSAGE_SIZE - 2).into(),
+                        ])
+                        .spacing(SPACING)
+                        .align_items(Align::Center);
+                        widgets.push(
+                            Tooltip::new(
+                                Button::new(state, content)
+                                    .style(theme.secondary())
+                                    .padding(PADDING / 4)
+                                    .on_press(Message::RemoveAttachment(index)),
+                                "Click to remove",
+                                iced::tooltip::Position::Top,
+                            )
+                            .style(theme)
                             .into(),
+                        );
+                    }
+                    rows.push(
+                        Container::new(Row::with_children(widgets).align_items(Align::Center).spacing(SPACING))
+                            .center_y()
+                            .center_x()
+                            .height(length!(= BAR_HEIGHT))
+                            .padding(PADDING / 2),
+                        f32::from(BAR_HEIGHT),
                     );
-                    message_area_widgets.push(mk_seperator());
+                    rows.push_separator(theme);
                 }
                 if let Some(reply_message) = self.reply_to.map(|id| {
                     let id = MessageId::Ack(id);
@@ -524,7 +828,7 @@ impl MainScreen {
                         .style(theme)
                         .padding(PADDING / 4)
                         .on_press(Message::ClearReply);
-                    message_area_widgets.push(
+                    rows.push(
                         Container::new(
                             Row::with_children(vec![
                                 label!("Replying to").size(MESSAGE_SIZE).into(),
@@ -537,14 +841,15 @@ impl MainScreen {
                         )
                         .center_x()
                         .center_y()
-                        .padding(PADDING / 2)
-                        .into(),
+                        .height(length!(= BAR_HEIGHT))
+                        .padding(PADDING / 2),
+                        f32::from(BAR_HEIGHT),
                     );
-                    message_area_widgets.push(mk_seperator());
+                    rows.push_separator(theme);
                 }
 
                 let mut autocompleting = false;
-                if let Some((word, _, _)) = self.composer_state.get_word_at_cursor(&self.message) {
+                if let Some((word, word_start, _)) = self.composer_state.get_word_at_cursor(&self.message) {
                     const LEN: u16 = MESSAGE_SIZE + 10;
                     if word.starts_with(':') && !word.ends_with(':') {
                         let emote_name = word.trim_end_matches(':').trim_start_matches(':');
@@ -583,7 +888,7 @@ impl MainScreen {
                             }
 
                             let current = self.completion_current.clone();
-                            message_area_widgets.push(
+                            rows.push(
                                 Row::with_children(
                                     matched_emotes
                                         .into_iter()
@@ -615,10 +920,11 @@ impl MainScreen {
                                 )
                                 .align_items(Align::Center)
                                 .spacing(SPACING)
-                                .padding(PADDING / 4)
-                                .into(),
+                                .height(length!(= BAR_HEIGHT))
+                                .padding(PADDING / 4),
+                                f32::from(BAR_HEIGHT),
                             );
-                            message_area_widgets.push(mk_seperator());
+                            rows.push_separator(theme);
                             autocompleting = true;
                         }
                     } else if word.starts_with('@') {
@@ -667,7 +973,7 @@ impl MainScreen {
                             }
 
                             let current = self.completion_current.clone();
-                            message_area_widgets.push(
+                            rows.push(
                                 Row::with_children(
                                     matched_members
                                         .into_iter()
@@ -698,10 +1004,68 @@ impl MainScreen {
                                 )
                                 .align_items(Align::Center)
                                 .spacing(SPACING)
-                                .padding(PADDING / 4)
-                                .into(),
+                                .height(length!(= BAR_HEIGHT))
+                                .padding(PADDING / 4),
+                                f32::from(BAR_HEIGHT),
                             );
-                            message_area_widgets.push(mk_seperator());
+                            rows.push_separator(theme);
+                            autocompleting = true;
+                        }
+                    } else if !self.message.contains('\n') {
+                        let matched_commands = commands::completions(&self.message, word_start, word);
+
+                        if !matched_commands.is_empty() {
+                            if let Some(pos) = self
+                                .completion_current
+                                .as_deref()
+                                .and_then(|s| matched_commands.iter().position(|name| s == *name))
+                            {
+                                self.before_after_completion_items = (
+                                    (pos == 0)
+                                        .not()
+                                        .then(|| matched_commands.get(pos - 1))
+                                        .flatten()
+                                        .or_else(|| matched_commands.last())
+                                        .map(SmolStr::new),
+                                    matched_commands
+                                        .get(pos + 1)
+                                        .or_else(|| matched_commands.first())
+                                        .map(SmolStr::new),
+                                );
+                            } else {
+                                self.before_after_completion_items = (
+                                    matched_commands.last().map(SmolStr::new),
+                                    matched_commands.first().map(SmolStr::new),
+                                );
+                            }
+
+                            let current = self.completion_current.clone();
+                            rows.push(
+                                Row::with_children(
+                                    matched_commands
+                                        .into_iter()
+                                        .map(|name| {
+                                            // Command names are shown with their arguments
+                                            let text = commands::find(name)
+                                                .filter(|_| word_start == 0)
+                                                .map_or_else(|| name.to_string(), |command| command.usage_line());
+                                            let bg_color = (current.as_deref() == Some(name))
+                                                .then(|| theme.user_theme.accent)
+                                                .unwrap_or(theme.user_theme.primary_bg);
+                                            Container::new(label!(text).size(MESSAGE_SIZE))
+                                                .style(theme.background_color(bg_color).round())
+                                                .padding(PADDING / 4)
+                                                .into()
+                                        })
+                                        .collect(),
+                                )
+                                .align_items(Align::Center)
+                                .spacing(SPACING)
+                                .height(length!(= BAR_HEIGHT))
+                                .padding(PADDING / 4),
+                                f32::from(BAR_HEIGHT),
+                            );
+                            rows.push_separator(theme);
                             autocompleting = true;
                         }
                     }
@@ -750,8 +1114,36 @@ impl MainScreen {
                         ])
                         .into(),
                     ])
-                    .height(length!(= 14));
-                    message_area_widgets.push(typing_users.into());
+                    .height(length!(= TYPING_HEIGHT));
+                    rows.push(typing_users, f32::from(TYPING_HEIGHT));
+                }
+
+                if self.previewing && !self.message.trim().is_empty() {
+                    let text = expand_mentions_and_emotes(client, guild_id, &self.message);
+                    let preview = build_message_text(
+                        &text,
+                        &[],
+                        None,
+                        &self.revealed_spoilers,
+                        theme.user_theme.text,
+                        false,
+                        thumbnail_cache,
+                        client,
+                        guild,
+                        &client.members,
+                        &mut self.preview_buts_state,
+                        theme,
+                    );
+                    // The preview is as tall as its text, so its height can only be estimated
+                    let preview_height =
+                        estimate_text_height(&text, history_width - f32::from(PADDING)) + f32::from(PADDING);
+                    rows.push(
+                        Container::new(Column::with_children(preview).align_items(Align::Start))
+                            .width(length!(+))
+                            .padding(PADDING / 2),
+                        preview_height,
+                    );
+                    rows.push_separator(theme);
                 }
 
                 let mut send_file_button =
@@ -769,8 +1161,8 @@ impl MainScreen {
                         Mode::Normal | Mode::EditingMessage(_) => TextInput::new(
                             &mut self.composer_state,
                             "Enter your message here...",
-                            self.message.as_str(),
-                            Message::ComposerMessageChanged,
+                            &self.message.replace('\n', NEWLINE_MARKER),
+                            |text| Message::ComposerMessageChanged(text.replace(NEWLINE_MARKER, "\n")),
                         )
                         .padding((PADDING / 4) * 3)
                         .size(MESSAGE_SIZE)
@@ -789,7 +1181,60 @@ impl MainScreen {
 
                 let mut bottom_area_widgets = vec![send_file_button.into(), message_composer];
 
-                if channel.looking_at_message < channel.messages.len().saturating_sub(SHOWN_MSGS_LIMIT) {
+                if channel.has_perm(MESSAGES_SEND) {
+                    bottom_area_widgets.insert(
+                        1,
+                        Tooltip::new(
+                            Button::new(&mut self.send_embed_but_state, icon(Icon::CardText).size(icon_size))
+                                .style(theme.secondary().border_width(2.0))
+                                .padding(PADDING / 4)
+                                .on_press(Message::OpenEmbedComposer),
+                            "Send an embed",
+                            iced::tooltip::Position::Top,
+                        )
+                        .style(theme)
+                        .into(),
+                    );
+
+                    let (preview_icon, preview_tooltip) = if self.previewing {
+                        (Icon::EyeSlash, "Hide preview")
+                    } else {
+                        (Icon::Eye, "Preview message")
+                    };
+                    bottom_area_widgets.push(
+                        Tooltip::new(
+                            Button::new(&mut self.preview_but_state, icon(preview_icon).size(icon_size))
+                                .style(theme.secondary().border_width(2.0))
+                                .padding(PADDING / 4)
+                                .on_press(Message::TogglePreview),
+                            preview_tooltip,
+                            iced::tooltip::Position::Top,
+                        )
+                        .style(theme)
+                        .into(),
+                    );
+                }
+
+                let is_before_gap = channel
+                    .viewport
+                    .anchor()
+                    .and_then(|id| channel.messages.get_index_of(&id))
+                    .map_or(false, |index| channel.messages.has_gap_from(index));
+                if !channel.reached_bottom || is_before_gap {
+                    let content = Row::with_children(vec![
+                        icon(Icon::ArrowDown).size(icon_size).into(),
+                        label!("Jump to present").size(MESSAGE_SIZE).into(),
+                    ])
+                    .spacing(SPACING)
+                    .align_items(Align::Center);
+                    bottom_area_widgets.push(
+                        Button::new(&mut self.scroll_to_bottom_but_state, content)
+                            .padding(PADDING / 4)
+                            .style(theme.secondary())
+                            .on_press(Message::JumpToPresent)
+                            .into(),
+                    );
+                } else if !channel.viewport.is_at_bottom() {
                     bottom_area_widgets.push(
                         Tooltip::new(
                             Button::new(
@@ -807,20 +1252,74 @@ impl MainScreen {
                     );
                 }
 
-                message_area_widgets.push(
+                rows.push(
                     Container::new(
                         Row::with_children(bottom_area_widgets)
                             .spacing(SPACING * 2)
                             .width(length!(+)),
                     )
                     .width(length!(+))
-                    .padding(PADDING / 2)
-                    .into(),
+                    .height(length!(= BAR_HEIGHT))
+                    .center_y()
+                    .padding(PADDING / 2),
+                    f32::from(BAR_HEIGHT),
+                );
+
+                let mut status_bars_height = 0;
+                if !self.error_text.is_empty() {
+                    status_bars_height += STATUS_BAR_HEIGHT;
+                }
+                if client.connection_status != ConnectionStatus::Connected {
+                    status_bars_height += STATUS_BAR_HEIGHT;
+                }
+                let history_height = (window_height.saturating_sub(u32::from(status_bars_height)) as f32 - rows.height)
+                    .max(f32::from(BAR_HEIGHT));
+                self.history_size = Some((history_width, history_height));
+                self.history_layout.update(guild_id, channel_id, channel, history_width);
+                let message_history_list = build_event_history(
+                    client.content_store(),
+                    thumbnail_cache,
+                    client,
+                    guild,
+                    channel,
+                    &client.members,
+                    current_user_id,
+                    &mut self.history_layout,
+                    history_height,
+                    self.new_messages_after,
+                    &self.revealed_spoilers,
+                    &mut self.event_history_state,
+                    &mut self.history_buts_sate,
+                    self.mode,
+                    theme,
                 );
+                rows.widgets.insert(2, message_history_list);
 
-                let message_area = Column::with_children(message_area_widgets);
+                let message_area = Column::with_children(rows.widgets);
 
                 screen_widgets.push(fill_container(message_area).style(theme).into());
+
+                if let Some(message_id) = self.thread_of {
+                    let thread_panel = build_thread_panel(
+                        client,
+                        channel,
+                        message_id,
+                        &self.thread_message,
+                        channel.has_perm(MESSAGES_SEND),
+                        &mut self.thread_scroll_state,
+                        &mut self.thread_buts_state,
+                        &mut self.close_thread_but_state,
+                        &mut self.thread_composer_state,
+                        theme,
+                    );
+                    screen_widgets.push(
+                        Container::new(thread_panel)
+                            .width(length!(= THREAD_PANEL_WIDTH))
+                            .height(length!(+))
+                            .style(theme)
+                            .into(),
+                    );
+                }
             } else {
                 let no_selected_channel_warning =
                     fill_container(label!("Select a channel").size(35).color(theme.user_theme.dimmed_text))
@@ -839,7 +1338,7 @@ impl MainScreen {
                     .width(length!(+))
                     .height(length!(+)),
                 )
-                .width(length!(= 220))
+                .width(length!(= SIDE_PANEL_WIDTH))
                 .height(length!(+))
                 .style(theme)
                 .into(),
@@ -859,7 +1358,7 @@ impl MainScreen {
                         .width(length!(+))
                         .height(length!(+)),
                 )
-                .width(length!(= 220))
+                .width(length!(= SIDE_PANEL_WIDTH))
                 .height(length!(+))
                 .style(theme)
                 .into(),
@@ -894,7 +1393,29 @@ impl MainScreen {
                     .padding(PADDING / 4),
                 )
                 .style(theme)
-                .height(length!(-))
+                .height(length!(= STATUS_BAR_HEIGHT))
+                .into(),
+                content,
+            ])
+            .width(length!(+))
+            .height(length!(+))
+            .align_items(Align::Center)
+            .into()
+        };
+
+        // Show connection status while the events socket is down
+        let content = if client.connection_status == ConnectionStatus::Connected {
+            content
+        } else {
+            Column::with_children(vec![
+                fill_container(
+                    label!(client.connection_status.to_string())
+                        .color(theme.user_theme.dimmed_text)
+                        .width(length!(+)),
+                )
+                .padding(PADDING / 4)
+                .style(theme)
+                .height(length!(= STATUS_BAR_HEIGHT))
                 .into(),
                 content,
             ])
@@ -912,6 +1433,30 @@ impl MainScreen {
         .backdrop(Message::HelpModal(true))
         .on_esc(Message::HelpModal(true));
 
+        // Show UnknownEventsModal
+        let content = Modal::new(&mut self.unknown_events_modal, content, move |state| {
+            state.view(theme, client).map(Message::UnknownEventsModal)
+        })
+        .style(theme)
+        .backdrop(Message::UnknownEventsModal(true))
+        .on_esc(Message::UnknownEventsModal(true));
+
+        // Show RevisionsModal
+        let content = Modal::new(&mut self.revisions_modal, content, move |state| {
+            state.view(theme, client).map(Message::RevisionsModal)
+        })
+        .style(theme)
+        .backdrop(Message::RevisionsModal(true))
+        .on_esc(Message::RevisionsModal(true));
+
+        // Show EmbedComposerModal
+        let content = Modal::new(&mut self.embed_composer_modal, content, move |state| {
+            state.view(theme, thumbnail_cache).map(Message::EmbedComposerMsg)
+        })
+        .style(theme)
+        .backdrop(Message::EmbedComposerMsg(embed_composer::Message::Close))
+        .on_esc(Message::EmbedComposerMsg(embed_composer::Message::Close));
+
         // Show ProfileEditModal
         let content = Modal::new(&mut self.profile_edit_modal, content, move |state| {
             state.view(theme, client, thumbnail_cache).map(Message::ProfileEditMsg)
@@ -967,21 +1512,150 @@ impl MainScreen {
                 .guilds
                 .get_mut(&guild_id)
                 .and_then(|guild| guild.channels.get_mut(&channel_id))
-                .and_do(|c| c.looking_at_message = c.messages.len().saturating_sub(1));
+                .and_do(|c| c.viewport.scroll_to_bottom());
         }
 
         match msg {
+            Message::RetrySend(transaction_id) => {
+                if let Some(cmd) = client.retry_outbox_message(transaction_id) {
+                    return Command::perform(cmd, map_send_msg);
+                }
+            }
+            Message::DiscardSend(transaction_id) => client.discard_outbox_message(transaction_id),
             Message::GotoReply(message_id) => {
                 let guild_id = self.current_guild_id.unwrap();
                 let channel_id = self.current_channel_id.unwrap();
 
                 if let Some(channel) = client.get_channel(guild_id, channel_id) {
-                    if let Some(pos) = channel.messages.iter().position(|(id, _)| message_id.eq(id)) {
-                        channel.looking_at_message = pos;
-                        self.event_history_state.snap_to(0.0);
+                    if channel.messages.contains_key(&message_id) {
+                        channel.viewport.scroll_to(message_id);
                     }
                 }
             }
+            Message::ShowDatePicker(show) => self.date_picker_state.show(show),
+            Message::JumpToDate(date) => {
+                self.date_picker_state.show(false);
+                self.date_search = None;
+                let (guild_id, channel_id) = match (self.current_guild_id, self.current_channel_id) {
+                    (Some(guild_id), Some(channel_id)) => (guild_id, channel_id),
+                    _ => return Command::none(),
+                };
+
+                // Message timestamps are in UTC, while the picked date is in local time
+                let midnight = date.and_hms(0, 0, 0);
+                let since = Local
+                    .from_local_datetime(&midnight)
+                    .earliest()
+                    .map_or(midnight, |since| since.naive_utc());
+                let location = match client.get_channel(guild_id, channel_id) {
+                    Some(channel) => channel.locate_date(since),
+                    None => return Command::none(),
+                };
+                match location {
+                    DateLocation::Loaded(message_id) => {
+                        return self.update(Message::GotoReply(message_id), client, thumbnail_cache);
+                    }
+                    DateLocation::Latest => {
+                        return self.update(Message::JumpToPresent, client, thumbnail_cache);
+                    }
+                    DateLocation::NotLoaded { oldest_loaded } => {
+                        self.date_search = Some(DateSearchState {
+                            guild_id,
+                            channel_id,
+                            date,
+                            since,
+                            reached: None,
+                        });
+                        return search_date(client, guild_id, channel_id, DateSearch::new(since, oldest_loaded));
+                    }
+                }
+            }
+            Message::DateSearched {
+                guild_id,
+                channel_id,
+                since,
+                step,
+            } => {
+                // Results of a cancelled search are dropped
+                let searching = match &mut self.date_search {
+                    Some(searching)
+                        if (searching.guild_id, searching.channel_id, searching.since)
+                            == (guild_id, channel_id, since) =>
+                    {
+                        searching
+                    }
+                    _ => return Command::none(),
+                };
+                match step {
+                    DateSearchStep::Found(message_id) => {
+                        self.date_search = None;
+                        let message = message_id.map_or(Message::JumpToPresent, |message_id| Message::JumpToMessage {
+                            guild_id,
+                            channel_id,
+                            message_id,
+                        });
+                        return self.update(message, client, thumbnail_cache);
+                    }
+                    DateSearchStep::Narrowed(search) => {
+                        searching.reached = search.reached;
+                        return search_date(client, guild_id, channel_id, search);
+                    }
+                }
+            }
+            Message::CancelDateSearch => self.date_search = None,
+            Message::OpenSearch => return self.open_search(client, String::new()),
+            Message::JumpToMessage {
+                guild_id,
+                channel_id,
+                message_id,
+            } => {
+                let mut cmds = Vec::with_capacity(3);
+                if self.current_guild_id != Some(guild_id) {
+                    cmds.push(self.update(Message::GuildChanged(guild_id), client, thumbnail_cache));
+                }
+                if self.current_channel_id != Some(channel_id) {
+                    cmds.push(self.update(Message::ChannelChanged(channel_id), client, thumbnail_cache));
+                }
+
+                let is_loaded = client
+                    .get_channel(guild_id, channel_id)
+                    .map_or(false, |c| c.messages.contains_key(&MessageId::Ack(message_id)));
+                if is_loaded {
+                    cmds.push(self.update(Message::GotoReply(MessageId::Ack(message_id)), client, thumbnail_cache));
+                } else {
+                    // [ref:goto_message_after_around]
+                    cmds.push(client.mk_cmd(
+                        |inner| async move {
+                            inner
+                                .call(
+                                    GetChannelMessages::new(guild_id, channel_id)
+                                        .with_message_id(message_id)
+                                        .with_direction(Direction::Around),
+                                )
+                                .await
+                        },
+                        move |response| {
+                            TopLevelMessage::GetChannelMessagesResponse {
+                                messages: response
+                                    .messages
+                                    .into_iter()
+                                    .flat_map(|m| {
+                                        let msg = m.message?;
+                                        Some((m.message_id, msg))
+                                    })
+                                    .collect(),
+                                reached_top: response.reached_top,
+                                guild_id,
+                                channel_id,
+                                message_id,
+                                direction: Direction::Around,
+                            }
+                        },
+                    ));
+                }
+
+                return Command::batch(cmds);
+            }
             Message::ChangeUserStatus(new_status) => {
                 return client.mk_cmd(
                     |inner| async move { inner.call(UpdateProfile::default().with_new_status(new_status)).await },
@@ -1110,6 +1784,26 @@ impl MainScreen {
                     }
                 }
             }
+            Message::RecallSentMessage(older) => {
+                if let (Some(guild_id), Some(channel_id), Mode::Normal) =
+                    (self.current_guild_id, self.current_channel_id, self.mode)
+                {
+                    let recalled = if older {
+                        self.input_history
+                            .older(guild_id, channel_id, &self.message)
+                            .map(str::to_string)
+                    } else {
+                        self.input_history.newer(guild_id, channel_id)
+                    };
+                    if let Some(text) = recalled {
+                        self.message = text;
+                        self.composer_state.focus();
+                        self.composer_state.move_cursor_to_end();
+                        self.save_draft(client);
+                    }
+                }
+            }
+            Message::ShiftHeld(held) => self.shift_held = held,
             Message::ChangeMode(mode) => {
                 if let Mode::EditingMessage(mid) = mode {
                     if let (Some(gid), Some(cid)) = (self.current_guild_id, self.current_channel_id) {
@@ -1121,10 +1815,10 @@ impl MainScreen {
                         {
                             self.composer_state.focus();
                             if let IcyContent::Text(text) = &msg.content {
-                                client::tracing::debug!("editing message: {} / \"{}\"", mid, text);
+                                client::tracing::debug!("editing message: {} / \"{}\"", mid, text.text);
                                 self.message.clear();
                                 self.message
-                                    .push_str(&render_text(text, &client.members, &client.emote_packs));
+                                    .push_str(&render_text(&text.text, &client.members, &client.emote_packs));
                             }
                         }
                     } else {
@@ -1135,7 +1829,8 @@ impl MainScreen {
                 if self.current_guild_id.is_some() && self.current_channel_id.is_some() {
                     if let (Mode::EditingMessage(_), Mode::Normal) = (self.mode, mode) {
                         self.composer_state.unfocus();
-                        self.message.clear();
+                        self.mode = mode;
+                        self.restore_draft(client);
                     }
                     if let (Mode::Normal, Mode::Normal) = (self.mode, mode) {
                         self.composer_state.unfocus();
@@ -1155,6 +1850,17 @@ impl MainScreen {
                 self.image_viewer_modal.inner_mut().image_handle = Some((handle, (path, name)));
                 return self.update(Message::ChangeMode(Mode::Normal), client, thumbnail_cache);
             }
+            Message::EmbedComposerMsg(msg) => {
+                let (cmd, go_back) = self.embed_composer_modal.inner_mut().update(msg, client);
+                self.embed_composer_modal.show(!go_back);
+                return cmd;
+            }
+            Message::OpenEmbedComposer => {
+                if let (Some(guild_id), Some(channel_id)) = (self.current_guild_id, self.current_channel_id) {
+                    self.embed_composer_modal.inner_mut().channel = Some((guild_id, channel_id));
+                    self.embed_composer_modal.show(true);
+                }
+            }
             Message::ProfileEditMsg(msg) => {
                 let (cmd, go_back) = self.profile_edit_modal.inner_mut().update(msg, client);
                 self.profile_edit_modal.show(!go_back);
@@ -1168,6 +1874,18 @@ impl MainScreen {
             Message::HelpModal(should_show) => {
                 should_show.and_do(|| self.help_modal.show(false));
             }
+            Message::UnknownEventsModal(should_close) => {
+                should_close.and_do(|| self.unknown_events_modal.show(false));
+            }
+            Message::RevisionsModal(should_close) => {
+                should_close.and_do(|| self.revisions_modal.show(false));
+            }
+            Message::ShowRevisions(message_id) => {
+                if let (Some(guild_id), Some(channel_id)) = (self.current_guild_id, self.current_channel_id) {
+                    self.revisions_modal.inner_mut().message = Some((guild_id, channel_id, message_id));
+                    self.revisions_modal.show(true);
+                }
+            }
             Message::LogoutChoice(confirm) => {
                 self.logout_modal.show(false);
                 return self.logout_modal.inner_mut().update(confirm, client);
@@ -1176,67 +1894,109 @@ impl MainScreen {
                 // these are safe since we dont show message history scroller if not in a channel
                 let guild_id = self.current_guild_id.unwrap();
                 let channel_id = self.current_channel_id.unwrap();
+                // The event history is laid out before it can be scrolled
+                let (width, height) = match self.history_size {
+                    Some(size) => size,
+                    None => return Command::none(),
+                };
+                let (scrolled_up, scrolled_down) = (
+                    scroll_perc <= self.prev_scroll_perc,
+                    scroll_perc >= self.prev_scroll_perc,
+                );
+                self.prev_scroll_perc = scroll_perc;
 
-                if scroll_perc < 0.01 && scroll_perc <= self.prev_scroll_perc {
-                    if let Some((oldest_msg_id, disp, reached_top, loading_messages_history, looking_at_message)) =
-                        client.get_channel(guild_id, channel_id).map(|channel| {
-                            (
-                                channel.last_known_message_id,
-                                channel.messages.len(),
-                                channel.reached_top,
-                                &mut channel.loading_messages_history,
-                                &mut channel.looking_at_message,
-                            )
-                        })
-                    {
-                        (*looking_at_message == disp.saturating_sub(1))
-                            .and_do(|| *looking_at_message = disp.saturating_sub(SHOWN_MSGS_LIMIT + 1))
-                            .or_do(|| *looking_at_message = looking_at_message.saturating_sub(1));
-
-                        if !reached_top && *looking_at_message < 2 && !*loading_messages_history {
-                            *loading_messages_history = true;
-                            return client.mk_cmd(
-                                |inner| async move {
-                                    inner
-                                        .call(
-                                            GetChannelMessages::new(guild_id, channel_id)
-                                                .with_message_id(oldest_msg_id),
-                                        )
-                                        .await
-                                        .map(|response| TopLevelMessage::GetChannelMessagesResponse {
-                                            messages: response
-                                                .messages
-                                                .into_iter()
-                                                .flat_map(|m| {
-                                                    let msg = m.message?;
-                                                    Some((m.message_id, msg))
-                                                })
-                                                .collect(),
-                                            reached_top: response.reached_top,
-                                            guild_id,
-                                            channel_id,
-                                            message_id: oldest_msg_id,
-                                            direction: Direction::BeforeUnspecified,
+                let c = match client.get_channel(guild_id, channel_id) {
+                    Some(c) => c,
+                    None => return Command::none(),
+                };
+                let heights = self.history_layout.update(guild_id, channel_id, c, width);
+                c.viewport.scrolled(&c.messages, heights, height, scroll_perc);
+
+                // Messages are fetched before they come into view, when some are missing next to the ones that
+                // are laid out
+                let visible = c.viewport.visible(&c.messages, heights, height);
+                self.history_layout.scrolled(&c.viewport);
+                let can_fetch = !c.loading_messages_history;
+                let fetch_before = (scrolled_up && can_fetch)
+                    .then(|| c.missing_before(visible.range.start))
+                    .flatten();
+                let fetch_after = (scrolled_down && can_fetch)
+                    .then(|| c.missing_after(visible.range.end.saturating_sub(1)))
+                    .flatten();
+                let reached_bottom_unread = c.reached_bottom && c.viewport.is_at_bottom() && c.has_unread();
+                if reached_bottom_unread {
+                    client.mark_channel_read(guild_id, channel_id);
+                }
+                if let Some(message_id) = fetch_after {
+                    return self.update(Message::LoadMissingMessages(message_id), client, thumbnail_cache);
+                }
+                if let Some(before_msg_id) = fetch_before {
+                    client
+                        .get_channel(guild_id, channel_id)
+                        .and_do(|c| c.loading_messages_history = true);
+                    return client.mk_cmd(
+                        |inner| async move {
+                            inner
+                                .call(GetChannelMessages::new(guild_id, channel_id).with_message_id(before_msg_id))
+                                .await
+                                .map(|response| TopLevelMessage::GetChannelMessagesResponse {
+                                    messages: response
+                                        .messages
+                                        .into_iter()
+                                        .flat_map(|m| {
+                                            let msg = m.message?;
+                                            Some((m.message_id, msg))
                                         })
-                                },
-                                identity,
-                            );
-                        }
-                    }
-                } else if scroll_perc > 0.99 && scroll_perc >= self.prev_scroll_perc {
-                    client.get_channel(guild_id, channel_id).and_do(|c| {
-                        let disp = c.messages.len();
-                        if c.looking_at_message > disp.saturating_sub(SHOWN_MSGS_LIMIT) {
-                            c.looking_at_message = disp.saturating_sub(1);
-                            if c.has_unread {
-                                c.has_unread = false;
-                            }
-                        } else {
-                            c.looking_at_message = c.looking_at_message.saturating_add(1).min(disp);
-                        }
-                    });
+                                        .collect(),
+                                    reached_top: response.reached_top,
+                                    guild_id,
+                                    channel_id,
+                                    message_id: before_msg_id,
+                                    direction: Direction::BeforeUnspecified,
+                                })
+                        },
+                        identity,
+                    );
                 }
-                self.prev_scroll_perc = scroll_perc;
+            }
+            Message::WindowResized(width, height) => self.window_size = Some((width, height)),
+            Message::LoadMissingMessages(message_id) => {
+                let (guild_id, channel_id) = match (self.current_guild_id, self.current_channel_id) {
+                    (Some(guild_id), Some(channel_id)) => (guild_id, channel_id),
+                    _ => return Command::none(),
+                };
+                match client.get_channel(guild_id, channel_id) {
+                    Some(c) if !c.loading_messages_history => c.loading_messages_history = true,
+                    _ => return Command::none(),
+                }
+
+                return client.mk_cmd(
+                    |inner| async move {
+                        inner
+                            .call(
+                                GetChannelMessages::new(guild_id, channel_id)
+                                    .with_message_id(message_id)
+                                    .with_direction(Direction::After),
+                            )
+                            .await
+                            .map(|response| TopLevelMessage::GetChannelMessagesResponse {
+                                messages: response
+                                    .messages
+                                    .into_iter()
+                                    .flat_map(|m| {
+                                        let msg = m.message?;
+                                        Some((m.message_id, msg))
+                                    })
+                                    .collect(),
+                                reached_top: response.reached_top,
+                                guild_id,
+                                channel_id,
+                                message_id,
+                                direction: Direction::After,
+                            })
+                    },
+                    identity,
+                );
             }
             Message::SelectedMember(user_id) => {
                 let modal = self.profile_edit_modal.inner_mut();
@@ -1258,6 +2018,18 @@ impl MainScreen {
                         )
                     });
                 }
+                GuildMenuOption::MarkGuildRead => {
+                    let guild_id = self.current_guild_id.unwrap(); // [ref:guild_menu_entry]
+                    client.mark_guild_read(guild_id);
+                    self.new_messages_after = None;
+                }
+                GuildMenuOption::MarkChannelRead => {
+                    let guild_id = self.current_guild_id.unwrap(); // [ref:guild_menu_entry]
+                    if let Some(channel_id) = self.current_channel_id {
+                        client.mark_channel_read(guild_id, channel_id);
+                        self.new_messages_after = None;
+                    }
+                }
                 GuildMenuOption::LeaveGuild => {
                     let guild_id = self.current_guild_id.unwrap(); // [ref:guild_menu_entry]
                     return client.mk_cmd(
@@ -1272,14 +2044,19 @@ impl MainScreen {
                         Default::default(),
                     )));
                 }
+                ProfileMenuOption::SearchMessages => {
+                    return self.update(Message::OpenSearch, client, thumbnail_cache);
+                }
+                ProfileMenuOption::Bookmarks => {
+                    return TopLevelScreen::push_screen_cmd(TopLevelScreen::Bookmarks(Box::new(Default::default())));
+                }
                 ProfileMenuOption::Logout => {
                     self.logout_modal.show(true);
                     return self.update(Message::ChangeMode(Mode::Normal), client, thumbnail_cache);
                 }
-                ProfileMenuOption::SwitchAccount => {
-                    return Command::perform(client.logout(false), |result| {
-                        result.unwrap().map_to_msg_def(|_| TopLevelMessage::PopScreen)
-                    });
+                ProfileMenuOption::AddAccount => {
+                    // The current account stays connected while logging in to another one
+                    return TopLevelScreen::push_screen_cmd(TopLevelScreen::Login(LoginScreen::new().into()));
                 }
                 ProfileMenuOption::EditProfile => {
                     let modal = self.profile_edit_modal.inner_mut();
@@ -1299,12 +2076,17 @@ impl MainScreen {
                     self.help_modal.show(true);
                     return self.update(Message::ChangeMode(Mode::Normal), client, thumbnail_cache);
                 }
+                ProfileMenuOption::UnknownEvents => {
+                    self.unknown_events_modal.show(true);
+                    return self.update(Message::ChangeMode(Mode::Normal), client, thumbnail_cache);
+                }
                 ProfileMenuOption::Exit => {
                     return Command::perform(async { TopLevelMessage::Exit }, identity);
                 }
             },
             Message::ComposerMessageChanged(new_msg) => {
                 self.message = new_msg;
+                self.save_draft(client);
                 let gid = self.current_guild_id.unwrap();
                 let cid = self.current_channel_id.unwrap();
                 let user_id = client.user_id.unwrap();
@@ -1322,11 +2104,48 @@ impl MainScreen {
                     }
                 }
             }
+            Message::JumpToPresent => {
+                let (guild_id, channel_id) = match (self.current_guild_id, self.current_channel_id) {
+                    (Some(guild_id), Some(channel_id)) => (guild_id, channel_id),
+                    _ => return Command::none(),
+                };
+                match client.get_channel(guild_id, channel_id) {
+                    Some(c) if c.reached_bottom => {
+                        return self.update(Message::ScrollToBottom(channel_id), client, thumbnail_cache);
+                    }
+                    Some(c) => c.loading_messages_history = true,
+                    None => return Command::none(),
+                }
+
+                // Scrolls to the bottom once they are loaded [ref:scroll_after_newest_messages]
+                return client.mk_cmd(
+                    |inner| async move {
+                        inner
+                            .call(GetChannelMessages::new(guild_id, channel_id))
+                            .await
+                            .map(|response| TopLevelMessage::GetChannelMessagesResponse {
+                                messages: response
+                                    .messages
+                                    .into_iter()
+                                    .flat_map(|m| {
+                                        let msg = m.message?;
+                                        Some((m.message_id, msg))
+                                    })
+                                    .collect(),
+                                reached_top: response.reached_top,
+                                guild_id,
+                                channel_id,
+                                message_id: 0,
+                                direction: Direction::BeforeUnspecified,
+                            })
+                    },
+                    identity,
+                );
+            }
             Message::ScrollToBottom(sent_channel_id) => {
                 if let (Some(guild_id), Some(channel_id)) = (self.current_guild_id, self.current_channel_id) {
                     if sent_channel_id == channel_id {
                         scroll_to_bottom(client, guild_id, channel_id);
-                        self.event_history_state.snap_to(1.0);
                     }
                 }
             }
@@ -1403,41 +2222,66 @@ impl MainScreen {
                 };
             }
             Message::SendMessageComposer { guild_id, channel_id } => {
-                let replace_stuff = |text: &str| {
-                    let mut text = text.to_string();
-                    if let Some(guild) = client.guilds.get(&self.current_guild_id.unwrap()) {
-                        for (id, member) in client.members.iter().filter(|(id, _)| guild.members.contains_key(id)) {
-                            use client::byte_writer::Writer;
-                            use std::fmt::Write;
-
-                            let mut pattern_arr = [b'0'; 23];
-                            write!(Writer(&mut pattern_arr), "<@{}>", id).unwrap();
-                            text = text.replace(
-                                &format!("@{}", member.username),
-                                (unsafe { std::str::from_utf8_unchecked(&pattern_arr) }).trim_end_matches(|c| c != '>'),
-                            );
-                        }
+                if self.shift_held {
+                    let (start, end) = self.composer_state.selected_range(&self.message);
+                    self.message.replace_range(start..end, "\n");
+                    self.composer_state
+                        .move_cursor_to(self.message[..=start].chars().count());
+                    self.save_draft(client);
+                    return Command::none();
+                }
+
+                if self.mode == Mode::Normal {
+                    // Enter picks the selected completion instead of running an unfinished command
+                    if self.completion_current.is_some() && self.message.starts_with('/') {
+                        return Command::none();
                     }
-                    for pack in client.emote_packs.values() {
-                        for (image_id, name) in &pack.emotes {
-                            text = text.replace(&format!(":{}:", name), &format!("<:{}:>", image_id));
+                    match commands::parse(&self.message) {
+                        Some(Err(err)) => {
+                            self.error_text = err.to_string();
+                            return Command::none();
                         }
+                        Some(Ok(SlashCommand::Say(text))) => self.message = text,
+                        Some(Ok(command)) => {
+                            self.input_history
+                                .push(guild_id, channel_id, self.message.trim().to_string());
+                            self.message.clear();
+                            self.save_draft(client);
+                            return self.run_command(command, client, thumbnail_cache);
+                        }
+                        None => {}
                     }
-                    text
-                };
+                }
+
+                let mut cmds = Vec::with_capacity(2);
+                // Files and text can't be in the same message, so the files are sent first
+                if self.mode == Mode::Normal && !self.attachments.is_empty() {
+                    let message = IcyMessage {
+                        content: IcyContent::Files(std::mem::take(&mut self.attachments)),
+                        sender: client.user_id.unwrap(),
+                        reply_to: self.message.trim().is_empty().then(|| self.reply_to.take()).flatten(),
+                        ..Default::default()
+                    };
+                    self.save_draft(client);
+                    let cmd = client.send_msg_cmd(
+                        guild_id,
+                        channel_id,
+                        Duration::from_secs(0),
+                        MessageId::default(),
+                        message,
+                    );
+                    scroll_to_bottom(client, guild_id, channel_id);
+                    cmds.push(Command::perform(cmd, map_send_msg));
+                }
+
+                let replace_stuff = |text: &str| expand_mentions_and_emotes(client, guild_id, text);
 
                 if !self.message.trim().is_empty() {
                     match self.mode {
                         Mode::EditingMessage(message_id) => {
                             let new_content = replace_stuff(self.message.trim());
-                            self.message.clear();
-                            if let Some(msg) = client
-                                .get_channel(guild_id, channel_id)
-                                .and_then(|c| c.messages.get_mut(&MessageId::Ack(message_id)))
-                            {
-                                msg.being_edited = Some(new_content.clone());
-                            }
                             self.mode = Mode::Normal;
+                            self.restore_draft(client);
                             return Command::perform(
                                 client.edit_msg_cmd(guild_id, channel_id, message_id, new_content),
                                 |(guild_id, channel_id, message_id, err)| TopLevelMessage::MessageEdited {
@@ -1449,33 +2293,43 @@ impl MainScreen {
                             );
                         }
                         Mode::Normal => {
+                            self.input_history
+                                .push(guild_id, channel_id, self.message.trim().to_string());
                             let message = IcyMessage {
-                                content: IcyContent::Text(replace_stuff(self.message.trim())),
+                                content: IcyContent::text(replace_stuff(self.message.trim())),
                                 sender: client.user_id.unwrap(),
                                 reply_to: self.reply_to.take(),
                                 ..Default::default()
                             };
                             self.message.clear();
-                            if let Some(cmd) = client.send_msg_cmd(
+                            self.save_draft(client);
+                            let cmd = client.send_msg_cmd(
                                 guild_id,
                                 channel_id,
                                 Duration::from_secs(0),
                                 MessageId::default(),
                                 message,
-                            ) {
-                                scroll_to_bottom(client, guild_id, channel_id);
-                                self.event_history_state.snap_to(1.0);
-                                return Command::perform(cmd, map_send_msg);
-                            }
+                            );
+                            scroll_to_bottom(client, guild_id, channel_id);
+                            cmds.push(Command::perform(cmd, map_send_msg));
                         }
                     }
                 } else if let Mode::EditingMessage(mid) = self.mode {
                     self.mode = Mode::Normal;
+                    self.restore_draft(client);
                     return Command::perform(
                         client.delete_msg_cmd(guild_id, channel_id, mid),
                         ResultExt::map_to_nothing,
                     );
                 }
+                // Sent messages are shown after the newest ones, not after older ones that were jumped to
+                let is_detached = client
+                    .get_channel(guild_id, channel_id)
+                    .map_or(false, |c| !c.reached_bottom);
+                if is_detached && !cmds.is_empty() {
+                    cmds.push(self.update(Message::JumpToPresent, client, thumbnail_cache));
+                }
+                return Command::batch(cmds);
             }
             Message::SelectFilesToSend => {
                 if let (Some(guild_id), Some(channel_id)) = (self.current_guild_id, self.current_channel_id) {
@@ -1530,21 +2384,16 @@ impl MainScreen {
                 if let Some(channel) = client.get_channel(guild_id, channel_id) {
                     channel.uploading_files.clear();
                     match *result {
+                        // Uploaded files are sent with the next message of the channel
                         Ok(attachments) => {
-                            let sender = client.user_id.unwrap();
-                            return Command::perform(
-                                ready(TopLevelMessage::SendMessage {
-                                    message: IcyMessage {
-                                        content: IcyContent::Files(attachments),
-                                        sender,
-                                        ..Default::default()
-                                    },
-                                    retry_after: Duration::from_secs(0),
-                                    guild_id,
-                                    channel_id,
-                                }),
-                                identity,
-                            );
+                            if (self.current_guild_id, self.current_channel_id) == (Some(guild_id), Some(channel_id)) {
+                                self.attachments.extend(attachments);
+                                self.save_draft(client);
+                            } else {
+                                let mut draft = client.drafts.get(guild_id, channel_id).cloned().unwrap_or_default();
+                                draft.attachments.extend(attachments);
+                                client.drafts.set(guild_id, channel_id, draft);
+                            }
                         }
                         Err(err) => {
                             return Command::perform(ready(TopLevelMessage::Error(Box::new(err))), identity);
@@ -1552,9 +2401,17 @@ impl MainScreen {
                     }
                 }
             }
+            Message::SwitchAccount(account) => {
+                return Command::perform(ready(TopLevelMessage::SwitchToAccount(account)), identity);
+            }
             Message::GuildChanged(guild_id) => {
                 self.mode = Mode::Normal;
+                self.date_search = None;
                 self.message.clear();
+                self.reply_to = None;
+                self.attachments.clear();
+                self.input_history.stop_browsing();
+                self.close_thread();
                 self.current_guild_id = Some(guild_id);
                 if let Some(guild) = client.get_guild(guild_id) {
                     if guild.channels.is_empty() && !guild.init_fetching {
@@ -1647,68 +2504,63 @@ impl MainScreen {
             }
             Message::ChannelChanged(channel_id) => {
                 let guild_id = self.current_guild_id.unwrap();
+                self.date_search = None;
 
-                if let Some(channel_id) = self.current_channel_id {
+                let mut cmds = Vec::with_capacity(2);
+
+                if let Some(prev_channel_id) = self.current_channel_id {
                     client
-                        .get_channel(guild_id, channel_id)
+                        .get_channel(guild_id, prev_channel_id)
                         .and_do(|c| c.looking_at_channel = false);
+                    if let Some(save) = (prev_channel_id != channel_id)
+                        .then(|| client.save_channel_cache(guild_id, prev_channel_id))
+                        .flatten()
+                    {
+                        cmds.push(Command::perform(save, ResultExt::map_to_nothing));
+                    }
                 }
 
                 self.mode = Mode::Normal;
-                self.message.clear();
+                if self.current_channel_id != Some(channel_id) {
+                    self.close_thread();
+                    self.revealed_spoilers.clear();
+                }
                 self.current_channel_id = Some(channel_id);
+                self.input_history.stop_browsing();
+                self.restore_draft(client);
+                client.save_drafts();
                 self.guild_last_channels.insert(guild_id, channel_id);
 
+                let mut mark_read = false;
                 if let Some(c) = client.get_channel(guild_id, channel_id) {
                     let disp = c.messages.len();
                     let reached_top = c.reached_top;
                     c.looking_at_channel = true;
+                    self.new_messages_after = c.has_unread().then(|| c.read_marker.last_read).flatten();
 
-                    (c.looking_at_message >= disp.saturating_sub(SHOWN_MSGS_LIMIT)).and_do(|| {
-                        c.has_unread = false;
-                        c.looking_at_message = disp.saturating_sub(1);
-                        self.event_history_state.snap_to(1.0);
-                    });
+                    if c.viewport.is_at_bottom() {
+                        mark_read = c.has_unread();
+                    }
 
-                    let mut cmds = Vec::with_capacity(2);
                     // Try to messages if we dont have any and we arent at the top
+                    // Cached messages are shown first, newer ones are fetched after [ref:channel_cache_loaded]
                     if !reached_top && disp == 0 && !c.init_fetching {
-                        let convert_to_event = move |m: GetChannelMessagesResponse| {
-                            Box::new(TopLevelMessage::GetChannelMessagesResponse {
-                                guild_id,
-                                channel_id,
-                                message_id: 0,
-                                messages: m
-                                    .messages
-                                    .into_iter()
-                                    .flat_map(|m| {
-                                        let msg = m.message?;
-                                        Some((m.message_id, msg))
-                                    })
-                                    .collect(),
-                                reached_top: m.reached_top,
-                                direction: Direction::BeforeUnspecified,
-                            })
-                        };
                         c.init_fetching = true;
-                        let inner = client.inner_arc();
                         cmds.push(Command::perform(
-                            async move {
-                                inner
-                                    .call(GetChannelMessages::new(guild_id, channel_id))
-                                    .await
-                                    .map(convert_to_event)
-                                    .map_err(ClientError::from)
-                            },
-                            move |events| TopLevelMessage::InitialChannelLoad {
+                            client.load_channel_cache(guild_id, channel_id),
+                            move |cache| TopLevelMessage::ChannelCacheLoaded {
                                 guild_id,
                                 channel_id,
-                                events,
+                                cache,
                             },
                         ));
                     }
-                    return Command::batch(cmds);
                 }
+                if mark_read {
+                    client.mark_channel_read(guild_id, channel_id);
+                }
+
+                return Command::batch(cmds);
             }
             Message::NextBeforeGuild(before) => {
                 let change_guild_to = if let Some(guild_pos) = self
@@ -1778,6 +2630,7 @@ impl MainScreen {
                     self.composer_state.focus();
                     self.message.push(c);
                     self.composer_state.move_cursor_to_end();
+                    self.save_draft(client);
                 }
             }
             Message::CopyToClipboard(value) => return iced::clipboard::write(value),
@@ -1791,14 +2644,17 @@ impl MainScreen {
                             .and_then(|c| c.messages.get(&id))
                             .map_or_else(Command::none, |m| {
                                 if let IcyContent::Text(text) = &m.content {
-                                    iced::clipboard::write(text.clone())
+                                    iced::clipboard::write(text.text.clone())
                                 } else {
                                     Command::none()
                                 }
                             });
                     }
                 }
-                MessageMenuOption::Reply(id) => self.reply_to = Some(id),
+                MessageMenuOption::Reply(id) => {
+                    self.reply_to = Some(id);
+                    self.save_draft(client);
+                }
                 MessageMenuOption::Edit(id) => {
                     return self.update(Message::ChangeMode(Mode::EditingMessage(id)), client, thumbnail_cache);
                 }
@@ -1811,8 +2667,124 @@ impl MainScreen {
                     }
                 }
                 MessageMenuOption::CopyMessageId(id) => return iced::clipboard::write(id.to_string()),
+                MessageMenuOption::MarkUnread(message_id) => {
+                    if let (Some(guild_id), Some(channel_id)) = (self.current_guild_id, self.current_channel_id) {
+                        client.mark_unread_from(guild_id, channel_id, message_id);
+                        self.new_messages_after = client
+                            .guilds
+                            .get(&guild_id)
+                            .and_then(|g| g.channels.get(&channel_id))
+                            .and_then(|c| c.read_marker.last_read);
+                    }
+                }
+                MessageMenuOption::Save(message_id) => {
+                    if let (Some(guild_id), Some(channel_id)) = (self.current_guild_id, self.current_channel_id) {
+                        client.add_bookmark(guild_id, channel_id, message_id);
+                    }
+                }
+                MessageMenuOption::OpenThread(message_id) => {
+                    return self.update(Message::OpenThread(message_id), client, thumbnail_cache);
+                }
+                MessageMenuOption::React {
+                    message_id,
+                    image_id,
+                    name,
+                    ..
+                } => {
+                    return self.update(
+                        Message::ToggleReaction {
+                            message_id,
+                            image_id,
+                            name,
+                        },
+                        client,
+                        thumbnail_cache,
+                    );
+                }
             },
-            Message::ClearReply => self.reply_to = None,
+            Message::OpenThread(message_id) => {
+                self.thread_of = Some(message_id);
+                self.thread_message.clear();
+                self.thread_composer_state.focus();
+                if let (Some(guild_id), Some(channel_id)) = (self.current_guild_id, self.current_channel_id) {
+                    let missing_parent = client
+                        .get_channel(guild_id, channel_id)
+                        .and_then(|c| c.thread(message_id).missing_parent);
+                    if let Some(message_id) = missing_parent {
+                        // Parents above this one are fetched as each one arrives, since fetched messages
+                        // ask for the message they reply to
+                        let fetch = PostProcessEvent::FetchMessage {
+                            guild_id,
+                            channel_id,
+                            message_id,
+                        };
+                        return Command::perform(ready(TopLevelMessage::PostProcessEvents(vec![fetch])), identity);
+                    }
+                }
+            }
+            Message::CloseThread => self.close_thread(),
+            Message::RevealSpoiler(message_id, at) => {
+                self.revealed_spoilers.insert((message_id, at));
+            }
+            Message::TogglePreview => self.previewing = !self.previewing,
+            Message::ThreadComposerChanged(new_msg) => self.thread_message = new_msg,
+            Message::SendThreadMessage => {
+                if let (Some(guild_id), Some(channel_id), Some(reply_to)) =
+                    (self.current_guild_id, self.current_channel_id, self.thread_of)
+                {
+                    if !self.thread_message.trim().is_empty() {
+                        let message = IcyMessage {
+                            content: IcyContent::text(expand_mentions_and_emotes(
+                                client,
+                                guild_id,
+                                self.thread_message.trim(),
+                            )),
+                            sender: client.user_id.unwrap(),
+                            reply_to: Some(reply_to),
+                            ..Default::default()
+                        };
+                        self.thread_message.clear();
+                        let cmd = client.send_msg_cmd(
+                            guild_id,
+                            channel_id,
+                            Duration::from_secs(0),
+                            MessageId::default(),
+                            message,
+                        );
+                        return Command::perform(cmd, map_send_msg);
+                    }
+                }
+            }
+            Message::ToggleReaction {
+                message_id,
+                image_id,
+                name,
+            } => {
+                if let (Some(guild_id), Some(channel_id)) = (self.current_guild_id, self.current_channel_id) {
+                    let cmd =
+                        client.toggle_reaction_cmd(guild_id, channel_id, message_id, image_id.clone(), name.clone());
+                    if let Some(cmd) = cmd {
+                        return Command::perform(cmd, move |result| TopLevelMessage::ReactionToggled {
+                            guild_id,
+                            channel_id,
+                            message_id,
+                            image_id: image_id.clone(),
+                            name: name.clone(),
+                            result,
+                        });
+                    }
+                }
+            }
+            Message::ClearReply => {
+                self.reply_to = None;
+                self.save_draft(client);
+            }
+            Message::RemoveAttachment(index) => {
+                if index < self.attachments.len() {
+                    self.attachments.remove(index);
+                    self.save_draft(client);
+                }
+            }
             Message::AutoCompleteBefore => {
                 if let Some(before) = &self.before_after_completion_items.0 {
                     self.completion_current = Some(before.clone());
@@ -1851,7 +2823,20 @@ impl MainScreen {
 
                             self.composer_state.focus();
                             self.composer_state.move_cursor_to(idx + 1);
+                        } else if !self.message.contains('\n')
+                            && !commands::completions(&self.message, start, word).is_empty()
+                        {
+                            let completion = if start == 0 {
+                                format!("/{} ", completion_item)
+                            } else {
+                                format!("{} ", completion_item)
+                            };
+                            self.message.replace_range(start..end, &completion);
+
+                            self.composer_state.focus();
+                            self.composer_state.move_cursor_to(start + completion.len());
                         }
+                        self.save_draft(client);
                     }
                 }
             }
@@ -1861,7 +2846,7 @@ impl MainScreen {
     }
 
     pub fn subscription(&self) -> Subscription<TopLevelMessage> {
-        use iced_native::{event::Status, keyboard, Event};
+        use iced_native::{event::Status, keyboard, window, Event};
 
         let filter_events = |ev: Event, status: Status| -> Option<TopLevelMessage> {
             type Ke = keyboard::Event;
@@ -1875,6 +2860,10 @@ impl MainScreen {
                     key_code: Kc::K,
                     modifiers,
                 }) => modifiers.control().then(|| TopLevelMessage::main(Message::QuickSwitch)),
+                Event::Keyboard(Ke::KeyPressed {
+                    key_code: Kc::F,
+                    modifiers,
+                }) => modifiers.control().then(|| TopLevelMessage::main(Message::OpenSearch)),
                 Event::Keyboard(Ke::KeyPressed {
                     key_code: Kc::Up,
                     modifiers,
@@ -1883,7 +2872,7 @@ impl MainScreen {
                         if modifiers.alt() {
                             TopLevelMessage::main(Message::NextBeforeGuild(true))
                         } else {
-                            return None;
+                            TopLevelMessage::main(Message::RecallSentMessage(true))
                         }
                     } else if modifiers.alt() {
                         TopLevelMessage::main(Message::NextBeforeChannel(true))
@@ -1900,7 +2889,7 @@ impl MainScreen {
                         if modifiers.alt() {
                             TopLevelMessage::main(Message::NextBeforeGuild(false))
                         } else {
-                            return None;
+                            TopLevelMessage::main(Message::RecallSentMessage(false))
                         }
                     } else if modifiers.alt() {
                         TopLevelMessage::main(Message::NextBeforeChannel(false))
@@ -1922,9 +2911,15 @@ impl MainScreen {
                 Event::Keyboard(Ke::KeyPressed {
                     key_code: Kc::Enter, ..
                 }) => Some(TopLevelMessage::main(Message::AutoComplete)),
+                Event::Keyboard(Ke::ModifiersChanged(modifiers)) => {
+                    Some(TopLevelMessage::main(Message::ShiftHeld(modifiers.shift())))
+                }
                 Event::Keyboard(Ke::CharacterReceived(c)) => (matches!(status, Status::Ignored)
                     && !['', '\t'].contains(&c))
                 .then(|| TopLevelMessage::main(Message::FocusComposer(c))),
+                Event::Window(window::Event::Resized { width, height }) => {
+                    Some(TopLevelMessage::main(Message::WindowResized(width, height)))
+                }
                 _ => None,
             }
         };
@@ -1932,6 +2927,88 @@ impl MainScreen {
         iced_native::subscription::events_with(filter_events)
     }
 
+    fn open_search(&self, client: &Client, query: String) -> Command<TopLevelMessage> {
+        let mut screen = match (self.current_guild_id, self.current_channel_id) {
+            (Some(guild_id), Some(channel_id)) => super::SearchScreen::in_channel(guild_id, channel_id),
+            _ => super::SearchScreen::default(),
+        }
+        .with_query(query);
+        screen.search(client);
+        TopLevelScreen::push_screen_cmd(TopLevelScreen::Search(screen.into()))
+    }
+
+    /// Runs a slash command typed in the composer of the given channel.
+    fn run_command(
+        &mut self,
+        command: SlashCommand,
+        client: &mut Client,
+        thumbnail_cache: &ThumbnailCache,
+    ) -> Command<TopLevelMessage> {
+        match command {
+            // The composer sends the text like any other message
+            SlashCommand::Say(_) => Command::none(),
+            SlashCommand::SetStatus(status) => self.update(Message::ChangeUserStatus(status), client, thumbnail_cache),
+            SlashCommand::SetUsername(username) => client.mk_cmd(
+                |inner| async move { inner.call(UpdateProfile::default().with_new_username(username)).await },
+                map_to_nothing,
+            ),
+            SlashCommand::JoinGuild(invite) => client.mk_cmd(
+                |inner| async move { inner.call(JoinGuildRequest::new(invite)).await },
+                map_to_nothing,
+            ),
+            SlashCommand::LeaveGuild => self.update(
+                Message::SelectedGuildMenuOption(GuildMenuOption::LeaveGuild),
+                client,
+                thumbnail_cache,
+            ),
+            SlashCommand::Upload => self.update(Message::SelectFilesToSend, client, thumbnail_cache),
+            SlashCommand::ComposeEmbed => self.update(Message::OpenEmbedComposer, client, thumbnail_cache),
+            SlashCommand::ClearComposer => {
+                self.reply_to = None;
+                self.attachments.clear();
+                self.save_draft(client);
+                Command::none()
+            }
+            SlashCommand::Search(query) => self.open_search(client, query),
+            SlashCommand::Help => {
+                self.help_modal.show(true);
+                Command::none()
+            }
+        }
+    }
+
+    /// Keeps what is in the composer as the draft of the current channel.
+    fn save_draft(&self, client: &mut Client) {
+        if let (Some(guild_id), Some(channel_id), Mode::Normal) =
+            (self.current_guild_id, self.current_channel_id, self.mode)
+        {
+            let draft = Draft {
+                text: self.message.clone(),
+                reply_to: self.reply_to,
+                attachments: self.attachments.clone(),
+            };
+            client.drafts.set(guild_id, channel_id, draft);
+        }
+    }
+
+    /// Puts the draft of the current channel in the composer.
+    fn restore_draft(&mut self, client: &Client) {
+        let draft = self
+            .current_guild_id
+            .zip(self.current_channel_id)
+            .and_then(|(guild_id, channel_id)| client.drafts.get(guild_id, channel_id))
+            .cloned()
+            .unwrap_or_default();
+        self.message = draft.text;
+        self.reply_to = draft.reply_to;
+        self.attachments = draft.attachments;
+    }
+
+    fn close_thread(&mut self) {
+        self.thread_of = None;
+        self.thread_message.clear();
+    }
+
     pub fn on_error(&mut self, error: ClientError) -> Command<TopLevelMessage> {
         if let ClientError::Internal(InnerClientError::Reqwest(error)) = &error {
             if error.status() == Some(StatusCode::NOT_FOUND) {
@@ -1940,6 +3017,48 @@ impl MainScreen {
         }
         self.error_text = error.to_string();
         self.logout_modal.show(false);
+        self.date_search = None;
         Command::none()
     }
 }
+
+/// Fetches the next history page of a date search.
+fn search_date(client: &Client, guild_id: u64, channel_id: u64, search: DateSearch) -> Command<TopLevelMessage> {
+    let since = search.since;
+    client.mk_cmd(
+        |inner| async move { fetch_first_message_since(&inner, guild_id, channel_id, search).await },
+        move |step| {
+            TopLevelMessage::main(Message::DateSearched {
+                guild_id,
+                channel_id,
+                since,
+                step,
+            })
+        },
+    )
+}
+
+/// Replaces `@username` mentions of guild members and `:name:` emotes in `text` with their
+/// message syntax.
+fn expand_mentions_and_emotes(client: &Client, guild_id: u64, text: &str) -> String {
+    let mut text = text.to_string();
+    if let Some(guild) = client.guilds.get(&guild_id) {
+        for (id, member) in client.members.iter().filter(|(id, _)| guild.members.contains_key(id)) {
+            use client::byte_writer::Writer;
+            use std::fmt::Write;
+
+            let mut pattern_arr = [b'0'; 23];
+            write!(Writer(&mut pattern_arr), "<@{}>", id).unwrap();
+            text = text.replace(
+                &format!("@{}", member.username),
+                (unsafe { std::str::from_utf8_unchecked(&pattern_arr) }).trim_end_matches(|c| c != '>'),
+            );
+        }
+    }
+    for pack in client.emote_packs.values() {
+        for (image_id, name) in &pack.emotes {
+            text = text.replace(&format!(":{}:", name), &format!("<:{}:>", image_id));
+        }
+    }
+    text
+}
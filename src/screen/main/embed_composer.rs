@@ -0,0 +1,445 @@
+use std::{convert::identity, ops::Not};
+
+use client::{
+    harmony_rust_sdk::client::api::rest::FileId,
+    message::{Attachment, Content as IcyContent, Embed, EmbedField, EmbedHeading, Message as IcyMessage},
+    smol_str::SmolStr,
+};
+use iced::{futures::future::ready, Tooltip};
+use iced_aw::{color_picker, Card, ColorPicker};
+
+use super::super::{select_upload_files, Message as TopLevelMessage};
+use crate::{
+    client::{Client, PostProcessEvent},
+    component::{event_history::build_embed, *},
+    label, label_button, length,
+    screen::ClientExt,
+    space,
+    style::{tuple_to_iced_color, Theme, DEF_SIZE, PADDING, SPACING},
+};
+
+#[derive(Debug, Clone, Copy)]
+pub enum HeadingPart {
+    Text,
+    Subtext,
+    Url,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum FieldPart {
+    Title,
+    Subtitle,
+    Body,
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    TitleChanged(String),
+    BodyChanged(String),
+    ShowColorPicker(bool),
+    ColorPicked(Color),
+    ClearColor,
+    HeadingChanged {
+        footer: bool,
+        part: HeadingPart,
+        value: String,
+    },
+    UploadIcon {
+        footer: bool,
+    },
+    IconUploaded {
+        footer: bool,
+        icon: Attachment,
+    },
+    AddField,
+    RemoveField(usize),
+    FieldChanged {
+        index: usize,
+        part: FieldPart,
+        value: String,
+    },
+    OpenUrl(SmolStr),
+    Send,
+    Close,
+}
+
+#[derive(Debug, Default, Clone)]
+struct HeadingInput {
+    text: String,
+    subtext: String,
+    url: String,
+    icon: Option<FileId>,
+    text_state: text_input::State,
+    subtext_state: text_input::State,
+    url_state: text_input::State,
+    icon_but_state: button::State,
+}
+
+impl HeadingInput {
+    /// The heading, if it has text.
+    fn heading(&self) -> Option<EmbedHeading> {
+        self.text.trim().is_empty().not().then(|| EmbedHeading {
+            text: self.text.trim().to_string(),
+            subtext: non_empty(&self.subtext),
+            url: non_empty(&self.url).map(Into::into),
+            icon: self.icon.clone(),
+        })
+    }
+
+    fn view<'a>(&'a mut self, name: &str, footer: bool, theme: &Theme) -> Element<'a, Message> {
+        let input = |state: &'a mut text_input::State, placeholder: &str, value: &str, part| -> Element<'a, Message> {
+            TextInput::new(state, placeholder, value, move |value| Message::HeadingChanged {
+                footer,
+                part,
+                value,
+            })
+            .padding(PADDING / 2)
+            .style(theme)
+            .into()
+        };
+        let icon_text = if self.icon.is_some() {
+            "Change icon"
+        } else {
+            "Upload icon"
+        };
+
+        Column::with_children(vec![
+            label!(name).into(),
+            input(&mut self.text_state, "Text", &self.text, HeadingPart::Text),
+            input(&mut self.subtext_state, "Subtext", &self.subtext, HeadingPart::Subtext),
+            input(&mut self.url_state, "Link", &self.url, HeadingPart::Url),
+            label_button!(&mut self.icon_but_state, icon_text)
+                .on_press(Message::UploadIcon { footer })
+                .style(theme)
+                .into(),
+        ])
+        .spacing(SPACING)
+        .into()
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+struct FieldInput {
+    title: String,
+    subtitle: String,
+    body: String,
+    title_state: text_input::State,
+    subtitle_state: text_input::State,
+    body_state: text_input::State,
+    remove_but_state: button::State,
+}
+
+/// Builds an embed and sends it to a channel.
+#[derive(Debug, Default, Clone)]
+pub struct EmbedComposerModal {
+    /// Guild ID and channel ID of the channel the embed is sent to.
+    pub channel: Option<(u64, u64)>,
+    title: String,
+    body: String,
+    color: Option<[u8; 3]>,
+    header: HeadingInput,
+    footer: HeadingInput,
+    fields: Vec<FieldInput>,
+    title_state: text_input::State,
+    body_state: text_input::State,
+    color_picker_state: color_picker::State,
+    color_but_state: button::State,
+    clear_color_but_state: button::State,
+    add_field_but_state: button::State,
+    send_but_state: button::State,
+    form_state: scrollable::State,
+    preview_header_but_state: button::State,
+    preview_footer_but_state: button::State,
+}
+
+fn non_empty(value: &str) -> Option<String> {
+    let value = value.trim();
+    value.is_empty().not().then(|| value.to_string())
+}
+
+impl EmbedComposerModal {
+    /// The embed as it is currently written.
+    pub fn embed(&self) -> Embed {
+        Embed {
+            title: self.title.trim().to_string(),
+            body: non_empty(&self.body),
+            color: self.color,
+            header: self.header.heading(),
+            footer: self.footer.heading(),
+            fields: self
+                .fields
+                .iter()
+                .filter(|field| field.title.trim().is_empty().not())
+                .map(|field| EmbedField {
+                    title: field.title.trim().to_string(),
+                    subtitle: non_empty(&field.subtitle),
+                    body: non_empty(&field.body),
+                })
+                .collect(),
+        }
+    }
+
+    pub fn view<'a>(&'a mut self, theme: &'a Theme, thumbnail_cache: &'a ThumbnailCache) -> Element<'a, Message> {
+        let embed = self.embed();
+        let can_send = embed.title.is_empty().not();
+
+        let mut form = Scrollable::new(&mut self.form_state)
+            .align_items(Align::Start)
+            .height(length!(= 480))
+            .width(length!(= 360))
+            .spacing(SPACING * 2)
+            .style(theme);
+
+        form = form.push(
+            TextInput::new(&mut self.title_state, "Title", &self.title, Message::TitleChanged)
+                .padding(PADDING / 2)
+                .style(theme),
+        );
+        form = form.push(
+            TextInput::new(&mut self.body_state, "Body", &self.body, Message::BodyChanged)
+                .padding(PADDING / 2)
+                .style(theme),
+        );
+
+        let color_button = Button::new(
+            &mut self.color_but_state,
+            Row::with_children(vec![
+                Container::new(space!(= DEF_SIZE, DEF_SIZE))
+                    .style(
+                        theme
+                            .border_width(0.0)
+                            .background_color(self.color.map_or(theme.user_theme.dimmed_text, tuple_to_iced_color)),
+                    )
+                    .into(),
+                label!("Pick color").into(),
+            ])
+            .spacing(SPACING)
+            .align_items(Align::Center),
+        )
+        .style(theme)
+        .on_press(Message::ShowColorPicker(true));
+        let mut clear_color = label_button!(&mut self.clear_color_but_state, "Clear color").style(theme);
+        if self.color.is_some() {
+            clear_color = clear_color.on_press(Message::ClearColor);
+        }
+        form = form.push(
+            Row::with_children(vec![
+                ColorPicker::new(
+                    &mut self.color_picker_state,
+                    color_button,
+                    Message::ShowColorPicker(false),
+                    Message::ColorPicked,
+                )
+                .style(theme)
+                .into(),
+                clear_color.into(),
+            ])
+            .spacing(SPACING)
+            .align_items(Align::Center),
+        );
+
+        form = form.push(self.header.view("Header", false, theme));
+
+        for (index, field) in self.fields.iter_mut().enumerate() {
+            let input =
+                |state: &'a mut text_input::State, placeholder: &str, value: &str, part| -> Element<'a, Message> {
+                    TextInput::new(state, placeholder, value, move |value| Message::FieldChanged {
+                        index,
+                        part,
+                        value,
+                    })
+                    .padding(PADDING / 2)
+                    .style(theme)
+                    .into()
+                };
+            let remove = Tooltip::new(
+                Button::new(&mut field.remove_but_state, icon(Icon::X))
+                    .on_press(Message::RemoveField(index))
+                    .style(theme),
+                "Remove field",
+                iced::tooltip::Position::Top,
+            )
+            .style(theme);
+            form = form.push(
+                Column::with_children(vec![
+                    Row::with_children(vec![
+                        label!(format!("Field {}", index + 1)).width(length!(+)).into(),
+                        remove.into(),
+                    ])
+                    .align_items(Align::Center)
+                    .into(),
+                    input(&mut field.title_state, "Title", &field.title, FieldPart::Title),
+                    input(
+                        &mut field.subtitle_state,
+                        "Subtitle",
+                        &field.subtitle,
+                        FieldPart::Subtitle,
+                    ),
+                    input(&mut field.body_state, "Body", &field.body, FieldPart::Body),
+                ])
+                .spacing(SPACING),
+            );
+        }
+        form = form.push(
+            label_button!(&mut self.add_field_but_state, "Add field")
+                .on_press(Message::AddField)
+                .style(theme),
+        );
+
+        form = form.push(self.footer.view("Footer", true, theme));
+
+        // The preview is built like embeds in the event history
+        let preview = build_embed(
+            &embed,
+            thumbnail_cache,
+            &mut self.preview_header_but_state,
+            &mut self.preview_footer_but_state,
+            Message::OpenUrl,
+            theme,
+        );
+        let preview = Column::with_children(vec![
+            label!("Preview").color(theme.user_theme.dimmed_text).into(),
+            if can_send {
+                preview
+            } else {
+                label!("Embeds need a title.")
+                    .color(theme.user_theme.dimmed_text)
+                    .into()
+            },
+        ])
+        .spacing(SPACING)
+        .width(length!(= 320));
+
+        let mut send = label_button!(&mut self.send_but_state, "Send").style(theme);
+        if can_send {
+            send = send.on_press(Message::Send);
+        }
+
+        Container::new(
+            Card::new(
+                label!("Send an embed").width(length!(= 720 - PADDING - SPACING)),
+                Row::with_children(vec![form.into(), preview.into()])
+                    .spacing(SPACING * 3)
+                    .align_items(Align::Start),
+            )
+            .foot(send)
+            .style(theme.round())
+            .on_close(Message::Close),
+        )
+        .width(length!(= 720))
+        .style(theme.round().border_width(0.0))
+        .center_x()
+        .center_y()
+        .into()
+    }
+
+    pub fn update(&mut self, msg: Message, client: &Client) -> (Command<TopLevelMessage>, bool) {
+        let mut go_back = false;
+        let cmd = match msg {
+            Message::TitleChanged(title) => {
+                self.title = title;
+                Command::none()
+            }
+            Message::BodyChanged(body) => {
+                self.body = body;
+                Command::none()
+            }
+            Message::ShowColorPicker(show) => {
+                self.color_picker_state.show(show);
+                Command::none()
+            }
+            Message::ColorPicked(color) => {
+                self.color = Some([
+                    (color.r * 255.0) as u8,
+                    (color.g * 255.0) as u8,
+                    (color.b * 255.0) as u8,
+                ]);
+                self.color_picker_state.show(false);
+                Command::none()
+            }
+            Message::ClearColor => {
+                self.color = None;
+                Command::none()
+            }
+            Message::HeadingChanged { footer, part, value } => {
+                let heading = if footer { &mut self.footer } else { &mut self.header };
+                match part {
+                    HeadingPart::Text => heading.text = value,
+                    HeadingPart::Subtext => heading.subtext = value,
+                    HeadingPart::Url => heading.url = value,
+                }
+                Command::none()
+            }
+            Message::UploadIcon { footer } => {
+                let content_store = client.content_store_arc();
+                client.mk_cmd(
+                    |inner| async move { select_upload_files(&inner, content_store, true).await },
+                    move |mut uploaded| {
+                        let icon = uploaded.remove(0);
+                        TopLevelMessage::main(super::Message::EmbedComposerMsg(Message::IconUploaded { footer, icon }))
+                    },
+                )
+            }
+            Message::IconUploaded { footer, icon } => {
+                let heading = if footer { &mut self.footer } else { &mut self.header };
+                heading.icon = Some(icon.id.clone());
+                // Fetched so the preview can show it
+                Command::perform(
+                    ready(TopLevelMessage::PostProcessEvents(vec![
+                        PostProcessEvent::FetchThumbnail(icon),
+                    ])),
+                    identity,
+                )
+            }
+            Message::AddField => {
+                self.fields.push(FieldInput::default());
+                Command::none()
+            }
+            Message::RemoveField(index) => {
+                if index < self.fields.len() {
+                    self.fields.remove(index);
+                }
+                Command::none()
+            }
+            Message::FieldChanged { index, part, value } => {
+                if let Some(field) = self.fields.get_mut(index) {
+                    match part {
+                        FieldPart::Title => field.title = value,
+                        FieldPart::Subtitle => field.subtitle = value,
+                        FieldPart::Body => field.body = value,
+                    }
+                }
+                Command::none()
+            }
+            Message::OpenUrl(url) => {
+                Command::perform(ready(TopLevelMessage::main(super::Message::OpenUrl(url))), identity)
+            }
+            Message::Send => match self.channel {
+                Some((guild_id, channel_id)) if self.title.trim().is_empty().not() => {
+                    let message = IcyMessage {
+                        content: IcyContent::Embeds(vec![self.embed()]),
+                        sender: client.user_id.unwrap(),
+                        ..Default::default()
+                    };
+                    *self = Self::default();
+                    go_back = true;
+                    Command::perform(
+                        ready(TopLevelMessage::SendMessage {
+                            message,
+                            retry_after: std::time::Duration::from_secs(0),
+                            guild_id,
+                            channel_id,
+                        }),
+                        identity,
+                    )
+                }
+                _ => Command::none(),
+            },
+            Message::Close => {
+                go_back = true;
+                Command::none()
+            }
+        };
+        (cmd, go_back)
+    }
+}
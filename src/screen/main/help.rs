@@ -1,3 +1,4 @@
+use client::commands::COMMANDS;
 use iced_aw::Card;
 
 use crate::{component::*, length, style::*};
@@ -6,6 +7,16 @@ pub type Message = bool;
 
 const HELP: &str = include_str!("help.txt");
 
+/// The help text, followed by the commands that can be typed in the composer.
+fn help_text() -> String {
+    let commands = COMMANDS
+        .iter()
+        .map(|command| command.help_line())
+        .collect::<Vec<_>>()
+        .join("\n");
+    format!("{}\n\nCommands:\n{}", HELP.trim_end(), commands)
+}
+
 #[derive(Debug, Default, Clone)]
 pub struct HelpModal;
 
@@ -14,7 +25,7 @@ impl HelpModal {
         Container::new(
             Card::new(
                 label!("Help").width(length!(=512 - PADDING - SPACING)),
-                label!(HELP).width(length!(=512)),
+                label!(help_text()).width(length!(=512)),
             )
             .style(theme.round())
             .on_close(true),
@@ -0,0 +1,126 @@
+use chrono::TimeZone;
+use client::{
+    diff::{diff_words, Change},
+    message::MessageId,
+    Client,
+};
+use iced_aw::Card;
+
+use crate::{
+    component::*,
+    label, length,
+    style::{Theme, MESSAGE_SIZE, MESSAGE_TIMESTAMP_SIZE, PADDING, SPACING},
+};
+
+pub type Message = bool;
+
+/// Lists every known version of an edited message, with what changed in each edit.
+#[derive(Debug, Default, Clone)]
+pub struct RevisionsModal {
+    /// Guild ID, channel ID and message ID of the message whose revisions are shown.
+    pub message: Option<(u64, u64, u64)>,
+    revisions_state: scrollable::State,
+}
+
+/// Builds the lines of a revision, with removed words marked and added words highlighted.
+fn diff_lines<'a>(changes: &[Change], theme: &Theme) -> Vec<Element<'a, Message>> {
+    let mut lines = Vec::new();
+    let mut line = Vec::new();
+    for change in changes {
+        let (text, color, removed) = match *change {
+            Change::Same(text) => (text, theme.user_theme.text, false),
+            Change::Removed(text) => (text, theme.user_theme.error, true),
+            Change::Added(text) => (text, theme.user_theme.success, false),
+        };
+        for (index, part) in text.split('\n').enumerate() {
+            if index > 0 {
+                lines.push(Row::with_children(std::mem::take(&mut line)).into());
+            }
+            if part.is_empty() {
+                continue;
+            }
+            let label = label!(part).size(MESSAGE_SIZE).color(color);
+            // Text can't be drawn with a line through it, so removed words get a background instead
+            line.push(if removed {
+                Container::new(label)
+                    .style(theme.border_width(0.0).background_color(Color { a: 0.15, ..color }))
+                    .into()
+            } else {
+                label.into()
+            });
+        }
+    }
+    lines.push(Row::with_children(line).into());
+    lines
+}
+
+impl RevisionsModal {
+    pub fn view<'a>(&'a mut self, theme: &'a Theme, client: &'a Client) -> Element<'a, Message> {
+        let mut revisions = Scrollable::new(&mut self.revisions_state)
+            .align_items(Align::Start)
+            .height(length!(= 384))
+            .width(length!(+))
+            .spacing(SPACING * 2)
+            .style(theme);
+
+        let message = self.message.and_then(|(guild_id, channel_id, message_id)| {
+            client
+                .guilds
+                .get(&guild_id)?
+                .channels
+                .get(&channel_id)?
+                .messages
+                .get(&MessageId::Ack(message_id))
+        });
+        let history = message.map(|message| message.text_history()).unwrap_or_default();
+        let sent_at = message.map(|message| message.timestamp);
+
+        if history.len() < 2 {
+            revisions = revisions.push(label!("No earlier versions of this message are known."));
+        }
+        let timezone = chrono::Local::now().timezone();
+        // Newest first, each compared to the version it replaced
+        for (index, (text, written_at)) in history.iter().enumerate().rev() {
+            let changes = match index.checked_sub(1).and_then(|prev| history.get(prev)) {
+                Some((old, _)) => diff_words(&old.text, &text.text),
+                None => vec![Change::Same(&text.text)],
+            };
+            let is_original = Some(*written_at) == sent_at;
+            let written_at = timezone.from_utc_datetime(written_at);
+            let title = if is_original {
+                format!("Sent at {}", written_at.format("%d %B %Y %H:%M"))
+            } else {
+                format!("Edited at {}", written_at.format("%d %B %Y %H:%M"))
+            };
+
+            let mut widgets = Vec::with_capacity(2);
+            widgets.push(
+                label!(title)
+                    .size(MESSAGE_TIMESTAMP_SIZE)
+                    .color(theme.user_theme.dimmed_text)
+                    .into(),
+            );
+            widgets.extend(diff_lines(&changes, theme));
+            revisions = revisions.push(
+                Container::new(Column::with_children(widgets).spacing(SPACING / 2))
+                    .padding(PADDING / 2)
+                    .width(length!(+))
+                    .style(theme.secondary()),
+            );
+        }
+
+        Container::new(
+            Card::new(
+                label!("Message History").width(length!(=640 - PADDING - SPACING)),
+                revisions,
+            )
+            .style(theme.round())
+            .on_close(true),
+        )
+        .width(length!(= 640))
+        .style(theme.round().border_width(0.0))
+        .center_x()
+        .center_y()
+        .into()
+    }
+}
@@ -0,0 +1,55 @@
+use client::Client;
+use iced_aw::Card;
+
+use crate::{
+    component::*,
+    label, length,
+    style::{Theme, MESSAGE_SIZE, PADDING, SPACING},
+};
+
+pub type Message = bool;
+
+/// Lists events the client received but doesn't know how to handle, for debugging.
+#[derive(Debug, Default, Clone)]
+pub struct UnknownEventsModal {
+    events_state: scrollable::State,
+}
+
+impl UnknownEventsModal {
+    pub fn view<'a>(&'a mut self, theme: &'a Theme, client: &'a Client) -> Element<'a, Message> {
+        let mut events = Scrollable::new(&mut self.events_state)
+            .align_items(Align::Start)
+            .height(length!(= 384))
+            .width(length!(+))
+            .spacing(SPACING)
+            .style(theme);
+
+        if client.unknown_events.is_empty() {
+            events = events.push(label!("No unknown events received."));
+        }
+        for event in client.unknown_events.iter().rev() {
+            events = events.push(
+                label!(format!(
+                    "[{}] {}",
+                    event.received_at.format("%H:%M:%S"),
+                    event.description
+                ))
+                .size(MESSAGE_SIZE),
+            );
+        }
+
+        Container::new(
+            Card::new(
+                label!("Unknown Events").width(length!(=640 - PADDING - SPACING)),
+                events,
+            )
+            .style(theme.round())
+            .on_close(true),
+        )
+        .width(length!(= 640))
+        .style(theme.round().border_width(0.0))
+        .center_x()
+        .center_y()
+        .into()
+    }
+}
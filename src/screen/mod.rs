@@ -1,14 +1,18 @@
+pub mod bookmarks;
 pub mod emote_management;
 pub mod guild_discovery;
 pub mod guild_settings;
 pub mod login;
 pub mod main;
+pub mod search;
 
+pub use bookmarks::BookmarksScreen;
 pub use guild_discovery::GuildDiscovery;
 pub use guild_settings::GuildSettings;
 use image::imageops::FilterType;
 pub use login::LoginScreen;
 pub use main::MainScreen;
+pub use search::SearchScreen;
 use tokio::sync::mpsc;
 
 use crate::{
@@ -16,7 +20,7 @@ use crate::{
         content::ContentStore,
         error::{ClientError, ClientResult},
         message::{Attachment, Message as IcyMessage, MessageId},
-        Client, PostProcessEvent, Session,
+        Client, ConnectionStatus, PostProcessEvent, ReactionToggleResult, SendMessageResult, Session,
     },
     component::*,
     style::{Theme, UserTheme, AVATAR_WIDTH, PROFILE_AVATAR_WIDTH},
@@ -38,17 +42,22 @@ use client::{
                 stream_event::Event as EmoteEvent, EmotePackAdded, EmotePackEmotesUpdated, GetEmotePackEmotesRequest,
                 GetEmotePacksRequest,
             },
-            exports::hrpc::client::transport::http::HyperError,
             mediaproxy::{fetch_link_metadata_response::Data as FetchLinkData, FetchLinkMetadataRequest},
             profile::{stream_event::Event as ProfileEvent, GetProfileRequest, Profile, ProfileUpdated, UserStatus},
             rest::FileId,
         },
         client::{
-            api::{auth::AuthStepResponse, chat::EventSource, profile::UpdateProfile, rest::download_extract_file},
-            error::{ClientError as InnerClientError, InternalClientError as HrpcClientError},
+            api::{
+                auth::AuthStepResponse,
+                chat::{channel::GetChannelMessages, EventSource},
+                profile::UpdateProfile,
+                rest::download_extract_file,
+            },
+            error::ClientError as InnerClientError,
             Client as InnerClient, EventsReadSocket, EventsWriteSocket,
         },
     },
+    smol_str::SmolStr,
     tracing::{self, debug, error, warn},
     OptionExt, Uri,
 };
@@ -77,11 +86,22 @@ pub enum ScreenMessage {
     GuildDiscovery(guild_discovery::Message),
     GuildSettings(guild_settings::Message),
     EmoteManagement(emote_management::Message),
+    Search(search::Message),
+    Bookmarks(bookmarks::Message),
 }
 
 #[derive(Debug)]
 pub enum Message {
     ChildMessage(Box<ScreenMessage>),
+    /// A message that should be handled by the given account, whether it's shown or not.
+    ForAccount {
+        account: AccountKey,
+        msg: Box<Message>,
+    },
+    /// Sent when a saved session was connected in the background at startup.
+    AccountConnected(Box<(Client, Option<Profile>)>),
+    /// Shows another connected account.
+    SwitchToAccount(AccountKey),
     PopScreen,
     PushScreen(Box<Screen>),
     Logout(Box<Screen>),
@@ -106,11 +126,25 @@ pub enum Message {
         channel_id: u64,
         events: ClientResult<Box<Message>>,
     },
+    ChannelCacheLoaded {
+        guild_id: u64,
+        channel_id: u64,
+        cache: ClientResult<Option<Vec<(u64, IcyMessage)>>>,
+    },
     SocketEvent {
         tx: Option<EventsWriteSocket>,
         rx: EventsReadSocket,
         event: Option<Result<Event, ClientError>>,
     },
+    /// Opens a new events socket; `attempt` is 0 unless previous attempts failed.
+    ConnectSocket {
+        attempt: u32,
+    },
+    /// Sent when connecting the events socket failed; tries again after a backoff.
+    SocketConnectFailed {
+        attempt: u32,
+        err: Box<ClientError>,
+    },
     GetChannelMessagesResponse {
         messages: Vec<(u64, HarmonyMessage)>,
         reached_top: bool,
@@ -119,6 +153,22 @@ pub enum Message {
         message_id: u64,
         direction: Direction,
     },
+    /// Messages fetched with `Client::get_messages_after_cmd`.
+    NewerMessagesFetched {
+        messages: Vec<(u64, HarmonyMessage)>,
+        reached_end: bool,
+        guild_id: u64,
+        channel_id: u64,
+        message_id: u64,
+    },
+    /// Goes back to the main screen and shows the given message.
+    JumpToMessage {
+        guild_id: u64,
+        channel_id: u64,
+        message_id: u64,
+    },
+    /// Processes events the client or a screen asked for, like fetching a message.
+    PostProcessEvents(Vec<PostProcessEvent>),
     GetReplyMessage {
         guild_id: u64,
         channel_id: u64,
@@ -131,12 +181,22 @@ pub enum Message {
         guild_id: u64,
         channel_id: u64,
     },
+    /// Sent when messages were sent (or failed to be sent) to the homeserver.
+    SendMessageResults(Vec<SendMessageResult>),
     MessageEdited {
         guild_id: u64,
         channel_id: u64,
         message_id: u64,
         err: Option<Box<ClientError>>,
     },
+    ReactionToggled {
+        guild_id: u64,
+        channel_id: u64,
+        message_id: u64,
+        image_id: SmolStr,
+        name: SmolStr,
+        result: ReactionToggleResult,
+    },
     /// Sent whenever an error occurs.
     Error(Box<ClientError>),
     Exit,
@@ -170,6 +230,16 @@ impl Message {
     pub fn emote_management(msg: emote_management::Message) -> Self {
         Self::ChildMessage(ScreenMessage::EmoteManagement(msg).into())
     }
+
+    #[inline(always)]
+    pub fn search(msg: search::Message) -> Self {
+        Self::ChildMessage(ScreenMessage::Search(msg).into())
+    }
+
+    #[inline(always)]
+    pub fn bookmarks(msg: bookmarks::Message) -> Self {
+        Self::ChildMessage(ScreenMessage::Bookmarks(msg).into())
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -179,6 +249,8 @@ pub enum Screen {
     GuildDiscovery(Box<GuildDiscovery>),
     GuildSettings(Box<GuildSettings>),
     EmoteManagement(Box<ManageEmotesScreen>),
+    Search(Box<SearchScreen>),
+    Bookmarks(Box<BookmarksScreen>),
 }
 
 impl Screen {
@@ -190,6 +262,8 @@ impl Screen {
             Screen::Main(screen) => screen.on_error(error),
             Screen::GuildSettings(screen) => screen.on_error(error),
             Screen::EmoteManagement(screen) => screen.on_error(error),
+            Screen::Search(screen) => screen.on_error(error),
+            Screen::Bookmarks(screen) => screen.on_error(error),
         }
     }
 
@@ -201,6 +275,8 @@ impl Screen {
             Screen::GuildDiscovery(screen) => screen.subscription(),
             Screen::Login(screen) => screen.subscription(),
             Screen::EmoteManagement(screen) => screen.subscription(),
+            Screen::Search(screen) => screen.subscription(),
+            Screen::Bookmarks(screen) => screen.subscription(),
         }
     }
 
@@ -209,6 +285,7 @@ impl Screen {
         &'a mut self,
         theme: &'a Theme,
         client: Option<&'a Client>,
+        accounts: Vec<AccountInfo>,
         content_store: &'a Arc<ContentStore>,
         thumbnail_cache: &'a ThumbnailCache,
     ) -> Element<Message> {
@@ -218,6 +295,7 @@ impl Screen {
                 .view(
                     theme,
                     client.unwrap(), // This will not panic cause [ref:client_set_before_main_view]
+                    accounts,
                     thumbnail_cache,
                 )
                 .map(ScreenMessage::MainScreen),
@@ -230,6 +308,12 @@ impl Screen {
             Screen::EmoteManagement(screen) => screen
                 .view(theme, client.unwrap(), thumbnail_cache) // This will not panic cause [ref:client_set_before_main_view]
                 .map(ScreenMessage::EmoteManagement),
+            Screen::Search(screen) => screen
+                .view(theme, client.unwrap()) // This will not panic cause [ref:client_set_before_main_view]
+                .map(ScreenMessage::Search),
+            Screen::Bookmarks(screen) => screen
+                .view(theme, client.unwrap()) // This will not panic cause [ref:client_set_before_main_view]
+                .map(ScreenMessage::Bookmarks),
         }
         .map(|msg| Message::ChildMessage(msg.into()));
         fill_container(element).style(theme.border_radius(0.0)).into()
@@ -269,6 +353,16 @@ impl Screen {
                     return screen.update(msg, client);
                 }
             }
+            ScreenMessage::Search(msg) => {
+                if let (Screen::Search(screen), Some(client)) = (self, client) {
+                    return screen.update(msg, client);
+                }
+            }
+            ScreenMessage::Bookmarks(msg) => {
+                if let (Screen::Bookmarks(screen), Some(client)) = (self, client) {
+                    return screen.update(msg, client);
+                }
+            }
         }
         Command::none()
     }
@@ -342,15 +436,60 @@ impl ScreenStack {
     }
 }
 
+/// Identifies an account by its homeserver and user ID.
+pub type AccountKey = (SmolStr, u64);
+
+pub fn account_key(client: &Client) -> AccountKey {
+    (
+        client.inner().homeserver_url().to_string().into(),
+        client.user_id.unwrap_or(0),
+    )
+}
+
+/// A name for an account, used where more than one account is shown.
+pub fn account_label(client: &Client) -> String {
+    let user_name = client
+        .user_id
+        .and_then(|id| client.members.get(&id))
+        .map_or("unknown", |member| member.username.as_str());
+    let homeserver = client.inner().homeserver_url();
+    format!(
+        "{}@{}",
+        user_name,
+        homeserver.host().unwrap_or_else(|| homeserver.path())
+    )
+}
+
+/// What the account switcher shows about a connected account.
+#[derive(Debug, Clone)]
+pub struct AccountInfo {
+    pub key: AccountKey,
+    pub label: String,
+    pub is_current: bool,
+    pub mention_count: u32,
+}
+
+/// A connected account that isn't the one shown, with its own events socket.
+struct Account {
+    /// Only `None` while the account is swapped in by [`ScreenManager::in_account`].
+    client: Option<Box<Client>>,
+    sub_tx: mpsc::UnboundedSender<Option<EventSource>>,
+    socket_tx: mpsc::Sender<EventsWriteSocket>,
+}
+
 pub struct ScreenManager {
     theme: Box<Theme>,
     screens: ScreenStack,
+    /// The account that is shown.
     client: Option<Box<Client>>,
+    /// Every other account that is connected.
+    other_accounts: Vec<Account>,
+    /// Whether we are handling a message for an account that isn't shown.
+    in_background: bool,
     content_store: Arc<ContentStore>,
     thumbnail_cache: ThumbnailCache,
     sub_tx: mpsc::UnboundedSender<Option<EventSource>>,
     socket_tx: mpsc::Sender<EventsWriteSocket>,
-    socket_reset: bool,
     should_exit: bool,
     is_window_focused: bool,
     theme_rx: Receiver<()>,
@@ -384,46 +523,18 @@ impl ScreenManager {
             std::thread::park();
         });
 
-        let (socket_tx, mut socket_rx) = mpsc::channel::<EventsWriteSocket>(2);
-        let (sub_tx, mut sub_rx) = mpsc::unbounded_channel::<Option<EventSource>>();
-        tokio::spawn(async move {
-            let mut tx = socket_rx.recv().await.expect("no socket");
-
-            loop {
-                tokio::select! {
-                    maybe_socket = socket_rx.recv() => {
-                        match maybe_socket {
-                            Some(socket) => tx = socket,
-                            None => break,
-                        }
-                    }
-                    Some(maybe_sub) = sub_rx.recv() => {
-                        match maybe_sub {
-                            Some(sub) => {
-                                if let Err(err) = tx.add_source(sub).await {
-                                    tracing::error!("error sending source: {}", err);
-                                }
-                            }
-                            None => break,
-                        }
-                    }
-                }
-            }
-
-            if let Err(err) = tx.close().await {
-                tracing::error!("error closing socket: {}", err);
-            }
-        });
+        let (sub_tx, socket_tx) = spawn_socket_task();
 
         let mut this = Self {
             theme: Box::new(Theme::default()),
             screens: ScreenStack::new(Screen::Login(LoginScreen::new().into())),
             client: None,
+            other_accounts: Vec::new(),
+            in_background: false,
             content_store,
             thumbnail_cache: ThumbnailCache::default(),
             sub_tx,
             socket_tx,
-            socket_reset: false,
             should_exit: false,
             is_window_focused: true,
             theme_rx: ev_rx,
@@ -443,11 +554,163 @@ impl ScreenManager {
         self.theme.user_theme = user_theme;
     }
 
+    fn other_account_index(&self, account: &AccountKey) -> Option<usize> {
+        self.other_accounts
+            .iter()
+            .position(|a| a.client.as_deref().map(account_key).as_ref() == Some(account))
+    }
+
+    /// Swaps the shown account with another connected account, including their sockets.
+    fn swap_account(&mut self, index: usize) {
+        let account = &mut self.other_accounts[index];
+        std::mem::swap(&mut self.client, &mut account.client);
+        std::mem::swap(&mut self.sub_tx, &mut account.sub_tx);
+        std::mem::swap(&mut self.socket_tx, &mut account.socket_tx);
+    }
+
+    /// Runs `f` as if the account at `index` was the shown one. Messages produced by the returned
+    /// command are handled by that account too.
+    fn in_account(&mut self, index: usize, f: impl FnOnce(&mut Self) -> Command<Message>) -> Command<Message> {
+        self.swap_account(index);
+        let account = self.client.as_deref().map(account_key);
+        let was_in_background = std::mem::replace(&mut self.in_background, true);
+        let cmd = f(self);
+        self.in_background = was_in_background;
+        self.swap_account(index);
+        // The account might have logged out while it was swapped in [tag:in_account]
+        self.other_accounts.retain(|a| a.client.is_some());
+        account.map_or(cmd, |account| for_account(account, cmd))
+    }
+
+    /// Moves the shown account to the background so another one can be shown. Accounts that
+    /// aren't logged in yet are dropped instead.
+    fn background_current_account(&mut self) {
+        let (sub_tx, socket_tx) = spawn_socket_task();
+        let sub_tx = std::mem::replace(&mut self.sub_tx, sub_tx);
+        let socket_tx = std::mem::replace(&mut self.socket_tx, socket_tx);
+        match self.client.take() {
+            Some(client) if client.user_id.is_some() => self.other_accounts.push(Account {
+                client: Some(client),
+                sub_tx,
+                socket_tx,
+            }),
+            _ => {
+                let _ = sub_tx.send(None);
+            }
+        }
+    }
+
+    /// Shows another connected account, starting from a fresh main screen.
+    fn switch_account(&mut self, index: usize) {
+        self.swap_account(index);
+        self.other_accounts
+            .retain(|a| a.client.as_ref().map_or(false, |c| c.user_id.is_some()));
+        self.screens.clear(Screen::Login(LoginScreen::new().into()));
+        self.screens.push(Screen::Main(Box::new(MainScreen::default()))); // [ref:client_set_before_main_view]
+    }
+
+    fn account_infos(&self) -> Vec<AccountInfo> {
+        let info = |client: &Client, is_current: bool| AccountInfo {
+            key: account_key(client),
+            label: account_label(client),
+            is_current,
+            mention_count: client
+                .guilds
+                .values()
+                .flat_map(|g| g.channels.values())
+                .map(|c| c.read_marker.mention_count)
+                .sum(),
+        };
+        self.client
+            .as_deref()
+            .filter(|c| c.user_id.is_some())
+            .map(|c| info(c, true))
+            .into_iter()
+            .chain(
+                self.other_accounts
+                    .iter()
+                    .flat_map(|a| a.client.as_deref())
+                    .map(|c| info(c, false)),
+            )
+            .collect()
+    }
+
+    /// Loads everything an account needs after it logged in and connects its events socket.
+    fn start_account(&mut self, maybe_profile: Option<Profile>) -> Command<Message> {
+        let ws_cmd = self.handle(Message::ConnectSocket { attempt: 0 });
+        let client = self.client.as_mut().unwrap();
+        client.user_id = Some(client.inner().auth_status().session().unwrap().user_id);
+        if client::journal::recording_enabled() {
+            match client.start_recording() {
+                Ok(path) => tracing::info!("recording client events to {}", path.display()),
+                Err(err) => error!("couldn't start recording client events: {}", err),
+            }
+        }
+        if let Err(err) = client.load_read_markers() {
+            error!("couldn't load read markers: {}", err);
+        }
+        if let Err(err) = client.load_bookmarks() {
+            error!("couldn't load bookmarks: {}", err);
+        }
+        if let Err(err) = client.load_drafts() {
+            error!("couldn't load drafts: {}", err);
+        }
+        // Send whatever we couldn't send before we were closed
+        if let Err(err) = client.load_outbox() {
+            error!("couldn't load unsent messages: {}", err);
+        }
+        let outbox_cmd = Command::perform(client.resend_outbox(false), Message::SendMessageResults);
+        let self_id = client.user_id.unwrap();
+        let init = client.mk_cmd(
+            |inner| async move {
+                let self_profile = if let Some(profile) = maybe_profile {
+                    profile
+                } else {
+                    inner
+                        .call(GetProfileRequest::new(self_id))
+                        .await?
+                        .profile
+                        .unwrap_or_default()
+                };
+                let guilds = inner.call(GetGuildListRequest::new()).await?.guilds;
+                let mut events = Vec::with_capacity(guilds.len() + 1);
+                events.extend(guilds.into_iter().map(|guild| {
+                    Event::Chat(ChatEvent::GuildAddedToList(GuildAddedToList {
+                        guild_id: guild.guild_id,
+                        homeserver: guild.server_id,
+                    }))
+                }));
+                events.push(Event::Profile(ProfileEvent::ProfileUpdated(ProfileUpdated {
+                    new_is_bot: Some(self_profile.is_bot),
+                    new_avatar: self_profile.user_avatar,
+                    new_status: Some(UserStatus::Online.into()),
+                    new_username: Some(self_profile.user_name),
+                    new_account_kind: None,
+                    user_id: self_id,
+                })));
+                events.extend(inner.call(GetEmotePacksRequest::new()).await.map(|resp| {
+                    resp.packs
+                        .into_iter()
+                        .map(|pack| Event::Emote(EmoteEvent::EmotePackAdded(EmotePackAdded { pack: Some(pack) })))
+                })?);
+                inner
+                    .call(UpdateProfile::default().with_new_status(UserStatus::Online))
+                    .await?;
+                ClientResult::Ok(events)
+            },
+            Message::EventsReceived,
+        );
+        Command::batch(vec![ws_cmd, init, outbox_cmd])
+    }
+
     fn process_post_event(&mut self, post: PostProcessEvent) -> Command<Message> {
         if let Some(client) = self.client.as_mut() {
             match post {
-                PostProcessEvent::SendNotification { content, title, .. } => {
-                    if !self.is_window_focused {
+                PostProcessEvent::SendNotification { content, mut title, .. } => {
+                    if !self.other_accounts.is_empty() {
+                        title = format!("[{}] {}", account_label(client), title);
+                    }
+                    if !self.is_window_focused || self.in_background {
                         let _ = notify_rust::Notification::new()
                             .summary(&title)
                             .body(&truncate_string(&content, 50))
@@ -502,7 +765,9 @@ impl ScreenManager {
                     Message::EventsReceived,
                 ),
                 PostProcessEvent::GoToFirstMsgOnChannel(channel_id) => {
-                    if let Some(s) = self.screens.find_map_mut(Screen::main_screen_mut) {
+                    if self.in_background {
+                        Command::none()
+                    } else if let Some(s) = self.screens.find_map_mut(Screen::main_screen_mut) {
                         s.update(main::Message::ScrollToBottom(channel_id), client, &self.thumbnail_cache)
                     } else {
                         Command::none()
@@ -617,80 +882,43 @@ impl ScreenManager {
             Command::none()
         }
     }
-}
-
-impl Application for ScreenManager {
-    type Executor = executor::Default;
-    type Message = Message;
-    type Flags = ContentStore;
 
-    fn new(content_store: Self::Flags) -> (Self, Command<Self::Message>) {
-        let content_store = Arc::new(content_store);
-        let mut manager = ScreenManager::new(content_store.clone());
-        let cmd = if content_store.latest_session_file().exists() {
-            if let Screen::Login(screen) = manager.screens.current_mut() {
-                screen.waiting = true;
+    fn handle(&mut self, msg: Message) -> Command<Message> {
+        match msg {
+            // Screens belong to the shown account, so results of things started by an account that
+            // isn't shown anymore are dropped
+            Message::ChildMessage(_) | Message::PopScreen | Message::PushScreen(_) | Message::JumpToMessage { .. }
+                if self.in_background =>
+            {
+                debug!("dropping screen message for an account that isn't shown: {:?}", msg);
             }
-            Command::perform(
-                async move {
-                    let raw = tokio::fs::read(content_store.latest_session_file()).await?;
-                    let session: Session = toml::de::from_slice(&raw).map_err(|_| ClientError::MissingLoginInfo)?;
-                    let client = Client::new(
-                        session.homeserver.parse().unwrap(),
-                        Some(session.clone().into()),
-                        content_store,
-                    )
-                    .await?;
-                    client
-                        .inner_arc()
-                        .call(GetProfileRequest::new(client.user_id.unwrap()))
-                        .await
-                        .map(|resp| (client, resp))
-                        .map_err(|err| {
-                            let err = err.into();
-                            try_convert_err_to_login_err(&err, &session).unwrap_or(err)
-                        })
-                },
-                |result: ClientResult<_>| {
-                    result
-                        .map_to_msg_def(|(client, resp)| Message::LoginComplete(Box::new((Some(client), resp.profile))))
-                },
-            )
-        } else {
-            Command::none()
-        };
-        (manager, cmd)
-    }
-
-    fn title(&self) -> String {
-        use std::fmt::Write;
-
-        let mut title = String::from("Loqui");
-        if let (Screen::Main(screen), Some(client)) = (self.screens.current(), self.client.as_ref()) {
-            if let Some(guild) = screen.current_guild_id.map(|id| client.guilds.get(&id)).flatten() {
-                write!(&mut title, " | *{}", guild.name).unwrap();
-                if let Some(channel) = screen.current_channel_id.map(|id| guild.channels.get(&id)).flatten() {
-                    write!(&mut title, " | #{}", channel.name).unwrap();
+            Message::ForAccount { account, msg } => {
+                return match self.other_account_index(&account) {
+                    Some(index) => self.in_account(index, |this| this.handle(*msg)),
+                    None => self.handle(*msg),
+                };
+            }
+            Message::AccountConnected(res) => {
+                let (client, maybe_profile) = *res;
+                let key = account_key(&client);
+                let is_connected = self.client.as_deref().map(account_key).as_ref() == Some(&key)
+                    || self.other_account_index(&key).is_some();
+                if !is_connected {
+                    let (sub_tx, socket_tx) = spawn_socket_task();
+                    self.other_accounts.push(Account {
+                        client: Some(Box::new(client)),
+                        sub_tx,
+                        socket_tx,
+                    });
+                    let index = self.other_accounts.len() - 1;
+                    return self.in_account(index, |this| this.start_account(maybe_profile));
+                }
+            }
+            Message::SwitchToAccount(account) => {
+                if let Some(index) = self.other_account_index(&account) {
+                    self.switch_account(index);
                 }
             }
-        }
-        title
-    }
-
-    fn update(&mut self, msg: Self::Message) -> Command<Self::Message> {
-        // TODO: move this to a subscription
-        self.client.as_mut().and_do(|client| {
-            client
-                .members
-                .values_mut()
-                .for_each(|m| m.typing_in_channel = m.typing_in_channel.filter(|d| d.2.elapsed().as_secs() < 5))
-        });
-
-        if self.theme_rx.try_recv().is_ok() {
-            self.reload_user_theme();
-        }
-
-        match msg {
             Message::ChildMessage(msg) => {
                 return self.screens.current_mut().update(
                     *msg,
@@ -702,10 +930,24 @@ impl Application for ScreenManager {
             Message::Nothing => {}
             Message::Exit => {
                 let _ = self.sub_tx.send(None);
-                let inner = self.client.as_ref().map(|c| c.inner_arc());
+                self.other_accounts.iter().for_each(|a| {
+                    let _ = a.sub_tx.send(None);
+                });
+                let clients = self
+                    .client
+                    .as_deref()
+                    .into_iter()
+                    .chain(self.other_accounts.iter().flat_map(|a| a.client.as_deref()));
+                let mut exits = Vec::with_capacity(self.other_accounts.len() + 1);
+                for client in clients {
+                    client.save_read_markers();
+                    client.save_drafts();
+                    exits.push((client.inner_arc(), client.save_all_channel_caches()));
+                }
                 return Command::perform(
                     async move {
-                        if let Some(inner) = inner {
+                        for (inner, save_caches) in exits {
+                            save_caches.await;
                             let _ = inner
                                 .call(UpdateProfile::default().with_new_status(UserStatus::OfflineUnspecified))
                                 .await;
@@ -723,6 +965,8 @@ impl Application for ScreenManager {
                     },
                     |step| Message::login(login::Message::AuthStep(step.map(|s| s.step).flatten())),
                 );
+                // Logging in to another account keeps the current one connected
+                self.background_current_account();
                 self.client = Some(client);
                 return cmd;
             }
@@ -730,40 +974,38 @@ impl Application for ScreenManager {
                 return if self.client.is_some() {
                     let mut cmds = Vec::with_capacity(2);
 
-                    event
-                        .and_do(|ev| {
-                            debug!("event received from socket: {:?}", ev);
-                            let cmd = match ev {
-                                Ok(ev) => self.update(Message::EventsReceived(vec![ev])),
-                                Err(err) => self.update(err.into()),
-                            };
-                            cmds.push(cmd);
-                        })
-                        .or_do(|| {
-                            if let Some(tx) = tx {
-                                self.socket_tx
-                                    .try_send(tx)
-                                    .expect("sockets shouldnt be dropped and created fast");
-                            }
-                        });
-
-                    self.socket_reset
-                        .and_do(|| {
-                            let client = self.client.as_ref().unwrap();
-                            cmds.push(client.mk_cmd(
-                                |inner| async move { inner.subscribe_events(false).await },
-                                |socket| {
-                                    let (tx, rx) = socket.split();
-                                    Message::SocketEvent {
-                                        tx: Some(tx),
-                                        rx,
-                                        event: None,
-                                    }
-                                },
+                    if let Some(tx) = tx {
+                        // A new socket, hand it over to the subscription task and catch up
+                        // on everything we missed while we weren't connected [tag:socket_connected]
+                        self.socket_tx
+                            .try_send(tx)
+                            .expect("sockets shouldnt be dropped and created fast");
+                        let client = self.client.as_mut().unwrap();
+                        let reconnected = client.connection_status != ConnectionStatus::Connecting;
+                        client.connection_status = ConnectionStatus::Connected;
+                        if reconnected {
+                            cmds.push(backfill_channels(client));
+                            cmds.push(Command::perform(
+                                client.resend_outbox(true),
+                                Message::SendMessageResults,
                             ));
-                            self.socket_reset = false;
-                        })
-                        .or_do(|| {
+                        }
+                    }
+
+                    match event {
+                        Some(Err(err)) => {
+                            // The socket is unusable after an error, so drop it and make a new one
+                            error!("events socket closed: {}", err);
+                            cmds.push(self.handle(Message::SocketConnectFailed {
+                                attempt: 0,
+                                err: Box::new(err),
+                            }));
+                        }
+                        maybe_event => {
+                            if let Some(Ok(ev)) = maybe_event {
+                                debug!("event received from socket: {:?}", ev);
+                                cmds.push(self.handle(Message::EventsReceived(vec![ev])));
+                            }
                             cmds.push(Command::perform(
                                 async move {
                                     let event = rx.get_event().await.map_err(Into::into).transpose();
@@ -771,89 +1013,122 @@ impl Application for ScreenManager {
                                 },
                                 identity,
                             ));
-                        });
+                        }
+                    }
 
                     Command::batch(cmds)
                 } else {
                     tx.map_or_else(Command::none, |tx| Command::perform(tx.close(), |_| Message::Nothing))
                 };
             }
+            Message::ConnectSocket { attempt } => {
+                if let Some(client) = self.client.as_ref() {
+                    let inner = client.inner_arc();
+                    let sources = client.subscribe_to();
+                    return Command::perform(
+                        async move {
+                            let (mut tx, rx) = inner.subscribe_events(false).await?.split();
+                            // A new socket doesn't have any of the old socket's subscriptions
+                            for source in sources {
+                                tx.add_source(source).await?;
+                            }
+                            ClientResult::Ok((tx, rx))
+                        },
+                        move |result| match result {
+                            Ok((tx, rx)) => Message::SocketEvent {
+                                tx: Some(tx),
+                                rx,
+                                event: None,
+                            },
+                            Err(err) => Message::SocketConnectFailed {
+                                attempt,
+                                err: Box::new(err),
+                            },
+                        },
+                    );
+                }
+            }
+            Message::SocketConnectFailed { attempt, err } => {
+                if let Some(client) = self.client.as_mut() {
+                    let retry_after = ConnectionStatus::backoff(attempt);
+                    warn!(
+                        "couldn't connect events socket (attempt {}), retrying in {}s: {}",
+                        attempt + 1,
+                        retry_after.as_secs(),
+                        err
+                    );
+                    client.connection_status = ConnectionStatus::Reconnecting { attempt, retry_after };
+                    return Command::perform(tokio::time::sleep(retry_after), move |_| Message::ConnectSocket {
+                        attempt: attempt + 1,
+                    });
+                }
+            }
             Message::LoginComplete(res) => {
                 let (maybe_client, maybe_profile) = *res;
                 if let Some(client) = maybe_client {
+                    let key = account_key(&client);
+                    // A saved session that is already connected is just shown
+                    if let Some(index) = self.other_account_index(&key) {
+                        self.switch_account(index);
+                        return Command::none();
+                    }
+                    if self.client.as_deref().map(account_key).as_ref() == Some(&key) {
+                        self.screens.clear(Screen::Login(LoginScreen::new().into()));
+                        self.screens.push(Screen::Main(Box::new(MainScreen::default())));
+                        return Command::none();
+                    }
+                    self.background_current_account();
                     self.client = Some(client.into()); // This is the only place we set a main screen [tag:client_set_before_main_view]
                 }
-                if let Screen::Login(screen) = self.screens.current_mut() {
-                    screen.waiting = false;
-                    screen.reset_to_first_step();
-                }
+                self.screens.clear(Screen::Login(LoginScreen::new().into()));
                 self.screens.push(Screen::Main(Box::new(MainScreen::default())));
 
-                let client = self.client.as_mut().unwrap();
-                let ws_cmd = client.mk_cmd(
-                    |inner| async move { inner.subscribe_events(false).await },
-                    |socket| {
-                        let (tx, rx) = socket.split();
-                        Message::SocketEvent {
-                            tx: Some(tx),
-                            rx,
-                            event: None,
-                        }
-                    },
-                );
-                client.user_id = Some(client.inner().auth_status().session().unwrap().user_id);
-                let self_id = client.user_id.unwrap();
-                let init = client.mk_cmd(
-                    |inner| async move {
-                        let self_profile = if let Some(profile) = maybe_profile {
-                            profile
-                        } else {
-                            inner
-                                .call(GetProfileRequest::new(self_id))
-                                .await?
-                                .profile
-                                .unwrap_or_default()
-                        };
-                        let guilds = inner.call(GetGuildListRequest::new()).await?.guilds;
-                        let mut events = Vec::with_capacity(guilds.len() + 1);
-                        events.extend(guilds.into_iter().map(|guild| {
-                            Event::Chat(ChatEvent::GuildAddedToList(GuildAddedToList {
-                                guild_id: guild.guild_id,
-                                homeserver: guild.server_id,
-                            }))
-                        }));
-                        events.push(Event::Profile(ProfileEvent::ProfileUpdated(ProfileUpdated {
-                            new_is_bot: Some(self_profile.is_bot),
-                            new_avatar: self_profile.user_avatar,
-                            new_status: Some(UserStatus::Online.into()),
-                            new_username: Some(self_profile.user_name),
-                            new_account_kind: None,
-                            user_id: self_id,
-                        })));
-                        events.extend(inner.call(GetEmotePacksRequest::new()).await.map(|resp| {
-                            resp.packs.into_iter().map(|pack| {
-                                Event::Emote(EmoteEvent::EmotePackAdded(EmotePackAdded { pack: Some(pack) }))
-                            })
-                        })?);
-                        inner
-                            .call(UpdateProfile::default().with_new_status(UserStatus::Online))
-                            .await?;
-                        ClientResult::Ok(events)
-                    },
-                    Message::EventsReceived,
-                );
-                return Command::batch(vec![ws_cmd, init]);
+                return self.start_account(maybe_profile);
             }
             Message::PopScreen => {
                 self.screens.pop();
+                // Going back from adding an account that didn't finish logging in
+                let logged_in = self.client.as_ref().map_or(false, |c| c.user_id.is_some());
+                if matches!(self.screens.current(), Screen::Main(_)) && !logged_in {
+                    if self.other_accounts.is_empty() {
+                        self.screens.clear(Screen::Login(LoginScreen::new().into()));
+                    } else {
+                        self.switch_account(0);
+                    }
+                }
             }
             Message::PushScreen(screen) => {
                 self.screens.push(*screen);
             }
+            Message::JumpToMessage {
+                guild_id,
+                channel_id,
+                message_id,
+            } => {
+                while !matches!(self.screens.current(), Screen::Main(_)) {
+                    if self.screens.pop().is_none() {
+                        return Command::none();
+                    }
+                }
+                return self.handle(Message::main(main::Message::JumpToMessage {
+                    guild_id,
+                    channel_id,
+                    message_id,
+                }));
+            }
             Message::Logout(screen) => {
+                let _ = self.sub_tx.send(None);
                 self.client = None;
-                self.socket_reset = false;
-                self.screens.clear(*screen);
+                if self.in_background {
+                    // [ref:in_account] removes the account once we are done with it
+                    return Command::none();
+                }
+                if self.other_accounts.is_empty() {
+                    self.screens.clear(*screen);
+                } else {
+                    // Keep going with one of the other connected accounts
+                    self.switch_account(0);
+                }
             }
             Message::MessageEdited {
                 guild_id,
@@ -861,14 +1136,27 @@ impl Application for ScreenManager {
                 message_id,
                 err,
             } => {
-                self.client
-                    .as_mut()
-                    .and_then(|client| client.get_channel(guild_id, channel_id))
-                    .and_then(|c| c.messages.get_mut(&MessageId::Ack(message_id)))
-                    .and_do(|msg| msg.being_edited = None);
+                if let Some(client) = self.client.as_mut() {
+                    client.process_edit_result(guild_id, channel_id, message_id);
+                }
 
                 if let Some(err) = err {
-                    return self.update(Message::Error(err));
+                    return self.handle(Message::Error(err));
+                }
+            }
+            Message::ReactionToggled {
+                guild_id,
+                channel_id,
+                message_id,
+                image_id,
+                name,
+                result,
+            } => {
+                let err = self.client.as_mut().and_then(|client| {
+                    client.process_reaction_toggle_result(guild_id, channel_id, message_id, &image_id, &name, result)
+                });
+                if let Some(err) = err {
+                    return self.handle(Message::Error(Box::new(err)));
                 }
             }
             Message::SendMessage {
@@ -877,14 +1165,21 @@ impl Application for ScreenManager {
                 guild_id,
                 channel_id,
             } => {
-                let maybe_cmd = self
-                    .client
-                    .as_mut()
-                    .and_then(|c| c.send_msg_cmd(guild_id, channel_id, retry_after, MessageId::default(), message));
-                if let Some(cmd) = maybe_cmd {
+                if let Some(client) = self.client.as_mut() {
+                    let cmd = client.send_msg_cmd(guild_id, channel_id, retry_after, MessageId::default(), message);
                     return Command::perform(cmd, map_send_msg);
                 }
             }
+            Message::SendMessageResults(results) => {
+                if let Some(client) = self.client.as_mut() {
+                    let cmds = results
+                        .into_iter()
+                        .flat_map(|result| client.process_send_result(result))
+                        .map(|cmd| Command::perform(cmd, map_send_msg))
+                        .collect::<Vec<_>>();
+                    return Command::batch(cmds);
+                }
+            }
             Message::DownloadedThumbnail {
                 data,
                 thumbnail,
@@ -901,6 +1196,7 @@ impl Application for ScreenManager {
                     self.thumbnail_cache
                         .put_profile_avatar_thumbnail(data.id.clone(), profile_avatar);
                 });
+                let open = open && !self.in_background;
                 thumbnail.and_do(|thumbnail| {
                     if let (Screen::Main(screen), true) = (self.screens.current_mut(), open) {
                         screen.image_viewer_modal.inner_mut().image_handle =
@@ -985,7 +1281,7 @@ impl Application for ScreenManager {
                 message_id,
                 direction,
             } => {
-                return self
+                let mut cmds = self
                     .client
                     .as_mut()
                     .map(|client| {
@@ -1002,26 +1298,57 @@ impl Application for ScreenManager {
                             direction,
                         )
                     })
-                    .map_or_else(Command::none, |posts| {
-                        Command::batch(posts.into_iter().map(|post| self.process_post_event(post)))
+                    .map_or_else(Vec::new, |posts| {
+                        posts.into_iter().map(|post| self.process_post_event(post)).collect()
                     });
+                // Messages around a message are only fetched when we want to show that message [tag:goto_message_after_around]
+                if matches!(direction, Direction::Around) && !self.in_background {
+                    cmds.push(self.handle(Message::main(main::Message::GotoReply(MessageId::Ack(message_id)))));
+                }
+                // The newest messages are fetched when a channel is first loaded, or to go back to them from older
+                // ones [tag:scroll_after_newest_messages]
+                if matches!(direction, Direction::BeforeUnspecified) && message_id == 0 && !self.in_background {
+                    cmds.push(self.handle(Message::main(main::Message::ScrollToBottom(channel_id))));
+                }
+                return Command::batch(cmds);
+            }
+            Message::NewerMessagesFetched {
+                messages,
+                reached_end,
+                guild_id,
+                channel_id,
+                message_id,
+            } => {
+                let posts = self.client.as_mut().map_or_else(Vec::new, |client| {
+                    client.process_messages_after(guild_id, channel_id, message_id, messages, reached_end)
+                });
+                return Command::batch(posts.into_iter().map(|post| self.process_post_event(post)));
             }
             Message::Error(err) => {
                 let err_disp = err.to_string();
                 error!("{}\n{:?}", err_disp, err);
 
-                // Reset socket if socket error happened
-                matches!(
-                    &*err,
-                    ClientError::Internal(InnerClientError::Internal(HrpcClientError::Transport(
-                        HyperError::SocketInitError(_)
-                    )))
-                )
-                .and_do(|| self.socket_reset = true);
+                if self.in_background {
+                    let client = self.client.as_deref().unwrap();
+                    let label = account_label(client);
+                    if err_disp.contains("invalid-session") {
+                        // The session can't be used anymore, stop keeping the account connected
+                        let _ = self.sub_tx.send(None);
+                        self.client = None;
+                    }
+                    return self
+                        .screens
+                        .current_mut()
+                        .on_error(ClientError::Custom(format!("{}: {}", label, err_disp)));
+                }
 
-                // Return to login screen if its a connection error
-                if err_disp.contains("invalid-session") || err_disp.contains("connect error") {
-                    self.update(Message::Logout(Screen::Login(LoginScreen::new().into()).into()));
+                // Return to login screen if its a connection error, unless we are already trying to reconnect
+                let reconnecting = self
+                    .client
+                    .as_ref()
+                    .map_or(false, |c| c.connection_status != ConnectionStatus::Connected);
+                if err_disp.contains("invalid-session") || (err_disp.contains("connect error") && !reconnecting) {
+                    self.handle(Message::Logout(Screen::Login(LoginScreen::new().into()).into()));
                 }
 
                 return self.screens.current_mut().on_error(*err);
@@ -1032,8 +1359,8 @@ impl Application for ScreenManager {
                     client.get_guild(guild_id).and_do(|g| g.init_fetching = false);
                 }
                 return match events {
-                    Ok(events) => self.update(Message::TryEventsReceived(events)),
-                    Err(err) => self.update(Message::Error(err.into())),
+                    Ok(events) => self.handle(Message::TryEventsReceived(events)),
+                    Err(err) => self.handle(Message::Error(err.into())),
                 };
             }
             Message::InitialChannelLoad {
@@ -1047,9 +1374,61 @@ impl Application for ScreenManager {
                         .and_do(|c| c.init_fetching = false);
                 }
                 return match events {
-                    Ok(events) => self.update(*events),
-                    Err(err) => self.update(Message::Error(err.into())),
+                    Ok(events) => self.handle(*events),
+                    Err(err) => self.handle(Message::Error(err.into())),
+                };
+            }
+            // [tag:channel_cache_loaded]
+            Message::ChannelCacheLoaded {
+                guild_id,
+                channel_id,
+                cache,
+            } => {
+                let client = match self.client.as_mut() {
+                    Some(client) => client,
+                    None => return Command::none(),
+                };
+
+                let messages = match cache {
+                    Ok(Some(messages)) if !messages.is_empty() => messages,
+                    Ok(_) => return fetch_initial_messages(client, guild_id, channel_id),
+                    Err(err) => {
+                        warn!("couldn't load message cache, fetching from homeserver: {}", err);
+                        return fetch_initial_messages(client, guild_id, channel_id);
+                    }
                 };
+
+                // Cache is newest first, so this is the newest message we know of
+                let newest_message_id = messages[0].0;
+                let posts = client.process_cached_messages(guild_id, channel_id, messages);
+                if let Some(c) = client.get_channel(guild_id, channel_id) {
+                    c.viewport.scroll_to_bottom();
+                }
+
+                let fetch_newer = client.get_messages_after_cmd(guild_id, channel_id, newest_message_id);
+                let mut cmds = posts
+                    .into_iter()
+                    .map(|post| self.process_post_event(post))
+                    .collect::<Vec<_>>();
+                cmds.push(Command::perform(
+                    async move {
+                        fetch_newer.await.map(|(messages, reached_end)| {
+                            Box::new(Message::NewerMessagesFetched {
+                                messages,
+                                reached_end,
+                                guild_id,
+                                channel_id,
+                                message_id: newest_message_id,
+                            })
+                        })
+                    },
+                    move |events| Message::InitialChannelLoad {
+                        guild_id,
+                        channel_id,
+                        events,
+                    },
+                ));
+                return Command::batch(cmds);
             }
             Message::TryEventsReceived(maybe_events) => {
                 let mut cmds = Vec::with_capacity(maybe_events.len());
@@ -1057,10 +1436,10 @@ impl Application for ScreenManager {
                 for maybe_event in maybe_events {
                     match maybe_event {
                         Ok(event) => events.push(event),
-                        Err(err) => cmds.push(self.update(Message::Error(Box::new(err)))),
+                        Err(err) => cmds.push(self.handle(Message::Error(Box::new(err)))),
                     }
                 }
-                cmds.push(self.update(Message::EventsReceived(events)));
+                cmds.push(self.handle(Message::EventsReceived(events)));
                 return Command::batch(cmds);
             }
             Message::FetchLinkDataReceived(data, url) => {
@@ -1106,6 +1485,11 @@ impl Application for ScreenManager {
                     return cmd;
                 }
             }
+            Message::PostProcessEvents(posts) => {
+                if self.client.is_some() {
+                    return Command::batch(posts.into_iter().map(|post| self.process_post_event(post)));
+                }
+            }
             Message::GetReplyMessage {
                 guild_id,
                 channel_id,
@@ -1120,6 +1504,98 @@ impl Application for ScreenManager {
         }
         Command::none()
     }
+}
+
+impl Application for ScreenManager {
+    type Executor = executor::Default;
+    type Message = Message;
+    type Flags = ContentStore;
+
+    fn new(content_store: Self::Flags) -> (Self, Command<Self::Message>) {
+        let content_store = Arc::new(content_store);
+        let mut manager = ScreenManager::new(content_store.clone());
+        let latest_session = std::fs::read(content_store.latest_session_file())
+            .ok()
+            .and_then(|raw| toml::de::from_slice::<Session>(&raw).ok());
+        let mut cmds = Vec::new();
+        if let Some(session) = latest_session.clone() {
+            if let Screen::Login(screen) = manager.screens.current_mut() {
+                screen.waiting = true;
+            }
+            cmds.push(Command::perform(
+                connect_session(session, content_store.clone()),
+                |result| {
+                    result.map_to_msg_def(|(client, profile)| Message::LoginComplete(Box::new((Some(client), profile))))
+                },
+            ));
+        } else if content_store.latest_session_file().exists() {
+            cmds.push(Command::perform(ready(ClientError::MissingLoginInfo), Message::from));
+        }
+        // Every other saved session is connected in the background
+        let saved_sessions = std::fs::read_dir(content_store.sessions_dir())
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter(|entry| entry.path() != content_store.latest_session_file())
+            .flat_map(|entry| toml::de::from_slice::<Session>(&std::fs::read(entry.path()).ok()?).ok())
+            .filter(|session| {
+                latest_session.as_ref().map_or(true, |latest| {
+                    (&latest.homeserver, &latest.user_id) != (&session.homeserver, &session.user_id)
+                })
+            })
+            .collect::<Vec<_>>();
+        for session in saved_sessions {
+            cmds.push(Command::perform(
+                connect_session(session, content_store.clone()),
+                |result| {
+                    result.map_to_msg_def(|(client, profile)| Message::AccountConnected(Box::new((client, profile))))
+                },
+            ));
+        }
+        let cmd = Command::batch(cmds);
+        (manager, cmd)
+    }
+
+    fn title(&self) -> String {
+        use std::fmt::Write;
+
+        let mut title = String::from("Loqui");
+        if let (Screen::Main(screen), Some(client)) = (self.screens.current(), self.client.as_ref()) {
+            if let Some(guild) = screen.current_guild_id.map(|id| client.guilds.get(&id)).flatten() {
+                write!(&mut title, " | *{}", guild.name).unwrap();
+                if let Some(channel) = screen.current_channel_id.map(|id| guild.channels.get(&id)).flatten() {
+                    write!(&mut title, " | #{}", channel.name).unwrap();
+                }
+            }
+        }
+        title
+    }
+
+    fn update(&mut self, msg: Self::Message) -> Command<Self::Message> {
+        // TODO: move this to a subscription
+        let clients = self
+            .client
+            .iter_mut()
+            .chain(self.other_accounts.iter_mut().flat_map(|a| a.client.as_mut()));
+        for client in clients {
+            client
+                .members
+                .values_mut()
+                .for_each(|m| m.typing_in_channel = m.typing_in_channel.filter(|d| d.2.elapsed().as_secs() < 5));
+        }
+
+        if self.theme_rx.try_recv().is_ok() {
+            self.reload_user_theme();
+        }
+
+        let cmd = self.handle(msg);
+        // Whatever was started belongs to the account that is shown, even if the user switches
+        // to another account before it finishes
+        match self.client.as_deref().filter(|c| c.user_id.is_some()) {
+            Some(client) => for_account(account_key(client), cmd),
+            None => cmd,
+        }
+    }
 
     fn subscription(&self) -> Subscription<Self::Message> {
         use iced_native::{window, Event};
@@ -1139,9 +1615,11 @@ impl Application for ScreenManager {
     }
 
     fn view(&mut self) -> Element<Self::Message> {
+        let accounts = self.account_infos();
         self.screens.current_mut().view(
             self.theme.as_ref(),
             self.client.as_ref().map(Box::as_ref),
+            accounts,
             &self.content_store,
             &self.thumbnail_cache,
         )
@@ -1189,6 +1667,115 @@ impl From<InnerClientError> for Message {
     }
 }
 
+/// Spawns the task that owns the write half of an account's events socket, returning the senders
+/// used to subscribe to new sources (`None` closes the socket) and to hand over new sockets.
+fn spawn_socket_task() -> (
+    mpsc::UnboundedSender<Option<EventSource>>,
+    mpsc::Sender<EventsWriteSocket>,
+) {
+    let (socket_tx, mut socket_rx) = mpsc::channel::<EventsWriteSocket>(2);
+    let (sub_tx, mut sub_rx) = mpsc::unbounded_channel::<Option<EventSource>>();
+    tokio::spawn(async move {
+        // The account was dropped before it ever connected
+        let mut tx = match socket_rx.recv().await {
+            Some(tx) => tx,
+            None => return,
+        };
+
+        loop {
+            tokio::select! {
+                maybe_socket = socket_rx.recv() => {
+                    match maybe_socket {
+                        Some(socket) => tx = socket,
+                        None => break,
+                    }
+                }
+                Some(maybe_sub) = sub_rx.recv() => {
+                    match maybe_sub {
+                        Some(sub) => {
+                            if let Err(err) = tx.add_source(sub).await {
+                                tracing::error!("error sending source: {}", err);
+                            }
+                        }
+                        None => break,
+                    }
+                }
+            }
+        }
+
+        if let Err(err) = tx.close().await {
+            tracing::error!("error closing socket: {}", err);
+        }
+    });
+
+    (sub_tx, socket_tx)
+}
+
+/// Makes the messages produced by a command be handled by the given account, even if another
+/// account is shown by the time they arrive.
+fn for_account(account: AccountKey, cmd: Command<Message>) -> Command<Message> {
+    cmd.map(move |msg| Message::ForAccount {
+        account: account.clone(),
+        msg: Box::new(msg),
+    })
+}
+
+fn fetch_initial_messages(client: &Client, guild_id: u64, channel_id: u64) -> Command<Message> {
+    let inner = client.inner_arc();
+    Command::perform(
+        async move {
+            inner
+                .call(GetChannelMessages::new(guild_id, channel_id))
+                .await
+                .map(|response| {
+                    Box::new(Message::GetChannelMessagesResponse {
+                        guild_id,
+                        channel_id,
+                        message_id: 0,
+                        messages: response
+                            .messages
+                            .into_iter()
+                            .flat_map(|m| {
+                                let msg = m.message?;
+                                Some((m.message_id, msg))
+                            })
+                            .collect(),
+                        reached_top: response.reached_top,
+                        direction: Direction::BeforeUnspecified,
+                    })
+                })
+                .map_err(ClientError::from)
+        },
+        move |events| Message::InitialChannelLoad {
+            guild_id,
+            channel_id,
+            events,
+        },
+    )
+}
+
+/// Fetches the messages sent after the newest message we have in every loaded channel,
+/// so there is no gap in history after the events socket was down. [ref:socket_connected]
+fn backfill_channels(client: &mut Client) -> Command<Message> {
+    let cmds = client
+        .newest_acked_messages()
+        .into_iter()
+        .map(|(guild_id, channel_id, message_id)| {
+            let fetch_newer = client.get_messages_after_cmd(guild_id, channel_id, message_id);
+            Command::perform(fetch_newer, move |result| {
+                result.map_to_msg_def(|(messages, reached_end)| Message::NewerMessagesFetched {
+                    messages,
+                    reached_end,
+                    guild_id,
+                    channel_id,
+                    message_id,
+                })
+            })
+        })
+        .collect::<Vec<_>>();
+    Command::batch(cmds)
+}
+
 pub fn map_to_nothing<T>(_: T) -> Message {
     Message::Nothing
 }
@@ -1207,17 +1794,8 @@ impl<T, Err: Into<ClientError>> ResultExt<T> for Result<T, Err> {
     }
 }
 
-fn map_send_msg(data: (u64, u64, u64, IcyMessage, Duration, Option<u64>)) -> Message {
-    let (guild_id, channel_id, _, message, retry_after, res) = data;
-    res.map_or(
-        Message::SendMessage {
-            guild_id,
-            channel_id,
-            message,
-            retry_after,
-        },
-        |_| Message::Nothing,
-    )
+fn map_send_msg(data: SendMessageResult) -> Message {
+    Message::SendMessageResults(vec![data])
 }
 
 fn make_thumbnail_command(client: &Client, data: Attachment, thumbnail_cache: &mut ThumbnailCache) -> Command<Message> {
@@ -1378,6 +1956,28 @@ async fn select_upload_files(
     upload_files(inner, content_store, select_files(one).await?).await
 }
 
+/// Creates a client for a saved session and checks that the session is still valid.
+async fn connect_session(
+    session: Session,
+    content_store: Arc<ContentStore>,
+) -> ClientResult<(Client, Option<Profile>)> {
+    let client = Client::new(
+        session.homeserver.parse().unwrap(),
+        Some(session.clone().into()),
+        content_store,
+    )
+    .await?;
+    client
+        .inner_arc()
+        .call(GetProfileRequest::new(client.user_id.unwrap()))
+        .await
+        .map(|resp| (client, resp.profile))
+        .map_err(|err| {
+            let err = err.into();
+            try_convert_err_to_login_err(&err, &session).unwrap_or(err)
+        })
+}
+
 fn try_convert_err_to_login_err(err: &ClientError, session: &Session) -> Option<ClientError> {
     let err_text = err.to_string();
     if err_text.contains("invalid-session") {
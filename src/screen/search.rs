@@ -0,0 +1,320 @@
+use std::fmt::{self, Display, Formatter};
+
+use chrono::{NaiveDate, TimeZone};
+use client::{
+    error::ClientError,
+    search::{SearchFilter, SearchHit},
+    smol_str::SmolStr,
+};
+
+use super::{sub_escape_pop_screen, truncate_string, Message as TopLevelMessage, Screen as TopLevelScreen};
+
+use crate::{
+    client::Client,
+    component::*,
+    label, label_button, length, space,
+    style::{Theme, DEF_SIZE, MESSAGE_SIZE, PADDING, SPACING},
+};
+
+/// How many hits are shown at most.
+const MAX_HITS: usize = 100;
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UserFilter {
+    id: Option<u64>,
+    name: SmolStr,
+}
+
+impl Display for UserFilter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.id {
+            Some(_) => write!(f, "from {}", self.name),
+            None => f.write_str("from anyone"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChannelFilter {
+    ids: Option<(u64, u64)>,
+    name: String,
+}
+
+impl Display for ChannelFilter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.ids {
+            Some(_) => write!(f, "in {}", self.name),
+            None => f.write_str("in any channel"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    QueryChanged(String),
+    UserFilterSelected(UserFilter),
+    ChannelFilterSelected(ChannelFilter),
+    SinceChanged(String),
+    UntilChanged(String),
+    GoToHit(usize),
+    GoBack,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct SearchScreen {
+    query_state: text_input::State,
+    since_state: text_input::State,
+    until_state: text_input::State,
+    user_filter_state: pick_list::State<UserFilter>,
+    channel_filter_state: pick_list::State<ChannelFilter>,
+    hits_state: scrollable::State,
+    hits_buts_state: Vec<button::State>,
+    back_but_state: button::State,
+    query: String,
+    since: String,
+    until: String,
+    filter: SearchFilter,
+    hits: Vec<SearchHit>,
+    error_text: String,
+}
+
+impl SearchScreen {
+    /// Creates a search screen that only looks at the given channel.
+    pub fn in_channel(guild_id: u64, channel_id: u64) -> Self {
+        let mut screen = Self::default();
+        screen.filter.in_channel = Some((guild_id, channel_id));
+        screen
+    }
+
+    pub fn with_query(mut self, query: String) -> Self {
+        self.query = query;
+        self
+    }
+
+    pub fn view(&mut self, theme: &Theme, client: &Client) -> Element<Message> {
+        self.query_state.focus();
+
+        let query_input = TextInput::new(
+            &mut self.query_state,
+            "Search messages...",
+            &self.query,
+            Message::QueryChanged,
+        )
+        .padding(PADDING / 2)
+        .size(DEF_SIZE + 4)
+        .style(theme);
+
+        let mut user_filters = vec![UserFilter {
+            id: None,
+            name: SmolStr::default(),
+        }];
+        user_filters.extend(client.members.iter().map(|(id, member)| UserFilter {
+            id: Some(*id),
+            name: member.username.clone(),
+        }));
+        user_filters[1..].sort_unstable_by(|a, b| a.name.cmp(&b.name));
+        let selected_user = user_filters
+            .iter()
+            .find(|filter| filter.id == self.filter.from_user)
+            .cloned();
+        let user_filter = PickList::new(
+            &mut self.user_filter_state,
+            user_filters,
+            selected_user,
+            Message::UserFilterSelected,
+        )
+        .padding(PADDING / 2)
+        .width(length!(+))
+        .style(theme);
+
+        let mut channel_filters = vec![ChannelFilter {
+            ids: None,
+            name: String::new(),
+        }];
+        channel_filters.extend(client.guilds.iter().flat_map(|(guild_id, guild)| {
+            guild
+                .channels
+                .iter()
+                .filter(|(_, channel)| !channel.is_category)
+                .map(move |(channel_id, channel)| ChannelFilter {
+                    ids: Some((*guild_id, *channel_id)),
+                    name: format!("{} #{}", truncate_string(&guild.name, 16), channel.name),
+                })
+        }));
+        let selected_channel = channel_filters
+            .iter()
+            .find(|filter| filter.ids == self.filter.in_channel)
+            .cloned();
+        let channel_filter = PickList::new(
+            &mut self.channel_filter_state,
+            channel_filters,
+            selected_channel,
+            Message::ChannelFilterSelected,
+        )
+        .padding(PADDING / 2)
+        .width(length!(+))
+        .style(theme);
+
+        let since_input = TextInput::new(
+            &mut self.since_state,
+            "since (YYYY-MM-DD)",
+            &self.since,
+            Message::SinceChanged,
+        )
+        .padding(PADDING / 2)
+        .width(length!(+))
+        .style(theme);
+        let until_input = TextInput::new(
+            &mut self.until_state,
+            "until (YYYY-MM-DD)",
+            &self.until,
+            Message::UntilChanged,
+        )
+        .padding(PADDING / 2)
+        .width(length!(+))
+        .style(theme);
+
+        let filters = row(vec![
+            user_filter.into(),
+            channel_filter.into(),
+            since_input.into(),
+            until_input.into(),
+        ])
+        .width(length!(+));
+
+        let mut hits = Scrollable::new(&mut self.hits_state)
+            .align_items(Align::Start)
+            .height(length!(+))
+            .width(length!(+))
+            .padding(PADDING)
+            .spacing(SPACING)
+            .style(theme);
+
+        let timezone = chrono::Local::now().timezone();
+        self.hits_buts_state.resize_with(self.hits.len(), Default::default);
+        for (index, (hit, but_state)) in self.hits.iter().zip(self.hits_buts_state.iter_mut()).enumerate() {
+            let guild = client.guilds.get(&hit.guild_id);
+            let guild_name = guild.map_or("unknown guild", |g| g.name.as_str());
+            let channel_name = guild
+                .and_then(|g| g.channels.get(&hit.channel_id))
+                .map_or("unknown channel", |c| c.name.as_str());
+            let sender_name = client
+                .members
+                .get(&hit.sender)
+                .map_or("unknown user", |m| m.username.as_str());
+            let timestamp = timezone.from_utc_datetime(&hit.timestamp);
+
+            let content = column(vec![
+                label!(
+                    "{} #{} | {} | {}",
+                    truncate_string(guild_name, 24),
+                    truncate_string(channel_name, 24),
+                    truncate_string(sender_name, 24),
+                    timestamp.format("%d %B %Y %H:%M")
+                )
+                .size(MESSAGE_SIZE - 4)
+                .color(theme.user_theme.dimmed_text)
+                .into(),
+                label!(truncate_string(&hit.text, 200)).size(MESSAGE_SIZE).into(),
+            ]);
+
+            hits = hits.push(
+                Button::new(but_state, content)
+                    .width(length!(+))
+                    .on_press(Message::GoToHit(index))
+                    .style(theme.secondary()),
+            );
+        }
+
+        let status = if self.error_text.is_empty() {
+            label!("{} results", self.hits.len())
+        } else {
+            label!(&self.error_text).color(theme.user_theme.error)
+        };
+
+        let back = label_button!(&mut self.back_but_state, "Back")
+            .on_press(Message::GoBack)
+            .style(theme);
+
+        let panel = column(vec![
+            query_input.into(),
+            filters.into(),
+            status.into(),
+            hits.into(),
+            row(vec![
+                space!(w % 4).into(),
+                back.width(length!(% 2)).into(),
+                space!(w % 4).into(),
+            ])
+            .into(),
+        ]);
+
+        fill_container(panel).style(theme).into()
+    }
+
+    pub fn update(&mut self, msg: Message, client: &Client) -> Command<TopLevelMessage> {
+        match msg {
+            Message::QueryChanged(query) => self.query = query,
+            Message::UserFilterSelected(filter) => self.filter.from_user = filter.id,
+            Message::ChannelFilterSelected(filter) => self.filter.in_channel = filter.ids,
+            Message::SinceChanged(since) => self.since = since,
+            Message::UntilChanged(until) => self.until = until,
+            Message::GoToHit(index) => {
+                return self.hits.get(index).map_or_else(Command::none, |hit| {
+                    let (guild_id, channel_id, message_id) = (hit.guild_id, hit.channel_id, hit.message_id);
+                    Command::perform(
+                        async move {
+                            TopLevelMessage::JumpToMessage {
+                                guild_id,
+                                channel_id,
+                                message_id,
+                            }
+                        },
+                        std::convert::identity,
+                    )
+                })
+            }
+            Message::GoBack => return TopLevelScreen::pop_screen_cmd(),
+        }
+
+        self.search(client);
+
+        Command::none()
+    }
+
+    /// Runs the current query against the client's search index.
+    pub fn search(&mut self, client: &Client) {
+        self.error_text.clear();
+
+        let parse_date = |value: &str, name: &str, error_text: &mut String| {
+            let value = value.trim();
+            if value.is_empty() {
+                return None;
+            }
+            NaiveDate::parse_from_str(value, DATE_FORMAT)
+                .map_err(|_| *error_text = format!("Invalid {} date, expected YYYY-MM-DD", name))
+                .ok()
+        };
+
+        self.filter.since = parse_date(&self.since, "since", &mut self.error_text);
+        self.filter.until = parse_date(&self.until, "until", &mut self.error_text);
+
+        let nothing_to_search = self.query.trim().is_empty() && self.filter == SearchFilter::default();
+        self.hits = if nothing_to_search {
+            Vec::new()
+        } else {
+            client.search_index.search(&self.query, &self.filter, MAX_HITS)
+        };
+    }
+
+    pub fn subscription(&self) -> Subscription<TopLevelMessage> {
+        sub_escape_pop_screen()
+    }
+
+    pub fn on_error(&mut self, error: ClientError) -> Command<TopLevelMessage> {
+        self.error_text = error.to_string();
+
+        Command::none()
+    }
+}
@@ -1,5 +1,7 @@
 use crate::color;
-use client::{content::ThemeRaw, harmony_rust_sdk::api::profile::UserStatus};
+use client::{
+    content::ThemeRaw, formatting::TextColor, harmony_rust_sdk::api::profile::UserStatus, highlight::Highlight,
+};
 use hex_color::HexColor;
 use iced::{
     button, checkbox, container, pick_list, progress_bar, radio, rule, scrollable, slider, text_input, toggler, Color,
@@ -56,6 +58,36 @@ impl Theme {
         }
     }
 
+    pub fn highlight_color(&self, highlight: Highlight) -> Color {
+        match highlight {
+            Highlight::Plain => self.user_theme.text,
+            Highlight::Keyword => self.user_theme.code_keyword,
+            Highlight::String => self.user_theme.code_string,
+            Highlight::Number => self.user_theme.code_number,
+            Highlight::Comment => self.user_theme.code_comment,
+        }
+    }
+
+    /// Color of text colored by a format in a message.
+    pub fn text_color(&self, color: TextColor) -> Color {
+        match color {
+            TextColor::Dim => self.user_theme.dimmed_text,
+            TextColor::Bright => {
+                let text = self.user_theme.text;
+                Color {
+                    r: (text.r * 1.3).min(1.0),
+                    g: (text.g * 1.3).min(1.0),
+                    b: (text.b * 1.3).min(1.0),
+                    a: text.a,
+                }
+            }
+            TextColor::Negative => self.user_theme.error,
+            TextColor::Positive => self.user_theme.success,
+            TextColor::Info => self.user_theme.accent,
+            TextColor::Warning => self.user_theme.mention_color,
+        }
+    }
+
     pub const fn secondary(mut self) -> Self {
         self.secondary = true;
         self
@@ -126,6 +158,10 @@ pub struct UserTheme {
     pub dimmed_text: Color,
     pub accent: Color,
     pub mention_color: Color,
+    pub code_keyword: Color,
+    pub code_string: Color,
+    pub code_number: Color,
+    pub code_comment: Color,
 }
 
 const DEF_THEME: &[u8] = include_bytes!("../contrib/colorschemes/iced-dark.toml");
@@ -146,6 +182,10 @@ impl Default for UserTheme {
             dimmed_text: value.dimmed_text_color.parse_to_color().unwrap(),
             accent: value.accent_color.parse_to_color().unwrap(),
             mention_color: value.mention_color.parse_to_color().unwrap(),
+            code_keyword: value.code_keyword_color.parse_to_color().unwrap(),
+            code_string: value.code_string_color.parse_to_color().unwrap(),
+            code_number: value.code_number_color.parse_to_color().unwrap(),
+            code_comment: value.code_comment_color.parse_to_color().unwrap(),
         }
     }
 }
@@ -172,6 +212,16 @@ impl From<ThemeRaw> for UserTheme {
             dimmed_text: value.dimmed_text_color.parse_to_color().unwrap_or(default.dimmed_text),
             accent: value.accent_color.parse_to_color().unwrap_or(default.accent),
             mention_color: value.mention_color.parse_to_color().unwrap_or(default.mention_color),
+            code_keyword: value
+                .code_keyword_color
+                .parse_to_color()
+                .unwrap_or(default.code_keyword),
+            code_string: value.code_string_color.parse_to_color().unwrap_or(default.code_string),
+            code_number: value.code_number_color.parse_to_color().unwrap_or(default.code_number),
+            code_comment: value
+                .code_comment_color
+                .parse_to_color()
+                .unwrap_or(default.code_comment),
         }
     }
 }
@@ -402,6 +452,18 @@ impl From<&Theme> for Box<dyn style::color_picker::StyleSheet> {
     }
 }
 
+impl From<Theme> for Box<dyn style::date_picker::StyleSheet> {
+    fn from(theme: Theme) -> Self {
+        styles::DatePicker(theme.user_theme).into()
+    }
+}
+
+impl From<&Theme> for Box<dyn style::date_picker::StyleSheet> {
+    fn from(theme: &Theme) -> Self {
+        (*theme).into()
+    }
+}
+
 #[derive(Debug, Clone, Copy, Default)]
 pub struct OverrideStyle {
     border_color: Option<Color>,
@@ -562,6 +624,43 @@ mod styles {
         }
     }
 
+    pub struct DatePicker(pub UserTheme);
+
+    impl style::date_picker::StyleSheet for DatePicker {
+        fn active(&self) -> style::date_picker::Style {
+            style::date_picker::Style {
+                background: self.0.primary_bg.into(),
+                border_radius: self.0.border_radius.into(),
+                border_width: 1.0,
+                border_color: self.0.border,
+                text_color: self.0.text,
+                text_attenuated_color: self.0.dimmed_text,
+                day_background: self.0.secondary_bg.into(),
+            }
+        }
+
+        fn selected(&self) -> style::date_picker::Style {
+            style::date_picker::Style {
+                day_background: self.0.accent.into(),
+                ..self.active()
+            }
+        }
+
+        fn hovered(&self) -> style::date_picker::Style {
+            style::date_picker::Style {
+                day_background: self.0.primary_bg.into(),
+                ..self.active()
+            }
+        }
+
+        fn focused(&self) -> style::date_picker::Style {
+            style::date_picker::Style {
+                border_color: Color::from_rgb(0.5, 0.5, 0.5),
+                ..self.active()
+            }
+        }
+    }
+
     /*pub struct NumberInput(pub UserTheme);
 
     impl number_input::StyleSheet for NumberInput {